@@ -0,0 +1,11 @@
+// Floating point arithmetic benchmark.
+#include <stdio.h>
+
+int main() {
+  double s = 0;
+  for (int i = 1; i < 200000000; ++i) {
+    s += 1.0 / ((double)i * i);
+  }
+  printf("%.12f\n", s);
+  return 0;
+}
@@ -0,0 +1,12 @@
+// Integer arithmetic benchmark.
+#include <stdio.h>
+
+int main() {
+  unsigned long long x = 1, s = 0;
+  for (int i = 0; i < 400000000; ++i) {
+    x = x * 6364136223846793005ULL + 1442695040888963407ULL;
+    s ^= x >> 33;
+  }
+  printf("%llu\n", s);
+  return 0;
+}
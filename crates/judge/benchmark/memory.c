@@ -0,0 +1,18 @@
+// Random memory access benchmark.
+#include <stdio.h>
+#include <stdlib.h>
+
+#define N (1 << 24)
+
+int main() {
+  unsigned *a = malloc(sizeof(unsigned) * N);
+  for (unsigned i = 0; i < N; ++i) a[i] = i * 2654435761u % N;
+  unsigned p = 0, s = 0;
+  for (int i = 0; i < 5000000; ++i) {
+    p = a[p];
+    s += p;
+  }
+  printf("%u\n", s);
+  free(a);
+  return 0;
+}
@@ -1,8 +1,7 @@
-fn main() -> shadow_rs::SdResult<()> {
+fn main() {
   tonic_build::configure()
     .build_client(true)
     .build_server(false)
     .compile(&["proto/sandbox.proto"], &["proto/"])
     .unwrap();
-  return shadow_rs::new();
 }
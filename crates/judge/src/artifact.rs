@@ -0,0 +1,99 @@
+use std::{io, path, time};
+
+use sha2::{Digest, Sha256};
+
+use crate::CONFIG;
+
+/// Store of large outputs spilled out of records, such as full checker messages
+/// longer than `judge.message_limit` (in config).
+///
+/// Each artifact is stored as a file named by the SHA-256 of its content,
+/// so storing the same content twice keeps a single file.
+#[derive(Debug, Clone)]
+pub struct Store {
+  dir: path::PathBuf,
+}
+
+impl Store {
+  /// Open the store in a directory, which is created if not exists.
+  pub fn open(dir: impl Into<path::PathBuf>) -> io::Result<Self> {
+    let dir = dir.into();
+    std::fs::create_dir_all(&dir)?;
+    return Ok(Self { dir });
+  }
+
+  /// Open the store in `data_dir` of config, None if it is not set.
+  pub fn from_config() -> io::Result<Option<Self>> {
+    return CONFIG
+      .data_dir
+      .as_ref()
+      .map(|x| Self::open(path::Path::new(x).join("artifacts")))
+      .transpose();
+  }
+
+  /// Path of an artifact, None if the id is not a hex SHA-256.
+  fn path(&self, id: &str) -> Option<path::PathBuf> {
+    if id.len() != 64 || !id.bytes().all(|x| x.is_ascii_hexdigit()) {
+      return None;
+    }
+    return Some(self.dir.join(id.to_ascii_lowercase()));
+  }
+
+  /// Store an artifact and return its id.
+  ///
+  /// The file is written aside and then renamed, so a crash never leaves a partial artifact.
+  pub async fn save(&self, content: &[u8]) -> io::Result<String> {
+    let id = hex::encode(Sha256::digest(content));
+    let path = self.dir.join(&id);
+    if tokio::fs::try_exists(&path).await? {
+      return Ok(id);
+    }
+    let tmp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+    tokio::fs::write(&tmp, content).await?;
+    tokio::fs::rename(&tmp, &path).await?;
+    return Ok(id);
+  }
+
+  /// Load an artifact, None if not found.
+  pub async fn load(&self, id: &str) -> io::Result<Option<Vec<u8>>> {
+    let path = match self.path(id) {
+      Some(x) => x,
+      None => return Ok(None),
+    };
+    return match tokio::fs::read(path).await {
+      Ok(x) => Ok(Some(x)),
+      Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(err),
+    };
+  }
+
+  /// Remove the artifacts left half written for longer than `age`, e.g. by a crashed instance,
+  /// and return the number of removed files.
+  pub async fn remove_orphans(&self, age: time::Duration) -> io::Result<usize> {
+    let mut removed = 0;
+    let mut entries = tokio::fs::read_dir(&self.dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+      if entry.path().extension().and_then(|x| x.to_str()) != Some("tmp") {
+        continue;
+      }
+      let modified = entry.metadata().await?.modified()?;
+      if modified.elapsed().unwrap_or_default() >= age {
+        tokio::fs::remove_file(entry.path()).await?;
+        removed += 1;
+      }
+    }
+    return Ok(removed);
+  }
+}
+
+lazy_static! {
+  /// Store of the artifacts spilled while judging, None if `data_dir` is not set in config
+  /// or the store can not be opened.
+  pub static ref STORE: Option<Store> = match Store::from_config() {
+    Ok(x) => x,
+    Err(err) => {
+      log::warn!("failed to open the artifact store: {}", err);
+      None
+    }
+  };
+}
@@ -1,5 +1,7 @@
 use std::{borrow::Cow, fmt::Display, str::FromStr};
 
+use regex::Regex;
+use serde::Serialize;
 use serde_with::{DeserializeFromStr, SerializeDisplay};
 use thiserror::Error;
 
@@ -19,6 +21,151 @@ mod pools {
   #[include = "*.cpp"]
   #[include = "*.h"]
   pub struct Checker;
+
+  /// Builtin validators.
+  #[derive(RustEmbed)]
+  #[folder = "third_party/testlib/validators/"]
+  #[include = "*.cpp"]
+  #[include = "*.h"]
+  pub struct Validator;
+
+  /// Builtin generators.
+  #[derive(RustEmbed)]
+  #[folder = "third_party/testlib/generators/"]
+  #[include = "*.cpp"]
+  #[include = "*.h"]
+  pub struct Generator;
+}
+
+/// List the paths of the files in a builtin pool, in sorted order.
+///
+/// # Errors
+///
+/// This function will return an error if the pool does not exist.
+pub fn list(pool: &str) -> Result<Vec<String>, FileNotExistError> {
+  let mut paths: Vec<_> = match pool {
+    "testlib" => pools::Testlib::iter().collect(),
+    "checker" => pools::Checker::iter().collect(),
+    "validator" => pools::Validator::iter().collect(),
+    "generator" => pools::Generator::iter().collect(),
+    _ => return Err(FileNotExistError::Pool(pool.to_string())),
+  };
+  paths.sort();
+  return Ok(paths.into_iter().map(|x| x.into_owned()).collect());
+}
+
+/// Metadata of a builtin checker, see `checkers`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct CheckerInfo {
+  /// Name of the checker, the file name without extension (e.g. `ncmp`).
+  pub name: String,
+
+  /// Path of the checker in the `checker` pool (e.g. `ncmp.cpp`).
+  pub path: String,
+
+  /// What the checker compares, from `setName` or the header comment of the source.
+  pub description: Option<String>,
+
+  /// Whether the checker compares a single value or a sequence, guessed from the description.
+  pub arity: Option<Arity>,
+
+  /// Max absolute or relative error of floating-point numbers, from the `EPS` constant.
+  pub tolerance: Option<f64>,
+}
+
+/// Number of values compared by a checker.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Arity {
+  Single,
+  Sequence,
+}
+
+lazy_static! {
+  static ref SET_NAME: Regex = Regex::new(r#"(?s)setName\s*\((.*?)\);"#).unwrap();
+  static ref STRING_LITERAL: Regex = Regex::new(r#""((?:[^"\\]|\\.)*)""#).unwrap();
+  static ref FORMAT_SPEC: Regex = Regex::new(r"%[-+ #0-9.]*[a-zA-Z]").unwrap();
+  static ref EPS: Regex = Regex::new(r"(?i)double\s+eps\s*=\s*([0-9.eE+-]+)").unwrap();
+}
+
+impl CheckerInfo {
+  /// Parse the metadata from the source of a checker at `path` of the `checker` pool.
+  pub fn parse(path: &str, source: &str) -> Self {
+    let description = set_name(source).or_else(|| header_comment(source));
+    let arity = description.as_deref().and_then(|x| {
+      let x = x.to_lowercase();
+      if ["sequence", "lines", "tokens"]
+        .iter()
+        .any(|y| x.contains(y))
+      {
+        return Some(Arity::Sequence);
+      }
+      if ["single", "two ", "one "].iter().any(|y| x.contains(y)) {
+        return Some(Arity::Single);
+      }
+      return None;
+    });
+    return Self {
+      name: path.rsplit_once('.').map_or(path, |x| x.0).to_string(),
+      path: path.to_string(),
+      description,
+      arity,
+      tolerance: EPS.captures(source).and_then(|x| x[1].parse::<f64>().ok()),
+    };
+  }
+}
+
+/// The first string literal passed to `setName` which is not only format specifiers.
+fn set_name(source: &str) -> Option<String> {
+  let args = SET_NAME.captures(source)?;
+  return STRING_LITERAL
+    .captures_iter(&args[1])
+    .map(|x| x[1].to_string())
+    .find(|x| {
+      FORMAT_SPEC
+        .replace_all(x, "")
+        .chars()
+        .any(|c| c.is_alphabetic())
+    });
+}
+
+/// Text of the comments before the first line of code, None if there is none.
+fn header_comment(source: &str) -> Option<String> {
+  let mut lines = vec![];
+  for line in source.lines().map(str::trim) {
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let text = match line.strip_prefix("//") {
+      Some(x) => x,
+      None if line.starts_with("/*") || line.starts_with('*') => line
+        .trim_start_matches("/*")
+        .trim_end_matches("*/")
+        .trim_start_matches('*'),
+      None => break,
+    };
+    let text = text.trim();
+    if !text.is_empty() {
+      lines.push(text);
+    }
+  }
+  if lines.is_empty() {
+    return None;
+  }
+  return Some(lines.join(" "));
+}
+
+/// Metadata of all the builtin checkers, sorted by path.
+pub fn checkers() -> Vec<CheckerInfo> {
+  return list("checker")
+    .unwrap()
+    .into_iter()
+    .filter(|x| x.ends_with(".cpp"))
+    .map(|path| {
+      let file = File::new("checker", &path).unwrap();
+      CheckerInfo::parse(&path, &String::from_utf8_lossy(file.as_bytes()))
+    })
+    .collect();
 }
 
 /// Parsed builtin data.
@@ -57,6 +204,8 @@ impl File {
       content: match pool {
         "testlib" => pools::Testlib::get(path),
         "checker" => pools::Checker::get(path),
+        "validator" => pools::Validator::get(path),
+        "generator" => pools::Generator::get(path),
         _ => return Err(FileNotExistError::Pool(pool.to_string())),
       }
       .map_or(
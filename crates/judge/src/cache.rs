@@ -0,0 +1,63 @@
+use std::{
+  collections::HashMap,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+  },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::etc;
+
+/// In-memory cache of task results, keyed by the hashes of everything the task depends on.
+///
+/// Lookups always miss if caching is disabled by `etc::Options::no_cache`.
+#[derive(Debug)]
+pub struct Cache<V> {
+  entries: Mutex<HashMap<String, V>>,
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+/// Hit metrics of a cache.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub struct CacheStats {
+  pub hits: u64,
+  pub misses: u64,
+}
+
+impl<V> Default for Cache<V> {
+  fn default() -> Self {
+    Self {
+      entries: Mutex::new(HashMap::new()),
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+    }
+  }
+}
+
+impl<V: Clone> Cache<V> {
+  pub fn stats(&self) -> CacheStats {
+    CacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+    }
+  }
+
+  pub(crate) fn get(&self, key: &str) -> Option<V> {
+    let ret = match etc::options().no_cache {
+      true => None,
+      false => self.entries.lock().unwrap().get(key).cloned(),
+    };
+    match ret {
+      Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+      None => self.misses.fetch_add(1, Ordering::Relaxed),
+    };
+    return ret;
+  }
+
+  pub(crate) fn insert(&self, key: String, value: V) {
+    self.entries.lock().unwrap().insert(key, value);
+  }
+}
@@ -0,0 +1,145 @@
+use std::{collections::HashMap, fmt, str::FromStr, sync::OnceLock, time};
+
+use rust_embed::RustEmbed;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{data, error, judge, lang, program, sandbox, CONFIG};
+
+/// Reference programs of the benchmark suite.
+#[derive(RustEmbed)]
+#[folder = "benchmark/"]
+#[include = "*.c"]
+struct Benchmark;
+
+/// Nominal CPU time of each benchmark program, the speed factor is relative to them.
+const NOMINAL_TIMES: [(&str, time::Duration); 3] = [
+  ("int.c", time::Duration::from_millis(500)),
+  ("float.c", time::Duration::from_millis(350)),
+  ("memory.c", time::Duration::from_millis(450)),
+];
+
+/// Result of a calibration run.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Calibration {
+  /// Measured CPU time of each benchmark program.
+  pub times: HashMap<String, time::Duration>,
+
+  /// Machine speed factor, the geometric mean of `measured / nominal` of all benchmark programs.
+  ///
+  /// A factor greater than 1 means the sandbox is slower than the nominal machine.
+  /// It can be set as `sandbox.time_factor` in config to scale time limits of solutions.
+  pub factor: f64,
+}
+
+impl fmt::Display for Calibration {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut times: Vec<_> = self.times.iter().collect();
+    times.sort();
+    for (name, time) in times {
+      writeln!(f, "{}: {} ms", name, time.as_millis())?;
+    }
+    return writeln!(f, "speed factor: {:.3}", self.factor);
+  }
+}
+
+/// Speed factor measured by `auto_calibrate`.
+static MEASURED_FACTOR: OnceLock<f64> = OnceLock::new();
+
+/// Speed factor measured by `auto_calibrate`, None if not calibrated.
+pub fn measured_factor() -> Option<f64> {
+  return MEASURED_FACTOR.get().copied();
+}
+
+/// Calibrate the sandbox if `sandbox.auto_calibrate` is enabled and `sandbox.time_factor`
+/// is not set (in config), so time limits of solutions are scaled by the measured factor
+/// from now on (see `etc::SandboxCfg::effective_time_factor`).
+///
+/// Returns the calibration, None if the sandbox is not calibrated.
+///
+/// # Errors
+///
+/// This function will return an error if the calibration fails, then time limits are not scaled.
+pub async fn auto_calibrate() -> Result<Option<Calibration>, CalibrateError> {
+  if !CONFIG.sandbox.auto_calibrate || CONFIG.sandbox.time_factor.is_some() {
+    return Ok(None);
+  }
+  let calibration = calibrate().await?;
+  _ = MEASURED_FACTOR.set(calibration.factor);
+  return Ok(Some(calibration));
+}
+
+/// Run the benchmark suite on the sandbox and compute the machine speed factor.
+///
+/// # Errors
+///
+/// This function will return an error if a benchmark program fails to compile or run.
+pub async fn calibrate() -> Result<Calibration, CalibrateError> {
+  let lang = lang::Lang::from_str("c").unwrap();
+  let mut times = HashMap::new();
+  let mut log_sum = 0.;
+
+  // Run the programs one by one, so they do not interfere with each other.
+  for (name, nominal) in NOMINAL_TIMES {
+    let exec = program::Source {
+      lang: lang.clone(),
+      data: data::Provider::Memory(Benchmark::get(name).unwrap().data.to_vec()),
+    }
+    .compile(vec![], HashMap::new())
+    .await
+    .map_err(|err| CalibrateError::Compile {
+      name: name.to_string(),
+      err,
+    })?;
+
+    let (res, _) = exec
+      .judge_batch(
+        vec![],
+        sandbox::FileHandle::upload(&[])
+          .await
+          .map_err(|err| CalibrateError::Runtime {
+            name: name.to_string(),
+            err: err.into(),
+          })?,
+        HashMap::new(),
+        CONFIG.judge.time_limit,
+        CONFIG.judge.memory_limit,
+        None,
+        judge::TimeKind::Cpu,
+        None,
+        CONFIG.judge.stdout_limit,
+        &judge::OutputSource::Stdout,
+      )
+      .await;
+    if res.status != sandbox::Status::Accepted {
+      return Err(CalibrateError::Runtime {
+        name: name.to_string(),
+        err: res.into(),
+      });
+    }
+
+    log_sum += (res.time.as_secs_f64() / nominal.as_secs_f64()).ln();
+    times.insert(name.to_string(), res.time);
+  }
+
+  return Ok(Calibration {
+    times,
+    factor: (log_sum / NOMINAL_TIMES.len() as f64).exp(),
+  });
+}
+
+/// Error when a benchmark program can not be compiled or run.
+#[derive(Debug, Error, Clone)]
+pub enum CalibrateError {
+  #[error("benchmark {name} compile failed: {err}")]
+  Compile {
+    name: String,
+    err: error::CompileError,
+  },
+
+  #[error("benchmark {name} run failed: {err}")]
+  Runtime {
+    name: String,
+    err: error::RuntimeError,
+  },
+}
@@ -1,34 +1,36 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
 
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use strum::Display;
+use sha2::{Digest, Sha256};
 
-use crate::{error, program, sandbox};
+use crate::{artifact, cache, error, program, record, sandbox, CONFIG};
 
-/// Limit the message to a maximum of 'LIMIT' characters.
-fn limit_message(s: &str) -> String {
-  const LIMIT: usize = 1024;
-  if s.as_bytes().len() <= LIMIT {
+pub use crate::cache::CacheStats;
+pub use rindag_types::checker::Status;
+
+/// Limit the message to a maximum of `judge.message_limit` (in config) bytes.
+pub(crate) fn limit_message(s: &str) -> String {
+  return truncate_message(s, CONFIG.judge.message_limit);
+}
+
+/// Limit the message to a maximum of `limit` bytes, marking a cut message with `...`.
+pub(crate) fn truncate_message(s: &str, limit: usize) -> String {
+  if s.as_bytes().len() <= limit {
     return s.to_string();
   }
-  return String::from_utf8_lossy(&s.bytes().into_iter().take(LIMIT - 3).collect::<Vec<_>>())
-    .to_string()
+  return String::from_utf8_lossy(
+    &s.bytes()
+      .into_iter()
+      .take(limit.saturating_sub(3))
+      .collect::<Vec<_>>(),
+  )
+  .to_string()
     + "...";
 }
 
-#[derive(Debug, PartialEq, strum::EnumString, Serialize, Deserialize, Clone, Display)]
-#[strum(serialize_all = "snake_case")]
-pub enum Status {
-  Accepted,
-  WrongAnswer,
-  PartiallyCorrect,
-  PresentationError,
-  SystemError,
-}
-
 /// Parsed testlib checker output.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Output {
   /// Testlib parsed status.
   pub status: Status,
@@ -36,8 +38,16 @@ pub struct Output {
   /// Length limited output message.
   pub message: String,
 
+  /// Id of the artifact holding the full message (see `artifact::Store`),
+  /// None if the message is not cut or it is not spilled.
+  pub artifact: Option<String>,
+
   /// Floating point score value in [0,1].
   pub score: f32,
+
+  /// Points reported by the checker (like `points 35`) before clamped into `score`,
+  /// None if the checker reported no points.
+  pub raw_points: Option<f64>,
 }
 
 impl Output {
@@ -52,12 +62,20 @@ impl Output {
   ///   - 0 < score < 1: PartiallyCorrect, real_score = score.
   ///   - score >= 1: Accepted, real_score = 1.
   ///
+  /// The points (or the value of a `score(...)` line) are kept unclamped in `raw_points`.
+  ///
   /// If there is a line in the output that starts with `status(...)`,
   /// it will try to use the value in parentheses as the result status.
   ///
   /// If there is a line in the output that starts with `score(...)`,
   /// it will try to use the number in parentheses as the result score.
+  ///
+  /// An output in the XML result format of testlib `-appes` mode is parsed by `parse_appes`.
   pub fn parse(output: &str) -> Self {
+    if let Some(ret) = Self::parse_appes(output) {
+      return ret;
+    }
+
     lazy_static! {
       static ref PC_PAT: Regex =
         Regex::new(r"\A(?:partially correct|points) \(?([0-9]*\.?[0-9]*)\)?").unwrap();
@@ -66,6 +84,7 @@ impl Output {
     }
 
     let mut ret = (Status::SystemError, 0.);
+    let mut raw_points = None;
 
     if output.starts_with("ok") {
       ret = (Status::Accepted, 1.);
@@ -76,7 +95,9 @@ impl Output {
     } else if output.starts_with("wrong output format") {
       ret = (Status::PresentationError, 0.);
     } else if let Some(cap) = PC_PAT.captures(output) {
-      if let Ok(score) = cap[1].parse::<f32>() {
+      if let Ok(points) = cap[1].parse::<f64>() {
+        raw_points = Some(points);
+        let score = points as f32;
         if score >= 1. {
           ret = (Status::Accepted, 1.);
         } else if score <= 0. {
@@ -93,8 +114,9 @@ impl Output {
           ret.0 = stat;
         }
       } else if &cap[1] == "score" {
-        if let Ok(stat) = cap[2].parse::<f32>() {
-          ret.1 = stat.clamp(0., 1.);
+        if let Ok(points) = cap[2].parse::<f64>() {
+          raw_points = Some(points);
+          ret.1 = (points as f32).clamp(0., 1.);
         }
       }
     }
@@ -102,9 +124,261 @@ impl Output {
     return Self {
       status: ret.0,
       score: ret.1,
+      raw_points,
       message: limit_message(output),
+      artifact: None,
+    };
+  }
+
+  /// Parse the XML result written by a testlib checker in `-appes` mode,
+  /// like `<result outcome = "points" points = "0.5">message</result>`.
+  ///
+  /// - accepted -> Accepted.
+  /// - wrong-answer -> WrongAnswer.
+  /// - fail -> SystemError.
+  /// - presentation-error, unexpected-eof -> PresentationError.
+  /// - points: the `points` attribute is the score, judged like `points` of `parse`.
+  /// - partially-correct: the `pctype` attribute is the score in percent.
+  ///
+  /// Returns None if the output is not such a result.
+  pub fn parse_appes(output: &str) -> Option<Self> {
+    let output = output.trim();
+    if !output.starts_with("<?xml") && !output.starts_with("<result") {
+      return None;
+    }
+    let doc = roxmltree::Document::parse(output).ok()?;
+    let root = doc.root_element();
+    if !root.has_tag_name("result") {
+      return None;
+    }
+    let scored = |score: f64| match score {
+      x if x >= 1. => (Status::Accepted, 1., Some(x)),
+      x if x > 0. => (Status::PartiallyCorrect, x as f32, Some(x)),
+      x => (Status::WrongAnswer, 0., Some(x)),
+    };
+    let (status, score, raw_points) = match root.attribute("outcome")? {
+      "accepted" => (Status::Accepted, 1., None),
+      "wrong-answer" => (Status::WrongAnswer, 0., None),
+      "fail" => (Status::SystemError, 0., None),
+      "presentation-error" | "unexpected-eof" => (Status::PresentationError, 0., None),
+      "points" => scored(root.attribute("points")?.trim().parse::<f64>().ok()?),
+      "partially-correct" => scored(root.attribute("pctype")?.trim().parse::<f64>().ok()? / 100.),
+      _ => return None,
+    };
+    return Some(Self {
+      status,
+      score,
+      raw_points,
+      message: limit_message(root.text().unwrap_or_default()),
+      artifact: None,
+    });
+  }
+
+  /// Parse the output like `parse`, and spill the full output to the artifact store
+  /// (see `artifact::STORE`) if the message is cut, so no diagnostics are lost.
+  ///
+  /// If the store is not available, the message is only cut as by `parse`.
+  pub async fn parse_spilled(output: &str) -> Self {
+    let mut ret = Self::parse(output);
+    if output.len() <= CONFIG.judge.message_limit {
+      return ret;
+    }
+    match artifact::STORE.as_ref() {
+      Some(store) => match store.save(output.as_bytes()).await {
+        Ok(id) => ret.artifact = Some(id),
+        Err(err) => log::warn!("failed to spill a checker message: {}", err),
+      },
+      None => log::debug!(
+        "checker message of {} bytes is cut, set data_dir to keep it",
+        output.len()
+      ),
+    }
+    return ret;
+  }
+}
+
+/// How a presentation error reported by the checker or interactor is judged.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum PresentationErrorPolicy {
+  /// Reported as presentation error with no score.
+  #[default]
+  AsIs,
+
+  /// Reported as wrong answer.
+  WrongAnswer,
+
+  /// Reported as presentation error, but only partially penalized,
+  /// which keeps `score` (in range \[0,1\]) of the full score.
+  Penalized { score: f32 },
+}
+
+impl PresentationErrorPolicy {
+  /// Apply the policy to a parsed output, outputs of other statuses are returned as is.
+  pub fn apply(&self, mut output: Output) -> Output {
+    if output.status != Status::PresentationError {
+      return output;
+    }
+    match self {
+      Self::AsIs => {}
+      Self::WrongAnswer => {
+        output.status = Status::WrongAnswer;
+        output.score = 0.;
+      }
+      Self::Penalized { score } => output.score = score.clamp(0., 1.),
+    }
+    return output;
+  }
+}
+
+/// Checker comparing the output with the answer natively,
+/// which saves compiling a checker and running it in sandbox for the common cases.
+///
+/// Tokens are separated by ASCII whitespace.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(tag = "compare", rename_all = "snake_case")]
+pub enum Builtin {
+  /// Compare the sequences of tokens (like testlib `wcmp`).
+  Tokens,
+
+  /// Compare line by line, ignoring the trailing whitespace of each line
+  /// and the empty lines at the end.
+  Lines,
+
+  /// Compare the sequences of tokens, and numbers are equal
+  /// if their absolute or relative error is at most `eps` (like testlib `rcmp`).
+  Floats { eps: f64 },
+}
+
+/// English ordinal of a positive number, like `1st` and `12th`.
+fn ordinal(n: usize) -> String {
+  let suffix = match (n % 10, n % 100) {
+    (_, 11..=13) => "th",
+    (1, _) => "st",
+    (2, _) => "nd",
+    (3, _) => "rd",
+    _ => "th",
+  };
+  return format!("{}{}", n, suffix);
+}
+
+impl Builtin {
+  /// Compare the output with the answer, and return the output in the form of testlib checkers.
+  pub fn compare(&self, output: &[u8], answer: &[u8]) -> Output {
+    let output = String::from_utf8_lossy(output);
+    let answer = String::from_utf8_lossy(answer);
+    let message = match self {
+      Self::Tokens => compare_seq(
+        "words",
+        output.split_ascii_whitespace(),
+        answer.split_ascii_whitespace(),
+        |x, y| x == y,
+      ),
+      Self::Lines => {
+        fn lines(s: &str) -> Vec<&str> {
+          let mut lines: Vec<_> = s.lines().map(str::trim_end).collect();
+          while lines.last() == Some(&"") {
+            lines.pop();
+          }
+          return lines;
+        }
+        compare_seq("lines", lines(&output), lines(&answer), |x, y| x == y)
+      }
+      Self::Floats { eps } => compare_seq(
+        "numbers",
+        output.split_ascii_whitespace(),
+        answer.split_ascii_whitespace(),
+        |x, y| match (x.parse::<f64>(), y.parse::<f64>()) {
+          (Ok(x), Ok(y)) => x == y || (x - y).abs() <= *eps || (x - y).abs() <= eps * y.abs(),
+          _ => x == y,
+        },
+      ),
+    };
+    return Output::parse(&message);
+  }
+}
+
+/// Compare the items of output and answer in order,
+/// and return a testlib checker message naming the items as `name`.
+fn compare_seq<'a>(
+  name: &str,
+  output: impl IntoIterator<Item = &'a str>,
+  answer: impl IntoIterator<Item = &'a str>,
+  eq: impl Fn(&str, &str) -> bool,
+) -> String {
+  let mut output = output.into_iter();
+  let mut count = 0;
+  for expected in answer {
+    count += 1;
+    let found = match output.next() {
+      Some(x) => x,
+      None => {
+        return format!(
+          "wrong answer Answer contains longer sequence [length = {}+], but output contains {} elements",
+          count,
+          count - 1
+        )
+      }
     };
+    if !eq(found, expected) {
+      return format!(
+        "wrong answer {} {} differ - expected: '{}', found: '{}'",
+        ordinal(count),
+        name,
+        truncate_message(expected, 64),
+        truncate_message(found, 64)
+      );
+    }
+  }
+  if output.next().is_some() {
+    return format!(
+      "wrong answer Output contains longer sequence [length = {}+], but answer contains {} elements",
+      count + 1,
+      count
+    );
   }
+  return format!("ok {} {}", count, name);
+}
+
+/// Source of a checker: a program compiled and run in sandbox, or a builtin comparison.
+#[derive(Debug, Clone)]
+pub enum Source {
+  Program(program::Source),
+  Builtin(Builtin),
+}
+
+impl From<program::Source> for Source {
+  fn from(source: program::Source) -> Self {
+    Self::Program(source)
+  }
+}
+
+impl Source {
+  /// Source of the checker program, None for a builtin checker.
+  pub fn program(&self) -> Option<&program::Source> {
+    return match self {
+      Self::Program(x) => Some(x),
+      Self::Builtin(_) => None,
+    };
+  }
+
+  /// The builtin comparison, None for a checker program.
+  pub fn builtin(&self) -> Option<&Builtin> {
+    return match self {
+      Self::Program(_) => None,
+      Self::Builtin(x) => Some(x),
+    };
+  }
+}
+
+/// How a checker checks the outputs.
+#[derive(Debug, Clone)]
+pub enum Kind {
+  /// Run a compiled checker program in sandbox.
+  Program(program::Executable),
+
+  /// Compare natively, nothing is run in sandbox.
+  Builtin(Builtin),
 }
 
 /// Checker is a type of executable program,
@@ -112,45 +386,237 @@ impl Output {
 /// standard answer on a given input, or to judge the "correctness" of the user's answer.
 #[derive(Debug, Clone)]
 pub struct Checker {
-  pub exec: program::Executable,
+  pub kind: Kind,
+
+  /// Cache of check results, None to disable caching.
+  pub cache: Option<Arc<Cache>>,
+
+  pub presentation_error: PresentationErrorPolicy,
+
+  /// Key of the seeds passed to the checker, None to not seed the checker.
+  pub seed: Option<String>,
+}
+
+/// Environment variable of the seed passed to a seeded checker.
+///
+/// A randomized checker can call `rnd.setSeed` with it, so that re-judging a submission
+/// reproduces the exact same checker behavior.
+pub const SEED_ENV: &str = "RINDAG_CHECKER_SEED";
+
+/// Name of the XML result file written by checkers in testlib `-appes` mode,
+/// see `JudgeCfg::checker_appes`.
+const APPES_FILE: &str = "result.xml";
+
+impl From<Kind> for Checker {
+  fn from(kind: Kind) -> Self {
+    Self {
+      kind,
+      cache: None,
+      presentation_error: PresentationErrorPolicy::default(),
+      seed: None,
+    }
+  }
 }
 
 impl From<program::Executable> for Checker {
   fn from(exec: program::Executable) -> Self {
-    Self { exec }
+    return Self::from(Kind::Program(exec));
+  }
+}
+
+impl From<Builtin> for Checker {
+  fn from(builtin: Builtin) -> Self {
+    return Self::from(Kind::Builtin(builtin));
   }
 }
 
+/// Cache of checker outputs, keyed by the content hashes of input, output and answer files.
+///
+/// A cache must belong to a single checker, and should only be enabled for deterministic checkers
+/// (checkers using randomness or scoring relative to other runs must not be cached).
+pub type Cache = cache::Cache<program::Run<Output>>;
+
 impl Checker {
+  /// Enable caching of check results with the given cache.
+  pub fn with_cache(mut self, cache: Arc<Cache>) -> Self {
+    self.cache = Some(cache);
+    self
+  }
+
+  /// Judge presentation errors with the given policy.
+  pub fn with_presentation_error(mut self, policy: PresentationErrorPolicy) -> Self {
+    self.presentation_error = policy;
+    self
+  }
+
+  /// Seed the checker with seeds derived from `key`, which should identify the problem
+  /// and the submission being judged.
+  ///
+  /// The seed of each test is passed in the `RINDAG_CHECKER_SEED` environment variable.
+  pub fn with_seed(mut self, key: String) -> Self {
+    self.seed = Some(key);
+    self
+  }
+
+  /// Seed of the checker on a test, None if the checker is not seeded.
+  pub fn seed(&self, test_id: &record::TestId) -> Option<u64> {
+    let key = self.seed.as_ref()?;
+    let hash = Sha256::new()
+      .chain_update(key)
+      .chain_update([0])
+      .chain_update(test_id.to_string())
+      .finalize();
+    return Some(u64::from_be_bytes(hash[..8].try_into().unwrap()));
+  }
+
+  /// Key of a check in the cache.
+  ///
+  /// Input, output and answer files are keyed by their digests (see `sandbox::FileHandle::digest`),
+  /// which are known at upload or computed in the sandbox, so the files are never downloaded.
+  /// Extra files are keyed by their sandbox file ids, as they are shared by all checks of a problem.
+  async fn cache_key(
+    &self,
+    args: &[String],
+    seed: Option<u64>,
+    input_file: &sandbox::FileHandle,
+    output_file: &sandbox::FileHandle,
+    answer_file: &sandbox::FileHandle,
+    copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> Option<String> {
+    let (input, output, answer) = futures::join!(
+      input_file.digest(),
+      output_file.digest(),
+      answer_file.digest()
+    );
+    let mut hasher = Sha256::new();
+    for x in [input.ok()?, output.ok()?, answer.ok()?] {
+      hasher.update(x);
+      hasher.update([0]);
+    }
+    for x in args {
+      hasher.update(x);
+      hasher.update([0]);
+    }
+    if let Some(seed) = seed {
+      hasher.update(seed.to_le_bytes());
+    }
+    let mut copy_in: Vec<_> = copy_in.iter().collect();
+    copy_in.sort_by_key(|x| x.0);
+    for (name, file) in copy_in {
+      hasher.update(name);
+      hasher.update([0]);
+      hasher.update(file.id());
+      hasher.update([0]);
+    }
+    return Some(hex::encode(hasher.finalize()));
+  }
+
   /// Run the checker with input, output and answer file.
   ///
-  /// Returns the parsed testlib output.
+  /// Returns the parsed testlib output, with the presentation error policy applied,
+  /// and the execution result of the checker.
+  /// The cache keeps the outputs before applying the policy.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error with the execution result and the stderr of the checker
+  /// if it does not finish normally (e.g. time limit exceeded or signalled).
   pub async fn check(
     &self,
     args: Vec<String>,
     input_file: sandbox::FileHandle,
     output_file: sandbox::FileHandle,
     answer_file: sandbox::FileHandle,
+    copy_in: HashMap<String, sandbox::FileHandle>,
+  ) -> Result<program::Run<Output>, error::RuntimeError> {
+    return self
+      .check_seeded(None, args, input_file, output_file, answer_file, copy_in)
+      .await;
+  }
+
+  /// Like `check`, but passes `seed` to the checker if given (see `Checker::seed`).
+  ///
+  /// A builtin checker only reads the output and answer files, which are compared natively,
+  /// and its execution result is an accepted run taking no time.
+  pub async fn check_seeded(
+    &self,
+    seed: Option<u64>,
+    args: Vec<String>,
+    input_file: sandbox::FileHandle,
+    output_file: sandbox::FileHandle,
+    answer_file: sandbox::FileHandle,
     mut copy_in: HashMap<String, sandbox::FileHandle>,
-  ) -> Result<Output, error::RuntimeError> {
-    copy_in.insert(self.exec.lang.exec().to_string(), self.exec.file.clone());
+  ) -> Result<program::Run<Output>, error::RuntimeError> {
+    let exec = match &self.kind {
+      Kind::Program(x) => x,
+      Kind::Builtin(builtin) => {
+        let (output, answer) = futures::try_join!(output_file.context(), answer_file.context())?;
+        return Ok(program::Run {
+          output: self
+            .presentation_error
+            .apply(builtin.compare(&output, &answer)),
+          result: sandbox::ExecuteResult {
+            status: sandbox::Status::Accepted,
+            time: std::time::Duration::ZERO,
+            wall_time: std::time::Duration::ZERO,
+            memory: 0,
+            exit_code: 0,
+            error: String::new(),
+            timing: sandbox::Timing::default(),
+          },
+        });
+      }
+    };
+    let cache_key = match &self.cache {
+      Some(_) => {
+        self
+          .cache_key(
+            &args,
+            seed,
+            &input_file,
+            &output_file,
+            &answer_file,
+            &copy_in,
+          )
+          .await
+      }
+      None => None,
+    };
+    if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
+      if let Some(run) = cache.get(key) {
+        return Ok(program::Run {
+          output: self.presentation_error.apply(run.output),
+          result: run.result,
+        });
+      }
+    }
+
+    copy_in.insert(exec.lang.exec().to_string(), exec.file.clone());
     copy_in.insert("inf.txt".to_string(), input_file);
     copy_in.insert("ouf.txt".to_string(), output_file);
     copy_in.insert("ans.txt".to_string(), answer_file);
 
+    let mut files = vec![
+      "inf.txt".to_string(),
+      "ouf.txt".to_string(),
+      "ans.txt".to_string(),
+    ];
+    let mut copy_out = vec!["stderr".to_string()];
+    if CONFIG.judge.checker_appes {
+      files.extend([APPES_FILE.to_string(), "-appes".to_string()]);
+      copy_out.push(format!("{}?", APPES_FILE));
+    }
+
     let mut res = sandbox::Request::Run(sandbox::Cmd {
-      args: [
-        self.exec.lang.run_cmd().clone(),
-        vec![
-          "inf.txt".to_string(),
-          "ouf.txt".to_string(),
-          "ans.txt".to_string(),
-        ],
-        args,
-      ]
-      .concat(),
+      args: [exec.lang.run_cmd().clone(), files, args].concat(),
       copy_in,
-      copy_out: vec!["stderr".to_string()],
+      labels: exec.labels.clone(),
+      copy_out,
+      copy_out_max: CONFIG.judge.checker_output_limit as u64,
+      stderr_limit: CONFIG.judge.checker_output_limit,
+      env: seed
+        .map(|x| vec![format!("{}={}", SEED_ENV, x)])
+        .unwrap_or_default(),
       ..Default::default()
     })
     .exec()
@@ -159,11 +625,39 @@ impl Checker {
     assert_eq!(res.len(), 1);
     let res = res.pop().unwrap();
 
-    match res.result.status {
-      sandbox::Status::Accepted | sandbox::Status::NonZeroExitStatus => Ok(Output::parse(
-        &String::from_utf8_lossy(&res.files["stderr"].context().await.unwrap()),
-      )),
-      _ => Err(res.result.into()),
+    let output = match res.result.status {
+      sandbox::Status::Accepted | sandbox::Status::NonZeroExitStatus => {
+        // A checker not writing the XML result is judged by its stderr.
+        let result = match res.files.get(APPES_FILE) {
+          Some(f) => Some(String::from_utf8_lossy(&f.context().await?).into_owned())
+            .filter(|x| Output::parse_appes(x).is_some()),
+          None => None,
+        };
+        let output = match result {
+          Some(x) => x,
+          None => String::from_utf8_lossy(&res.files["stderr"].context().await?).into_owned(),
+        };
+        Output::parse_spilled(&output).await
+      }
+      _ => {
+        let err = error::RuntimeError::from(res.result);
+        return Err(match res.files.get("stderr") {
+          Some(f) => err.with_stderr(&f.context().await.unwrap_or_default()),
+          None => err,
+        });
+      }
+    };
+    let run = program::Run {
+      output,
+      result: res.result,
+    };
+
+    if let (Some(cache), Some(key)) = (&self.cache, cache_key) {
+      cache.insert(key, run.clone());
     }
+    return Ok(program::Run {
+      output: self.presentation_error.apply(run.output),
+      result: run.result,
+    });
   }
 }
@@ -0,0 +1,581 @@
+use std::{fmt, path, time};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{data, problem, program};
+
+/// A contest made of multiple problems, built into a single package.
+pub struct Contest {
+  pub name: String,
+
+  pub problems: Vec<ContestProblem>,
+
+  /// Rules which every problem of the contest must follow.
+  pub policy: Policy,
+}
+
+/// A problem in a contest.
+pub struct ContestProblem {
+  /// Short label of the problem in the contest (e.g. `A`),
+  /// also used as the directory name of the problem in the package.
+  pub label: String,
+
+  pub problem: problem::Problem,
+}
+
+/// Rules which every problem of a contest must follow, checked before building.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Policy {
+  /// Max time limit of any subtask.
+  pub max_time_limit: Option<time::Duration>,
+
+  /// Max memory limit of any subtask in bytes.
+  pub max_memory_limit: Option<u64>,
+
+  /// Languages allowed for checkers and interactors, empty means any language.
+  pub checker_langs: Vec<String>,
+
+  /// Require all subtasks of all problems to have the same time and memory limits.
+  pub same_limits: bool,
+
+  /// Required total score of each problem.
+  pub total_score: Option<f32>,
+}
+
+/// A problem which breaks the contest policy.
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum PolicyViolation {
+  #[error("problem {label} subtask {subtask}: time limit {limit:?} exceeds {max:?}")]
+  TimeLimit {
+    label: String,
+    subtask: usize,
+    limit: time::Duration,
+    max: time::Duration,
+  },
+
+  #[error("problem {label} subtask {subtask}: memory limit {limit} bytes exceeds {max} bytes")]
+  MemoryLimit {
+    label: String,
+    subtask: usize,
+    limit: u64,
+    max: u64,
+  },
+
+  #[error("problem {label}: checker language {lang} is not allowed")]
+  CheckerLang { label: String, lang: String },
+
+  #[error("problem {label} subtask {subtask}: limits differ from the rest of the contest")]
+  InconsistentLimits { label: String, subtask: usize },
+
+  #[error("problem {label}: total score {score} is not {expected}")]
+  TotalScore {
+    label: String,
+    score: f32,
+    expected: f32,
+  },
+
+  #[error("problem label {0} is duplicated")]
+  DuplicateLabel(String),
+}
+
+/// Index of a built contest package, written as `contest.json` at the package root.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Index {
+  pub name: String,
+  pub problems: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexEntry {
+  pub label: String,
+  pub kind: problem::Kind,
+  pub score: f32,
+  pub subtasks: usize,
+
+  /// Number of tests, written as `<label>/tests/01`, `<label>/tests/01.a`, ... in the package.
+  pub tests: usize,
+
+  /// Max time limit of the subtasks.
+  pub time_limit: time::Duration,
+
+  /// Max memory limit of the subtasks in bytes.
+  pub memory_limit: u64,
+
+  /// Revision of the built problem, see `problem::Problem::revision`.
+  #[serde(default)]
+  pub revision: String,
+
+  /// Revisions of the parts of the built problem, to plan rejudges after rebuilding
+  /// (see `Contest::plan_rejudge`).
+  #[serde(default)]
+  pub revisions: problem::Revisions,
+}
+
+/// Aggregate report of building all problems of a contest.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BuildReport {
+  pub problems: Vec<ProblemReport>,
+
+  /// Number of compilations saved by sharing executables between problems
+  /// (e.g. a builtin checker used by many problems is compiled only once).
+  pub compile_cache_hits: u64,
+
+  /// Submissions to rejudge against the previous build in the same directory,
+  /// None if there is no previous build.
+  pub rejudge: Option<RejudgePlan>,
+}
+
+impl BuildReport {
+  /// Returns true if all problems were built without failures.
+  pub fn is_ok(&self) -> bool {
+    self.problems.iter().all(|x| x.failures.is_empty())
+  }
+}
+
+impl fmt::Display for BuildReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for problem in &self.problems {
+      write!(
+        f,
+        "{}: {} tests, {} failures",
+        problem.label,
+        problem.tests,
+        problem.failures.len()
+      )?;
+      for failure in &problem.failures {
+        write!(f, "\n- {}", failure)?;
+      }
+      writeln!(f)?;
+    }
+    write!(f, "compile cache hits: {}", self.compile_cache_hits)?;
+    if let Some(rejudge) = &self.rejudge {
+      write!(f, "\n{}", rejudge)?;
+    }
+    return Ok(());
+  }
+}
+
+/// Build result of a single problem.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProblemReport {
+  pub label: String,
+
+  /// Number of tests written to the package.
+  pub tests: usize,
+
+  /// Human readable failures, e.g. compile errors and broken tests.
+  pub failures: Vec<String>,
+}
+
+/// Submissions of a contest to rejudge after rebuilding it, made by `Contest::plan_rejudge`,
+/// so that only the submissions of changed problems and subtasks are judged again.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct RejudgePlan {
+  pub problems: Vec<RejudgeDecision>,
+}
+
+impl RejudgePlan {
+  /// Whether the results of a subtask (by id) of a problem have to be rejudged,
+  /// true if the problem is not in the plan.
+  pub fn should_rejudge(&self, label: &str, subtask: usize) -> bool {
+    return match self.problems.iter().find(|x| x.label == label) {
+      Some(x) => match &x.action {
+        RejudgeAction::Skip => false,
+        RejudgeAction::Subtasks { subtasks } => subtasks.contains(&subtask),
+        RejudgeAction::All { .. } => true,
+      },
+      None => true,
+    };
+  }
+}
+
+impl fmt::Display for RejudgePlan {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "rejudge:")?;
+    for problem in &self.problems {
+      write!(f, "\n- {}: ", problem.label)?;
+      match &problem.action {
+        RejudgeAction::Skip => write!(f, "skipped, unchanged")?,
+        RejudgeAction::Subtasks { subtasks } => write!(
+          f,
+          "subtasks {}",
+          subtasks
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+        )?,
+        RejudgeAction::All { reason } => write!(f, "all, {}", reason)?,
+      }
+    }
+    return Ok(());
+  }
+}
+
+/// Rejudge decision of a problem.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RejudgeDecision {
+  pub label: String,
+  pub action: RejudgeAction,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RejudgeAction {
+  /// The problem is unchanged, its submissions are not rejudged.
+  Skip,
+
+  /// Only these subtasks (by id) are rejudged:
+  /// the changed ones and the ones depending on them.
+  Subtasks { subtasks: Vec<usize> },
+
+  /// All the subtasks are rejudged.
+  All { reason: String },
+}
+
+/// Error when a contest can not be built at all.
+#[derive(Debug, Error)]
+pub enum BuildError {
+  #[error("contest breaks the policy:{}", .0.iter().map(|x| format!("\n- {}", x)).collect::<String>())]
+  Policy(Vec<PolicyViolation>),
+
+  #[error("can not write package: {0}")]
+  Io(#[from] std::io::Error),
+
+  #[error("can not serialize contest index: {0}")]
+  Json(#[from] serde_json::Error),
+}
+
+impl Contest {
+  /// Load a contest from Polygon packages, each given as a pair of label and package root.
+  pub async fn load_polygon(
+    name: String,
+    policy: Policy,
+    packages: &[(String, path::PathBuf)],
+  ) -> Result<Self, problem::package::ImportError> {
+    let mut problems = vec![];
+    for (label, root) in packages {
+      problems.push(ContestProblem {
+        label: label.clone(),
+        problem: problem::package::import_polygon(root).await?,
+      });
+    }
+    return Ok(Self {
+      name,
+      problems,
+      policy,
+    });
+  }
+
+  /// Check all problems against the contest policy.
+  ///
+  /// Returns every violation found, an empty vector means the contest is consistent.
+  pub fn validate(&self) -> Vec<PolicyViolation> {
+    let policy = &self.policy;
+    let mut ret = vec![];
+    let mut labels = std::collections::HashSet::new();
+    let mut limits = None;
+
+    for ContestProblem { label, problem } in &self.problems {
+      if !labels.insert(label) {
+        ret.push(PolicyViolation::DuplicateLabel(label.clone()));
+      }
+
+      // Builtin checkers are not programs, so they are allowed by any policy.
+      for source in problem
+        .checker
+        .program()
+        .into_iter()
+        .chain(&problem.interactor)
+      {
+        let lang = source.lang.name();
+        if !policy.checker_langs.is_empty() && !policy.checker_langs.iter().any(|x| x == lang) {
+          ret.push(PolicyViolation::CheckerLang {
+            label: label.clone(),
+            lang: lang.to_string(),
+          });
+        }
+      }
+
+      for subtask in &problem.subtasks {
+        // Overrides on single tests must obey the ceilings too.
+        let (time_limit, memory_limit) = subtask
+          .tests
+          .iter()
+          .map(|x| subtask.limits(x))
+          .fold((subtask.time_limit, subtask.memory_limit), |x, y| {
+            (x.0.max(y.0), x.1.max(y.1))
+          });
+        if let Some(max) = policy.max_time_limit.filter(|&x| time_limit > x) {
+          ret.push(PolicyViolation::TimeLimit {
+            label: label.clone(),
+            subtask: subtask.id,
+            limit: time_limit,
+            max,
+          });
+        }
+        if let Some(max) = policy.max_memory_limit.filter(|&x| memory_limit > x) {
+          ret.push(PolicyViolation::MemoryLimit {
+            label: label.clone(),
+            subtask: subtask.id,
+            limit: memory_limit,
+            max,
+          });
+        }
+        if policy.same_limits
+          && *limits.get_or_insert((subtask.time_limit, subtask.memory_limit))
+            != (subtask.time_limit, subtask.memory_limit)
+        {
+          ret.push(PolicyViolation::InconsistentLimits {
+            label: label.clone(),
+            subtask: subtask.id,
+          });
+        }
+      }
+
+      let score: f32 = problem.subtasks.iter().map(|x| x.score).sum();
+      if let Some(expected) = policy.total_score {
+        if (score - expected).abs() > 1e-6 {
+          ret.push(PolicyViolation::TotalScore {
+            label: label.clone(),
+            score,
+            expected,
+          });
+        }
+      }
+    }
+
+    return ret;
+  }
+
+  /// Decide which submissions to rejudge, by comparing the revisions of the problems
+  /// with the index of a previous build.
+  ///
+  /// A problem whose programs, settings or extra files changed is rejudged entirely,
+  /// otherwise only its changed subtasks and the subtasks depending on them are rejudged.
+  pub fn plan_rejudge(&self, previous: &Index) -> RejudgePlan {
+    let problems = self
+      .problems
+      .iter()
+      .map(|ContestProblem { label, problem }| {
+        let revisions = problem.revisions();
+        let all = |reason: &str| RejudgeAction::All {
+          reason: reason.to_string(),
+        };
+        let action = match previous.problems.iter().find(|x| &x.label == label) {
+          None => all("new problem"),
+          Some(x) if x.revisions.shared.is_empty() => all("no revisions in the previous build"),
+          Some(x) if x.revisions.shared != revisions.shared => {
+            all("programs, settings or extra files changed")
+          }
+          Some(x) if x.revisions.subtasks.len() != revisions.subtasks.len() => {
+            all("subtasks added or removed")
+          }
+          Some(x) => {
+            let mut changed: Vec<_> = (0..revisions.subtasks.len())
+              .map(|i| x.revisions.subtasks[i] != revisions.subtasks[i])
+              .collect();
+            // A subtask is scored by the results of its dependences, so propagate the changes.
+            let mut updated = true;
+            while updated {
+              updated = false;
+              for (i, subtask) in problem.subtasks.iter().enumerate() {
+                if !changed[i]
+                  && problem
+                    .subtasks
+                    .iter()
+                    .enumerate()
+                    .any(|(j, x)| changed[j] && subtask.dependences.contains(&x.id))
+                {
+                  changed[i] = true;
+                  updated = true;
+                }
+              }
+            }
+            let subtasks: Vec<_> = problem
+              .subtasks
+              .iter()
+              .zip(changed)
+              .filter(|x| x.1)
+              .map(|x| x.0.id)
+              .collect();
+            match subtasks.is_empty() {
+              true => RejudgeAction::Skip,
+              false => RejudgeAction::Subtasks { subtasks },
+            }
+          }
+        };
+        if action == RejudgeAction::Skip {
+          log::info!("problem {} is unchanged, rejudge skipped", label);
+        }
+        RejudgeDecision {
+          label: label.clone(),
+          action,
+        }
+      })
+      .collect();
+    return RejudgePlan { problems };
+  }
+
+  /// Validate the policy, then build all problems into a contest package at `out`.
+  ///
+  /// Problems are built concurrently and share compiled executables.
+  /// Tests are written as `<label>/tests/01` (input) and `<label>/tests/01.a` (answer),
+  /// the index is written as `contest.json` and the report as `build-report.json`.
+  /// If `out` has a previous build, the report also plans the rejudge against it.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the contest breaks the policy,
+  /// or the package can not be written.
+  /// Failures of single problems (e.g. compile errors) are listed in the report instead.
+  pub async fn build(&self, out: &path::Path) -> Result<BuildReport, BuildError> {
+    let violations = self.validate();
+    if !violations.is_empty() {
+      return Err(BuildError::Policy(violations));
+    }
+
+    let previous = match tokio::fs::read(out.join("contest.json")).await {
+      Ok(x) => match serde_json::from_slice::<Index>(&x) {
+        Ok(x) => Some(x),
+        Err(err) => {
+          log::warn!("previous contest index can not be read: {}", err);
+          None
+        }
+      },
+      Err(_) => None,
+    };
+
+    let cache = program::CompileCache::default();
+    let problems =
+      futures::future::try_join_all(self.problems.iter().map(|x| build_problem(x, out, &cache)))
+        .await?;
+
+    let index = Index {
+      name: self.name.clone(),
+      problems: self
+        .problems
+        .iter()
+        .zip(&problems)
+        .map(|(ContestProblem { label, problem }, report)| IndexEntry {
+          label: label.clone(),
+          kind: problem.kind,
+          score: problem.subtasks.iter().map(|x| x.score).sum(),
+          subtasks: problem.subtasks.len(),
+          tests: report.tests,
+          time_limit: problem
+            .subtasks
+            .iter()
+            .map(|x| x.time_limit)
+            .max()
+            .unwrap_or_default(),
+          memory_limit: problem
+            .subtasks
+            .iter()
+            .map(|x| x.memory_limit)
+            .max()
+            .unwrap_or_default(),
+          revision: problem.revision(),
+          revisions: problem.revisions(),
+        })
+        .collect(),
+    };
+    let report = BuildReport {
+      problems,
+      compile_cache_hits: cache.stats().hits,
+      rejudge: previous.map(|x| self.plan_rejudge(&x)),
+    };
+
+    tokio::fs::write(out.join("contest.json"), serde_json::to_vec_pretty(&index)?).await?;
+    tokio::fs::write(
+      out.join("build-report.json"),
+      serde_json::to_vec_pretty(&report)?,
+    )
+    .await?;
+
+    return Ok(report);
+  }
+}
+
+/// Compile the programs of a problem and write its tests to `<out>/<label>`.
+async fn build_problem(
+  ContestProblem { label, problem }: &ContestProblem,
+  out: &path::Path,
+  cache: &program::CompileCache,
+) -> Result<ProblemReport, std::io::Error> {
+  let dir = out.join(label);
+  let mut report = ProblemReport {
+    label: label.clone(),
+    tests: 0,
+    failures: vec![],
+  };
+  tokio::fs::create_dir_all(dir.join("tests")).await?;
+
+  let user_copy_in = data::upload_all(&problem.user_copy_in).await;
+  let judge_copy_in = data::upload_all(&problem.judge_copy_in).await;
+
+  let (checker, standard_solution) = futures::join!(
+    async {
+      match problem.checker.program() {
+        Some(x) => x
+          .compile_cached(vec![], user_copy_in.clone(), cache)
+          .await
+          .map(Some),
+        None => Ok(None),
+      }
+    },
+    problem
+      .standard_solution
+      .compile_cached(vec![], judge_copy_in.clone(), cache),
+  );
+  if let Err(err) = checker {
+    report.failures.push(format!("checker: {}", err));
+  }
+  if let Some(interactor) = &problem.interactor {
+    if let Err(err) = interactor
+      .compile_cached(vec![], user_copy_in.clone(), cache)
+      .await
+    {
+      report.failures.push(format!("interactor: {}", err));
+    }
+  }
+  let standard_solution = match standard_solution {
+    Ok(x) => x,
+    Err(err) => {
+      report.failures.push(format!("standard solution: {}", err));
+      return Ok(report);
+    }
+  };
+
+  for subtask in &problem.subtasks {
+    let tests = subtask
+      .make_tests(
+        &standard_solution,
+        &problem.answer_limits,
+        &problem.output,
+        &user_copy_in,
+        &judge_copy_in,
+      )
+      .await;
+    for (i, test) in tests.into_iter().enumerate() {
+      let (input, answer) = match test {
+        Ok(x) => x,
+        Err(err) => {
+          report
+            .failures
+            .push(format!("subtask {} test {}: {}", subtask.id, i, err));
+          continue;
+        }
+      };
+      report.tests += 1;
+      let path = dir.join("tests").join(format!("{:02}", report.tests));
+      tokio::fs::write(&path, input).await?;
+      tokio::fs::write(path.with_extension("a"), answer).await?;
+    }
+  }
+
+  log::info!("problem {} built: {} tests", label, report.tests);
+  return Ok(report);
+}
@@ -1,6 +1,9 @@
+use std::{borrow::Cow, collections::HashMap, io, path, pin::Pin};
+
 use serde::{Deserialize, Serialize};
+use tokio::io::AsyncRead;
 
-use crate::builtin;
+use crate::{builtin, sandbox};
 
 /// Data provider for files.
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -9,15 +12,81 @@ pub enum Provider {
   #[serde(with = "serde_bytes")]
   Memory(Vec<u8>),
   Builtin(builtin::File),
+
+  /// A file on the local disk, which is only read when it is used (e.g. uploaded to sandbox),
+  /// so huge files are never held in memory with the problem.
+  ///
+  /// It can not be (de)serialized, so requests can not read arbitrary files of the judge.
+  #[serde(skip)]
+  Local {
+    path: path::PathBuf,
+  },
 }
 
 impl Provider {
+  /// Contents of in-memory data.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the data is a local file, which has to be read by `read` or `open`.
   pub fn as_bytes(&self) -> &[u8] {
     match self {
       Self::Memory(m) => &m,
       Self::Builtin(b) => &b.as_bytes(),
+      Self::Local { path } => panic!("local file {} is not in memory", path.display()),
     }
   }
+
+  /// Read the whole contents.
+  pub async fn read(&self) -> io::Result<Cow<'_, [u8]>> {
+    return match self {
+      Self::Local { path } => Ok(Cow::Owned(tokio::fs::read(path).await?)),
+      _ => Ok(Cow::Borrowed(self.as_bytes())),
+    };
+  }
+
+  /// Open the contents as a stream, without reading a local file into memory at once.
+  pub async fn open(&self) -> io::Result<Pin<Box<dyn AsyncRead + Send + '_>>> {
+    return match self {
+      Self::Local { path } => Ok(Box::pin(tokio::io::BufReader::new(
+        tokio::fs::File::open(path).await?,
+      ))),
+      _ => Ok(Box::pin(self.as_bytes())),
+    };
+  }
+
+  /// Size of the data held in memory in bytes, 0 for a local file.
+  pub fn memory_size(&self) -> usize {
+    return match self {
+      Self::Local { .. } => 0,
+      _ => self.as_bytes().len(),
+    };
+  }
+
+  /// Bytes identifying the data without reading a local file:
+  /// the contents of in-memory data, or the path, size and modified time of a local file.
+  pub fn fingerprint(&self) -> Cow<'_, [u8]> {
+    return match self {
+      Self::Local { path } => {
+        let meta = std::fs::metadata(path).ok();
+        Cow::Owned(
+          format!(
+            "{}:{:?}:{:?}",
+            path.display(),
+            meta.as_ref().map(|x| x.len()),
+            meta.and_then(|x| x.modified().ok())
+          )
+          .into_bytes(),
+        )
+      }
+      _ => Cow::Borrowed(self.as_bytes()),
+    };
+  }
+
+  /// Upload the data to sandbox, streamed from a local file without reading it into memory.
+  pub async fn upload(&self) -> io::Result<sandbox::FileHandle> {
+    return sandbox::FileHandle::upload_stream(self.open().await?).await;
+  }
 }
 
 impl From<builtin::File> for Provider {
@@ -31,3 +100,20 @@ impl From<Vec<u8>> for Provider {
     Self::Memory(f)
   }
 }
+
+/// Upload all the files to sandbox, keeping their names.
+///
+/// # Panics
+///
+/// Panics if a local file can not be read, like a sandbox error.
+pub async fn upload_all(files: &HashMap<String, Provider>) -> HashMap<String, sandbox::FileHandle> {
+  let mut ret = HashMap::new();
+  for (name, data) in files {
+    let file = data
+      .upload()
+      .await
+      .unwrap_or_else(|err| panic!("can not read file {}: {}", name, err));
+    ret.insert(name.clone(), file);
+  }
+  return ret;
+}
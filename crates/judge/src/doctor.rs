@@ -0,0 +1,222 @@
+use std::{collections::HashMap, fmt, str::FromStr, time};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{data, error, judge, lang, program, sandbox, CONFIG};
+
+/// CPU time limit of running the sample programs.
+const TIME_LIMIT: time::Duration = time::Duration::from_secs(1);
+
+/// A check of a language by running one of its sample programs (see `etc::SampleCfg`).
+#[derive(Debug, PartialEq, Eq, strum::Display, Serialize, Deserialize, Clone, Copy)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Probe {
+  /// The hello program compiles, runs and prints `hello`.
+  Hello,
+
+  /// The compiler runs and rejects the program with a message.
+  CompileError,
+
+  /// The program exits with a non-zero status or is signalled.
+  RuntimeError,
+
+  /// The program is killed by the time limit.
+  TimeLimit,
+}
+
+/// Result of a probe.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ProbeResult {
+  pub probe: Probe,
+
+  /// Why the probe failed, None if it passed.
+  pub failure: Option<String>,
+}
+
+/// Results of the probes of a language.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct LangReport {
+  pub lang: String,
+
+  /// Results of all the probes, empty if the language has no sample programs.
+  pub probes: Vec<ProbeResult>,
+}
+
+/// Results of checking all the configured languages.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Report {
+  pub langs: Vec<LangReport>,
+}
+
+impl Report {
+  /// Whether all the probes passed.
+  pub fn is_ok(&self) -> bool {
+    return self
+      .langs
+      .iter()
+      .flat_map(|x| &x.probes)
+      .all(|x| x.failure.is_none());
+  }
+}
+
+impl fmt::Display for Report {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for lang in &self.langs {
+      if lang.probes.is_empty() {
+        writeln!(f, "{}: skipped, no sample programs", lang.lang)?;
+      }
+      for probe in &lang.probes {
+        match &probe.failure {
+          None => writeln!(f, "{} {}: ok", lang.lang, probe.probe)?,
+          Some(x) => writeln!(f, "{} {}: FAILED: {}", lang.lang, probe.probe, x)?,
+        }
+      }
+    }
+    return Ok(());
+  }
+}
+
+/// Check every configured language end to end, by compiling and running its sample programs
+/// in the sandbox, and report the failed probes.
+///
+/// It catches misconfigured languages (e.g. a wrong compiler path in `compile_cmd`)
+/// before they fail real submissions.
+pub async fn diagnose() -> Report {
+  let mut names: Vec<_> = CONFIG.lang.keys().collect();
+  names.sort();
+
+  let mut langs = vec![];
+  // Check the languages one by one, so the time limit probes do not interfere.
+  for name in names {
+    langs.push(diagnose_lang(&lang::Lang::from_str(name).unwrap()).await);
+  }
+  return Report { langs };
+}
+
+/// Check a language by its sample programs, see `diagnose`.
+pub async fn diagnose_lang(lang: &lang::Lang) -> LangReport {
+  let samples = match &CONFIG.lang[lang.name()].samples {
+    Some(x) => x,
+    None => {
+      return LangReport {
+        lang: lang.to_string(),
+        probes: vec![],
+      }
+    }
+  };
+
+  let source = |code: &String| program::Source {
+    lang: lang.clone(),
+    data: data::Provider::Memory(code.as_bytes().to_vec()),
+  };
+  let probes = vec![
+    ProbeResult {
+      probe: Probe::Hello,
+      failure: probe_hello(&source(&samples.hello)).await.err(),
+    },
+    ProbeResult {
+      probe: Probe::CompileError,
+      failure: probe_compile_error(&source(&samples.compile_error))
+        .await
+        .err(),
+    },
+    ProbeResult {
+      probe: Probe::RuntimeError,
+      failure: probe_run(
+        &source(&samples.runtime_error),
+        &[
+          sandbox::Status::NonZeroExitStatus,
+          sandbox::Status::Signalled,
+        ],
+      )
+      .await
+      .err(),
+    },
+    ProbeResult {
+      probe: Probe::TimeLimit,
+      failure: probe_run(
+        &source(&samples.time_limit),
+        &[sandbox::Status::TimeLimitExceeded],
+      )
+      .await
+      .err(),
+    },
+  ];
+
+  return LangReport {
+    lang: lang.to_string(),
+    probes,
+  };
+}
+
+/// Compile and run a sample program with empty input.
+async fn run(
+  source: &program::Source,
+) -> Result<(sandbox::ExecuteResult, Option<sandbox::FileHandle>), String> {
+  let exec = source
+    .compile(vec![], HashMap::new())
+    .await
+    .map_err(|err| format!("compile failed: {}", err))?;
+  return Ok(
+    exec
+      .judge_batch(
+        vec![],
+        sandbox::FileHandle::upload(&[])
+          .await
+          .map_err(|err| err.to_string())?,
+        HashMap::new(),
+        TIME_LIMIT,
+        CONFIG.judge.memory_limit,
+        None,
+        judge::TimeKind::Cpu,
+        None,
+        CONFIG.judge.stdout_limit,
+        &judge::OutputSource::Stdout,
+      )
+      .await,
+  );
+}
+
+async fn probe_hello(source: &program::Source) -> Result<(), String> {
+  let (res, output) = run(source).await?;
+  let output = match output {
+    Some(x) => x,
+    None => return Err(format!("run failed: {}", error::RuntimeError::from(res))),
+  };
+  let output = output
+    .context()
+    .await
+    .map_err(|err| format!("output can not be read: {}", err))?;
+  let output = String::from_utf8_lossy(&output);
+  if output.trim() != "hello" {
+    return Err(format!(
+      "expected output `hello`, found `{}`",
+      output.trim()
+    ));
+  }
+  return Ok(());
+}
+
+async fn probe_compile_error(source: &program::Source) -> Result<(), String> {
+  return match source.compile(vec![], HashMap::new()).await {
+    Ok(_) => Err("compiled successfully".to_string()),
+    Err(err) if err.result.status != sandbox::Status::NonZeroExitStatus => {
+      Err(format!("compiler did not run normally: {}", err))
+    }
+    Err(err) if err.message.trim().is_empty() => Err("no compile message".to_string()),
+    Err(_) => Ok(()),
+  };
+}
+
+/// Run a sample program, and check the status is one of `expected`.
+async fn probe_run(source: &program::Source, expected: &[sandbox::Status]) -> Result<(), String> {
+  let (res, _) = run(source).await?;
+  if !expected.contains(&res.status) {
+    return Err(format!(
+      "unexpected result: {}",
+      error::RuntimeError::from(res)
+    ));
+  }
+  return Ok(());
+}
@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::CONFIG;
+
+/// Environment which a judgement runs in.
+///
+/// It is recorded in final responses and exported packages,
+/// so that a disputed verdict can be traced to the exact toolchain later.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Environment {
+  /// Identifier of the sandbox node, the sandbox host if not set in config.
+  pub node: String,
+
+  /// Version of the go-judge sandbox, if set in config.
+  pub sandbox_version: Option<String>,
+
+  /// SHA-256 of the language config, which covers the compile and run commands.
+  pub lang_hash: String,
+
+  /// Version of the judge crate.
+  pub judge_version: String,
+}
+
+lazy_static! {
+  /// Environment of the current judge process, captured from the global config.
+  pub static ref ENVIRONMENT: Environment = Environment::capture();
+}
+
+impl Environment {
+  /// Capture the environment from the global config.
+  pub fn capture() -> Self {
+    // Sort the languages, so the hash does not depend on the order of a hash map.
+    let lang: BTreeMap<_, _> = CONFIG.lang.iter().collect();
+    let lang_hash = Sha256::digest(serde_json::to_vec(&lang).unwrap());
+
+    return Self {
+      node: CONFIG
+        .sandbox
+        .node
+        .clone()
+        .unwrap_or_else(|| CONFIG.sandbox.host.clone()),
+      sandbox_version: CONFIG.sandbox.version.clone(),
+      lang_hash: hex::encode(lang_hash),
+      judge_version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+  }
+}
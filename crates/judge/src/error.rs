@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-use crate::sandbox;
+use crate::{checker, program, sandbox, validator, CONFIG};
 
 /// Error when task does not executed normally (result != Accepted).
 #[derive(Debug, Error, Clone)]
@@ -10,11 +10,37 @@ use crate::sandbox;
   )]
 pub struct RuntimeError {
   pub result: sandbox::ExecuteResult,
+
+  /// Stderr of the program limited to `judge.message_limit` (in config) bytes,
+  /// empty if it is not collected.
+  pub stderr: String,
+}
+
+impl RuntimeError {
+  /// Attach the stderr of the program to the error.
+  pub fn with_stderr(mut self, stderr: &[u8]) -> Self {
+    self.stderr = checker::limit_message(String::from_utf8_lossy(stderr).trim());
+    self
+  }
 }
 
 impl From<sandbox::ExecuteResult> for RuntimeError {
   fn from(result: sandbox::ExecuteResult) -> Self {
-    Self { result }
+    Self {
+      result,
+      stderr: String::new(),
+    }
+  }
+}
+
+/// A sandbox error is a system error of the program.
+impl From<sandbox::Error> for RuntimeError {
+  fn from(err: sandbox::Error) -> Self {
+    log::error!("{}", err);
+    Self {
+      result: sandbox::ExecuteResult::internal_error(),
+      stderr: String::new(),
+    }
   }
 }
 
@@ -30,3 +56,62 @@ pub struct CompileError {
   /// Compile message, usually the error message output by the compiler.
   pub message: String,
 }
+
+impl CompileError {
+  /// Resource usage of the failed compilation.
+  pub fn report(&self) -> program::CompileReport {
+    return program::CompileReport::from(&self.result);
+  }
+
+  /// Compile message limited to `judge.compile_message_limit` (in config) bytes.
+  pub fn diagnostics(&self) -> String {
+    return checker::truncate_message(&self.message, CONFIG.judge.compile_message_limit);
+  }
+}
+
+/// Error when an input is rejected by the validator, or the validator does not finish normally.
+#[derive(Debug, Error, Clone)]
+#[error(
+    "validate failed (status: {0}, time: {1:?}, memory: {2} bytes, exit code: {3}): {message}",
+    result.status, result.time, result.memory, result.exit_code
+  )]
+pub struct ValidateError {
+  pub result: sandbox::ExecuteResult,
+
+  /// Validate message, usually the error message output by testlib,
+  /// limited to `judge.message_limit` (in config) bytes.
+  pub message: String,
+
+  /// Where and why the input is rejected, None if the validator did not finish normally
+  /// or reported no `FAIL` line.
+  pub rejection: Option<validator::Rejection>,
+}
+
+impl ValidateError {
+  /// Create an error from the execution result and the stderr of the validator,
+  /// parsing the rejection if the input is rejected.
+  pub fn new(result: sandbox::ExecuteResult, stderr: &[u8]) -> Self {
+    let message = checker::limit_message(String::from_utf8_lossy(stderr).trim());
+    let rejection = match result.status {
+      sandbox::Status::NonZeroExitStatus => validator::Rejection::parse(&message),
+      _ => None,
+    };
+    return Self {
+      result,
+      message,
+      rejection,
+    };
+  }
+}
+
+/// A sandbox error is a system error of the validator.
+impl From<sandbox::Error> for ValidateError {
+  fn from(err: sandbox::Error) -> Self {
+    log::error!("{}", err);
+    Self {
+      result: sandbox::ExecuteResult::internal_error(),
+      message: String::new(),
+      rejection: None,
+    }
+  }
+}
@@ -1,19 +1,57 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use crate::{error, program, sandbox};
+use sha2::{Digest, Sha256};
+
+use crate::{cache, error, program, sandbox};
 
 #[derive(Debug, Clone)]
 pub struct Generator {
   pub exec: program::Executable,
+
+  /// Cache of generated files, None to disable caching.
+  pub cache: Option<Arc<Cache>>,
 }
 
 impl From<program::Executable> for Generator {
   fn from(exec: program::Executable) -> Self {
-    Self { exec }
+    Self { exec, cache: None }
   }
 }
 
+/// Cache of generated files, keyed by the arguments and extra files.
+///
+/// A cache must belong to a single generator, and should only be enabled for deterministic
+/// generators (which is required by testlib anyway).
+pub type Cache = cache::Cache<sandbox::FileHandle>;
+
 impl Generator {
+  /// Enable caching of generated files with the given cache.
+  pub fn with_cache(mut self, cache: Arc<Cache>) -> Self {
+    self.cache = Some(cache);
+    self
+  }
+
+  /// Key of a generation in the cache.
+  ///
+  /// Extra files are keyed by their sandbox file ids, as they are shared by all generations
+  /// of a problem.
+  fn cache_key(&self, args: &[String], copy_in: &HashMap<String, sandbox::FileHandle>) -> String {
+    let mut hasher = Sha256::new();
+    for x in args {
+      hasher.update(x);
+      hasher.update([0]);
+    }
+    let mut copy_in: Vec<_> = copy_in.iter().collect();
+    copy_in.sort_by_key(|x| x.0);
+    for (name, file) in copy_in {
+      hasher.update(name);
+      hasher.update([0]);
+      hasher.update(file.id());
+      hasher.update([0]);
+    }
+    return hex::encode(hasher.finalize());
+  }
+
   /// Run the generator and returns the file id of generated output.
   ///
   /// It will do these following:
@@ -31,11 +69,19 @@ impl Generator {
     args: Vec<String>,
     mut copy_in: HashMap<String, sandbox::FileHandle>,
   ) -> Result<sandbox::FileHandle, error::RuntimeError> {
+    let cache_key = self.cache.as_ref().map(|_| self.cache_key(&args, &copy_in));
+    if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
+      if let Some(file) = cache.get(key) {
+        return Ok(file);
+      }
+    }
+
     copy_in.insert(self.exec.lang.exec().to_string(), self.exec.file.clone());
 
     let mut res = sandbox::Request::Run(sandbox::Cmd {
       args: [self.exec.lang.run_cmd().clone(), args].concat(),
       copy_in,
+      labels: self.exec.labels.clone(),
       copy_out: vec!["stdout".to_string()],
       ..Default::default()
     })
@@ -45,9 +91,14 @@ impl Generator {
     assert_eq!(res.len(), 1);
     let res = res.pop().unwrap();
 
-    match res.result.status {
-      sandbox::Status::Accepted => Ok(res.files["stdout"].clone()),
-      _ => Err(res.result.into()),
+    if res.result.status != sandbox::Status::Accepted {
+      return Err(res.result.into());
+    }
+
+    let file = res.files["stdout"].clone();
+    if let (Some(cache), Some(key)) = (&self.cache, cache_key) {
+      cache.insert(key, file.clone());
     }
+    return Ok(file);
   }
 }
@@ -0,0 +1,156 @@
+use std::{collections::HashMap, time};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{checker, error, judge, problem, program, record, sandbox, validator, CONFIG};
+
+/// A hack (a.k.a. challenge) against a solution which has passed the pretests.
+///
+/// The hack input is made (uploaded or generated by the hacker's generator), validated,
+/// and then the standard solution and the defender's solution are run on it.
+/// The hack succeeds if the defender does not get accepted by the checker.
+#[derive(Debug, Clone)]
+pub struct Hack {
+  /// Input provided by the hacker, either plain text or a generator with arguments.
+  pub input: problem::Input,
+
+  pub validator: validator::Validator,
+
+  pub standard_solution: program::Executable,
+
+  /// Solution of the defender.
+  pub defender: program::Executable,
+
+  pub checker: checker::Checker,
+
+  pub time_limit: time::Duration,
+
+  pub memory_limit: u64,
+
+  /// Which stream or file written by solutions is the output passed to the checker.
+  pub output: judge::OutputSource,
+
+  /// Extra files when running generator, validator and checker.
+  pub user_copy_in: HashMap<String, sandbox::FileHandle>,
+
+  /// Extra files when running solutions.
+  pub judge_copy_in: HashMap<String, sandbox::FileHandle>,
+}
+
+/// Result of a hack.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+  /// The defender failed on the hack input, with the record of the defender.
+  Success { record: record::Record },
+
+  /// The defender passed the hack input.
+  Defended { record: record::Record },
+
+  /// The hack input can not be used, the defender was not run.
+  Invalid(InvalidReason),
+}
+
+/// Reason why a hack input can not be used.
+#[derive(Debug, Error, Serialize, Deserialize, Clone)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum InvalidReason {
+  /// The generator of the hacker exits abnormally (e.g. time limit exceeded).
+  #[error("hack generator failed (status: {status}): {message}")]
+  Generator {
+    status: sandbox::Status,
+    message: String,
+  },
+
+  /// The input is rejected by the validator.
+  #[error("hack input is invalid: {message}")]
+  Validator { message: String },
+}
+
+impl Hack {
+  /// Run the hack and return the outcome.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the standard solution or the checker fails
+  /// on a valid input, which means the problem itself is broken.
+  pub async fn run(&self) -> Result<Outcome, HackError> {
+    let input = match self.input.make(self.user_copy_in.clone()).await {
+      Ok(x) => x,
+      Err(err) => {
+        return Ok(Outcome::Invalid(InvalidReason::Generator {
+          status: err.result.status.clone(),
+          message: err.to_string(),
+        }));
+      }
+    };
+
+    if let Err(err) = self
+      .validator
+      .validate(vec![], input.clone(), self.user_copy_in.clone())
+      .await
+    {
+      return Ok(Outcome::Invalid(InvalidReason::Validator {
+        message: err.to_string(),
+      }));
+    }
+
+    let (answer, defender) = futures::join!(
+      problem::Answer::Generated.make(
+        &self.standard_solution,
+        input.clone(),
+        self.judge_copy_in.clone(),
+        self.time_limit,
+        self.memory_limit,
+        &self.output,
+      ),
+      self.defender.judge_batch(
+        vec![],
+        input.clone(),
+        self.judge_copy_in.clone(),
+        self.time_limit,
+        self.memory_limit,
+        None,
+        judge::TimeKind::Cpu,
+        None,
+        CONFIG.judge.stdout_limit,
+        &self.output,
+      ),
+    );
+
+    let answer = answer.map_err(HackError::StandardSolution)?;
+
+    let (result, output) = match defender {
+      (res, Some(f)) => (res, f),
+      (res, None) => {
+        log::debug!("hack succeeded, defender exited abnormally: {}", res.status);
+        return Ok(Outcome::Success {
+          record: record::Record::new_interrupted(&res),
+        });
+      }
+    };
+
+    let checker_output = self
+      .checker
+      .check(vec![], input, output, answer, self.user_copy_in.clone())
+      .await
+      .map_err(HackError::Checker)?
+      .output;
+
+    let record = record::Record::new_checked(&result, &checker_output);
+    if checker_output.status == checker::Status::Accepted {
+      return Ok(Outcome::Defended { record });
+    }
+    return Ok(Outcome::Success { record });
+  }
+}
+
+/// Error when the problem is broken on a valid hack input.
+#[derive(Debug, Error, Clone)]
+pub enum HackError {
+  #[error("standard solution failed on the hack input: {0}")]
+  StandardSolution(error::RuntimeError),
+
+  #[error("checker failed on the hack input: {0}")]
+  Checker(error::RuntimeError),
+}
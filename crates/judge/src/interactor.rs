@@ -0,0 +1,246 @@
+use std::{collections::HashMap, time};
+
+use crate::{checker, judge, program, record, sandbox, CONFIG};
+
+/// Interactor is a type of executable program,
+/// which talks with the user's program through pipes on an interactive problem.
+///
+/// The interactor is a testlib interactor, which is run as `interactor inf.txt tout.txt`,
+/// and writes its verdict to stderr.
+/// If a checker is attached, it checks `tout.txt` after the interaction finishes,
+/// which allows problems graded on the transcript.
+#[derive(Debug, Clone)]
+pub struct Interactor {
+  pub exec: program::Executable,
+
+  /// Checker run on the output of the interactor, None to use the verdict of the interactor.
+  pub checker: Option<checker::Checker>,
+
+  /// Policy for presentation errors in the verdict of the interactor.
+  pub presentation_error: checker::PresentationErrorPolicy,
+}
+
+impl From<program::Executable> for Interactor {
+  fn from(exec: program::Executable) -> Self {
+    Self {
+      exec,
+      checker: None,
+      presentation_error: checker::PresentationErrorPolicy::default(),
+    }
+  }
+}
+
+/// Result of running a solution on a test case of interactive problem.
+#[derive(Debug, Clone)]
+pub struct InteractResult {
+  /// Execution result of the solution.
+  pub solution: sandbox::ExecuteResult,
+
+  /// Execution result of the interactor.
+  pub interactor: sandbox::ExecuteResult,
+
+  /// Parsed testlib output of the interactor.
+  ///
+  /// None if the interactor exits abnormally (e.g. time limit exceeded or signalled).
+  pub interactor_output: Option<checker::Output>,
+
+  /// The output file (`tout.txt`) written by the interactor, which can be passed to a checker.
+  pub output_file: Option<sandbox::FileHandle>,
+
+  /// Captured data written by the solution and by the interactor.
+  pub transcript: [Option<sandbox::FileHandle>; 2],
+}
+
+impl Interactor {
+  /// Attach a checker, which checks the output of the interactor after the interaction.
+  pub fn with_checker(mut self, checker: checker::Checker) -> Self {
+    self.checker = Some(checker);
+    self
+  }
+
+  /// Judge presentation errors in the verdict of the interactor with the given policy.
+  pub fn with_presentation_error(mut self, policy: checker::PresentationErrorPolicy) -> Self {
+    self.presentation_error = policy;
+    self
+  }
+
+  /// Run the solution on a test case, the solution and the interactor are connected to each
+  /// other by pipes.
+  ///
+  /// The data transferred through the pipes is captured as the transcript.
+  ///
+  /// The time of `time_kind` of the solution is limited by `time_limit`, the wall clock time
+  /// for solutions mostly waiting on the interactor.
+  pub async fn interact(
+    &self,
+    solution: &program::Executable,
+    args: Vec<String>,
+    input_file: sandbox::FileHandle,
+    mut copy_in: HashMap<String, sandbox::FileHandle>,
+    mut interactor_copy_in: HashMap<String, sandbox::FileHandle>,
+    time_limit: time::Duration,
+    time_kind: judge::TimeKind,
+    memory_limit: u64,
+  ) -> InteractResult {
+    copy_in.insert(solution.lang.exec().to_string(), solution.file.clone());
+    interactor_copy_in.insert(self.exec.lang.exec().to_string(), self.exec.file.clone());
+    interactor_copy_in.insert("inf.txt".to_string(), input_file);
+
+    let mut solution_cmd = sandbox::Cmd {
+      args: [solution.lang.run_cmd().clone(), args].concat(),
+      copy_in,
+      labels: solution.labels.clone(),
+      copy_out: vec!["stderr".to_string()],
+      memory_limit,
+      timed: true,
+      ..Default::default()
+    };
+    time_kind.limit(&mut solution_cmd, time_limit);
+    let mut res = sandbox::Request::RunPiped {
+      cmds: [
+        solution_cmd,
+        sandbox::Cmd {
+          args: [
+            self.exec.lang.run_cmd().clone(),
+            vec!["inf.txt".to_string(), "tout.txt".to_string()],
+          ]
+          .concat(),
+          copy_in: interactor_copy_in,
+          copy_out: vec!["stderr".to_string(), "tout.txt".to_string()],
+          labels: self.exec.labels.clone(),
+          stderr_limit: CONFIG.judge.checker_output_limit,
+          // The interactor should never be killed before the solution.
+          time_limit: judge::grace_time_limit(time_limit).max(CONFIG.judge.time_limit),
+          ..Default::default()
+        },
+      ],
+      transcript: Some(sandbox::Transcript {
+        names: [
+          "solution.transcript".to_string(),
+          "interactor.transcript".to_string(),
+        ],
+        max: CONFIG.judge.transcript_limit,
+      }),
+    }
+    .exec()
+    .await;
+
+    assert_eq!(res.len(), 2);
+    let interactor_res = res.pop().unwrap();
+    let mut solution_res = res.pop().unwrap();
+    judge::apply_time_limit(&mut solution_res.result, time_limit, time_kind);
+
+    // The sandbox may attach a captured file to either side of the pipe.
+    let transcript = ["solution.transcript", "interactor.transcript"].map(|name| {
+      [&solution_res, &interactor_res]
+        .iter()
+        .find_map(|x| x.files.get(name).cloned())
+    });
+
+    InteractResult {
+      interactor_output: match interactor_res.result.status {
+        sandbox::Status::Accepted | sandbox::Status::NonZeroExitStatus => {
+          match interactor_res.files.get("stderr") {
+            Some(f) => Some(
+              self.presentation_error.apply(
+                checker::Output::parse_spilled(&String::from_utf8_lossy(
+                  &f.context().await.unwrap(),
+                ))
+                .await,
+              ),
+            ),
+            None => None,
+          }
+        }
+        _ => None,
+      },
+      output_file: interactor_res.files.get("tout.txt").cloned(),
+      solution: solution_res.result,
+      interactor: interactor_res.result,
+      transcript,
+    }
+  }
+
+  /// Run the solution on a test case and return the record.
+  ///
+  /// A rejection of the interactor is final. Otherwise, if a checker is attached,
+  /// the record is decided by the checker on the input, the output of the interactor
+  /// and the answer.
+  pub async fn judge(
+    &self,
+    solution: &program::Executable,
+    checker_args: Vec<String>,
+    input_file: sandbox::FileHandle,
+    answer_file: sandbox::FileHandle,
+    copy_in: HashMap<String, sandbox::FileHandle>,
+    interactor_copy_in: HashMap<String, sandbox::FileHandle>,
+    time_limit: time::Duration,
+    time_kind: judge::TimeKind,
+    memory_limit: u64,
+  ) -> record::Record {
+    let res = self
+      .interact(
+        solution,
+        vec![],
+        input_file.clone(),
+        copy_in,
+        interactor_copy_in.clone(),
+        time_limit,
+        time_kind,
+        memory_limit,
+      )
+      .await;
+
+    // The solution usually dies of a broken pipe after being rejected,
+    // so the verdict of the interactor goes first.
+    let interactor_output = match res.interactor_output {
+      Some(x) if x.status != checker::Status::Accepted => {
+        return record::Record::new_checked(&res.solution, &x).with_time_kind(time_kind);
+      }
+      x => x,
+    };
+
+    if res.solution.status != sandbox::Status::Accepted {
+      return record::Record::new_interrupted(&res.solution)
+        .with_time_kind(time_kind)
+        .with_almost_tle(time_limit);
+    }
+
+    let interactor_output = match interactor_output {
+      Some(x) => x,
+      None => {
+        return record::Record::new_system_error(&format!(
+          "interactor exited abnormally: {}",
+          res.interactor.status
+        ));
+      }
+    };
+
+    let (checker, output_file) = match (&self.checker, res.output_file) {
+      (None, _) => {
+        return record::Record::new_checked(&res.solution, &interactor_output)
+          .with_time_kind(time_kind)
+      }
+      (Some(checker), Some(f)) => (checker, f),
+      (Some(_), None) => {
+        return record::Record::new_system_error("interactor output file is missing");
+      }
+    };
+
+    match checker
+      .check(
+        checker_args,
+        input_file,
+        output_file,
+        answer_file,
+        interactor_copy_in,
+      )
+      .await
+    {
+      Ok(run) => record::Record::new_checked(&res.solution, &run.output).with_time_kind(time_kind),
+      Err(err) => record::Record::new_system_error(
+        &("checker execute failed: ".to_string() + &err.to_string()),
+      ),
+    }
+  }
+}
@@ -1,6 +1,55 @@
 use std::{collections::HashMap, time};
 
-use crate::{program, sandbox};
+use serde::{Deserialize, Serialize};
+
+use crate::{program, sandbox, CONFIG};
+
+/// Which stream or file written by a solution is treated as its output.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputSource {
+  #[default]
+  Stdout,
+
+  /// Standard error, for problems grading what the program logs.
+  Stderr,
+
+  /// A file written by the solution in its working directory.
+  File(String),
+}
+
+/// Which time of a solution is limited by the time limit.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeKind {
+  /// CPU time, the wall clock time is limited separately (see `Cmd::wall_time_limit`).
+  #[default]
+  Cpu,
+
+  /// Wall clock time, for solutions mostly waiting on others (e.g. an interactor).
+  ///
+  /// The CPU time is limited by the same limit, as a single thread never uses more CPU time
+  /// than wall clock time.
+  Wall,
+}
+
+impl TimeKind {
+  /// Time of a run limited by the time limit.
+  pub fn of(&self, result: &sandbox::ExecuteResult) -> time::Duration {
+    return match self {
+      Self::Cpu => result.time,
+      Self::Wall => result.wall_time,
+    };
+  }
+
+  /// Limit the time of a command by `time_limit` extended by the grace window.
+  pub(crate) fn limit(&self, cmd: &mut sandbox::Cmd, time_limit: time::Duration) {
+    cmd.time_limit = grace_time_limit(time_limit);
+    if *self == Self::Wall {
+      cmd.wall_time_limit = Some(cmd.time_limit);
+    }
+  }
+}
 
 impl program::Executable {
   /// Run the given executable file on a test case of batch problem (aka. traditional problem),
@@ -8,8 +57,16 @@ impl program::Executable {
   ///
   /// Second return value =
   ///
-  /// - JudgeResult == AC => Some(file id of stdout)
+  /// - JudgeResult == AC => Some(file id of the output selected by `output`)
   /// - Otherwise => None
+  ///
+  /// If the output is a file which the solution does not write, the result is `FileError`.
+  /// If the output file is larger than `stdout_limit`, the result is `OutputLimitExceeded`.
+  /// If `wall_time_limit` is None, the wall clock time is limited by `judge.clock_limit_ratio`
+  /// (in config) of the time limit.
+  /// If `time_kind` is `Wall`, `time_limit` limits the wall clock time and `wall_time_limit`
+  /// is ignored.
+  /// If `cpu_affinity` is None, the solution runs on `judge.cpu_set_limit` (in config).
   pub async fn judge_batch(
     &self,
     args: Vec<String>,
@@ -17,30 +74,84 @@ impl program::Executable {
     mut copy_in: HashMap<String, sandbox::FileHandle>,
     time_limit: time::Duration,
     memory_limit: u64,
+    wall_time_limit: Option<time::Duration>,
+    time_kind: TimeKind,
+    cpu_affinity: Option<&sandbox::CpuAffinity>,
+    stdout_limit: i64,
+    output: &OutputSource,
   ) -> (sandbox::ExecuteResult, Option<sandbox::FileHandle>) {
     copy_in.insert(self.lang.exec().to_string(), self.file.clone());
 
-    let mut res = sandbox::Request::Run(sandbox::Cmd {
+    let output_name = match output {
+      OutputSource::Stdout => "stdout",
+      OutputSource::Stderr => "stderr",
+      OutputSource::File(name) => name,
+    };
+    let mut copy_out = vec!["stdout".to_string(), "stderr".to_string()];
+    if let OutputSource::File(name) = output {
+      copy_out.push(name.clone());
+    }
+
+    let mut cmd = sandbox::Cmd {
       args: [self.lang.run_cmd().clone(), args].concat(),
       stdin: Some(input_file),
       copy_in,
-      copy_out: vec!["stdout".to_string(), "stderr".to_string()],
-      time_limit,
+      labels: self.labels.clone(),
+      copy_out,
       memory_limit,
+      wall_time_limit,
+      timed: true,
+      stdout_limit,
+      // An output file is limited like stdout, instead of being copied out whatever its size.
+      copy_out_max: stdout_limit as u64,
       ..Default::default()
-    })
-    .exec()
-    .await;
+    };
+    time_kind.limit(&mut cmd, time_limit);
+    if let Some(affinity) = cpu_affinity {
+      cmd.cpu_set_limit = affinity.cpus.clone();
+      cmd.pin_cpu = affinity.pin;
+    }
+    let mut res = sandbox::Request::Run(cmd).exec().await;
 
     assert_eq!(res.len(), 1);
-    let res = res.pop().unwrap();
+    let mut res = res.pop().unwrap();
+    apply_time_limit(&mut res.result, time_limit, time_kind);
 
     (
       res.result.clone(),
       match res.result.status {
-        sandbox::Status::Accepted => Some(res.files["stdout"].clone()),
+        sandbox::Status::Accepted => Some(res.files[output_name].clone()),
         _ => None,
       },
     )
   }
 }
+
+/// CPU time limit of a solution given to the sandbox,
+/// which is the time limit extended by the grace window in config.
+///
+/// A solution finishing within the grace window is still time limit exceeded,
+/// but its actual time is known.
+pub fn grace_time_limit(time_limit: time::Duration) -> time::Duration {
+  return time_limit.mul_f64(1. + CONFIG.judge.time_limit_grace);
+}
+
+/// Mark a solution run with the grace window (see `grace_time_limit`) as time limit exceeded
+/// if it used more time of `time_kind` than `time_limit`.
+///
+/// The wall clock time limit is the time limit of `Wall`,
+/// so exceeding it is time limit exceeded rather than idleness limit exceeded.
+pub(crate) fn apply_time_limit(
+  result: &mut sandbox::ExecuteResult,
+  time_limit: time::Duration,
+  time_kind: TimeKind,
+) {
+  let exceeded = match result.status {
+    sandbox::Status::Accepted => time_kind.of(result) > time_limit,
+    sandbox::Status::IdlenessLimitExceeded => time_kind == TimeKind::Wall,
+    _ => false,
+  };
+  if exceeded {
+    result.status = sandbox::Status::TimeLimitExceeded;
+  }
+}
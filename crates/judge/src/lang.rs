@@ -1,3 +1,4 @@
+use regex::Regex;
 use serde_with::{DeserializeFromStr, SerializeDisplay};
 use std::{fmt::Display, hash::Hash, str::FromStr};
 use thiserror::Error;
@@ -30,6 +31,33 @@ impl Lang {
   pub fn exec(&self) -> &str {
     &CONFIG.lang[&self.name].exec
   }
+
+  /// Labels a sandbox backend must have to compile and run programs of the language.
+  pub fn labels(&self) -> &Vec<String> {
+    &CONFIG.lang[&self.name].labels
+  }
+
+  /// Check extra compile arguments against the allowed and denied patterns of the language.
+  ///
+  /// An invalid pattern matches nothing when allowing, and everything when denying.
+  pub fn check_compile_args(&self, args: &[String]) -> Result<(), DeniedArgError> {
+    let conf = &CONFIG.lang[&self.name];
+    let is_match = |pattern: &String, arg: &str, invalid: bool| {
+      Regex::new(pattern).map_or(invalid, |x| x.is_match(arg))
+    };
+    for arg in args {
+      let allowed =
+        conf.allowed_args.is_empty() || conf.allowed_args.iter().any(|x| is_match(x, arg, false));
+      let denied = conf.denied_args.iter().any(|x| is_match(x, arg, true));
+      if !allowed || denied {
+        return Err(DeniedArgError {
+          lang: self.name.clone(),
+          arg: arg.clone(),
+        });
+      }
+    }
+    return Ok(());
+  }
 }
 
 impl FromStr for Lang {
@@ -59,3 +87,11 @@ impl Display for Lang {
 pub struct InvalidLangError {
   pub lang: String,
 }
+
+/// Error when an extra compile argument is not allowed by the language config.
+#[derive(Error, Debug, Clone)]
+#[error("compile argument is not allowed for {lang}: {arg}")]
+pub struct DeniedArgError {
+  pub lang: String,
+  pub arg: String,
+}
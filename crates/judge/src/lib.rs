@@ -0,0 +1,37 @@
+#[cfg(test)]
+mod test;
+
+pub mod artifact;
+pub mod builtin;
+pub(crate) mod cache;
+pub mod calibrate;
+pub mod checker;
+pub mod contest;
+pub(crate) mod data;
+pub mod doctor;
+pub mod environment;
+pub(crate) mod error;
+pub mod etc;
+pub mod generator;
+pub mod hack;
+pub mod interactor;
+pub(crate) mod judge;
+pub mod lang;
+/// Re-exports of the types most users of the judge need, with stable paths.
+pub mod prelude;
+pub mod preview;
+pub mod problem;
+pub mod program;
+pub mod record;
+pub mod report;
+pub mod sandbox;
+pub mod schema;
+pub mod stress;
+pub mod validator;
+pub mod workflow;
+
+pub use crate::etc::CONFIG;
+
+#[macro_use]
+extern crate lazy_static;
+extern crate log;
@@ -0,0 +1,18 @@
+pub use crate::{
+  checker::{Checker, Status as CheckerStatus},
+  data::Provider,
+  error::{CompileError, RuntimeError, ValidateError},
+  generator::Generator,
+  interactor::Interactor,
+  judge::{OutputSource, TimeKind},
+  lang::Lang,
+  problem::{Answer, Input, Kind, Problem, Subtask, Test, Testset},
+  program::{Executable, Source},
+  record::{Record, RecordStatus, TestId},
+  sandbox::{ExecuteResult, FileHandle, Status as ExecuteStatus},
+  validator::Validator,
+  workflow::{
+    CheckTask, CompileTask, GenerateTask, InteractTask, JudgeBatchTask, StressTask, Task,
+    TaskError, TestPlanTask, ValidateTask, VerifyAnswersTask, Workflow,
+  },
+};
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sandbox;
+
+/// Detected charset of a file.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Charset {
+  Ascii,
+  Utf8,
+  /// Not valid UTF-8, which is usually a binary file.
+  Binary,
+}
+
+/// Bounded preview of a test input, answer or output file, which is safe to show in UIs.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Preview {
+  /// Head of the file, cut at a character boundary.
+  ///
+  /// Invalid UTF-8 sequences and control characters other than line breaks and tabs
+  /// are replaced with U+FFFD.
+  pub head: String,
+
+  /// Whether `head` is only a part of the file.
+  pub truncated: bool,
+
+  /// Size of the whole file in bytes.
+  pub size: usize,
+
+  /// Number of lines of the whole file, a last line without line break is counted.
+  pub lines: usize,
+
+  /// Number of whitespace separated tokens of the whole file.
+  pub tokens: usize,
+
+  pub charset: Charset,
+}
+
+impl Preview {
+  /// Make a preview of the content, with at most `limit` bytes of head.
+  pub fn new(content: &[u8], limit: usize) -> Self {
+    let mut head = &content[..content.len().min(limit)];
+    // Do not leave half of a multi-byte character at the end.
+    if let Err(err) = std::str::from_utf8(head) {
+      if err.error_len().is_none() {
+        head = &head[..err.valid_up_to()];
+      }
+    }
+
+    let charset = match std::str::from_utf8(content) {
+      Ok(x) if x.is_ascii() => Charset::Ascii,
+      Ok(_) => Charset::Utf8,
+      Err(_) => Charset::Binary,
+    };
+
+    return Self {
+      head: String::from_utf8_lossy(head)
+        .chars()
+        .map(|x| match x {
+          '\n' | '\r' | '\t' => x,
+          x if x.is_control() => char::REPLACEMENT_CHARACTER,
+          x => x,
+        })
+        .collect(),
+      truncated: head.len() < content.len(),
+      size: content.len(),
+      lines: content.iter().filter(|&&x| x == b'\n').count()
+        + usize::from(content.last().is_some_and(|&x| x != b'\n')),
+      tokens: content
+        .split(|x| x.is_ascii_whitespace())
+        .filter(|x| !x.is_empty())
+        .count(),
+      charset,
+    };
+  }
+
+  /// Make a preview of a file in sandbox, with at most `limit` bytes of head.
+  ///
+  /// The sandbox can only send whole files, so the file is still downloaded to the judge,
+  /// but only the bounded preview is passed on.
+  pub async fn of_file(file: &sandbox::FileHandle, limit: usize) -> Result<Self, String> {
+    return file
+      .context()
+      .await
+      .map(|x| Self::new(&x, limit))
+      .map_err(|err| err.to_string());
+  }
+}
@@ -1,6 +1,11 @@
 use std::collections::HashMap;
 
-use crate::{error, program, sandbox};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{error, judge, program, record, sandbox, CONFIG};
+
+use super::{verify_copy_out, Subtask};
 
 /// Answer of test case.
 #[derive(Debug, Clone)]
@@ -21,18 +26,209 @@ impl Answer {
     copy_in: HashMap<String, sandbox::FileHandle>,
     time_limit: std::time::Duration,
     memory_limit: u64,
+    output: &judge::OutputSource,
   ) -> Result<sandbox::FileHandle, error::RuntimeError> {
     match self {
       Answer::Generated => {
         let (res, file) = standard_solution
-          .judge_batch(vec![], input_file, copy_in, time_limit, memory_limit)
+          .judge_batch(
+            vec![],
+            input_file,
+            copy_in,
+            time_limit,
+            memory_limit,
+            None,
+            judge::TimeKind::Cpu,
+            None,
+            CONFIG.judge.stdout_limit,
+            output,
+          )
           .await;
         if res.status != sandbox::Status::Accepted {
           return Err(error::RuntimeError::from(res));
         }
-        Ok(file.unwrap())
+        let file = file.unwrap();
+        verify_copy_out(&file).await?;
+        Ok(file)
       }
-      Answer::Plain { context } => Ok(sandbox::FileHandle::upload(context).await),
+      Answer::Plain { context } => Ok(sandbox::FileHandle::upload(context).await?),
     }
   }
 }
+
+/// Expectations of answer files, checked when building the problem.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AnswerLimits {
+  /// Max size of an answer file in bytes.
+  ///
+  /// None means the stdout limit of solutions in global config,
+  /// since a solution can never print an answer larger than that.
+  pub max_size: Option<u64>,
+
+  /// Max number of lines of an answer file.
+  pub max_lines: Option<usize>,
+
+  /// Max number of whitespace separated tokens in a single line.
+  pub max_tokens_per_line: Option<usize>,
+}
+
+impl AnswerLimits {
+  /// Check the content of an answer file against the limits.
+  pub fn check(&self, content: &[u8]) -> Result<(), AnswerLimitError> {
+    let max_size = self
+      .max_size
+      .unwrap_or(u64::try_from(CONFIG.judge.stdout_limit).unwrap_or(0));
+    if content.len() as u64 > max_size {
+      return Err(AnswerLimitError::Size {
+        size: content.len() as u64,
+        limit: max_size,
+      });
+    }
+
+    let content = String::from_utf8_lossy(content);
+
+    if let Some(limit) = self.max_lines {
+      let lines = content.lines().count();
+      if lines > limit {
+        return Err(AnswerLimitError::Lines { lines, limit });
+      }
+    }
+
+    if let Some(limit) = self.max_tokens_per_line {
+      for (i, line) in content.lines().enumerate() {
+        let tokens = line.split_whitespace().count();
+        if tokens > limit {
+          return Err(AnswerLimitError::TokensPerLine {
+            line: i + 1,
+            tokens,
+            limit,
+          });
+        }
+      }
+    }
+
+    return Ok(());
+  }
+}
+
+/// Error when an answer file exceeds the answer limits.
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum AnswerLimitError {
+  #[error("answer size {size} bytes exceeds the limit {limit} bytes")]
+  Size { size: u64, limit: u64 },
+
+  #[error("answer has {lines} lines, exceeds the limit {limit}")]
+  Lines { lines: usize, limit: usize },
+
+  #[error("line {line} of answer has {tokens} tokens, exceeds the limit {limit}")]
+  TokensPerLine {
+    line: usize,
+    tokens: usize,
+    limit: usize,
+  },
+}
+
+/// Pre-checks of the answer files uploaded for a submit answer problem,
+/// run before any checker.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SubmissionLimits {
+  /// Max size of an uploaded file in bytes.
+  ///
+  /// None means the stdout limit of solutions in global config.
+  pub max_size: Option<u64>,
+
+  /// Allow files which are not valid UTF-8.
+  pub allow_binary: bool,
+
+  /// Required file name of the answer of each test,
+  /// `{testset}`, `{subtask}` and `{index}` (1-based) are replaced by the test id.
+  pub file_name: String,
+}
+
+impl Default for SubmissionLimits {
+  fn default() -> Self {
+    Self {
+      max_size: None,
+      allow_binary: false,
+      file_name: "{subtask}-{index}.out".to_string(),
+    }
+  }
+}
+
+/// An uploaded file rejected by the pre-checks.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Rejection {
+  pub file: String,
+  pub reason: RejectReason,
+}
+
+/// Reason why an uploaded file is rejected.
+#[derive(Debug, Error, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RejectReason {
+  #[error("file size {size} bytes exceeds the limit {limit} bytes")]
+  TooLarge { size: u64, limit: u64 },
+
+  #[error("file is not valid UTF-8 text")]
+  Binary,
+
+  #[error("file name does not match any test")]
+  UnknownName,
+}
+
+impl SubmissionLimits {
+  /// Required file name of the answer of the test.
+  pub fn file_name(&self, test_id: &record::TestId) -> String {
+    self
+      .file_name
+      .replace("{testset}", &test_id.testset.to_string())
+      .replace("{subtask}", &test_id.subtask.to_string())
+      .replace("{index}", &(test_id.index + 1).to_string())
+  }
+
+  /// Check the uploaded files and map the accepted ones to the tests of the subtasks.
+  ///
+  /// Returns the accepted files and the rejected files with reasons.
+  /// Tests without an accepted file are judged as missing answers.
+  pub fn check(
+    &self,
+    files: HashMap<String, Vec<u8>>,
+    subtasks: &[Subtask],
+  ) -> (HashMap<record::TestId, Vec<u8>>, Vec<Rejection>) {
+    let names: HashMap<_, _> = subtasks
+      .iter()
+      .flat_map(|subtask| (0..subtask.tests.len()).map(|i| subtask.test_id(i)))
+      .map(|id| (self.file_name(&id), id))
+      .collect();
+    let max_size = self
+      .max_size
+      .unwrap_or(u64::try_from(CONFIG.judge.stdout_limit).unwrap_or(0));
+
+    let mut accepted = HashMap::new();
+    let mut rejections = vec![];
+    for (file, content) in files {
+      let reason = match names.get(&file) {
+        None => Some(RejectReason::UnknownName),
+        Some(_) if content.len() as u64 > max_size => Some(RejectReason::TooLarge {
+          size: content.len() as u64,
+          limit: max_size,
+        }),
+        Some(_) if !self.allow_binary && std::str::from_utf8(&content).is_err() => {
+          Some(RejectReason::Binary)
+        }
+        Some(&id) => {
+          accepted.insert(id, content);
+          None
+        }
+      };
+      if let Some(reason) = reason {
+        rejections.push(Rejection { file, reason });
+      }
+    }
+    rejections.sort_by(|a, b| a.file.cmp(&b.file));
+
+    return (accepted, rejections);
+  }
+}
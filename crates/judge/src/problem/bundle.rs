@@ -0,0 +1,173 @@
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+
+use super::{PreparedProblem, Subtask, Test};
+use crate::{error, etc, program, record, sandbox, CONFIG};
+
+/// Files to reproduce the result of a solution on a single test,
+/// for the submitter or an administrator to download when the solution fails.
+#[derive(Debug, Clone)]
+pub struct Bundle {
+  /// Record of the test, with the status and the checker message.
+  pub record: record::Record,
+
+  pub input: Vec<u8>,
+
+  /// Output of the solution, None if the solution did not finish normally.
+  pub output: Option<Vec<u8>>,
+
+  pub answer: Vec<u8>,
+
+  /// Names of the files cut to the size limit.
+  pub truncated: Vec<String>,
+}
+
+impl Bundle {
+  /// Pack the bundle as a tar archive, with the files `input.txt`, `output.txt` (if any),
+  /// `answer.txt`, and the record in `record.json`.
+  pub fn to_tar(&self) -> std::io::Result<Vec<u8>> {
+    let record = serde_json::to_vec_pretty(&self.record)?;
+    let mut files = vec![("input.txt", &self.input)];
+    files.extend(self.output.as_ref().map(|x| ("output.txt", x)));
+    files.push(("answer.txt", &self.answer));
+    files.push(("record.json", &record));
+
+    let mut archive = tar::Builder::new(vec![]);
+    for (path, content) in files {
+      let mut header = tar::Header::new_gnu();
+      header.set_size(content.len() as u64);
+      header.set_mode(0o644);
+      header.set_mtime(0);
+      header.set_cksum();
+      archive.append_data(&mut header, path, content.as_slice())?;
+    }
+    return archive.into_inner();
+  }
+}
+
+impl PreparedProblem {
+  /// Assemble the bundle of a judged test on demand, by making the input and the answer
+  /// and running the solution on the test again.
+  ///
+  /// The test is found by the id of `record`, and each file is cut to `limit` bytes.
+  /// The output of the re-run may differ from the judged one if the solution is not
+  /// deterministic.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the test does not exist, the solution
+  /// does not compile, or the input or answer can not be made.
+  pub async fn bundle(
+    &self,
+    profile: &etc::ProfileCfg,
+    solution: &program::Source,
+    record: &record::Record,
+    limit: u64,
+  ) -> Result<Bundle, BundleError> {
+    let test_id = record.test_id.ok_or(BundleError::NoTest)?;
+    let (subtask, test) = self.find_test(&test_id).ok_or(BundleError::NoTest)?;
+
+    let (time_limit, memory_limit) = subtask.limits(test);
+    let (time_limit, memory_limit) = profile.limit(time_limit, memory_limit);
+    let wall_time_limit = subtask.scaled_wall_time_limit();
+    let time_limit = CONFIG.sandbox.scale_time_limit(time_limit);
+
+    let solution = solution
+      .compile(vec![], self.judge_copy_in.clone())
+      .await
+      .map_err(BundleError::Compile)?
+      .with_labels(&self.problem.labels);
+    let input = test
+      .input
+      .make(self.user_copy_in.clone())
+      .await
+      .map_err(BundleError::Input)?;
+    let (answer, (res, output)) = futures::join!(
+      test.answer.make(
+        &self.standard_solution,
+        input.clone(),
+        self.judge_copy_in.clone(),
+        time_limit,
+        memory_limit,
+        &self.problem.output,
+      ),
+      solution.judge_batch(
+        vec![],
+        input.clone(),
+        self.judge_copy_in.clone(),
+        time_limit,
+        memory_limit,
+        wall_time_limit,
+        subtask.time_kind,
+        subtask.cpu_affinity.as_ref(),
+        profile.stdout_limit(),
+        &self.problem.output,
+      ),
+    );
+    let answer = answer.map_err(BundleError::Answer)?;
+    log::debug!("bundle of test {} re-run: {:?}", test_id, res.status);
+
+    let mut truncated = vec![];
+    let mut cut = |name: &str, mut content: Vec<u8>| {
+      if content.len() as u64 > limit {
+        content.truncate(limit as usize);
+        truncated.push(name.to_string());
+      }
+      content
+    };
+    // Read at most one byte more than the limit, so huge outputs are never held in memory.
+    let fetch = |file: sandbox::FileHandle| async move {
+      let mut content = vec![];
+      file
+        .read_stream()
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut content)
+        .await
+        .map_err(|err| BundleError::Sandbox(err.to_string()))?;
+      return Ok::<_, BundleError>(content);
+    };
+    let input = cut("input.txt", fetch(input).await?);
+    let output = match output {
+      Some(x) => Some(cut("output.txt", fetch(x).await?)),
+      None => None,
+    };
+    let answer = cut("answer.txt", fetch(answer).await?);
+
+    return Ok(Bundle {
+      record: record.clone(),
+      input,
+      output,
+      answer,
+      truncated,
+    });
+  }
+
+  /// Find a test and its subtask by the id.
+  fn find_test(&self, test_id: &record::TestId) -> Option<(&Subtask, &Test)> {
+    let subtask = self
+      .problem
+      .subtasks
+      .iter()
+      .find(|x| x.testset == test_id.testset && x.id == test_id.subtask)?;
+    return Some((subtask, subtask.tests.get(test_id.index)?));
+  }
+}
+
+/// Error when a bundle can not be assembled.
+#[derive(Debug, Error, Clone)]
+pub enum BundleError {
+  #[error("the record does not belong to a test of the problem")]
+  NoTest,
+
+  #[error("solution: {0}")]
+  Compile(error::CompileError),
+
+  #[error("input file generated failed: {0}")]
+  Input(error::RuntimeError),
+
+  #[error("answer file generated failed: {0}")]
+  Answer(error::RuntimeError),
+
+  #[error("file can not be fetched from sandbox: {0}")]
+  Sandbox(String),
+}
@@ -0,0 +1,1027 @@
+use std::{
+  collections::{BTreeMap, HashMap, HashSet},
+  path,
+  str::FromStr,
+  time,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+  checker_args, Answer, AnswerLimits, Input, JudgeError, Kind, PreparedProblem, Problem,
+  ScoringPolicy, SubmissionLimits, Subtask, Test, Testset,
+};
+use crate::{
+  builtin, checker, data, error, etc, generator, judge, lang, program, record, sandbox, validator,
+};
+
+/// Name of the problem configuration file at the root of a problem directory.
+pub const CONFIG_FILE: &str = "problem.toml";
+
+/// Name of the generated header of the problem constants, see `ProblemConfig::constants`.
+pub const CONSTANTS_HEADER: &str = "constants.h";
+
+/// Declarative configuration of a problem, usually written in `problem.toml`.
+///
+/// Paths are relative to the problem directory.
+/// The language of a source is guessed from its extension if not given.
+///
+/// ```toml
+/// time_limit_ms = 1000
+/// memory_limit_mb = 256
+/// checker = { builtin = "ncmp.cpp" }
+/// validator = { path = "val.cpp" }
+/// constants = { MOD = 998244353, EPS = 1e-9 }
+/// standard_solution = { path = "std.cpp" }
+/// generators = { gen = { path = "gen.cpp" } }
+/// # Pin each run to its own core, one logical CPU of each core to avoid SMT siblings.
+/// cpu_affinity = { cpus = "0,2,4,6", pin = true }
+///
+/// [[subtasks]]
+/// score = 100
+/// tests = [{ input = "tests/1.in" }, { generate = "gen 10 20", tags = ["max_n"] }]
+///
+/// [[solutions]]
+/// path = "brute.cpp"
+/// expected = "TimeLimitExceeded"
+/// tests = [{ tags = ["max_n"], expected = "TimeLimitExceeded", exactly = true }]
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProblemConfig {
+  #[serde(default = "default_kind")]
+  pub kind: Kind,
+
+  /// Time limit of tests in milliseconds, unless overridden by the subtask.
+  pub time_limit_ms: u64,
+
+  /// Memory limit of tests in MiB, unless overridden by the subtask.
+  pub memory_limit_mb: u64,
+
+  /// Wall clock time limit of tests in milliseconds, unless overridden by the subtask,
+  /// see `Subtask::wall_time_limit`.
+  pub wall_time_limit_ms: Option<u64>,
+
+  /// Which time the time limit is of, unless overridden by the subtask,
+  /// see `Subtask::time_kind`.
+  pub time_kind: Option<judge::TimeKind>,
+
+  /// CPUs to run solutions on, unless overridden by the subtask,
+  /// see `Subtask::cpu_affinity`.
+  pub cpu_affinity: Option<sandbox::CpuAffinity>,
+
+  pub checker: CheckerConfig,
+
+  /// Seed the checker with deterministic seeds, see `Problem::seed_checker`.
+  #[serde(default)]
+  pub seed_checker: bool,
+
+  /// Labels of the sandbox backends to run solutions on, see `Problem::labels`.
+  #[serde(default)]
+  pub labels: Vec<String>,
+
+  pub interactor: Option<SourceConfig>,
+
+  /// Validator of all the inputs, run by the build checks.
+  pub validator: Option<SourceConfig>,
+
+  /// Also validate generated inputs at judge time, see `Problem::validator`.
+  #[serde(default)]
+  pub validate_generated: bool,
+
+  pub standard_solution: SourceConfig,
+
+  /// Generators by the names used in test commands.
+  #[serde(default)]
+  pub generators: HashMap<String, SourceConfig>,
+
+  /// Extra files when compiling or running checker, validator and generators.
+  ///
+  /// The builtin `testlib.h` is always provided unless a file of the same name is listed.
+  /// The files are read from disk when they are uploaded, instead of when loading the problem.
+  #[serde(default)]
+  pub files: Vec<String>,
+
+  /// Constants of the problem (e.g. `MOD`, `EPS`, `N_MAX`), defined as macros in the generated
+  /// header `constants.h` for the checker, validator, interactor and generators,
+  /// so one generic source can be reused by related problems with different parameters.
+  #[serde(default)]
+  pub constants: BTreeMap<String, Constant>,
+
+  pub subtasks: Vec<SubtaskConfig>,
+
+  /// Solutions checked by the build checks, with their expected verdicts.
+  #[serde(default)]
+  pub solutions: Vec<SolutionConfig>,
+}
+
+fn default_kind() -> Kind {
+  return Kind::Batch;
+}
+
+/// A constant of the problem, see `ProblemConfig::constants`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Constant {
+  Integer(i64),
+  Float(f64),
+  Bool(bool),
+  String(String),
+}
+
+impl Constant {
+  /// The constant as a C/C++ literal, None if it can not be written as one (e.g. NaN).
+  fn to_literal(&self) -> Option<String> {
+    return match self {
+      Self::Integer(x) => Some(x.to_string()),
+      // Debug keeps the decimal point, so the macro is never an integer.
+      Self::Float(x) if x.is_finite() => Some(format!("{:?}", x)),
+      Self::Float(_) => None,
+      Self::Bool(x) => Some(x.to_string()),
+      Self::String(x) => {
+        let mut ret = "\"".to_string();
+        for c in x.chars() {
+          match c {
+            '"' | '\\' => {
+              ret.push('\\');
+              ret.push(c);
+            }
+            '\n' => ret.push_str("\\n"),
+            '\t' => ret.push_str("\\t"),
+            x if x.is_control() => return None,
+            x => ret.push(x),
+          }
+        }
+        ret.push('"');
+        Some(ret)
+      }
+    };
+  }
+}
+
+/// Generate the header defining each constant as a macro, see `ProblemConfig::constants`.
+///
+/// # Errors
+///
+/// This function will return an error if a name is not a C identifier,
+/// or a value can not be written as a literal.
+pub fn constants_header(constants: &BTreeMap<String, Constant>) -> Result<String, LoadError> {
+  let mut ret = "#ifndef RINDAG_CONSTANTS_H\n#define RINDAG_CONSTANTS_H\n".to_string();
+  for (name, value) in constants {
+    if !name.starts_with(|x: char| x.is_ascii_alphabetic() || x == '_')
+      || !name.chars().all(|x| x.is_ascii_alphanumeric() || x == '_')
+    {
+      return Err(LoadError::Invalid(format!(
+        "constant name {} is not an identifier",
+        name
+      )));
+    }
+    let literal = value
+      .to_literal()
+      .ok_or_else(|| LoadError::Invalid(format!("constant {} is not a literal", name)))?;
+    ret.push_str(&format!("#define {} {}\n", name, literal));
+  }
+  ret.push_str("#endif\n");
+  return Ok(ret);
+}
+
+/// A source file of the problem.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+  pub path: String,
+
+  /// Language name in config, None to guess from the extension of `path`.
+  pub lang: Option<String>,
+}
+
+/// Checker of the problem, either a builtin checker or a custom source.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum CheckerConfig {
+  /// Builtin checker by its name or file name, e.g. `ncmp` or `ncmp.cpp`
+  /// (see `builtin::checkers`).
+  Builtin {
+    builtin: String,
+  },
+
+  /// Native comparison, e.g. `{ compare = "floats", eps = 1e-6 }` (see `checker::Builtin`).
+  Compare(checker::Builtin),
+
+  Custom(SourceConfig),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SubtaskConfig {
+  #[serde(default = "default_testset")]
+  pub testset: Testset,
+
+  pub score: f32,
+
+  /// Indexes of the subtasks (in the order of the config) it depends on.
+  #[serde(default)]
+  pub dependences: Vec<usize>,
+
+  #[serde(default)]
+  pub scoring: ScoringConfig,
+
+  pub time_limit_ms: Option<u64>,
+
+  pub memory_limit_mb: Option<u64>,
+
+  pub wall_time_limit_ms: Option<u64>,
+
+  pub time_kind: Option<judge::TimeKind>,
+
+  pub cpu_affinity: Option<sandbox::CpuAffinity>,
+
+  pub tests: Vec<TestConfig>,
+}
+
+fn default_testset() -> Testset {
+  return Testset::Main;
+}
+
+/// Scoring policy of a subtask, see `ScoringPolicy`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoringConfig {
+  #[default]
+  Min,
+  Sum,
+  Product,
+  LastTest,
+  Points,
+}
+
+impl From<ScoringConfig> for ScoringPolicy {
+  fn from(x: ScoringConfig) -> Self {
+    return match x {
+      ScoringConfig::Min => Self::Min,
+      ScoringConfig::Sum => Self::Sum,
+      ScoringConfig::Product => Self::Product,
+      ScoringConfig::LastTest => Self::LastTest,
+      ScoringConfig::Points => Self::Points,
+    };
+  }
+}
+
+/// A test, whose input is either a file or generated by a command.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TestConfig {
+  /// Path of the input file.
+  pub input: Option<String>,
+
+  /// Generator command, the name of a generator followed by its arguments.
+  pub generate: Option<String>,
+
+  /// Path of the answer file, None to generate by the standard solution.
+  pub answer: Option<String>,
+
+  /// Tags to select the test in the test expectations of solutions.
+  #[serde(default)]
+  pub tags: Vec<String>,
+}
+
+/// A solution checked by the build checks.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SolutionConfig {
+  pub path: String,
+
+  pub lang: Option<String>,
+
+  /// Expected verdict, `Accepted` means all tests are accepted,
+  /// otherwise at least one test gets this verdict.
+  pub expected: record::RecordStatus,
+
+  /// Expected verdicts of selected tests, to control where the solution fails.
+  #[serde(default)]
+  pub tests: Vec<TestExpectationConfig>,
+}
+
+/// Expected verdict of the tests selected by tags or ids.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TestExpectationConfig {
+  /// Select the tests with any of the tags.
+  #[serde(default)]
+  pub tags: Vec<String>,
+
+  /// Select the tests by ids (`testset/subtask/index`, see `record::TestId`).
+  #[serde(default)]
+  pub ids: Vec<String>,
+
+  /// Verdict of every selected test, `Accepted` also allows skipped tests.
+  pub expected: record::RecordStatus,
+
+  /// The other tests must not get the verdict.
+  #[serde(default)]
+  pub exactly: bool,
+}
+
+impl ProblemConfig {
+  /// Parse a problem configuration in TOML.
+  pub fn parse(content: &str) -> Result<Self, LoadError> {
+    return toml::from_str(content).map_err(|err| LoadError::Parse(err.to_string()));
+  }
+}
+
+/// Steps to check a loaded problem when building it, which need to run programs.
+///
+/// Files of a problem built by a `workflow::Workflow` are checked there instead,
+/// e.g. the answers by a `workflow::VerifyAnswersTask`.
+#[derive(Debug, Clone)]
+pub struct BuildChecks {
+  /// Validator of all the inputs.
+  pub validator: Option<program::Source>,
+
+  /// Solutions with their expected verdicts.
+  pub solutions: Vec<Solution>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Solution {
+  /// Path of the solution in the problem directory.
+  pub name: String,
+
+  pub source: program::Source,
+
+  pub expected: record::RecordStatus,
+
+  /// Expected verdicts of selected tests.
+  pub tests: Vec<TestExpectation>,
+}
+
+/// Expected verdict of some tests, resolved from a `TestExpectationConfig`.
+#[derive(Debug, Clone)]
+pub struct TestExpectation {
+  /// The selected tests.
+  pub tests: HashSet<record::TestId>,
+
+  pub expected: record::RecordStatus,
+
+  /// The other tests must not get the verdict.
+  pub exactly: bool,
+}
+
+impl Solution {
+  /// Check the verdicts of the tests against the test expectations,
+  /// and return an issue for each test violating one.
+  pub fn check_tests<'a>(
+    &self,
+    records: impl IntoIterator<Item = &'a record::Record>,
+  ) -> Vec<Issue> {
+    let mut issues = vec![];
+    for record in records {
+      let test_id = match record.test_id {
+        Some(x) => x,
+        None => continue,
+      };
+      for expectation in &self.tests {
+        let selected = expectation.tests.contains(&test_id);
+        let matched = record.status == expectation.expected
+          || (expectation.expected == record::RecordStatus::Accepted
+            && record.status == record::RecordStatus::Skipped);
+        if (selected && !matched) || (!selected && expectation.exactly && matched) {
+          issues.push(Issue::UnexpectedTestVerdict {
+            solution: self.name.clone(),
+            test_id,
+            expected: expectation.expected.clone(),
+            excluded: !selected,
+            actual: record.status.clone(),
+          });
+        }
+      }
+    }
+    return issues;
+  }
+}
+
+/// Usage of the standard solution in a run of the build checks.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+  /// Number of answers generated by running the standard solution before judging the solutions.
+  pub answers_generated: usize,
+
+  /// Number of tests judged with a generated answer instead of running the standard solution again.
+  pub answers_reused: usize,
+}
+
+/// A problem found by the build checks.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Issue {
+  /// The input of a test can not be made.
+  InputFailed {
+    test_id: record::TestId,
+    message: String,
+  },
+
+  /// The input of a test is rejected by the validator,
+  /// with where it is rejected if the validator reported it.
+  InvalidInput {
+    test_id: record::TestId,
+    message: String,
+    rejection: Option<validator::Rejection>,
+  },
+
+  /// The input of a test is accepted by the validator with warnings, which is not fatal.
+  InputWarnings {
+    test_id: record::TestId,
+    warnings: Vec<String>,
+  },
+
+  /// The answer file of a test is not accepted by the checker
+  /// against the output of the standard solution.
+  AnswerMismatch {
+    test_id: record::TestId,
+    message: String,
+  },
+
+  /// A solution does not compile.
+  CompileError { solution: String, message: String },
+
+  /// A solution does not get its expected verdict.
+  UnexpectedVerdict {
+    solution: String,
+    expected: record::RecordStatus,
+
+    /// Distinct verdicts of the tests.
+    actual: Vec<record::RecordStatus>,
+  },
+
+  /// A test of a solution violates a test expectation.
+  UnexpectedTestVerdict {
+    solution: String,
+    test_id: record::TestId,
+
+    /// Expected verdict, or the verdict the test must not get if `excluded`.
+    expected: record::RecordStatus,
+
+    /// The test is not selected by an `exactly` expectation, but gets its verdict.
+    excluded: bool,
+
+    actual: record::RecordStatus,
+  },
+}
+
+impl Issue {
+  /// Whether the issue fails the build, all issues but warnings do.
+  pub fn is_fatal(&self) -> bool {
+    return !matches!(self, Self::InputWarnings { .. });
+  }
+}
+
+/// Load a problem from a directory with a `problem.toml`,
+/// and return the problem and the checks to run on it when building.
+///
+/// Generators used by tests are compiled, so that the inputs can be generated on demand.
+///
+/// # Errors
+///
+/// This function will return an error if the config is invalid,
+/// a file can not be read, or a generator does not compile.
+pub async fn load(root: &path::Path) -> Result<(Problem, BuildChecks), LoadError> {
+  let config = ProblemConfig::parse(&String::from_utf8_lossy(&read(root, CONFIG_FILE).await?))?;
+
+  let mut user_copy_in = HashMap::from([(
+    "testlib.h".to_string(),
+    builtin::File::new("testlib", "testlib.h").unwrap().into(),
+  )]);
+  // Extra files may be huge archives, they are read from disk only when uploaded.
+  for path in &config.files {
+    let name = path.rsplit('/').next().unwrap().to_string();
+    tokio::fs::metadata(root.join(path))
+      .await
+      .map_err(|err| LoadError::Io {
+        path: path.to_string(),
+        message: err.to_string(),
+      })?;
+    user_copy_in.insert(
+      name,
+      data::Provider::Local {
+        path: root.join(path),
+      },
+    );
+  }
+
+  if !config.constants.is_empty() {
+    if user_copy_in.contains_key(CONSTANTS_HEADER) {
+      return Err(LoadError::Invalid(format!(
+        "file {} conflicts with the header of constants",
+        CONSTANTS_HEADER
+      )));
+    }
+    user_copy_in.insert(
+      CONSTANTS_HEADER.to_string(),
+      data::Provider::Memory(constants_header(&config.constants)?.into_bytes()),
+    );
+  }
+
+  // Compile the generators used by tests only.
+  let used: HashSet<_> = config
+    .subtasks
+    .iter()
+    .flat_map(|x| &x.tests)
+    .filter_map(|x| x.generate.as_ref())
+    .filter_map(|x| x.split_whitespace().next())
+    .collect();
+  let mut generators = HashMap::new();
+  if !used.is_empty() {
+    let copy_in = data::upload_all(&user_copy_in).await;
+    for name in used {
+      let source = match config.generators.get(name) {
+        Some(x) => load_source(root, x).await?,
+        None => return Err(LoadError::Invalid(format!("unknown generator {}", name))),
+      };
+      let exec = source
+        .compile(vec![], copy_in.clone())
+        .await
+        .map_err(|err| LoadError::Compile {
+          name: name.to_string(),
+          error: err,
+        })?;
+      generators.insert(name.to_string(), generator::Generator::from(exec));
+    }
+  }
+
+  let mut subtasks = vec![];
+  let mut tags: HashMap<&str, Vec<record::TestId>> = HashMap::new();
+  for (id, subtask) in config.subtasks.iter().enumerate() {
+    if let Some(&x) = subtask.dependences.iter().find(|&&x| x >= id) {
+      return Err(LoadError::Invalid(format!(
+        "subtask {} depends on subtask {} which is not before it",
+        id, x
+      )));
+    }
+    let mut tests = vec![];
+    for (index, test) in subtask.tests.iter().enumerate() {
+      for tag in &test.tags {
+        tags.entry(tag).or_default().push(record::TestId {
+          testset: subtask.testset,
+          subtask: id,
+          index,
+        });
+      }
+      let input = match (&test.input, &test.generate) {
+        (Some(path), None) => Input::Plain {
+          context: read(root, path).await?,
+        },
+        (None, Some(command)) => {
+          let mut args = command.split_whitespace().map(str::to_string);
+          let name = args.next().unwrap();
+          Input::Generated {
+            generator: generators[&name].clone(),
+            args: args.collect(),
+          }
+        }
+        _ => {
+          return Err(LoadError::Invalid(format!(
+            "test of subtask {} must have exactly one of input and generate",
+            id
+          )))
+        }
+      };
+      let answer = match &test.answer {
+        Some(path) => Answer::Plain {
+          context: read(root, path).await?,
+        },
+        None => Answer::Generated,
+      };
+      tests.push(Test {
+        input,
+        answer,
+        time_limit: None,
+        memory_limit: None,
+      });
+    }
+    let cpu_affinity = subtask
+      .cpu_affinity
+      .clone()
+      .or_else(|| config.cpu_affinity.clone());
+    if let Some(x) = &cpu_affinity {
+      match sandbox::parse_cpu_set(&x.cpus) {
+        Some(cpus) if !cpus.is_empty() => {}
+        _ => {
+          return Err(LoadError::Invalid(format!(
+            "subtask {}: invalid cpuset {:?}",
+            id, x.cpus
+          )))
+        }
+      }
+    }
+    subtasks.push(Subtask {
+      id,
+      score: subtask.score,
+      dependences: subtask.dependences.clone(),
+      testset: subtask.testset,
+      tests,
+      time_limit: time::Duration::from_millis(
+        subtask.time_limit_ms.unwrap_or(config.time_limit_ms),
+      ),
+      memory_limit: subtask.memory_limit_mb.unwrap_or(config.memory_limit_mb) * 1024 * 1024,
+      wall_time_limit: subtask
+        .wall_time_limit_ms
+        .or(config.wall_time_limit_ms)
+        .map(time::Duration::from_millis),
+      time_kind: subtask.time_kind.or(config.time_kind).unwrap_or_default(),
+      cpu_affinity,
+      scoring: subtask.scoring.into(),
+    });
+  }
+
+  let checker = match &config.checker {
+    CheckerConfig::Builtin { builtin } => program::Source {
+      lang: lang::Lang::from_str("cpp")?,
+      data: builtin::File::new("checker", builtin)
+        .or_else(|_| builtin::File::new("checker", &format!("{}.cpp", builtin)))
+        .map_err(|_| LoadError::Invalid(format!("unknown builtin checker {}", builtin)))?
+        .into(),
+    }
+    .into(),
+    CheckerConfig::Compare(x) => checker::Source::Builtin(x.clone()),
+    CheckerConfig::Custom(x) => load_source(root, x).await?.into(),
+  };
+  let interactor = match &config.interactor {
+    Some(x) => Some(load_source(root, x).await?),
+    None => None,
+  };
+
+  let validator = match &config.validator {
+    Some(x) => Some(load_source(root, x).await?),
+    None => None,
+  };
+
+  let problem = Problem {
+    subtasks,
+    kind: config.kind,
+    checker,
+    interactor,
+    validator: match config.validate_generated {
+      true => validator.clone(),
+      false => None,
+    },
+    output: Default::default(),
+    presentation_error: Default::default(),
+    cache_checker: false,
+    seed_checker: config.seed_checker,
+    labels: config.labels.clone(),
+    answer_limits: AnswerLimits::default(),
+    submission_limits: SubmissionLimits::default(),
+    standard_solution: load_source(root, &config.standard_solution).await?,
+    user_copy_in,
+    judge_copy_in: HashMap::new(),
+  };
+
+  let mut solutions = vec![];
+  for x in &config.solutions {
+    let mut expectations = vec![];
+    for expectation in &x.tests {
+      let mut tests = HashSet::new();
+      for tag in &expectation.tags {
+        tests.extend(tags.get(tag.as_str()).ok_or_else(|| {
+          LoadError::Invalid(format!("solution {}: unknown test tag {}", x.path, tag))
+        })?);
+      }
+      for id in &expectation.ids {
+        let test_id = record::TestId::from_str(id)
+          .map_err(|err| LoadError::Invalid(format!("solution {}: {}", x.path, err)))?;
+        if !problem.subtasks.iter().any(|subtask| {
+          subtask.testset == test_id.testset
+            && subtask.id == test_id.subtask
+            && test_id.index < subtask.tests.len()
+        }) {
+          return Err(LoadError::Invalid(format!(
+            "solution {}: unknown test {}",
+            x.path, test_id
+          )));
+        }
+        tests.insert(test_id);
+      }
+      expectations.push(TestExpectation {
+        tests,
+        expected: expectation.expected.clone(),
+        exactly: expectation.exactly,
+      });
+    }
+    solutions.push(Solution {
+      name: x.path.clone(),
+      source: load_source(
+        root,
+        &SourceConfig {
+          path: x.path.clone(),
+          lang: x.lang.clone(),
+        },
+      )
+      .await?,
+      expected: x.expected.clone(),
+      tests: expectations,
+    });
+  }
+
+  return Ok((
+    problem,
+    BuildChecks {
+      validator,
+      solutions,
+    },
+  ));
+}
+
+impl BuildChecks {
+  /// Run the checks on the loaded problem, and return the issues found
+  /// with the usage of the standard solution.
+  ///
+  /// Every input is validated (warnings of the validator are reported as non-fatal issues),
+  /// every answer file is checked against the output
+  /// of the standard solution, and then every solution is judged on all the test sets
+  /// to check its verdict and the verdicts of the tests.
+  /// Generated answers are made once before judging the solutions, and reused by all of them.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the validator does not compile,
+  /// or the problem can not be judged (e.g. the standard solution does not compile).
+  pub async fn run(&self, problem: &Problem) -> Result<(Vec<Issue>, Usage), BuildError> {
+    let mut issues = vec![];
+    let user_copy_in = data::upload_all(&problem.user_copy_in).await;
+
+    if let Some(source) = &self.validator {
+      let validator = validator::Validator::from(
+        source
+          .compile(vec![], user_copy_in.clone())
+          .await
+          .map_err(BuildError::Validator)?,
+      );
+      for subtask in &problem.subtasks {
+        for (index, test) in subtask.tests.iter().enumerate() {
+          let test_id = subtask.test_id(index);
+          let input = match test.input.make(user_copy_in.clone()).await {
+            Ok(x) => x,
+            Err(err) => {
+              issues.push(Issue::InputFailed {
+                test_id,
+                message: err.to_string(),
+              });
+              continue;
+            }
+          };
+          match validator
+            .validate(
+              checker_args(&subtask.testset, subtask.id),
+              input,
+              user_copy_in.clone(),
+            )
+            .await
+          {
+            Ok(run) if !run.output.warnings.is_empty() => issues.push(Issue::InputWarnings {
+              test_id,
+              warnings: run.output.warnings,
+            }),
+            Ok(_) => {}
+            Err(err) => issues.push(Issue::InvalidInput {
+              test_id,
+              message: err.to_string(),
+              rejection: err.rejection,
+            }),
+          }
+        }
+      }
+    }
+
+    issues.extend(self.verify_answers(problem, &user_copy_in).await?);
+
+    let mut testsets = vec![];
+    for subtask in &problem.subtasks {
+      if !testsets.contains(&subtask.testset) {
+        testsets.push(subtask.testset);
+      }
+    }
+    let mut usage = Usage::default();
+    if self.solutions.is_empty() {
+      return Ok((issues, usage));
+    }
+    let (prepared, generated) = self.prepare(problem).await?;
+    usage.answers_generated = generated.len();
+    for solution in &self.solutions {
+      let records = match prepared
+        .judge_source(
+          uuid::Uuid::new_v4(),
+          &etc::ProfileCfg::default(),
+          &testsets,
+          &solution.source,
+          None,
+          None,
+          None,
+        )
+        .await
+      {
+        Ok((_, x)) => x,
+        Err(JudgeError::Solution(err)) => {
+          issues.push(Issue::CompileError {
+            solution: solution.name.clone(),
+            message: err.to_string(),
+          });
+          continue;
+        }
+        Err(err) => return Err(BuildError::Judge(err)),
+      };
+
+      usage.answers_reused += records
+        .iter()
+        .flat_map(|x| &x.1)
+        .filter(|x| x.status != record::RecordStatus::Skipped)
+        .filter(|x| x.test_id.is_some_and(|x| generated.contains(&x)))
+        .count();
+
+      let mut actual = vec![];
+      for record in records.iter().flat_map(|x| &x.1) {
+        if !actual.contains(&record.status) {
+          actual.push(record.status.clone());
+        }
+      }
+      let ok = match solution.expected {
+        record::RecordStatus::Accepted => actual.iter().all(|x| {
+          matches!(
+            x,
+            record::RecordStatus::Accepted | record::RecordStatus::Skipped
+          )
+        }),
+        ref expected => actual.contains(expected),
+      };
+      if !ok {
+        issues.push(Issue::UnexpectedVerdict {
+          solution: solution.name.clone(),
+          expected: solution.expected.clone(),
+          actual,
+        });
+      }
+      issues.extend(solution.check_tests(records.iter().flat_map(|x| &x.1)));
+    }
+
+    return Ok((issues, usage));
+  }
+
+  /// Prepare the problem once for judging all the solutions, and generate its answers
+  /// with the standard solution (see `Problem::generate_answers`).
+  ///
+  /// Returns the prepared problem, and the ids of the tests whose answers are generated.
+  /// A test whose answer fails is left generated, so judging reports the failure as before.
+  async fn prepare(
+    &self,
+    problem: &Problem,
+  ) -> Result<(PreparedProblem, HashSet<record::TestId>), BuildError> {
+    let generated: Vec<_> = problem
+      .subtasks
+      .iter()
+      .flat_map(|subtask| {
+        subtask
+          .tests
+          .iter()
+          .enumerate()
+          .filter(|(_, test)| matches!(test.answer, Answer::Generated))
+          .map(|(index, _)| subtask.test_id(index))
+      })
+      .collect();
+
+    let mut prepared = problem
+      .clone()
+      .prepare(uuid::Uuid::new_v4(), None)
+      .await
+      .map_err(BuildError::Judge)?;
+    let failed: HashSet<_> = prepared
+      .problem
+      .generate_answers(
+        &prepared.standard_solution,
+        &prepared.user_copy_in,
+        &prepared.judge_copy_in,
+      )
+      .await
+      .into_iter()
+      .map(|x| x.0)
+      .collect();
+    let generated = generated
+      .into_iter()
+      .filter(|x| !failed.contains(x))
+      .collect();
+    return Ok((prepared, generated));
+  }
+
+  /// Check the answer files of the problem with the checker, see `Problem::check_answers`.
+  ///
+  /// Nothing is compiled if there is no answer file,
+  /// and answers of non-batch problems are not checked since they can not be re-generated.
+  async fn verify_answers(
+    &self,
+    problem: &Problem,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> Result<Vec<Issue>, BuildError> {
+    if problem.kind != Kind::Batch
+      || !problem
+        .subtasks
+        .iter()
+        .flat_map(|x| &x.tests)
+        .any(|x| matches!(x.answer, Answer::Plain { .. }))
+    {
+      return Ok(vec![]);
+    }
+
+    let judge_copy_in = data::upload_all(&problem.judge_copy_in).await;
+    let (standard_solution, checker) = futures::join!(
+      problem
+        .standard_solution
+        .compile(vec![], judge_copy_in.clone()),
+      async {
+        return match &problem.checker {
+          checker::Source::Program(x) => x
+            .compile(vec![], user_copy_in.clone())
+            .await
+            .map(checker::Kind::Program),
+          checker::Source::Builtin(x) => Ok(checker::Kind::Builtin(x.clone())),
+        };
+      },
+    );
+    let standard_solution =
+      standard_solution.map_err(|err| BuildError::Judge(JudgeError::StandardSolution(err)))?;
+    let checker =
+      problem.make_checker(checker.map_err(|err| BuildError::Judge(JudgeError::Checker(err)))?);
+
+    let drifts = problem
+      .check_answers(&standard_solution, &checker, user_copy_in, &judge_copy_in)
+      .await;
+    return Ok(
+      drifts
+        .into_iter()
+        .map(|x| Issue::AnswerMismatch {
+          test_id: x.test_id,
+          message: x.message,
+        })
+        .collect(),
+    );
+  }
+}
+
+/// Load a source file, guessing the language from the extension if not given.
+async fn load_source(
+  root: &path::Path,
+  config: &SourceConfig,
+) -> Result<program::Source, LoadError> {
+  let lang = match &config.lang {
+    Some(x) => x.as_str(),
+    None => path::Path::new(&config.path)
+      .extension()
+      .and_then(|x| x.to_str())
+      .ok_or_else(|| {
+        LoadError::Invalid(format!("can not guess the language of {}", config.path))
+      })?,
+  };
+  return Ok(program::Source {
+    lang: lang::Lang::from_str(lang)?,
+    data: data::Provider::Memory(read(root, &config.path).await?),
+  });
+}
+
+async fn read(root: &path::Path, path: &str) -> Result<Vec<u8>, LoadError> {
+  return tokio::fs::read(root.join(path))
+    .await
+    .map_err(|err| LoadError::Io {
+      path: path.to_string(),
+      message: err.to_string(),
+    });
+}
+
+/// Error when loading a problem from its configuration.
+#[derive(Debug, Error, Clone)]
+pub enum LoadError {
+  #[error("can not read {path}: {message}")]
+  Io { path: String, message: String },
+
+  #[error("invalid problem config: {0}")]
+  Parse(String),
+
+  #[error("invalid problem config: {0}")]
+  Invalid(String),
+
+  #[error(transparent)]
+  Lang(#[from] lang::InvalidLangError),
+
+  #[error("generator {name}: {error}")]
+  Compile {
+    name: String,
+    error: error::CompileError,
+  },
+}
+
+/// Error when the build checks can not run.
+#[derive(Debug, Error, Clone)]
+pub enum BuildError {
+  #[error("validator: {0}")]
+  Validator(error::CompileError),
+
+  #[error(transparent)]
+  Judge(JudgeError),
+}
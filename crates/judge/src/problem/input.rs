@@ -2,6 +2,8 @@ use std::collections::HashMap;
 
 use crate::{error, generator, sandbox};
 
+use super::verify_copy_out;
+
 /// Input of test case.
 #[derive(Debug, Clone)]
 pub enum Input {
@@ -22,8 +24,12 @@ impl Input {
     copy_in: HashMap<String, sandbox::FileHandle>,
   ) -> Result<sandbox::FileHandle, error::RuntimeError> {
     match self {
-      Input::Generated { generator, args } => generator.generate(args.clone(), copy_in).await,
-      Input::Plain { context } => Ok(sandbox::FileHandle::upload(context).await),
+      Input::Generated { generator, args } => {
+        let file = generator.generate(args.clone(), copy_in).await?;
+        verify_copy_out(&file).await?;
+        Ok(file)
+      }
+      Input::Plain { context } => Ok(sandbox::FileHandle::upload(context).await?),
     }
   }
 }
@@ -1,18 +1,41 @@
 mod answer;
+mod bundle;
+pub mod config;
 mod input;
+pub mod package;
+mod samples;
+mod scoring;
+pub mod testplan;
 
-use std::{collections::HashMap, time};
+use std::{
+  collections::{HashMap, HashSet},
+  future::Future,
+  sync::Arc,
+  time,
+};
 
 use futures::channel::mpsc;
-use futures::{stream, SinkExt, StreamExt};
+use futures::{stream, StreamExt};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::sync::watch;
 
-use crate::{checker, data, program, record, sandbox};
+use crate::{
+  checker, data, environment, error, etc, judge, program, record, sandbox, validator, CONFIG,
+};
 
-pub use self::answer::Answer;
+pub use self::answer::{
+  Answer, AnswerLimitError, AnswerLimits, RejectReason, Rejection, SubmissionLimits,
+};
+pub use self::bundle::{Bundle, BundleError};
 pub use self::input::Input;
+pub use self::samples::{SampleRun, SampleRunError};
+pub use self::scoring::{Aggregator, ScoringPolicy};
+pub use rindag_types::problem::Testset;
 
 /// Parsed problem.
+#[derive(Clone)]
 pub struct Problem {
   /// Subtasks of the problem.
   ///
@@ -24,8 +47,48 @@ pub struct Problem {
   pub kind: Kind,
 
   /// Checker of the problem.
-  /// If problem type is Interactive, it will be used as an interactor.
-  pub checker: program::Source,
+  ///
+  /// If problem type is Interactive, it checks the output of the interactor
+  /// after the interaction finishes.
+  /// If problem type is Interactive and there is no interactor, it will be used as an interactor.
+  pub checker: checker::Source,
+
+  /// Interactor of an interactive problem.
+  pub interactor: Option<program::Source>,
+
+  /// Validator run on every generated input before judging,
+  /// an input rejected by it is recorded as a system error.
+  ///
+  /// Plain inputs are validated when building the problem, so they are not validated again.
+  pub validator: Option<program::Source>,
+
+  /// Which stream or file written by solutions is the output passed to the checker.
+  pub output: judge::OutputSource,
+
+  /// How presentation errors are judged, which should be set on the checker and interactor
+  /// (see `checker::Checker::with_presentation_error`).
+  pub presentation_error: checker::PresentationErrorPolicy,
+
+  /// Cache checker results by the content hashes of input, output and answer files,
+  /// which saves checker runs when many solutions print the same output.
+  ///
+  /// Only enable it for deterministic checkers.
+  pub cache_checker: bool,
+
+  /// Seed the checker when judging from source, with seeds derived from the problem,
+  /// the test and the submission (see `checker::Checker::with_seed`),
+  /// so re-judging a submission reproduces the exact same checker behavior.
+  pub seed_checker: bool,
+
+  /// Labels a sandbox backend must have to run the solutions of the problem
+  /// (see `sandbox::Cmd::labels`), e.g. `fast-cpu` for tight time limits.
+  pub labels: Vec<String>,
+
+  /// Expectations of answer files, checked by `Subtask::verify_answers` when building.
+  pub answer_limits: AnswerLimits,
+
+  /// Pre-checks of uploaded answer files, if problem type is SubmitAnswer.
+  pub submission_limits: SubmissionLimits,
 
   /// Main correct solution of the problem.
   ///
@@ -41,6 +104,8 @@ pub struct Problem {
 }
 
 /// Type of the problem.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
 pub enum Kind {
   /// Batch problem (a.k.a. traditional problem).
   Batch,
@@ -50,16 +115,7 @@ pub enum Kind {
   SubmitAnswer,
 }
 
-/// Test set of a subtask or test case.
-#[derive(Debug, PartialEq, Eq, strum::EnumString, strum::Display, strum::EnumIter, Clone, Copy)]
-#[strum(serialize_all = "snake_case")]
-pub enum Testset {
-  Sample,
-  Pretests,
-  Main,
-  Hack,
-}
-
+#[derive(Clone)]
 pub struct Subtask {
   pub id: usize,
   pub score: f32,
@@ -68,28 +124,140 @@ pub struct Subtask {
   pub tests: Vec<Test>,
   pub time_limit: time::Duration,
   pub memory_limit: u64,
+
+  /// Wall clock time limit of the tests, None to use `judge.clock_limit_ratio` (in config)
+  /// of the time limit.
+  ///
+  /// A solution exceeding it but not the time limit is judged `IdlenessLimitExceeded`.
+  pub wall_time_limit: Option<time::Duration>,
+
+  /// Which time of the solution is limited by the time limit, the wall clock time for
+  /// solutions mostly waiting on others (`wall_time_limit` is ignored then).
+  pub time_kind: judge::TimeKind,
+
+  /// CPUs to run the solution on, None to use `judge.cpu_set_limit` (in config).
+  ///
+  /// Interactive problems ignore it, as the solution shares the CPUs with the interactor.
+  pub cpu_affinity: Option<sandbox::CpuAffinity>,
+
+  /// How the score of the subtask is aggregated from the scores of its tests.
+  pub scoring: ScoringPolicy,
 }
 
 /// Parsed test (a pair of input file and output file).
+#[derive(Clone)]
 pub struct Test {
   pub input: Input,
   pub answer: Answer,
+
+  /// Time limit of this test, None to use the time limit of the subtask.
+  pub time_limit: Option<time::Duration>,
+
+  /// Memory limit of this test in bytes, None to use the memory limit of the subtask.
+  pub memory_limit: Option<u64>,
+}
+
+/// Revisions of the parts of a problem, made by `Problem::revisions`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Revisions {
+  /// Hash of the programs, settings and extra files shared by all the subtasks.
+  pub shared: String,
+
+  /// Hash of the settings and tests of each subtask.
+  pub subtasks: Vec<String>,
+}
+
+/// Hex SHA-256 digest of the parts fed to `update`, each prefixed with its length.
+fn digest(f: impl FnOnce(&mut dyn FnMut(&[u8]))) -> String {
+  let mut hasher = Sha256::new();
+  f(&mut |x: &[u8]| {
+    hasher.update((x.len() as u64).to_le_bytes());
+    hasher.update(x);
+  });
+  return hex::encode(hasher.finalize());
+}
+
+/// Extra arguments passed to checker, to tell it which test is being checked.
+fn checker_args(testset: &Testset, subtask_id: usize) -> Vec<String> {
+  vec![
+    "--testset".to_string(),
+    testset.to_string(),
+    "--group".to_string(),
+    subtask_id.to_string(),
+  ]
+}
+
+/// Judge a test again right away while the record is flaky, up to `JudgeCfg::max_attempts` runs,
+/// and record the number of runs.
+///
+/// The re-runs take the place of the test in the queue instead of going to its end,
+/// so a flaky test does not delay the result of its subtask.
+/// If the last run still fails, the record is surfaced as a system error.
+pub(crate) async fn retry_flaky<F, Fut>(correlation_id: uuid::Uuid, judge: F) -> record::Record
+where
+  F: Fn() -> Fut,
+  Fut: Future<Output = record::Record>,
+{
+  let max_attempts = CONFIG.judge.max_attempts.max(1);
+  let mut attempts = 1;
+  loop {
+    let mut record = judge().await;
+    record.attempts = attempts;
+    if !record.is_flaky() {
+      return record;
+    }
+    if attempts >= max_attempts {
+      record.status = record::RecordStatus::SystemError;
+      record.score = 0.;
+      record.message = format!("{} (failed after {} attempts)", record.message, attempts);
+      return record;
+    }
+    log::warn!(
+      "[{}] flaky result ({}), re-running the test: {}",
+      correlation_id,
+      record.status,
+      record.message
+    );
+    attempts += 1;
+  }
 }
 
 impl Test {
+  /// Returns a system error record if the answer has to be generated on the fly,
+  /// but it is forbidden by `JudgeCfg::lazy_answers`.
+  fn check_answer_ready(&self) -> Option<record::Record> {
+    if !CONFIG.judge.lazy_answers && matches!(self.answer, Answer::Generated) {
+      return Some(record::Record::new_system_error(
+        "answer file is not generated before judging",
+      ));
+    }
+    return None;
+  }
+
   /// Run a solution on a single test and return the record.
   async fn judge(
     &self,
     testset: &Testset,
     subtask_id: usize,
+    checker_seed: Option<u64>,
     solution: &program::Executable,
     standard_solution: &program::Executable,
     checker: &checker::Checker,
+    validator: Option<&validator::Validator>,
     time_limit: time::Duration,
     memory_limit: u64,
+    wall_time_limit: Option<time::Duration>,
+    time_kind: judge::TimeKind,
+    cpu_affinity: Option<&sandbox::CpuAffinity>,
+    stdout_limit: i64,
+    output: &judge::OutputSource,
     user_copy_in: &HashMap<String, sandbox::FileHandle>,
     judge_copy_in: &HashMap<String, sandbox::FileHandle>,
   ) -> record::Record {
+    if let Some(record) = self.check_answer_ready() {
+      return record;
+    }
+
     // Generate input file.
     let input_file = match self.input.make(user_copy_in.clone()).await {
       Ok(x) => x,
@@ -100,6 +268,18 @@ impl Test {
       }
     };
 
+    // Validate the generated input file.
+    if let (Input::Generated { .. }, Some(validator)) = (&self.input, validator) {
+      if let Err(err) = validator
+        .check(vec![], input_file.clone(), user_copy_in.clone())
+        .await
+      {
+        return record::Record::new_system_error(
+          &("generated input is invalid: ".to_string() + &err.message),
+        );
+      }
+    }
+
     // Runs the given solution while executing the standard solution to generate answer data.
     let (answer_file, execute_result) = futures::join!(
       self.answer.make(
@@ -107,14 +287,20 @@ impl Test {
         input_file.clone(),
         judge_copy_in.clone(),
         time_limit,
-        memory_limit
+        memory_limit,
+        output
       ),
       solution.judge_batch(
         vec![].clone(),
         input_file.clone(),
         judge_copy_in.clone(),
         time_limit,
-        memory_limit
+        memory_limit,
+        wall_time_limit,
+        time_kind,
+        cpu_affinity,
+        stdout_limit,
+        output
       ),
     );
 
@@ -129,7 +315,9 @@ impl Test {
 
     // Handle the situation where the solution program exits abnormally.
     if execute_result.0.status != sandbox::Status::Accepted {
-      return record::Record::new_interrupted(&execute_result.0);
+      return record::Record::new_interrupted(&execute_result.0)
+        .with_time_kind(time_kind)
+        .with_almost_tle(time_limit);
     }
 
     let output_file = execute_result.1.unwrap();
@@ -137,13 +325,9 @@ impl Test {
 
     // Run the checker to see if the output is correct.
     let checker_result = checker
-      .check(
-        vec![
-          "--testset".to_string(),
-          testset.to_string(),
-          "--group".to_string(),
-          subtask_id.to_string(),
-        ],
+      .check_seeded(
+        checker_seed,
+        checker_args(testset, subtask_id),
         input_file,
         output_file,
         answer_file,
@@ -152,7 +336,85 @@ impl Test {
       .await;
 
     match checker_result {
-      Ok(checker_output) => record::Record::new_checked(&sol_result, &checker_output),
+      Ok(run) => record::Record::new_checked(&sol_result, &run.output)
+        .with_time_kind(time_kind)
+        .with_checker_seed(checker_seed),
+      Err(err) => record::Record::new_system_error(
+        &("checker execute failed: ".to_string() + &err.to_string()),
+      ),
+    }
+  }
+
+  /// Check a submitted answer file of a submit answer problem on a single test
+  /// and return the record.
+  ///
+  /// Nothing is compiled or executed except making the input and answer files,
+  /// the submitted file is passed to the checker as the output directly.
+  async fn judge_submit_answer(
+    &self,
+    testset: &Testset,
+    subtask_id: usize,
+    checker_seed: Option<u64>,
+    output_file: Option<sandbox::FileHandle>,
+    standard_solution: &program::Executable,
+    checker: &checker::Checker,
+    time_limit: time::Duration,
+    memory_limit: u64,
+    output: &judge::OutputSource,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+    judge_copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> record::Record {
+    let output_file = match output_file {
+      Some(f) => f,
+      None => return record::Record::new_answer_missing(),
+    };
+
+    if let Some(record) = self.check_answer_ready() {
+      return record;
+    }
+
+    let input_file = match self.input.make(user_copy_in.clone()).await {
+      Ok(x) => x,
+      Err(err) => {
+        return record::Record::new_system_error(
+          &("input file generated failed: ".to_string() + &err.to_string()),
+        );
+      }
+    };
+
+    let answer_file = match self
+      .answer
+      .make(
+        standard_solution,
+        input_file.clone(),
+        judge_copy_in.clone(),
+        time_limit,
+        memory_limit,
+        output,
+      )
+      .await
+    {
+      Ok(f) => f,
+      Err(err) => {
+        return record::Record::new_system_error(
+          &("answer file generated failed: ".to_string() + &err.to_string()),
+        );
+      }
+    };
+
+    let checker_result = checker
+      .check_seeded(
+        checker_seed,
+        checker_args(testset, subtask_id),
+        input_file,
+        output_file,
+        answer_file,
+        user_copy_in.clone(),
+      )
+      .await;
+
+    match checker_result {
+      Ok(run) => record::Record::new_answer_checked(&run.output).with_checker_seed(checker_seed),
       Err(err) => record::Record::new_system_error(
         &("checker execute failed: ".to_string() + &err.to_string()),
       ),
@@ -160,47 +422,1091 @@ impl Test {
   }
 }
 
-impl Subtask {
-  /// Run a solution on a subtask and return the score of subtask and each test's record.
+impl Problem {
+  /// Run a solution on the subtasks of the given test sets in order,
+  /// and return the total score and each subtask's records.
   ///
-  /// The score is unscaled (in range \[0,1\]),
-  /// which means it will ignore the `score` felid of `self`．
+  /// Subtasks are judged in the order of `schedule`, and a subtask is skipped
+  /// if any subtask it depends on scored zero (including being skipped).
+  /// The records of a skipped subtask are sent on `status_tx` as if it were judged.
+  ///
+  /// Besides the per-test progress on `status_tx`, semantic milestones
+  /// (first accepted test, subtask completed, final verdict) are sent on `milestone_tx`.
+  ///
+  /// If the job is cancelled through `cancel`, judging stops after the running subtask
+  /// finishes its stream with a `Cancelled` response, and the partial result is returned.
+  ///
+  /// The tests not finished before the deadline of the job or the budget of their subtask
+  /// (see `etc::ProfileCfg::deadline`) are skipped, and the job finishes as usual.
   pub async fn judge(
     &self,
+    correlation_id: uuid::Uuid,
+    profile: &etc::ProfileCfg,
+    testsets: &[Testset],
     solution: &program::Executable,
     standard_solution: &program::Executable,
     checker: &checker::Checker,
+    validator: Option<&validator::Validator>,
     user_copy_in: &HashMap<String, sandbox::FileHandle>,
     judge_copy_in: &HashMap<String, sandbox::FileHandle>,
     status_tx: Option<mpsc::UnboundedSender<Response>>,
-  ) -> (f32, Vec<record::Record>) {
-    let records: Vec<_> =
-      stream::FuturesOrdered::from_iter(self.tests.iter().enumerate().map(|t| {
-        t.1.judge(
-          &self.testset,
-          self.id,
-          &solution,
+    milestone_tx: Option<mpsc::UnboundedSender<Milestone>>,
+    cancel: Option<CancelToken>,
+  ) -> (f32, Vec<(usize, Vec<record::Record>)>) {
+    let solution = &solution.clone().with_labels(&self.labels);
+    let standard_solution = &standard_solution.clone().with_labels(&self.labels);
+    let send = |milestone: Milestone| {
+      if let Some(tx) = &milestone_tx {
+        _ = tx.unbounded_send(milestone);
+      }
+    };
+
+    let mut first_accepted = false;
+    let mut scores = HashMap::new();
+    let mut total = 0.;
+    let mut provisional_score = 0.;
+    let mut final_score = 0.;
+    let mut verdict = record::RecordStatus::Accepted;
+    let mut ret = vec![];
+    let deadline = profile.deadline.map(|x| tokio::time::Instant::now() + x);
+
+    for subtask in self.schedule(testsets) {
+      let skip = if subtask
+        .dependences
+        .iter()
+        .any(|x| scores.get(x) == Some(&0.))
+      {
+        Some(record::SkipReason::Dependency)
+      } else if deadline.is_some_and(|x| x <= tokio::time::Instant::now()) {
+        Some(record::SkipReason::DeadlineExceeded)
+      } else {
+        None
+      };
+      let (score, records) = if skip.is_none() {
+        // Watch the progress of tests for the first accepted one, and forward it.
+        let (tx, mut rx) = mpsc::unbounded();
+        let forward = async {
+          while let Some(res) = rx.next().await {
+            if let Response::CompleteOne { record, .. } = &res {
+              if !first_accepted && record.status == record::RecordStatus::Accepted {
+                first_accepted = true;
+                send(Milestone::FirstAccepted {
+                  correlation_id,
+                  timestamp: record::Timestamp::now(),
+                  test_id: record.test_id.unwrap(),
+                });
+              }
+            }
+            if let Some(tx) = &status_tx {
+              _ = tx.unbounded_send(res);
+            }
+          }
+        };
+        let judge = subtask.judge(
+          correlation_id,
+          profile,
+          solution,
+          standard_solution,
+          checker,
+          validator,
+          &self.output,
+          user_copy_in,
+          judge_copy_in,
+          Some(tx),
+          cancel.clone(),
+          [
+            deadline,
+            profile
+              .subtask_budget
+              .map(|x| tokio::time::Instant::now() + x),
+          ]
+          .into_iter()
+          .flatten()
+          .min(),
+        );
+        futures::join!(judge, forward).0
+      } else {
+        log::debug!("[{}] subtask {} skipped", correlation_id, subtask.id);
+        let records: Vec<_> = (0..subtask.tests.len())
+          .map(|i| {
+            record::Record::new_skipped(skip.unwrap()).with_id(subtask.test_id(i), correlation_id)
+          })
+          .collect();
+        // Skipped subtasks resolve on the stream like judged ones.
+        if let Some(tx) = &status_tx {
+          for record in &records {
+            _ = tx.unbounded_send(Response::CompleteOne {
+              correlation_id,
+              timestamp: record::Timestamp::now(),
+              record: record.clone(),
+            });
+          }
+          _ = tx.unbounded_send(Response::SubtaskFinished {
+            correlation_id,
+            timestamp: record::Timestamp::now(),
+            subtask: subtask.id,
+            score: 0.,
+            records: records.clone(),
+          });
+        }
+        (0., records)
+      };
+
+      scores.insert(subtask.id, score);
+      let points = subtask.points(score);
+      total += points;
+      provisional_score += points * profile.weights.provisional_weight(&subtask.testset);
+      final_score += points * profile.weights.final_weight(&subtask.testset);
+      if verdict == record::RecordStatus::Accepted {
+        if let Some(x) = records
+          .iter()
+          .find(|x| x.status != record::RecordStatus::Accepted)
+        {
+          verdict = x.status.clone();
+        }
+      }
+      send(Milestone::SubtaskCompleted {
+        correlation_id,
+        timestamp: record::Timestamp::now(),
+        subtask: subtask.id,
+        score: points,
+      });
+      ret.push((subtask.id, records));
+
+      // The cancelled subtask has finished the stream, and no verdict is made.
+      if cancel.as_ref().is_some_and(|x| x.borrow().is_some()) {
+        return (total, ret);
+      }
+    }
+
+    send(Milestone::Verdict {
+      correlation_id,
+      timestamp: record::Timestamp::now(),
+      status: verdict,
+      score: total,
+    });
+    if let Some(tx) = &status_tx {
+      _ = tx.unbounded_send(Response::Finished {
+        correlation_id,
+        timestamp: record::Timestamp::now(),
+        score: total,
+        provisional_score,
+        final_score,
+        records: ret.iter().flat_map(|x| x.1.clone()).collect(),
+        environment: environment::ENVIRONMENT.clone(),
+      });
+    }
+
+    return (total, ret);
+  }
+
+  /// Check that solutions of the problem can be judged from source (by `judge_source`
+  /// or `prepare`), so callers can reject them up front.
+  ///
+  /// # Errors
+  ///
+  /// This function will return `JudgeError::Kind` if the problem is not a batch problem.
+  pub fn check_judgeable(&self) -> Result<(), JudgeError> {
+    if self.kind != Kind::Batch {
+      return Err(JudgeError::Kind(self.kind));
+    }
+    return Ok(());
+  }
+
+  /// Judge a solution from source, like `judge` but does the preparation as well:
+  /// uploads the extra files, and compiles the solution, the standard solution, the checker
+  /// and the validator with the settings of the problem.
+  ///
+  /// The compilation of each program is reported on `status_tx` too.
+  ///
+  /// To judge many solutions of the same problem, prepare it once with `prepare` instead.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the problem is not a batch problem,
+  /// or any of the programs does not compile.
+  pub async fn judge_source(
+    &self,
+    correlation_id: uuid::Uuid,
+    profile: &etc::ProfileCfg,
+    testsets: &[Testset],
+    solution: &program::Source,
+    status_tx: Option<mpsc::UnboundedSender<Response>>,
+    milestone_tx: Option<mpsc::UnboundedSender<Milestone>>,
+    cancel: Option<CancelToken>,
+  ) -> Result<(f32, Vec<(usize, Vec<record::Record>)>), JudgeError> {
+    self.check_judgeable()?;
+
+    let (user_copy_in, judge_copy_in) = futures::join!(
+      data::upload_all(&self.user_copy_in),
+      data::upload_all(&self.judge_copy_in)
+    );
+    let (solution_exec, standard_solution, checker, validator) = futures::join!(
+      compile_reported(
+        correlation_id,
+        &status_tx,
+        CompileTarget::Solution,
+        solution,
+        judge_copy_in.clone()
+      ),
+      compile_reported(
+        correlation_id,
+        &status_tx,
+        CompileTarget::StandardSolution,
+        &self.standard_solution,
+        judge_copy_in.clone()
+      ),
+      self.compile_checker(correlation_id, &status_tx, &user_copy_in),
+      self.compile_validator(correlation_id, &status_tx, &user_copy_in),
+    );
+    let solution_exec = solution_exec.map_err(JudgeError::Solution)?;
+    let standard_solution = standard_solution.map_err(JudgeError::StandardSolution)?;
+    let checker = checker.map_err(JudgeError::Checker)?;
+    let validator = validator.map_err(JudgeError::Validator)?;
+
+    return Ok(
+      self
+        .judge_compiled(
+          correlation_id,
+          profile,
+          testsets,
+          solution,
+          &solution_exec,
           &standard_solution,
           &checker,
-          self.time_limit,
-          self.memory_limit,
+          validator.as_ref(),
           &user_copy_in,
           &judge_copy_in,
+          status_tx,
+          milestone_tx,
+          cancel,
         )
-      }))
-      .then(|f| async {
-        if let Some(mut tx) = status_tx.clone() {
-          _ = tx.send(Response::CompleteOne { record: f.clone() });
+        .await,
+    );
+  }
+
+  /// Prepare the problem to judge solutions from source:
+  /// uploads the extra files, and compiles the standard solution, the checker and the validator.
+  ///
+  /// The compilation of each program is reported on `status_tx` too.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the problem is not a batch problem,
+  /// or any of the programs does not compile.
+  pub async fn prepare(
+    self,
+    correlation_id: uuid::Uuid,
+    status_tx: Option<mpsc::UnboundedSender<Response>>,
+  ) -> Result<PreparedProblem, JudgeError> {
+    self.check_judgeable()?;
+
+    let (user_copy_in, judge_copy_in) = futures::join!(
+      data::upload_all(&self.user_copy_in),
+      data::upload_all(&self.judge_copy_in)
+    );
+    let (standard_solution, checker, validator) = futures::join!(
+      compile_reported(
+        correlation_id,
+        &status_tx,
+        CompileTarget::StandardSolution,
+        &self.standard_solution,
+        judge_copy_in.clone()
+      ),
+      self.compile_checker(correlation_id, &status_tx, &user_copy_in),
+      self.compile_validator(correlation_id, &status_tx, &user_copy_in),
+    );
+    let standard_solution = standard_solution
+      .map_err(JudgeError::StandardSolution)?
+      .with_labels(&self.labels);
+    let checker = checker.map_err(JudgeError::Checker)?;
+    let validator = validator.map_err(JudgeError::Validator)?;
+
+    return Ok(PreparedProblem {
+      revision: self.revision(),
+      problem: self,
+      standard_solution,
+      checker,
+      validator,
+      user_copy_in,
+      judge_copy_in,
+    });
+  }
+
+  /// Compile the checker of the problem with its checker settings,
+  /// a builtin checker is ready at once and reports no compilation.
+  async fn compile_checker(
+    &self,
+    correlation_id: uuid::Uuid,
+    status_tx: &Option<mpsc::UnboundedSender<Response>>,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> Result<checker::Checker, error::CompileError> {
+    let kind = match &self.checker {
+      checker::Source::Program(source) => checker::Kind::Program(
+        compile_reported(
+          correlation_id,
+          status_tx,
+          CompileTarget::Checker,
+          source,
+          user_copy_in.clone(),
+        )
+        .await?,
+      ),
+      checker::Source::Builtin(x) => checker::Kind::Builtin(x.clone()),
+    };
+    return Ok(self.make_checker(kind));
+  }
+
+  /// Compile the validator of the problem if there is one.
+  async fn compile_validator(
+    &self,
+    correlation_id: uuid::Uuid,
+    status_tx: &Option<mpsc::UnboundedSender<Response>>,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> Result<Option<validator::Validator>, error::CompileError> {
+    let source = match &self.validator {
+      Some(x) => x,
+      None => return Ok(None),
+    };
+    let exec = compile_reported(
+      correlation_id,
+      status_tx,
+      CompileTarget::Validator,
+      source,
+      user_copy_in.clone(),
+    )
+    .await?;
+    return Ok(Some(validator::Validator::from(exec)));
+  }
+
+  /// Checker of the problem from its compiled kind, with the checker settings of the problem.
+  fn make_checker(&self, kind: checker::Kind) -> checker::Checker {
+    let mut checker =
+      checker::Checker::from(kind).with_presentation_error(self.presentation_error.clone());
+    if self.cache_checker {
+      checker = checker.with_cache(Arc::new(checker::Cache::default()));
+    }
+    return checker;
+  }
+
+  /// Judge a compiled solution, with the spot check of answers if enabled.
+  async fn judge_compiled(
+    &self,
+    correlation_id: uuid::Uuid,
+    profile: &etc::ProfileCfg,
+    testsets: &[Testset],
+    solution: &program::Source,
+    solution_exec: &program::Executable,
+    standard_solution: &program::Executable,
+    checker: &checker::Checker,
+    validator: Option<&validator::Validator>,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+    judge_copy_in: &HashMap<String, sandbox::FileHandle>,
+    status_tx: Option<mpsc::UnboundedSender<Response>>,
+    milestone_tx: Option<mpsc::UnboundedSender<Milestone>>,
+    cancel: Option<CancelToken>,
+  ) -> (f32, Vec<(usize, Vec<record::Record>)>) {
+    let checker = match self.seed_checker {
+      true => checker.clone().with_seed(self.checker_seed_key(solution)),
+      false => checker.clone(),
+    };
+
+    let rate = CONFIG.judge.spot_check_rate;
+    let (ret, drifts) = futures::join!(
+      self.judge(
+        correlation_id,
+        profile,
+        testsets,
+        solution_exec,
+        standard_solution,
+        &checker,
+        validator,
+        user_copy_in,
+        judge_copy_in,
+        status_tx.clone(),
+        milestone_tx,
+        cancel,
+      ),
+      async {
+        if rate <= 0. {
+          return vec![];
         }
-        f
+        return self
+          .spot_check_answers(
+            correlation_id.as_bytes(),
+            rate,
+            standard_solution,
+            &checker,
+            user_copy_in,
+            judge_copy_in,
+          )
+          .await;
+      },
+    );
+
+    for drift in drifts {
+      log::error!(
+        "[{}] answer of test {} drifted: {}",
+        correlation_id,
+        drift.test_id,
+        drift.message
+      );
+      if let Some(tx) = &status_tx {
+        _ = tx.unbounded_send(Response::AnswerDrift {
+          correlation_id,
+          timestamp: record::Timestamp::now(),
+          drift,
+        });
+      }
+    }
+
+    return ret;
+  }
+
+  /// Key of the checker seeds of a submission, see `seed_checker`.
+  ///
+  /// The problem is identified by the sources of its checker and standard solution,
+  /// and the submission by its source.
+  pub fn checker_seed_key(&self, solution: &program::Source) -> String {
+    let mut hasher = Sha256::new();
+    if let Some(x) = self.checker.builtin() {
+      hasher.update(format!("{:?}", x));
+      hasher.update([0]);
+    }
+    for source in self
+      .checker
+      .program()
+      .into_iter()
+      .chain([&self.standard_solution, solution])
+    {
+      hasher.update(source.lang.to_string());
+      hasher.update([0]);
+      hasher.update(source.data.fingerprint());
+      hasher.update([0]);
+    }
+    return hex::encode(hasher.finalize());
+  }
+
+  /// Revision of the problem, the hash of its programs, settings, tests and extra files,
+  /// which changes whenever the problem is rebuilt with different content.
+  ///
+  /// Generated inputs are identified by the executable of the generator and the arguments,
+  /// so a problem whose generators are compiled again has a new revision.
+  /// Scoring policies are not part of the revision.
+  pub fn revision(&self) -> String {
+    return digest(|update| {
+      self.hash_programs(update);
+      for subtask in &self.subtasks {
+        subtask.hash(update);
+      }
+      self.hash_files(update);
+    });
+  }
+
+  /// Revisions of the parts of the problem, to find the subtasks affected by a change,
+  /// see `Problem::revision`.
+  pub fn revisions(&self) -> Revisions {
+    return Revisions {
+      shared: digest(|update| {
+        self.hash_programs(update);
+        self.hash_files(update);
+      }),
+      subtasks: self
+        .subtasks
+        .iter()
+        .map(|subtask| digest(|update| subtask.hash(update)))
+        .collect(),
+    };
+  }
+
+  /// Hash the kind, programs and settings of the problem.
+  fn hash_programs(&self, update: &mut dyn FnMut(&[u8])) {
+    update(format!("{:?}", self.kind).as_bytes());
+    if let Some(x) = self.checker.builtin() {
+      update(format!("{:?}", x).as_bytes());
+    }
+    for source in self
+      .checker
+      .program()
+      .into_iter()
+      .chain([&self.standard_solution])
+      .chain(&self.interactor)
+      .chain(&self.validator)
+    {
+      update(source.lang.to_string().as_bytes());
+      update(&source.data.fingerprint());
+    }
+    update(
+      format!(
+        "{:?} {:?} {} {}",
+        self.output, self.presentation_error, self.cache_checker, self.seed_checker
+      )
+      .as_bytes(),
+    );
+  }
+
+  /// Hash the extra files of the problem.
+  fn hash_files(&self, update: &mut dyn FnMut(&[u8])) {
+    for copy_in in [&self.user_copy_in, &self.judge_copy_in] {
+      let mut files: Vec<_> = copy_in.iter().collect();
+      files.sort_by_key(|x| x.0);
+      for (name, data) in files {
+        update(name.as_bytes());
+        update(&data.fingerprint());
+      }
+    }
+  }
+
+  /// Re-generate a sample of the stored answers with the standard solution,
+  /// and check the stored answers against them with the checker.
+  ///
+  /// Each test with a plain answer is sampled with probability `rate`, decided by hashing
+  /// `seed` and the test id, so the same seed always samples the same tests.
+  ///
+  /// Returns the tests whose stored answer is not accepted (or can not be re-generated),
+  /// which means the test data is corrupted or stale.
+  pub async fn spot_check_answers(
+    &self,
+    seed: &[u8],
+    rate: f64,
+    standard_solution: &program::Executable,
+    checker: &checker::Checker,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+    judge_copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> Vec<AnswerDrift> {
+    let checks: Vec<_> = self
+      .subtasks
+      .iter()
+      .flat_map(|subtask| {
+        subtask
+          .tests
+          .iter()
+          .enumerate()
+          .map(move |(index, test)| (subtask, subtask.test_id(index), test))
+      })
+      .filter(|(_, test_id, test)| {
+        matches!(test.answer, Answer::Plain { .. }) && sampled(seed, test_id, rate)
       })
+      .map(|(subtask, test_id, test)| async move {
+        let (time_limit, memory_limit) = subtask.limits(test);
+        let drift = |message: String| Some(AnswerDrift { test_id, message });
+
+        let input = match test.input.make(user_copy_in.clone()).await {
+          Ok(x) => x,
+          Err(err) => return drift(format!("input file generated failed: {}", err)),
+        };
+        let (stored, generated) = futures::join!(
+          test.answer.make(
+            standard_solution,
+            input.clone(),
+            judge_copy_in.clone(),
+            time_limit,
+            memory_limit,
+            &self.output,
+          ),
+          Answer::Generated.make(
+            standard_solution,
+            input.clone(),
+            judge_copy_in.clone(),
+            time_limit,
+            memory_limit,
+            &self.output,
+          ),
+        );
+        let (stored, generated) = match (stored, generated) {
+          (Ok(x), Ok(y)) => (x, y),
+          (Err(err), _) | (_, Err(err)) => {
+            return drift(format!("answer file generated failed: {}", err))
+          }
+        };
+
+        // The re-generated answer takes the place of the output, checked against the stored one.
+        return match checker
+          .check(
+            checker_args(&subtask.testset, subtask.id),
+            input,
+            generated,
+            stored,
+            user_copy_in.clone(),
+          )
+          .await
+        {
+          Ok(x) if x.output.status == checker::Status::Accepted => None,
+          Ok(x) => drift(format!("{}: {}", x.output.status, x.output.message)),
+          Err(err) => drift(format!("checker execute failed: {}", err)),
+        };
+      })
+      .collect();
+
+    // The futures are collected beforehand, so the stream does not hold closures over borrows,
+    // which keeps the future of judging `Send`.
+    let drifts: Vec<_> = stream::iter(checks)
+      .buffer_unordered(CONFIG.judge.answer_concurrency.max(1))
       .collect()
       .await;
+    return drifts.into_iter().flatten().collect();
+  }
 
-    let score = records.iter().fold(1f32, |a, b| a.min(b.score));
+  /// Re-generate all the stored answers with the standard solution,
+  /// and check the stored answers against them with the checker.
+  ///
+  /// It is the build-time counterpart of `spot_check_answers`, to catch answer files
+  /// supplied by the setter which the standard solution does not agree with.
+  ///
+  /// Returns the tests whose stored answer is not accepted (or can not be re-generated).
+  pub async fn check_answers(
+    &self,
+    standard_solution: &program::Executable,
+    checker: &checker::Checker,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+    judge_copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> Vec<AnswerDrift> {
+    return self
+      .spot_check_answers(
+        &[],
+        1.,
+        standard_solution,
+        checker,
+        user_copy_in,
+        judge_copy_in,
+      )
+      .await;
+  }
+
+  /// Generate the answers of all tests with the standard solution in bulk,
+  /// and store them in the problem as plain answers.
+  ///
+  /// It runs at most `JudgeCfg::answer_concurrency` standard solutions at the same time,
+  /// so that answers of huge test sets can be made once when building the problem,
+  /// instead of on every submission.
+  ///
+  /// Answers are checked against the answer limits, and the answer of a failed test
+  /// is kept generated. Returns the id of each failed test and the reason.
+  pub async fn generate_answers(
+    &mut self,
+    standard_solution: &program::Executable,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+    judge_copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> Vec<(record::TestId, AnswerVerifyError)> {
+    let this = &*self;
+    let tests = this.subtasks.iter().enumerate().flat_map(|(i, subtask)| {
+      subtask
+        .tests
+        .iter()
+        .enumerate()
+        .filter(|(_, test)| matches!(test.answer, Answer::Generated))
+        .map(move |(j, test)| (i, j, subtask, test))
+    });
+    let results: Vec<_> = stream::iter(tests)
+      .map(|(i, j, subtask, test)| async move {
+        let res = subtask
+          .make_test(
+            test,
+            standard_solution,
+            &this.answer_limits,
+            &this.output,
+            user_copy_in,
+            judge_copy_in,
+          )
+          .await;
+        (i, j, res)
+      })
+      .buffer_unordered(CONFIG.judge.answer_concurrency.max(1))
+      .collect()
+      .await;
+
+    let mut errors = vec![];
+    for (i, j, res) in results {
+      match res {
+        Ok((_, answer)) => {
+          self.subtasks[i].tests[j].answer = Answer::Plain { context: answer };
+        }
+        Err(err) => errors.push((self.subtasks[i].test_id(j), err)),
+      }
+    }
+    errors.sort_by_key(|x| (x.0.testset as usize, x.0.subtask, x.0.index));
+    return errors;
+  }
+
+  /// Subtasks of the given test sets in the order of judging.
+  ///
+  /// A subtask comes after the subtasks it depends on, otherwise the original order is kept.
+  /// Dependencies out of the test sets are ignored, and so are dependency cycles.
+  pub fn schedule(&self, testsets: &[Testset]) -> Vec<&Subtask> {
+    let mut pending: Vec<_> = self
+      .subtasks
+      .iter()
+      .filter(|x| testsets.contains(&x.testset))
+      .collect();
+    let ids: HashSet<_> = pending.iter().map(|x| x.id).collect();
+    let mut scheduled = HashSet::new();
+    let mut ret = vec![];
+
+    while !pending.is_empty() {
+      let index = pending
+        .iter()
+        .position(|x| {
+          x.dependences
+            .iter()
+            .all(|x| scheduled.contains(x) || !ids.contains(x))
+        })
+        .unwrap_or(0);
+      let subtask = pending.remove(index);
+      scheduled.insert(subtask.id);
+      ret.push(subtask);
+    }
+
+    return ret;
+  }
+
+  /// Append the input of a successful hack to the hack test set,
+  /// and return the id of the new test.
+  ///
+  /// Hack tests are kept in a single subtask worth no score.
+  /// If the problem has no such subtask, it is created with the limits of the main tests.
+  pub fn add_hack(&mut self, input: Input) -> record::TestId {
+    let index = match self
+      .subtasks
+      .iter()
+      .position(|x| x.testset == Testset::Hack)
+    {
+      Some(x) => x,
+      None => {
+        let main = self.subtasks.iter().find(|x| x.testset == Testset::Main);
+        let (time_limit, memory_limit, wall_time_limit) = main.map_or(
+          (CONFIG.judge.time_limit, CONFIG.judge.memory_limit, None),
+          |x| (x.time_limit, x.memory_limit, x.wall_time_limit),
+        );
+        let time_kind = main.map_or(judge::TimeKind::Cpu, |x| x.time_kind);
+        let cpu_affinity = main.and_then(|x| x.cpu_affinity.clone());
+        self.subtasks.push(Subtask {
+          id: self.subtasks.iter().map(|x| x.id + 1).max().unwrap_or(0),
+          score: 0.,
+          dependences: vec![],
+          testset: Testset::Hack,
+          tests: vec![],
+          time_limit,
+          memory_limit,
+          wall_time_limit,
+          time_kind,
+          cpu_affinity,
+          scoring: ScoringPolicy::Min,
+        });
+        self.subtasks.len() - 1
+      }
+    };
+
+    let subtask = &mut self.subtasks[index];
+    subtask.tests.push(Test {
+      input,
+      answer: Answer::Generated,
+      time_limit: None,
+      memory_limit: None,
+    });
+    return subtask.test_id(subtask.tests.len() - 1);
+  }
+}
+
+impl Subtask {
+  /// Hash the settings and tests of the subtask, see `Problem::revision`.
+  fn hash(&self, update: &mut dyn FnMut(&[u8])) {
+    update(
+      format!(
+        "{} {} {:?} {} {:?} {}",
+        self.id, self.score, self.dependences, self.testset, self.time_limit, self.memory_limit
+      )
+      .as_bytes(),
+    );
+    // Only hashed if set, so the revisions of the other problems stay the same.
+    if self.time_kind != judge::TimeKind::Cpu {
+      update(format!("{:?}", self.time_kind).as_bytes());
+    }
+    for test in &self.tests {
+      match &test.input {
+        Input::Plain { context } => update(context),
+        Input::Generated { generator, args } => {
+          update(generator.exec.file.id().as_bytes());
+          update(args.join(" ").as_bytes());
+        }
+      }
+      match &test.answer {
+        Answer::Plain { context } => update(context),
+        Answer::Generated => update(&[]),
+      }
+      update(format!("{:?} {:?}", test.time_limit, test.memory_limit).as_bytes());
+    }
+  }
+
+  /// Run a solution on a subtask and return the score of subtask and each test's record.
+  ///
+  /// The score is unscaled (in range \[0,1\]) and aggregated by the scoring policy,
+  /// which means it will ignore the `score` felid of `self`．
+  /// For the `Points` policy, it is the sum of the points of the tests (see `points`).
+  ///
+  /// Every record and response event is tagged with `correlation_id`,
+  /// which should be unique for each judge job.
+  ///
+  /// The limits of each test (see `limits`) are capped by the ceilings of the judging `profile`,
+  /// and the time limit is then scaled by the speed factor of sandbox.
+  ///
+  /// The tests not finished before `deadline` are skipped (see `collect_records`).
+  pub async fn judge(
+    &self,
+    correlation_id: uuid::Uuid,
+    profile: &etc::ProfileCfg,
+    solution: &program::Executable,
+    standard_solution: &program::Executable,
+    checker: &checker::Checker,
+    validator: Option<&validator::Validator>,
+    output: &judge::OutputSource,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+    judge_copy_in: &HashMap<String, sandbox::FileHandle>,
+    status_tx: Option<mpsc::UnboundedSender<Response>>,
+    cancel: Option<CancelToken>,
+    deadline: Option<tokio::time::Instant>,
+  ) -> (f32, Vec<record::Record>) {
+    let stdout_limit = profile.stdout_limit();
+    let wall_time_limit = self.scaled_wall_time_limit();
+
+    self
+      .collect_records(
+        correlation_id,
+        status_tx,
+        cancel,
+        deadline,
+        |index, test| async move {
+          let (time_limit, memory_limit) = self.limits(test);
+          let (time_limit, memory_limit) = profile.limit(time_limit, memory_limit);
+          let time_limit = CONFIG.sandbox.scale_time_limit(time_limit);
+          let checker_seed = checker.seed(&self.test_id(index));
+          retry_flaky(correlation_id, || {
+            test.judge(
+              &self.testset,
+              self.id,
+              checker_seed,
+              solution,
+              standard_solution,
+              checker,
+              validator,
+              time_limit,
+              memory_limit,
+              wall_time_limit,
+              self.time_kind,
+              self.cpu_affinity.as_ref(),
+              stdout_limit,
+              output,
+              user_copy_in,
+              judge_copy_in,
+            )
+          })
+          .await
+        },
+      )
+      .await
+  }
+
+  /// Check the submitted answer files of a submit answer problem on a subtask,
+  /// and return the score of subtask and each test's record.
+  ///
+  /// `answers` are the submitted files of each test,
+  /// a test without submitted file is recorded as `FileError`.
+  pub async fn judge_submit_answer(
+    &self,
+    correlation_id: uuid::Uuid,
+    answers: &HashMap<record::TestId, sandbox::FileHandle>,
+    standard_solution: &program::Executable,
+    checker: &checker::Checker,
+    output: &judge::OutputSource,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+    judge_copy_in: &HashMap<String, sandbox::FileHandle>,
+    status_tx: Option<mpsc::UnboundedSender<Response>>,
+    cancel: Option<CancelToken>,
+  ) -> (f32, Vec<record::Record>) {
+    self
+      .collect_records(
+        correlation_id,
+        status_tx,
+        cancel,
+        None,
+        |index, test| async move {
+          let (time_limit, memory_limit) = self.limits(test);
+          test
+            .judge_submit_answer(
+              &self.testset,
+              self.id,
+              checker.seed(&self.test_id(index)),
+              answers.get(&self.test_id(index)).cloned(),
+              standard_solution,
+              checker,
+              time_limit,
+              memory_limit,
+              output,
+              user_copy_in,
+              judge_copy_in,
+            )
+            .await
+        },
+      )
+      .await
+  }
 
-    if let Some(mut tx) = status_tx.clone() {
-      _ = tx.send(Response::Finished {
+  /// Time and memory limits of a test in the subtask,
+  /// the limits set on the test override the limits of the subtask.
+  pub fn limits(&self, test: &Test) -> (time::Duration, u64) {
+    return (
+      test.time_limit.unwrap_or(self.time_limit),
+      test.memory_limit.unwrap_or(self.memory_limit),
+    );
+  }
+
+  /// Wall clock time limit of the tests scaled by the speed factor of sandbox,
+  /// see `Subtask::wall_time_limit`.
+  pub fn scaled_wall_time_limit(&self) -> Option<time::Duration> {
+    return self
+      .wall_time_limit
+      .map(|x| CONFIG.sandbox.scale_time_limit(x));
+  }
+
+  /// Points of the subtask from its score returned by `judge`,
+  /// the score scaled by the full score of the subtask, or as is for the `Points` policy.
+  pub fn points(&self, score: f32) -> f32 {
+    return match self.scoring {
+      ScoringPolicy::Points => score,
+      _ => score * self.score,
+    };
+  }
+
+  /// Identifier of the test at `index` of the subtask.
+  pub fn test_id(&self, index: usize) -> record::TestId {
+    record::TestId {
+      testset: self.testset,
+      subtask: self.id,
+      index,
+    }
+  }
+
+  /// Run `judge` on each test, tag and stream the records,
+  /// and then return the score of subtask and each test's record.
+  ///
+  /// If the job is cancelled, the stream is finished by a `Cancelled` response with the records
+  /// completed so far, and the unfinished tests are recorded as skipped with no score.
+  /// The running tests are dropped at once, which aborts their sandbox requests
+  /// (see `sandbox::Request::exec`), so a cancelled job does not keep the sandbox busy.
+  ///
+  /// If `deadline` is reached, the unfinished tests are recorded as skipped in the same way,
+  /// but the subtask is finished as usual with the score of the records.
+  async fn collect_records<'a, F, Fut>(
+    &'a self,
+    correlation_id: uuid::Uuid,
+    status_tx: Option<mpsc::UnboundedSender<Response>>,
+    mut cancel: Option<CancelToken>,
+    deadline: Option<tokio::time::Instant>,
+    judge: F,
+  ) -> (f32, Vec<record::Record>)
+  where
+    F: Fn(usize, &'a Test) -> Fut,
+    Fut: Future<Output = record::Record>,
+  {
+    let judge = &judge;
+    let status_tx_ref = &status_tx;
+    let tests =
+      stream::FuturesOrdered::from_iter(self.tests.iter().enumerate().map(|t| async move {
+        let started_at = record::Timestamp::now();
+        if let Some(tx) = status_tx_ref {
+          _ = tx.unbounded_send(Response::TestStarted {
+            correlation_id,
+            timestamp: started_at,
+            test_id: self.test_id(t.0),
+          });
+        }
+        judge(t.0, t.1)
+          .await
+          .with_id(self.test_id(t.0), correlation_id)
+          .with_time_range(started_at, record::Timestamp::now())
+      }))
+      .then(|f| async {
+        log::debug!(
+          "[{}] test {} finished: {}",
+          correlation_id,
+          f.test_id.unwrap(),
+          f.status
+        );
+        if let Some(tx) = &status_tx {
+          _ = tx.unbounded_send(Response::CompleteOne {
+            correlation_id,
+            timestamp: record::Timestamp::now(),
+            record: f.clone(),
+          });
+        }
+        f
+      });
+    tokio::pin!(tests);
+
+    let mut records = vec![];
+    loop {
+      tokio::select! {
+        // Check the cancellation first, so no more test is recorded after it.
+        biased;
+        cancellation = cancelled(&mut cancel) => {
+          log::debug!(
+            "[{}] subtask {} cancelled: {:?}",
+            correlation_id,
+            self.id,
+            cancellation.reason
+          );
+          if let Some(tx) = &status_tx {
+            _ = tx.unbounded_send(Response::Cancelled {
+              correlation_id,
+              timestamp: record::Timestamp::now(),
+              reason: cancellation.reason,
+              by: cancellation.by,
+              records: records.clone(),
+            });
+          }
+          for index in records.len()..self.tests.len() {
+            records.push(
+              record::Record::new_skipped(record::SkipReason::Cancelled)
+                .with_id(self.test_id(index), correlation_id),
+            );
+          }
+          return (0., records);
+        }
+        _ = deadline_reached(deadline) => {
+          log::debug!(
+            "[{}] subtask {} exceeded the deadline",
+            correlation_id,
+            self.id
+          );
+          for index in records.len()..self.tests.len() {
+            records.push(
+              record::Record::new_skipped(record::SkipReason::DeadlineExceeded)
+                .with_id(self.test_id(index), correlation_id),
+            );
+          }
+          break;
+        }
+        record = tests.next() => match record {
+          Some(x) => records.push(x),
+          None => break,
+        },
+      }
+    }
+
+    let score = self.scoring.aggregate(
+      &records
+        .iter()
+        .map(|x| match self.scoring {
+          ScoringPolicy::Points => x.points(),
+          _ => x.score,
+        })
+        .collect::<Vec<_>>(),
+    );
+
+    if let Some(tx) = &status_tx {
+      _ = tx.unbounded_send(Response::SubtaskFinished {
+        correlation_id,
+        timestamp: record::Timestamp::now(),
+        subtask: self.id,
         score,
         records: records.clone(),
       });
@@ -208,17 +1514,543 @@ impl Subtask {
 
     return (score, records);
   }
+
+  /// Make the input and answer of every test with the standard solution,
+  /// and check the answers against the answer limits.
+  ///
+  /// Returns the contents of input and answer of each test in order.
+  pub async fn make_tests(
+    &self,
+    standard_solution: &program::Executable,
+    limits: &AnswerLimits,
+    output: &judge::OutputSource,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+    judge_copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> Vec<Result<(Vec<u8>, Vec<u8>), AnswerVerifyError>> {
+    stream::iter(self.tests.iter())
+      .then(|test| {
+        self.make_test(
+          test,
+          standard_solution,
+          limits,
+          output,
+          user_copy_in,
+          judge_copy_in,
+        )
+      })
+      .collect()
+      .await
+  }
+
+  /// Make the input and answer of a test in the subtask,
+  /// and check the answer against the answer limits.
+  async fn make_test(
+    &self,
+    test: &Test,
+    standard_solution: &program::Executable,
+    limits: &AnswerLimits,
+    output: &judge::OutputSource,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+    judge_copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> Result<(Vec<u8>, Vec<u8>), AnswerVerifyError> {
+    let (time_limit, memory_limit) = self.limits(test);
+    let input_file = test
+      .input
+      .make(user_copy_in.clone())
+      .await
+      .map_err(AnswerVerifyError::Input)?;
+    let answer_file = test
+      .answer
+      .make(
+        standard_solution,
+        input_file.clone(),
+        judge_copy_in.clone(),
+        time_limit,
+        memory_limit,
+        output,
+      )
+      .await
+      .map_err(AnswerVerifyError::Answer)?;
+    let (input, answer) = match futures::join!(input_file.context(), answer_file.context()) {
+      (Ok(input), Ok(answer)) => (input, answer),
+      (Err(err), _) | (_, Err(err)) => return Err(AnswerVerifyError::Sandbox(err.to_string())),
+    };
+    limits.check(&answer)?;
+    return Ok((input, answer));
+  }
+
+  /// Make the answer of every test with the standard solution,
+  /// and check them against the answer limits.
+  ///
+  /// Returns the index of each failed test and the reason,
+  /// an empty vector means all the answers are fine.
+  pub async fn verify_answers(
+    &self,
+    standard_solution: &program::Executable,
+    limits: &AnswerLimits,
+    output: &judge::OutputSource,
+    user_copy_in: &HashMap<String, sandbox::FileHandle>,
+    judge_copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> Vec<(usize, AnswerVerifyError)> {
+    self
+      .make_tests(
+        standard_solution,
+        limits,
+        output,
+        user_copy_in,
+        judge_copy_in,
+      )
+      .await
+      .into_iter()
+      .enumerate()
+      .filter_map(|(i, res)| res.err().map(|err| (i, err)))
+      .collect()
+  }
+}
+
+/// Error when a solution can not be judged on a problem.
+#[derive(Debug, Error, Clone)]
+pub enum JudgeError {
+  #[error("judging {0:?} problems from source is not supported")]
+  Kind(Kind),
+
+  #[error("solution: {0}")]
+  Solution(error::CompileError),
+
+  #[error("standard solution: {0}")]
+  StandardSolution(error::CompileError),
+
+  #[error("checker: {0}")]
+  Checker(error::CompileError),
+
+  #[error("validator: {0}")]
+  Validator(error::CompileError),
+}
+
+/// Error when verifying an answer file of a test.
+#[derive(Debug, Error, Clone)]
+pub enum AnswerVerifyError {
+  #[error("input file generated failed: {0}")]
+  Input(error::RuntimeError),
+
+  #[error("answer file generated failed: {0}")]
+  Answer(error::RuntimeError),
+
+  #[error("answer file can not be read: {0}")]
+  Sandbox(String),
+
+  #[error(transparent)]
+  Limit(#[from] AnswerLimitError),
+}
+
+/// A problem ready to judge solutions from source, made by `Problem::prepare`.
+///
+/// The extra files are uploaded and kept in the sandbox, and the standard solution,
+/// the checker and the validator are compiled, so it can be reused by many judge jobs of the problem.
+pub struct PreparedProblem {
+  pub problem: Problem,
+
+  /// Revision of the problem when it was prepared, see `Problem::revision`.
+  pub revision: String,
+
+  pub standard_solution: program::Executable,
+
+  pub checker: checker::Checker,
+
+  pub validator: Option<validator::Validator>,
+
+  pub user_copy_in: HashMap<String, sandbox::FileHandle>,
+
+  pub judge_copy_in: HashMap<String, sandbox::FileHandle>,
+}
+
+impl PreparedProblem {
+  /// Compile a solution with the extra files of the problem without judging it,
+  /// to check that it compiles before it is submitted.
+  ///
+  /// A compile error is an outcome of the check rather than an error,
+  /// and the outcome is also sent as a `Response::Compiled` after the compile events.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the executable can not be read from the sandbox.
+  pub async fn compile_only(
+    &self,
+    correlation_id: uuid::Uuid,
+    solution: &program::Source,
+    status_tx: Option<mpsc::UnboundedSender<Response>>,
+  ) -> Result<program::CompileCheck, sandbox::Error> {
+    let check = match compile_reported(
+      correlation_id,
+      &status_tx,
+      CompileTarget::Solution,
+      solution,
+      self.judge_copy_in.clone(),
+    )
+    .await
+    {
+      Ok(exec) => program::CompileCheck {
+        executable: Some(exec.file.sha256().await?),
+        diagnostics: checker::truncate_message(&exec.message, CONFIG.judge.compile_message_limit),
+        result: program::CompileResult {
+          status: sandbox::Status::Accepted,
+          exit_code: 0,
+          report: exec.report,
+        },
+      },
+      Err(err) => program::CompileCheck {
+        executable: None,
+        diagnostics: err.diagnostics(),
+        result: program::CompileResult::from(&err.result),
+      },
+    };
+    if let Some(tx) = status_tx {
+      _ = tx.unbounded_send(Response::Compiled {
+        correlation_id,
+        timestamp: record::Timestamp::now(),
+        check: check.clone(),
+      });
+    }
+    return Ok(check);
+  }
+
+  /// Judge a solution from source, like `Problem::judge_source`
+  /// but only the solution is compiled.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the solution does not compile.
+  pub async fn judge_source(
+    &self,
+    correlation_id: uuid::Uuid,
+    profile: &etc::ProfileCfg,
+    testsets: &[Testset],
+    solution: &program::Source,
+    status_tx: Option<mpsc::UnboundedSender<Response>>,
+    milestone_tx: Option<mpsc::UnboundedSender<Milestone>>,
+    cancel: Option<CancelToken>,
+  ) -> Result<(f32, Vec<(usize, Vec<record::Record>)>), JudgeError> {
+    let solution_exec = compile_reported(
+      correlation_id,
+      &status_tx,
+      CompileTarget::Solution,
+      solution,
+      self.judge_copy_in.clone(),
+    )
+    .await
+    .map_err(JudgeError::Solution)?;
+
+    return Ok(
+      self
+        .problem
+        .judge_compiled(
+          correlation_id,
+          profile,
+          testsets,
+          solution,
+          &solution_exec,
+          &self.standard_solution,
+          &self.checker,
+          self.validator.as_ref(),
+          &self.user_copy_in,
+          &self.judge_copy_in,
+          status_tx,
+          milestone_tx,
+          cancel,
+        )
+        .await,
+    );
+  }
+
+  /// Approximate size of the problem data held in memory, in bytes.
+  ///
+  /// It counts the plain tests, the extra files and the sources,
+  /// which take up nearly all the memory of a problem.
+  pub fn size(&self) -> u64 {
+    let problem = &self.problem;
+    let tests: usize = problem
+      .subtasks
+      .iter()
+      .flat_map(|x| &x.tests)
+      .map(|test| {
+        let input = match &test.input {
+          Input::Plain { context } => context.len(),
+          Input::Generated { .. } => 0,
+        };
+        let answer = match &test.answer {
+          Answer::Plain { context } => context.len(),
+          Answer::Generated => 0,
+        };
+        input + answer
+      })
+      .sum();
+    let files: usize = problem
+      .user_copy_in
+      .values()
+      .chain(problem.judge_copy_in.values())
+      .map(|x| x.memory_size())
+      .sum();
+    let sources: usize = problem
+      .checker
+      .program()
+      .into_iter()
+      .chain([&problem.standard_solution])
+      .chain(&problem.interactor)
+      .chain(&problem.validator)
+      .map(|x| x.data.memory_size())
+      .sum();
+    return (tests + files + sources) as u64;
+  }
+}
+
+/// Compile a program of a problem, and report the compilation on `status_tx`.
+async fn compile_reported(
+  correlation_id: uuid::Uuid,
+  status_tx: &Option<mpsc::UnboundedSender<Response>>,
+  program: CompileTarget,
+  source: &program::Source,
+  copy_in: HashMap<String, sandbox::FileHandle>,
+) -> Result<program::Executable, error::CompileError> {
+  let send = |res: Response| {
+    if let Some(tx) = status_tx {
+      _ = tx.unbounded_send(res);
+    }
+  };
+  send(Response::Compiling {
+    correlation_id,
+    timestamp: record::Timestamp::now(),
+    program,
+  });
+  let ret = source.compile(vec![], copy_in).await;
+  send(Response::CompileFinished {
+    correlation_id,
+    timestamp: record::Timestamp::now(),
+    program,
+    report: match &ret {
+      Ok(x) => x.report,
+      Err(err) => err.report(),
+    },
+    error: ret.as_ref().err().map(|x| x.to_string()),
+  });
+  if let Err(err) = &ret {
+    send(Response::CompileError {
+      correlation_id,
+      timestamp: record::Timestamp::now(),
+      program,
+      message: err.diagnostics(),
+      result: program::CompileResult::from(&err.result),
+    });
+  }
+  return ret;
+}
+
+/// A stored answer which does not match the answer re-generated by the standard solution.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnswerDrift {
+  pub test_id: record::TestId,
+
+  /// Why the stored answer is not accepted.
+  pub message: String,
+}
+
+/// Verify a file generated in the sandbox if `judge.verify_copy_out` is enabled (in config).
+///
+/// A mismatch is a system error of the program generating the file.
+async fn verify_copy_out(file: &sandbox::FileHandle) -> Result<(), error::RuntimeError> {
+  if !CONFIG.judge.verify_copy_out {
+    return Ok(());
+  }
+  return file
+    .verify()
+    .await
+    .map_err(|err| error::RuntimeError::from(err.clone()).with_stderr(err.to_string().as_bytes()));
+}
+
+/// Whether a test is in the sample of `rate` decided by `seed`.
+pub(crate) fn sampled(seed: &[u8], test_id: &record::TestId, rate: f64) -> bool {
+  if rate >= 1. {
+    return true;
+  }
+  let hash = Sha256::new()
+    .chain_update(seed)
+    .chain_update(test_id.to_string())
+    .finalize();
+  let value = u64::from_be_bytes(hash[..8].try_into().unwrap());
+  return (value as f64) < rate * u64::MAX as f64;
 }
 
 /// Judgement status of an entire problem.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(tag = "type")]
 pub enum Response {
+  /// A program started compiling.
+  Compiling {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    program: CompileTarget,
+  },
+  /// A program finished compiling, with the compile error message if failed.
+  CompileFinished {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    program: CompileTarget,
+    report: program::CompileReport,
+    error: Option<String>,
+  },
+  /// A program failed to compile, sent after its `CompileFinished`,
+  /// with the compiler diagnostics limited to `judge.compile_message_limit` (in config) bytes.
+  CompileError {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    program: CompileTarget,
+    message: String,
+    result: program::CompileResult,
+  },
+  /// A single test case started judging.
+  TestStarted {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    test_id: record::TestId,
+  },
   /// A single test case judge finished.
-  CompleteOne { record: record::Record },
-  /// The subject assessment is completed.
+  CompleteOne {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    record: record::Record,
+  },
+  /// All tests of a subtask are judged, with the unscaled score of the subtask.
+  SubtaskFinished {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    subtask: usize,
+    score: f32,
+    records: Vec<record::Record>,
+  },
+  /// The whole problem is judged, with the total score and the records of all subtasks.
   Finished {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
     score: f32,
+    /// Total score with the provisional weights of the profile (see `etc::ScoreWeights`).
+    provisional_score: f32,
+    /// Total score with the final weights of the profile (see `etc::ScoreWeights`).
+    final_score: f32,
     records: Vec<record::Record>,
+    environment: environment::Environment,
+  },
+  /// A solution is compiled without being judged (see `PreparedProblem::compile_only`).
+  Compiled {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    #[serde(flatten)]
+    check: program::CompileCheck,
+  },
+  /// The judge job is cancelled, with the records completed before the cancellation.
+  Cancelled {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    reason: CancelReason,
+    /// Who cancelled the job (e.g. the name of an admin), None if it is cancelled by the system.
+    by: Option<String>,
+    records: Vec<record::Record>,
+  },
+  /// A stored answer drifted from the standard solution, found by the spot check.
+  AnswerDrift {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    drift: AnswerDrift,
+  },
+}
+
+/// Program compiled before judging.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompileTarget {
+  Solution,
+  StandardSolution,
+  Checker,
+  Validator,
+}
+
+/// Why a judge job is cancelled.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelReason {
+  /// Cancelled by an administrator.
+  Admin,
+  /// The quota of the submitter is exhausted.
+  Quota,
+  /// The judge is shutting down.
+  Shutdown,
+}
+
+/// Cancellation of a judge job.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Cancellation {
+  pub reason: CancelReason,
+  pub by: Option<String>,
+}
+
+/// Receiver of the cancellation of judge jobs, created by `cancel_channel`.
+pub type CancelToken = watch::Receiver<Option<Cancellation>>;
+
+/// Create a channel to cancel judge jobs,
+/// sending `Some` cancellation cancels every job holding a token of it.
+pub fn cancel_channel() -> (watch::Sender<Option<Cancellation>>, CancelToken) {
+  return watch::channel(None);
+}
+
+/// Wait until the deadline, never resolves without a deadline.
+async fn deadline_reached(deadline: Option<tokio::time::Instant>) {
+  match deadline {
+    Some(x) => tokio::time::sleep_until(x).await,
+    None => futures::future::pending().await,
+  }
+}
+
+/// Wait until the job is cancelled, never resolves without a token.
+async fn cancelled(cancel: &mut Option<CancelToken>) -> Cancellation {
+  if let Some(rx) = cancel {
+    loop {
+      let cancellation = rx.borrow().clone();
+      if let Some(x) = cancellation {
+        return x;
+      }
+      if rx.changed().await.is_err() {
+        break;
+      }
+    }
+  }
+  return futures::future::pending().await;
+}
+
+/// Semantic milestone of a judge job, for integrations like scoreboards
+/// which do not care about the progress of every test.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Milestone {
+  /// The first test accepted in the judge job.
+  FirstAccepted {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    test_id: record::TestId,
+  },
+
+  /// A subtask is judged or skipped, with its scaled score.
+  SubtaskCompleted {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    subtask: usize,
+    score: f32,
+  },
+
+  /// All subtasks are completed.
+  ///
+  /// The status is the first non-accepted status of the records, or accepted.
+  Verdict {
+    correlation_id: uuid::Uuid,
+    timestamp: record::Timestamp,
+    status: record::RecordStatus,
+    score: f32,
   },
 }
@@ -0,0 +1,719 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  fmt, path,
+  str::FromStr,
+  time,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+
+use crate::{builtin, checker, data, environment, error, judge, lang, program, sandbox};
+
+use super::{
+  Answer, AnswerLimits, Input, Kind, Problem, ScoringPolicy, SubmissionLimits, Subtask, Test,
+  Testset,
+};
+
+/// Name of the optional checksum manifest at the root of a package.
+pub const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// Name of the manifest in an archive exported by `Problem::export_package`.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Declared SHA-256 checksums of the files in a problem package.
+///
+/// Key is the path relative to the package root, value is the lowercase hex digest.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Checksums(pub BTreeMap<String, String>);
+
+impl Checksums {
+  /// Parse a checksum manifest in `sha256sum` output format.
+  ///
+  /// Each non-empty line is `<hex digest> <path>` (the path may be prefixed by `*` in binary mode).
+  /// Lines starting with `#` are ignored.
+  ///
+  /// Paths must stay inside the package, so absolute paths and paths with `..` are rejected.
+  pub fn parse(s: &str) -> Result<Self, ChecksumsParseError> {
+    let mut ret = BTreeMap::new();
+    for (i, line) in s.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let err = || ChecksumsParseError {
+        line: i + 1,
+        content: line.to_string(),
+      };
+      let (digest, path) = line.split_once(char::is_whitespace).ok_or_else(err)?;
+      let path = path.trim_start().trim_start_matches('*');
+      if digest.len() != 64
+        || !digest.chars().all(|c| c.is_ascii_hexdigit())
+        || path.is_empty()
+        || !path::Path::new(path)
+          .components()
+          .all(|x| matches!(x, path::Component::Normal(_) | path::Component::CurDir))
+      {
+        return Err(err());
+      }
+      ret.insert(path.to_string(), digest.to_ascii_lowercase());
+    }
+    return Ok(Self(ret));
+  }
+
+  /// Verify all declared files under the package root directory.
+  ///
+  /// All files are checked even if some of them fail,
+  /// so the report lists every broken file at once.
+  pub async fn verify_dir(&self, root: &path::Path) -> Report {
+    let mut failures = vec![];
+    for (path, expected) in &self.0 {
+      match sha256_file(&root.join(path)).await {
+        Ok((actual, _)) if &actual == expected => {}
+        Ok((actual, size)) => failures.push(Failure {
+          path: path.clone(),
+          kind: FailureKind::Mismatch {
+            expected: expected.clone(),
+            actual,
+            size,
+          },
+        }),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => failures.push(Failure {
+          path: path.clone(),
+          kind: FailureKind::Missing,
+        }),
+        Err(err) => failures.push(Failure {
+          path: path.clone(),
+          kind: FailureKind::Unreadable(err.to_string()),
+        }),
+      }
+    }
+    return Report {
+      checked: self.0.len(),
+      failures,
+    };
+  }
+}
+
+/// Compute SHA-256 of a file without loading it into memory at once.
+///
+/// Returns the hex digest and the size of the file in bytes.
+async fn sha256_file(path: &path::Path) -> std::io::Result<(String, u64)> {
+  let mut file = tokio::fs::File::open(path).await?;
+  let mut hasher = Sha256::new();
+  let mut buf = vec![0u8; 64 * 1024];
+  let mut size = 0;
+  loop {
+    let n = file.read(&mut buf).await?;
+    if n == 0 {
+      break;
+    }
+    hasher.update(&buf[..n]);
+    size += n as u64;
+  }
+  return Ok((hex::encode(hasher.finalize()), size));
+}
+
+/// Result of verifying the checksums of a package.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Report {
+  /// Number of checked files.
+  pub checked: usize,
+
+  /// Files which failed verification.
+  pub failures: Vec<Failure>,
+}
+
+impl Report {
+  /// Returns true if all declared files passed verification.
+  pub fn is_ok(&self) -> bool {
+    self.failures.is_empty()
+  }
+}
+
+impl fmt::Display for Report {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} of {} files failed verification",
+      self.failures.len(),
+      self.checked
+    )?;
+    for failure in &self.failures {
+      write!(f, "\n- {}", failure)?;
+    }
+    Ok(())
+  }
+}
+
+/// A file which failed checksum verification.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Failure {
+  /// Path relative to the package root.
+  pub path: String,
+
+  pub kind: FailureKind,
+}
+
+impl fmt::Display for Failure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.kind {
+      FailureKind::Missing => write!(f, "{}: missing", self.path),
+      FailureKind::Mismatch {
+        expected,
+        actual,
+        size,
+      } => write!(
+        f,
+        "{}: checksum mismatch (expected {}, got {}, size {} bytes), the file may be truncated",
+        self.path, expected, actual, size
+      ),
+      FailureKind::Unreadable(err) => write!(f, "{}: unreadable: {}", self.path, err),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum FailureKind {
+  /// The declared file does not exist in the package.
+  Missing,
+
+  /// Content of the file does not match the declared checksum.
+  Mismatch {
+    expected: String,
+    actual: String,
+    size: u64,
+  },
+
+  /// The file exists but can not be read.
+  Unreadable(String),
+}
+
+/// Error when a checksum manifest line is malformed.
+#[derive(Debug, Error, Clone)]
+#[error("invalid checksum manifest at line {line}: {content}")]
+pub struct ChecksumsParseError {
+  pub line: usize,
+  pub content: String,
+}
+
+/// Import a problem from a full package exported by Codeforces Polygon.
+///
+/// The package must be unpacked to `root` and contains the generated tests
+/// (i.e. a "full" package, or a "standard" package whose tests were generated by `doall`).
+/// If a `SHA256SUMS` manifest exists at the package root, the package is verified first.
+///
+/// Mapping from Polygon to rindag:
+///
+/// - Testset `tests` is mapped to `Testset::Main`, and `pretests` to `Testset::Pretests`.
+/// - Each test group is mapped to a subtask (with its dependencies),
+///   tests without groups form a single subtask.
+/// - The score of a subtask is the points of the group, or the sum of points of its tests,
+///   or 100 if the problem has no points at all.
+/// - Groups with points policy `each-test` are scored by `ScoringPolicy::Sum`,
+///   other groups are all-or-nothing.
+/// - Answers in the package are used as plain answers, missing answers will be generated.
+/// - Standard checkers (`std::*.cpp`) are mapped to builtin checkers.
+/// - If the problem has an interactor, it is an interactive problem,
+///   and the checker checks the output of the interactor.
+/// - Resource files (e.g. `testlib.h`) are used as extra files when compiling or running checker.
+pub async fn import_polygon(root: &path::Path) -> Result<Problem, ImportError> {
+  let checksums_path = root.join(CHECKSUMS_FILE);
+  if let Ok(content) = tokio::fs::read_to_string(&checksums_path).await {
+    let report = Checksums::parse(&content)?.verify_dir(root).await;
+    if !report.is_ok() {
+      return Err(ImportError::Checksum(report));
+    }
+  }
+
+  let xml = read_to_string(root, "problem.xml").await?;
+  let doc = roxmltree::Document::parse(&xml).map_err(|e| ImportError::Xml(e.to_string()))?;
+  let problem = doc.root_element();
+
+  let checker = child(problem, &["assets", "checker"])
+    .ok_or_else(|| ImportError::Missing("assets/checker".to_string()))?;
+  let interactor = match child(problem, &["assets", "interactor"]) {
+    Some(x) => Some(import_source(root, x).await?),
+    None => None,
+  };
+
+  let standard_solution = child(problem, &["assets", "solutions"])
+    .and_then(|x| {
+      x.children()
+        .find(|x| x.has_tag_name("solution") && x.attribute("tag") == Some("main"))
+    })
+    .ok_or_else(|| ImportError::Missing("main solution".to_string()))?;
+
+  let mut user_copy_in = HashMap::new();
+  if let Some(resources) = child(problem, &["files", "resources"]) {
+    for file in resources.children().filter(|x| x.has_tag_name("file")) {
+      let path = attribute(file, "path")?;
+      let name = path.rsplit('/').next().unwrap().to_string();
+      user_copy_in.insert(name, data::Provider::Memory(read(root, path).await?));
+    }
+  }
+
+  let mut subtasks = vec![];
+  if let Some(judging) = child(problem, &["judging"]) {
+    for testset in judging.children().filter(|x| x.has_tag_name("testset")) {
+      let kind = match attribute(testset, "name")? {
+        "tests" => Testset::Main,
+        "pretests" => Testset::Pretests,
+        _ => continue,
+      };
+      let start_id = subtasks.len();
+      subtasks.extend(import_testset(root, testset, kind, start_id).await?);
+    }
+  }
+
+  return Ok(Problem {
+    subtasks,
+    kind: match interactor {
+      Some(_) => Kind::Interactive,
+      None => Kind::Batch,
+    },
+    checker: import_checker(root, checker).await?.into(),
+    interactor,
+    validator: None,
+    output: Default::default(),
+    presentation_error: Default::default(),
+    cache_checker: false,
+    seed_checker: false,
+    labels: vec![],
+    answer_limits: AnswerLimits::default(),
+    submission_limits: SubmissionLimits::default(),
+    standard_solution: import_source(root, standard_solution).await?,
+    user_copy_in,
+    judge_copy_in: HashMap::new(),
+  });
+}
+
+/// Import the tests of a Polygon testset into subtasks, whose ids start from `start_id`.
+async fn import_testset(
+  root: &path::Path,
+  testset: roxmltree::Node<'_, '_>,
+  kind: Testset,
+  start_id: usize,
+) -> Result<Vec<Subtask>, ImportError> {
+  let text = |name: &str| {
+    child(testset, &[name])
+      .and_then(|x| x.text())
+      .ok_or_else(|| ImportError::Missing(format!("testset/{}", name)))
+  };
+  let time_limit = time::Duration::from_millis(parse_number(text("time-limit")?)?);
+  let memory_limit = parse_number(text("memory-limit")?)?;
+  let input_pattern = text("input-path-pattern")?;
+  let answer_pattern = text("answer-path-pattern")?;
+
+  // (Group name, points of group, dependencies, scoring policy).
+  let mut groups: Vec<(String, Option<f32>, Vec<String>, ScoringPolicy)> = vec![];
+  if let Some(x) = child(testset, &["groups"]) {
+    for group in x.children().filter(|x| x.has_tag_name("group")) {
+      groups.push((
+        attribute(group, "name")?.to_string(),
+        group.attribute("points").map(parse_number).transpose()?,
+        group
+          .descendants()
+          .filter(|x| x.has_tag_name("dependency"))
+          .map(|x| attribute(x, "group").map(str::to_string))
+          .collect::<Result<_, _>>()?,
+        match group.attribute("points-policy") {
+          Some("each-test") => ScoringPolicy::Sum,
+          _ => ScoringPolicy::Min,
+        },
+      ));
+    }
+  }
+
+  let mut subtasks: Vec<Subtask> = vec![];
+  let mut test_points: Vec<f32> = vec![];
+  let tests = child(testset, &["tests"]).map_or(vec![], |x| {
+    x.children().filter(|x| x.has_tag_name("test")).collect()
+  });
+  for (i, test) in tests.into_iter().enumerate() {
+    let group = test.attribute("group").unwrap_or("");
+    let index = match groups.iter().position(|x| x.0 == group) {
+      Some(x) => x,
+      None => {
+        groups.push((group.to_string(), None, vec![], ScoringPolicy::Min));
+        groups.len() - 1
+      }
+    };
+    while subtasks.len() <= index {
+      subtasks.push(Subtask {
+        id: start_id + subtasks.len(),
+        score: 0.,
+        dependences: vec![],
+        testset: kind,
+        tests: vec![],
+        time_limit,
+        memory_limit,
+        wall_time_limit: None,
+        time_kind: judge::TimeKind::Cpu,
+        cpu_affinity: None,
+        scoring: ScoringPolicy::Min,
+      });
+      test_points.push(0.);
+    }
+    test_points[index] += test
+      .attribute("points")
+      .map(parse_number::<f32>)
+      .transpose()?
+      .unwrap_or(0.);
+
+    let input = read(root, &format_pattern(input_pattern, i + 1)).await?;
+    let answer = match tokio::fs::read(root.join(format_pattern(answer_pattern, i + 1))).await {
+      Ok(x) => Answer::Plain { context: x },
+      Err(_) => Answer::Generated,
+    };
+    subtasks[index].tests.push(Test {
+      input: Input::Plain { context: input },
+      answer,
+      time_limit: None,
+      memory_limit: None,
+    });
+  }
+
+  let no_points = groups.iter().all(|x| x.1.is_none()) && test_points.iter().all(|&x| x == 0.);
+  for (i, subtask) in subtasks.iter_mut().enumerate() {
+    subtask.score = match groups[i].1 {
+      Some(x) => x,
+      None if no_points => 100. / groups.len() as f32,
+      None => test_points[i],
+    };
+    subtask.dependences = groups[i]
+      .2
+      .iter()
+      .filter_map(|x| groups.iter().position(|y| &y.0 == x))
+      .map(|x| start_id + x)
+      .collect();
+    subtask.scoring = groups[i].3.clone();
+  }
+  subtasks.retain(|x| !x.tests.is_empty());
+
+  return Ok(subtasks);
+}
+
+/// Import the checker, mapping the standard checkers to builtin checkers.
+async fn import_checker(
+  root: &path::Path,
+  checker: roxmltree::Node<'_, '_>,
+) -> Result<program::Source, ImportError> {
+  if let Some(name) = checker
+    .attribute("name")
+    .and_then(|x| x.strip_prefix("std::"))
+  {
+    if let Ok(file) = builtin::File::new("checker", name) {
+      return Ok(program::Source {
+        lang: lang::Lang::from_str("cpp")?,
+        data: file.into(),
+      });
+    }
+  }
+  return import_source(root, checker).await;
+}
+
+/// Import the source file declared by the `<source>` child of node.
+async fn import_source(
+  root: &path::Path,
+  node: roxmltree::Node<'_, '_>,
+) -> Result<program::Source, ImportError> {
+  let source = child(node, &["source"])
+    .ok_or_else(|| ImportError::Missing(format!("{}/source", node.tag_name().name())))?;
+  // Polygon source types look like `cpp.g++17`, `c.gcc`.
+  let lang = attribute(source, "type")?.split('.').next().unwrap();
+  return Ok(program::Source {
+    lang: lang::Lang::from_str(lang)?,
+    data: data::Provider::Memory(read(root, attribute(source, "path")?).await?),
+  });
+}
+
+/// Format a Polygon path pattern like `tests/%02d` with the test index.
+fn format_pattern(pattern: &str, index: usize) -> String {
+  lazy_static! {
+    static ref PAT: regex::Regex = regex::Regex::new(r"%(0?)(\d*)d").unwrap();
+  }
+  PAT
+    .replace(pattern, |cap: &regex::Captures| {
+      let width = cap[2].parse().unwrap_or(0);
+      match &cap[1] {
+        "0" => format!("{:0width$}", index, width = width),
+        _ => format!("{:width$}", index, width = width),
+      }
+    })
+    .to_string()
+}
+
+/// Find the descendant of node by the path of tag names.
+fn child<'a, 'input>(
+  node: roxmltree::Node<'a, 'input>,
+  path: &[&str],
+) -> Option<roxmltree::Node<'a, 'input>> {
+  path.iter().try_fold(node, |node, name| {
+    node.children().find(|x| x.has_tag_name(*name))
+  })
+}
+
+fn attribute<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Result<&'a str, ImportError> {
+  node
+    .attribute(name)
+    .ok_or_else(|| ImportError::Missing(format!("{}@{}", node.tag_name().name(), name)))
+}
+
+fn parse_number<T: FromStr>(s: &str) -> Result<T, ImportError> {
+  s.trim()
+    .parse()
+    .map_err(|_| ImportError::Xml(format!("invalid number: {}", s)))
+}
+
+async fn read(root: &path::Path, path: &str) -> Result<Vec<u8>, ImportError> {
+  tokio::fs::read(root.join(path))
+    .await
+    .map_err(|err| ImportError::Io {
+      path: path.to_string(),
+      message: err.to_string(),
+    })
+}
+
+async fn read_to_string(root: &path::Path, path: &str) -> Result<String, ImportError> {
+  Ok(String::from_utf8_lossy(&read(root, path).await?).to_string())
+}
+
+/// Error when importing a problem package.
+#[derive(Debug, Error, Clone)]
+pub enum ImportError {
+  #[error("can not read {path}: {message}")]
+  Io { path: String, message: String },
+
+  #[error("invalid problem.xml: {0}")]
+  Xml(String),
+
+  #[error("missing {0} in problem.xml")]
+  Missing(String),
+
+  #[error(transparent)]
+  Lang(#[from] lang::InvalidLangError),
+
+  #[error(transparent)]
+  ChecksumsParse(#[from] ChecksumsParseError),
+
+  #[error("package verification failed: {0}")]
+  Checksum(Report),
+}
+
+/// Manifest of an exported problem package, describing where each file of the problem is.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Manifest {
+  pub kind: Kind,
+  pub checker: ManifestChecker,
+  pub interactor: Option<ManifestSource>,
+  pub standard_solution: ManifestSource,
+  pub subtasks: Vec<ManifestSubtask>,
+
+  /// Paths of extra files when compiling or running checker.
+  pub user_files: Vec<String>,
+
+  /// Paths of extra files when running solution.
+  pub judge_files: Vec<String>,
+
+  /// Environment which the answers were produced in.
+  #[serde(default)]
+  pub environment: Option<environment::Environment>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ManifestSource {
+  pub lang: String,
+  pub path: String,
+}
+
+/// Checker of an exported problem package, a source file or a builtin comparison.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ManifestChecker {
+  Source(ManifestSource),
+  Builtin(checker::Builtin),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ManifestSubtask {
+  pub id: usize,
+  pub score: f32,
+  pub dependences: Vec<usize>,
+  pub testset: Testset,
+  pub time_limit: time::Duration,
+  pub memory_limit: u64,
+
+  #[serde(default)]
+  pub wall_time_limit: Option<time::Duration>,
+
+  #[serde(default)]
+  pub time_kind: judge::TimeKind,
+
+  #[serde(default)]
+  pub cpu_affinity: Option<sandbox::CpuAffinity>,
+
+  /// Paths of input files, the answer of each test is at the path with `.a` appended.
+  pub tests: Vec<String>,
+}
+
+impl Problem {
+  /// Export the problem as a tar archive at `path`.
+  ///
+  /// Inputs are generated and answers are produced by the standard solution,
+  /// so the archive can be judged without the generators.
+  /// The archive contains `manifest.json`, the tests, the sources, the extra files
+  /// and a `SHA256SUMS` of all the other files.
+  /// The archive is reproducible: entries are sorted and their metadata is fixed.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the standard solution does not compile,
+  /// any test can not be made, or the archive can not be written.
+  pub async fn export_package(&self, path: &path::Path) -> Result<Manifest, ExportError> {
+    let user_copy_in = data::upload_all(&self.user_copy_in).await;
+    let judge_copy_in = data::upload_all(&self.judge_copy_in).await;
+    let standard_solution = self
+      .standard_solution
+      .compile(vec![], judge_copy_in.clone())
+      .await?;
+
+    let mut files = BTreeMap::new();
+    let mut subtasks = vec![];
+    for subtask in &self.subtasks {
+      let tests = subtask
+        .make_tests(
+          &standard_solution,
+          &self.answer_limits,
+          &self.output,
+          &user_copy_in,
+          &judge_copy_in,
+        )
+        .await;
+      let mut paths = vec![];
+      for (index, test) in tests.into_iter().enumerate() {
+        let (input, answer) = test.map_err(|err| ExportError::Test {
+          subtask: subtask.id,
+          index,
+          message: err.to_string(),
+        })?;
+        let path = format!("tests/{:02}", files.len() / 2 + 1);
+        files.insert(path.clone() + ".a", answer);
+        files.insert(path.clone(), input);
+        paths.push(path);
+      }
+      subtasks.push(ManifestSubtask {
+        id: subtask.id,
+        score: subtask.score,
+        dependences: subtask.dependences.clone(),
+        testset: subtask.testset,
+        time_limit: subtask.time_limit,
+        memory_limit: subtask.memory_limit,
+        wall_time_limit: subtask.wall_time_limit,
+        time_kind: subtask.time_kind,
+        cpu_affinity: subtask.cpu_affinity.clone(),
+        tests: paths,
+      });
+    }
+
+    let mut sources: Vec<_> = self
+      .checker
+      .program()
+      .map(|x| ("checker", x))
+      .into_iter()
+      .collect();
+    sources.extend(self.interactor.as_ref().map(|x| ("interactor", x)));
+    sources.push(("solution", &self.standard_solution));
+    let mut manifest_sources = HashMap::new();
+    for (name, source) in sources {
+      let ext = path::Path::new(source.lang.source())
+        .extension()
+        .map_or(String::new(), |x| format!(".{}", x.to_string_lossy()));
+      let path = format!("{}{}", name, ext);
+      files.insert(path.clone(), source.data.read().await?.into_owned());
+      manifest_sources.insert(
+        name,
+        ManifestSource {
+          lang: source.lang.name().to_string(),
+          path,
+        },
+      );
+    }
+    let checker = match manifest_sources.remove("checker") {
+      Some(x) => ManifestChecker::Source(x),
+      None => ManifestChecker::Builtin(self.checker.builtin().unwrap().clone()),
+    };
+    let interactor = manifest_sources.remove("interactor");
+    let solution = manifest_sources.remove("solution").unwrap();
+
+    let mut copy_in_paths = vec![];
+    for (dir, copy_in) in [("user", &self.user_copy_in), ("judge", &self.judge_copy_in)] {
+      let mut paths = vec![];
+      for (name, data) in copy_in {
+        let path = format!("files/{}/{}", dir, name);
+        files.insert(path.clone(), data.read().await?.into_owned());
+        paths.push(path);
+      }
+      paths.sort();
+      copy_in_paths.push(paths);
+    }
+    let judge_files = copy_in_paths.pop().unwrap();
+    let user_files = copy_in_paths.pop().unwrap();
+
+    let manifest = Manifest {
+      kind: self.kind,
+      checker,
+      interactor,
+      standard_solution: solution,
+      subtasks,
+      user_files,
+      judge_files,
+      environment: Some(environment::ENVIRONMENT.clone()),
+    };
+    files.insert(
+      MANIFEST_FILE.to_string(),
+      serde_json::to_vec_pretty(&manifest)?,
+    );
+
+    let checksums: String = files
+      .iter()
+      .map(|(path, content)| format!("{}  {}\n", hex::encode(Sha256::digest(content)), path))
+      .collect();
+    files.insert(CHECKSUMS_FILE.to_string(), checksums.into_bytes());
+
+    let mut archive = tar::Builder::new(vec![]);
+    for (path, content) in &files {
+      let mut header = tar::Header::new_gnu();
+      header.set_size(content.len() as u64);
+      header.set_mode(0o644);
+      header.set_mtime(0);
+      header.set_cksum();
+      archive.append_data(&mut header, path, content.as_slice())?;
+    }
+    tokio::fs::write(path, archive.into_inner()?).await?;
+
+    return Ok(manifest);
+  }
+}
+
+/// Error when exporting a problem package.
+#[derive(Debug, Error)]
+pub enum ExportError {
+  #[error("standard solution: {0}")]
+  Compile(#[from] error::CompileError),
+
+  #[error("subtask {subtask} test {index}: {message}")]
+  Test {
+    subtask: usize,
+    index: usize,
+    message: String,
+  },
+
+  #[error("can not write package: {0}")]
+  Io(#[from] std::io::Error),
+
+  #[error("can not serialize manifest: {0}")]
+  Json(#[from] serde_json::Error),
+}
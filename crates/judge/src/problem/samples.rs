@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+
+use super::{checker_args, Kind, PreparedProblem, Subtask, Testset};
+use crate::{checker, error, etc, program, record, sandbox, CONFIG};
+
+/// Result of a solution on a sample test, with the files for the submitter to compare.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SampleRun {
+  pub record: record::Record,
+
+  pub input: String,
+
+  /// Output of the solution, None if the solution did not finish normally.
+  pub output: Option<String>,
+
+  pub answer: String,
+
+  /// Names of the files cut to the size limit.
+  pub truncated: Vec<String>,
+}
+
+impl PreparedProblem {
+  /// Run a solution on the sample tests before it is submitted,
+  /// and return the record and the files of each test.
+  ///
+  /// The tests are run one by one with the limits of the profile, and the output of the solution
+  /// is limited to `limit` bytes besides the stdout limit of the profile.
+  /// Each returned file is cut to `limit` bytes, and decoded as UTF-8 lossily.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the problem is not a batch problem,
+  /// the solution does not compile, or the input or answer of a sample can not be made.
+  pub async fn run_samples(
+    &self,
+    profile: &etc::ProfileCfg,
+    solution: &program::Source,
+    limit: u64,
+  ) -> Result<Vec<SampleRun>, SampleRunError> {
+    if self.problem.kind != Kind::Batch {
+      return Err(SampleRunError::Unsupported(self.problem.kind));
+    }
+    let solution_exec = solution
+      .compile(vec![], self.judge_copy_in.clone())
+      .await
+      .map_err(SampleRunError::Compile)?
+      .with_labels(&self.problem.labels);
+    let checker = match self.problem.seed_checker {
+      true => self
+        .checker
+        .clone()
+        .with_seed(self.problem.checker_seed_key(solution)),
+      false => self.checker.clone(),
+    };
+
+    let mut runs = vec![];
+    for subtask in self.problem.schedule(&[Testset::Sample]) {
+      for index in 0..subtask.tests.len() {
+        runs.push(
+          self
+            .run_sample(profile, &solution_exec, &checker, subtask, index, limit)
+            .await?,
+        );
+      }
+    }
+    return Ok(runs);
+  }
+
+  /// Run a solution on the `index`-th test of a sample subtask.
+  async fn run_sample(
+    &self,
+    profile: &etc::ProfileCfg,
+    solution: &program::Executable,
+    checker: &checker::Checker,
+    subtask: &Subtask,
+    index: usize,
+    limit: u64,
+  ) -> Result<SampleRun, SampleRunError> {
+    let test = &subtask.tests[index];
+    let test_id = subtask.test_id(index);
+    let (time_limit, memory_limit) = subtask.limits(test);
+    let (time_limit, memory_limit) = profile.limit(time_limit, memory_limit);
+    let time_limit = CONFIG.sandbox.scale_time_limit(time_limit);
+    let wall_time_limit = subtask.scaled_wall_time_limit();
+    let stdout_limit = profile.stdout_limit().min(limit as i64);
+
+    let input = test
+      .input
+      .make(self.user_copy_in.clone())
+      .await
+      .map_err(SampleRunError::Input)?;
+    let (answer, (res, output)) = futures::join!(
+      test.answer.make(
+        &self.standard_solution,
+        input.clone(),
+        self.judge_copy_in.clone(),
+        time_limit,
+        memory_limit,
+        &self.problem.output,
+      ),
+      solution.judge_batch(
+        vec![],
+        input.clone(),
+        self.judge_copy_in.clone(),
+        time_limit,
+        memory_limit,
+        wall_time_limit,
+        subtask.time_kind,
+        subtask.cpu_affinity.as_ref(),
+        stdout_limit,
+        &self.problem.output,
+      ),
+    );
+    let answer = answer.map_err(SampleRunError::Answer)?;
+
+    let checker_seed = checker.seed(&test_id);
+    let record = match &output {
+      None => record::Record::new_interrupted(&res)
+        .with_time_kind(subtask.time_kind)
+        .with_almost_tle(time_limit),
+      Some(output) => match checker
+        .check_seeded(
+          checker_seed,
+          checker_args(&subtask.testset, subtask.id),
+          input.clone(),
+          output.clone(),
+          answer.clone(),
+          self.user_copy_in.clone(),
+        )
+        .await
+      {
+        Ok(x) => record::Record::new_checked(&res, &x.output)
+          .with_time_kind(subtask.time_kind)
+          .with_checker_seed(checker_seed),
+        Err(err) => record::Record::new_system_error(
+          &("checker execute failed: ".to_string() + &err.to_string()),
+        ),
+      },
+    };
+    let record = record.with_id(test_id, uuid::Uuid::new_v4());
+
+    let mut truncated = vec![];
+    let mut cut = |name: &str, mut content: Vec<u8>| {
+      if content.len() as u64 > limit {
+        content.truncate(limit as usize);
+        truncated.push(name.to_string());
+      }
+      String::from_utf8_lossy(&content).into_owned()
+    };
+    // Read at most one byte more than the limit, so huge files are never held in memory.
+    let fetch = |file: sandbox::FileHandle| async move {
+      let mut content = vec![];
+      file
+        .read_stream()
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut content)
+        .await
+        .map_err(|err| SampleRunError::Sandbox(err.to_string()))?;
+      return Ok::<_, SampleRunError>(content);
+    };
+    let input = cut("input.txt", fetch(input).await?);
+    let output = match output {
+      Some(x) => Some(cut("output.txt", fetch(x).await?)),
+      None => None,
+    };
+    let answer = cut("answer.txt", fetch(answer).await?);
+
+    return Ok(SampleRun {
+      record,
+      input,
+      output,
+      answer,
+      truncated,
+    });
+  }
+}
+
+/// Error when the samples can not be run.
+#[derive(Debug, Error, Clone)]
+pub enum SampleRunError {
+  #[error("samples can not be run on a problem of type {0:?}")]
+  Unsupported(Kind),
+
+  #[error("solution: {0}")]
+  Compile(error::CompileError),
+
+  #[error("input file generated failed: {0}")]
+  Input(error::RuntimeError),
+
+  #[error("answer file generated failed: {0}")]
+  Answer(error::RuntimeError),
+
+  #[error("file can not be fetched from sandbox: {0}")]
+  Sandbox(String),
+}
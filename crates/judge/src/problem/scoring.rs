@@ -0,0 +1,64 @@
+use std::{fmt, sync::Arc};
+
+/// Custom aggregator of test scores, takes the scores of tests in order.
+pub type Aggregator = Arc<dyn Fn(&[f32]) -> f32 + Send + Sync>;
+
+/// How the score of a subtask is aggregated from the scores of its tests.
+///
+/// Test scores and the result are unscaled (in range \[0,1\]), except for `Points`.
+/// A subtask without tests always gets full score, except for `Points` which gets no points.
+#[derive(Clone, Default)]
+pub enum ScoringPolicy {
+  /// Minimum of test scores, all-or-nothing if tests are scored 0 or 1.
+  #[default]
+  Min,
+
+  /// Sum of test scores, scaled so that each test is worth an equal part of the subtask.
+  Sum,
+
+  /// Product of test scores.
+  Product,
+
+  /// Score of the last test.
+  LastTest,
+
+  /// Sum of the points of tests (see `record::Record::points`), which is the score of the subtask
+  /// in absolute points instead of a fraction of its full score.
+  Points,
+
+  /// Scores are aggregated by a custom function.
+  Custom(Aggregator),
+}
+
+impl fmt::Debug for ScoringPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Min => write!(f, "Min"),
+      Self::Sum => write!(f, "Sum"),
+      Self::Product => write!(f, "Product"),
+      Self::LastTest => write!(f, "LastTest"),
+      Self::Points => write!(f, "Points"),
+      Self::Custom(_) => write!(f, "Custom"),
+    }
+  }
+}
+
+impl ScoringPolicy {
+  /// Aggregate the scores of tests into the score of subtask.
+  pub fn aggregate(&self, scores: &[f32]) -> f32 {
+    if scores.is_empty() {
+      return match self {
+        Self::Points => 0.,
+        _ => 1.,
+      };
+    }
+    return match self {
+      Self::Min => scores.iter().fold(1f32, |a, &b| a.min(b)),
+      Self::Sum => scores.iter().sum::<f32>() / scores.len() as f32,
+      Self::Product => scores.iter().product(),
+      Self::LastTest => *scores.last().unwrap(),
+      Self::Points => scores.iter().sum(),
+      Self::Custom(f) => f(scores),
+    };
+  }
+}
@@ -0,0 +1,223 @@
+use std::{
+  collections::{HashMap, HashSet},
+  path,
+  str::FromStr,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::Input;
+use crate::{error, generator, sandbox};
+
+/// A Polygon-style script of generator invocations, one test per line.
+///
+/// Blank lines and lines starting with `#` are ignored.
+/// A trailing `> $` (output to the next test) is accepted for compatibility with Polygon scripts.
+///
+/// ```text
+/// # small tests
+/// gen 1 5
+/// gen 1 5 > $
+/// gen --n 1000
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestPlan {
+  pub commands: Vec<Command>,
+}
+
+/// A generator invocation of a test plan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+  /// Name of the generator.
+  pub generator: String,
+
+  pub args: Vec<String>,
+
+  /// Seed of the test, passed to the generator as the last argument.
+  ///
+  /// It is derived from the command line and the number of identical command lines before it,
+  /// so the same command repeated produces different tests, and editing other lines of the plan
+  /// does not change it.
+  pub seed: u64,
+}
+
+impl Command {
+  /// Arguments of the generator, with the seed.
+  pub fn args(&self) -> Vec<String> {
+    let mut args = self.args.clone();
+    args.push(self.seed.to_string());
+    return args;
+  }
+}
+
+impl FromStr for TestPlan {
+  type Err = TestPlanError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut occurrences: HashMap<Vec<&str>, u64> = HashMap::new();
+    let mut commands = vec![];
+
+    for (index, line) in s.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      let mut words: Vec<_> = line.split_whitespace().collect();
+      if let Some(pos) = words.iter().position(|&x| x.starts_with('>')) {
+        if words[pos..] != [">", "$"] && words[pos..] != [">$"] {
+          return Err(TestPlanError::Parse {
+            line: index + 1,
+            message: "only `> $` is supported as the output of a command".to_string(),
+          });
+        }
+        words.truncate(pos);
+      }
+      if words.is_empty() {
+        return Err(TestPlanError::Parse {
+          line: index + 1,
+          message: "missing generator name".to_string(),
+        });
+      }
+
+      let occurrence = occurrences.entry(words.clone()).or_default();
+      let mut hasher = Sha256::new();
+      for x in &words {
+        hasher.update(x);
+        hasher.update([0]);
+      }
+      hasher.update(occurrence.to_le_bytes());
+      *occurrence += 1;
+
+      commands.push(Command {
+        generator: words[0].to_string(),
+        args: words[1..].iter().map(|x| x.to_string()).collect(),
+        seed: u64::from_be_bytes(hasher.finalize()[..8].try_into().unwrap()),
+      });
+    }
+
+    return Ok(Self { commands });
+  }
+}
+
+/// A test plan is (de)serialized as its script, the seeds only depend on the command lines,
+/// so they are kept.
+impl Serialize for TestPlan {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    return serializer.serialize_str(
+      &(self.commands.iter())
+        .map(|x| {
+          [vec![x.generator.clone()], x.args.clone()]
+            .concat()
+            .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n"),
+    );
+  }
+}
+
+impl<'de> Deserialize<'de> for TestPlan {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    return Self::from_str(&String::deserialize(deserializer)?).map_err(de::Error::custom);
+  }
+}
+
+impl TestPlan {
+  /// Names of the generators used by the plan.
+  pub fn generators(&self) -> HashSet<&str> {
+    return self.commands.iter().map(|x| x.generator.as_str()).collect();
+  }
+
+  /// Expand the plan to the inputs of tests, in order.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if a generator is not given.
+  pub fn expand(
+    &self,
+    generators: &HashMap<String, generator::Generator>,
+  ) -> Result<Vec<Input>, TestPlanError> {
+    return self
+      .commands
+      .iter()
+      .map(|command| match generators.get(&command.generator) {
+        Some(generator) => Ok(Input::Generated {
+          generator: generator.clone(),
+          args: command.args(),
+        }),
+        None => Err(TestPlanError::UnknownGenerator(command.generator.clone())),
+      })
+      .collect();
+  }
+
+  /// Generate all the inputs of the plan in parallel, and write them to `dir`
+  /// as `1.in`, `2.in`, …, in order of the plan.
+  ///
+  /// Returns the paths of the written files.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if a generator is not given or fails,
+  /// or a file can not be written.
+  pub async fn generate_all(
+    &self,
+    generators: &HashMap<String, generator::Generator>,
+    copy_in: &HashMap<String, sandbox::FileHandle>,
+    dir: &path::Path,
+  ) -> Result<Vec<path::PathBuf>, TestPlanError> {
+    let inputs = self.expand(generators)?;
+
+    let io_error = |path: &path::Path, err: std::io::Error| TestPlanError::Io {
+      path: path.display().to_string(),
+      message: err.to_string(),
+    };
+    tokio::fs::create_dir_all(dir)
+      .await
+      .map_err(|err| io_error(dir, err))?;
+
+    let paths =
+      futures::future::try_join_all(inputs.iter().enumerate().map(|(index, input)| async move {
+        let file = input
+          .make(copy_in.clone())
+          .await
+          .map_err(|err| TestPlanError::Generate { index, error: err })?;
+        let context = file.context().await.map_err(|err| TestPlanError::Io {
+          path: file.id().to_string(),
+          message: err.to_string(),
+        })?;
+
+        let path = dir.join(format!("{}.in", index + 1));
+        tokio::fs::write(&path, context)
+          .await
+          .map_err(|err| io_error(&path, err))?;
+        return Ok(path);
+      }))
+      .await?;
+
+    return Ok(paths);
+  }
+}
+
+/// Error when parsing or running a test plan.
+#[derive(Debug, Error, Clone)]
+pub enum TestPlanError {
+  #[error("test plan line {line}: {message}")]
+  Parse { line: usize, message: String },
+
+  #[error("unknown generator {0}")]
+  UnknownGenerator(String),
+
+  /// The generator of the test (0-based index in the plan) failed.
+  #[error("test {}: {error}", index + 1)]
+  Generate {
+    index: usize,
+    error: error::RuntimeError,
+  },
+
+  /// A generated file can not be fetched from the sandbox or written to `path`.
+  #[error("{path}: {message}")]
+  Io { path: String, message: String },
+}
@@ -1,8 +1,9 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time};
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-use crate::{data, error, lang, sandbox};
+use crate::{cache, data, error, lang, sandbox};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Source {
@@ -14,6 +15,188 @@ pub struct Source {
 pub struct Executable {
   pub lang: lang::Lang,
   pub file: sandbox::FileHandle,
+
+  /// Resource usage of the compilation which made this executable.
+  ///
+  /// For an executable from the compile cache, it is the usage of the first compilation.
+  pub report: CompileReport,
+
+  /// Compile message of the compilation, usually the warnings of the compiler.
+  pub message: String,
+
+  /// Labels a sandbox backend must have to run the executable (see `sandbox::Cmd::labels`),
+  /// the labels of its language and of the problem it is judged for.
+  pub labels: Vec<String>,
+}
+
+/// Resource usage of the compiler, to tell how close a compilation is to the compile limits.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompileReport {
+  pub time: time::Duration,
+
+  /// Memory used in bytes.
+  pub memory: u64,
+}
+
+impl From<&sandbox::ExecuteResult> for CompileReport {
+  fn from(result: &sandbox::ExecuteResult) -> Self {
+    return Self {
+      time: result.time,
+      memory: result.memory,
+    };
+  }
+}
+
+/// Execution result of a failed compilation.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CompileResult {
+  pub status: sandbox::Status,
+
+  pub exit_code: i32,
+
+  #[serde(flatten)]
+  pub report: CompileReport,
+}
+
+impl From<&sandbox::ExecuteResult> for CompileResult {
+  fn from(result: &sandbox::ExecuteResult) -> Self {
+    return Self {
+      status: result.status.clone(),
+      exit_code: result.exit_code,
+      report: CompileReport::from(result),
+    };
+  }
+}
+
+/// Outcome of compiling a source without running it (see `problem::PreparedProblem::compile_only`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CompileCheck {
+  /// Hex SHA-256 of the executable, None if the compilation failed.
+  pub executable: Option<String>,
+
+  /// Compile errors or warnings, limited to `judge.compile_message_limit` (in config) bytes.
+  pub diagnostics: String,
+
+  #[serde(flatten)]
+  pub result: CompileResult,
+}
+
+impl CompileCheck {
+  /// Whether the source compiled.
+  pub fn is_ok(&self) -> bool {
+    return self.executable.is_some();
+  }
+}
+
+/// A run of a program judging something (like a checker or a validator),
+/// with its parsed output and its execution result.
+#[derive(Debug, Clone)]
+pub struct Run<T> {
+  pub output: T,
+
+  /// Execution result of the program, telling the time and memory it used.
+  ///
+  /// For an output from a cache, it is the result of the run which produced the output.
+  pub result: sandbox::ExecuteResult,
+}
+
+/// Cache of compiled executables, keyed by the hash of language, source,
+/// compile arguments and the contents of extra files.
+pub type CompileCache = cache::Cache<Executable>;
+
+/// Reject the extra compile arguments denied by the language config,
+/// reported like a compiler refusing the arguments.
+fn check_compile_args(lang: &lang::Lang, args: &[String]) -> Result<(), error::CompileError> {
+  return lang
+    .check_compile_args(args)
+    .map_err(|err| error::CompileError {
+      result: sandbox::ExecuteResult {
+        status: sandbox::Status::NonZeroExitStatus,
+        time: std::time::Duration::ZERO,
+        wall_time: std::time::Duration::ZERO,
+        memory: 0,
+        exit_code: -1,
+        error: String::new(),
+        timing: sandbox::Timing::default(),
+      },
+      message: err.to_string(),
+    });
+}
+
+/// Compile a source file already in the sandbox, like `Source::compile`.
+///
+/// # Errors
+///
+/// This function will return an error if the compilation failed,
+/// an extra argument is denied by the language config,
+/// or a sandbox internal error was encountered.
+pub async fn compile_file(
+  lang: &lang::Lang,
+  source: sandbox::FileHandle,
+  args: Vec<String>,
+  copy_in: HashMap<String, sandbox::FileHandle>,
+) -> Result<Executable, error::CompileError> {
+  check_compile_args(lang, &args)?;
+  return compile_uploaded(lang, source, args, copy_in).await;
+}
+
+/// Compile an uploaded source file with arguments already checked.
+async fn compile_uploaded(
+  lang: &lang::Lang,
+  source: sandbox::FileHandle,
+  args: Vec<String>,
+  mut copy_in: HashMap<String, sandbox::FileHandle>,
+) -> Result<Executable, error::CompileError> {
+  copy_in.insert(lang.source().to_string(), source);
+
+  let mut res = sandbox::Request::Run(sandbox::Cmd {
+    args: [lang.compile_cmd().clone(), args].concat(),
+    copy_in,
+    labels: lang.labels().clone(),
+    copy_out: vec!["stderr".to_string(), lang.exec().to_string()],
+    ..Default::default()
+  })
+  .exec()
+  .await;
+
+  assert_eq!(res.len(), 1);
+  let res = res.pop().unwrap();
+
+  let message = match res.files.get("stderr") {
+    Some(message_file) => message_file
+      .context()
+      .await
+      .map_or("broken message".to_string(), |chars| {
+        String::from_utf8_lossy(&chars).to_string()
+      }),
+    None => "no compile message".to_string(),
+  };
+  if res.result.status != sandbox::Status::Accepted {
+    return Err(error::CompileError {
+      result: res.result,
+      message,
+    });
+  }
+
+  Ok(Executable {
+    lang: lang.clone(),
+    file: res.files[lang.exec()].clone(),
+    report: CompileReport::from(&res.result),
+    message,
+    labels: lang.labels().clone(),
+  })
+}
+
+impl Executable {
+  /// Require the labels of the backends running the executable too, e.g. those of a problem.
+  pub fn with_labels(mut self, labels: &[String]) -> Self {
+    for label in labels {
+      if !self.labels.contains(label) {
+        self.labels.push(label.clone());
+      }
+    }
+    return self;
+  }
 }
 
 impl Source {
@@ -27,48 +210,82 @@ impl Source {
   ///
   /// # Errors
   ///
-  /// This function will return an error if the compilation failed or
-  /// a sandbox internal error was encountered.
+  /// This function will return an error if the compilation failed,
+  /// an extra argument is denied by the language config,
+  /// or a sandbox internal error was encountered.
   pub async fn compile(
     &self,
     args: Vec<String>,
-    mut copy_in: HashMap<String, sandbox::FileHandle>,
+    copy_in: HashMap<String, sandbox::FileHandle>,
   ) -> Result<Executable, error::CompileError> {
-    copy_in.insert(
-      self.lang.source().to_string(),
-      sandbox::FileHandle::upload(&self.data.as_bytes()).await,
-    );
-
-    let mut res = sandbox::Request::Run(sandbox::Cmd {
-      args: [self.lang.compile_cmd().clone(), args].concat(),
-      copy_in,
-      copy_out: vec!["stderr".to_string(), self.lang.exec().to_string()],
-      ..Default::default()
-    })
-    .exec()
-    .await;
-
-    assert_eq!(res.len(), 1);
-    let res = res.pop().unwrap();
-
-    if res.result.status != sandbox::Status::Accepted {
-      return Err(error::CompileError {
-        result: res.result,
-        message: match res.files.get("stderr") {
-          Some(message_file) => message_file
-            .context()
-            .await
-            .map_or("broken message".to_string(), |chars| {
-              String::from_utf8_lossy(&chars).to_string()
-            }),
-          None => "no compile message".to_string(),
+    // Reject denied arguments before reaching the sandbox.
+    check_compile_args(&self.lang, &args)?;
+
+    let source = self
+      .data
+      .upload()
+      .await
+      .map_err(|err| error::CompileError {
+        result: sandbox::ExecuteResult {
+          status: sandbox::Status::FileError,
+          time: std::time::Duration::ZERO,
+          wall_time: std::time::Duration::ZERO,
+          memory: 0,
+          exit_code: -1,
+          error: String::new(),
+          timing: sandbox::Timing::default(),
         },
-      });
+        message: format!("source can not be read: {}", err),
+      })?;
+    return compile_uploaded(&self.lang, source, args, copy_in).await;
+  }
+
+  /// Compile the given code like `compile`, but skip the compilation if the same code
+  /// was compiled with the same arguments and extra files before.
+  ///
+  /// Compile errors are not cached.
+  pub async fn compile_cached(
+    &self,
+    args: Vec<String>,
+    copy_in: HashMap<String, sandbox::FileHandle>,
+    cache: &CompileCache,
+  ) -> Result<Executable, error::CompileError> {
+    let key = self.cache_key(&args, &copy_in).await;
+    if let Some(exec) = key.as_ref().and_then(|x| cache.get(x)) {
+      return Ok(exec);
+    }
+    let exec = self.compile(args, copy_in).await?;
+    if let Some(key) = key {
+      cache.insert(key, exec.clone());
     }
+    return Ok(exec);
+  }
 
-    Ok(Executable {
-      lang: self.lang.clone(),
-      file: res.files[self.lang.exec()].clone(),
-    })
+  /// Key of a compilation in the cache.
+  ///
+  /// Extra files are keyed by their contents, so sources of different problems sharing the same
+  /// extra files (e.g. `testlib.h`) hit the same entry.
+  async fn cache_key(
+    &self,
+    args: &[String],
+    copy_in: &HashMap<String, sandbox::FileHandle>,
+  ) -> Option<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(self.lang.name());
+    hasher.update([0]);
+    hasher.update(Sha256::digest(self.data.read().await.ok()?));
+    for x in args {
+      hasher.update(x);
+      hasher.update([0]);
+    }
+    let mut copy_in: Vec<_> = copy_in.iter().collect();
+    copy_in.sort_by_key(|x| x.0);
+    for (name, file) in copy_in {
+      hasher.update(name);
+      hasher.update([0]);
+      hasher.update(file.sha256().await.ok()?);
+      hasher.update([0]);
+    }
+    return Some(hex::encode(hasher.finalize()));
   }
 }
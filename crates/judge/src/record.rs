@@ -1,52 +1,57 @@
-use std::time;
+use std::{fmt, time};
 
 use serde::{Deserialize, Serialize};
 use strum::Display;
 
-use crate::{checker, error, sandbox};
+use crate::{checker, error, judge, sandbox};
 
-/// Judge result status for a program.
-#[derive(Debug, PartialEq, strum::EnumString, Serialize, Deserialize, Clone, Display)]
-#[strum(serialize_all = "snake_case")]
-pub enum RecordStatus {
-  Waiting,
-  Skipped,
-  Accepted,
-  WrongAnswer,
-  PartiallyCorrect,
-  PresentationError,
-  TimeLimitExceeded,
-  MemoryLimitExceeded,
-  OutputLimitExceeded,
-  FileError,
-  RuntimeError,
-  SystemError,
+pub use rindag_types::record::{RecordStatus, TestId, TestIdError, Timestamp};
+
+/// Why the sandbox terminated a program, besides exceeding the limits.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Detail {
+  /// The program is terminated by a signal.
+  Signal {
+    number: i32,
+
+    /// Description of the signal (see `sandbox::describe_signal`), None if it is not common.
+    description: Option<String>,
+  },
+
+  /// The program calls a syscall forbidden by the sandbox.
+  DangerousSyscall {
+    /// The syscall reported by the sandbox, None if it is not reported.
+    syscall: Option<String>,
+  },
 }
 
-impl From<sandbox::Status> for RecordStatus {
-  fn from(s: sandbox::Status) -> Self {
-    match s {
-      sandbox::Status::Accepted => Self::Accepted,
-      sandbox::Status::TimeLimitExceeded => Self::TimeLimitExceeded,
-      sandbox::Status::MemoryLimitExceeded => Self::MemoryLimitExceeded,
-      sandbox::Status::OutputLimitExceeded => Self::OutputLimitExceeded,
-      sandbox::Status::FileError => Self::FileError,
-      sandbox::Status::NonZeroExitStatus => Self::RuntimeError,
-      sandbox::Status::DangerousSyscall => Self::RuntimeError,
-      sandbox::Status::Signalled => Self::RuntimeError,
-      sandbox::Status::InternalError => Self::SystemError,
-    }
+impl Detail {
+  /// Detail of an execution result, None if it is neither signalled nor a dangerous syscall.
+  pub fn new(result: &sandbox::ExecuteResult) -> Option<Self> {
+    return match result.status {
+      sandbox::Status::Signalled => Some(Self::Signal {
+        number: result.exit_code,
+        description: sandbox::describe_signal(result.exit_code).map(str::to_string),
+      }),
+      sandbox::Status::DangerousSyscall => Some(Self::DangerousSyscall {
+        syscall: result.syscall().map(str::to_string),
+      }),
+      _ => None,
+    };
   }
 }
 
-impl From<checker::Status> for RecordStatus {
-  fn from(s: checker::Status) -> Self {
-    match s {
-      checker::Status::Accepted => Self::Accepted,
-      checker::Status::WrongAnswer => Self::WrongAnswer,
-      checker::Status::PartiallyCorrect => Self::PartiallyCorrect,
-      checker::Status::PresentationError => Self::PresentationError,
-      checker::Status::SystemError => Self::SystemError,
+impl fmt::Display for Detail {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Signal {
+        description: Some(x),
+        ..
+      } => write!(f, "{}", x),
+      Self::Signal { number, .. } => write!(f, "terminated by signal {}", number),
+      Self::DangerousSyscall { syscall: Some(x) } => write!(f, "dangerous syscall ({})", x),
+      Self::DangerousSyscall { syscall: None } => write!(f, "dangerous syscall"),
     }
   }
 }
@@ -54,12 +59,34 @@ impl From<checker::Status> for RecordStatus {
 /// A judge record of a solution running a single test.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Record {
+  /// Identifier of the test which this record belongs to.
+  ///
+  /// None if the record is not produced by a concrete test (e.g. the waiting placeholder).
+  pub test_id: Option<TestId>,
+
+  /// Id of the judge job which produced this record.
+  pub correlation_id: Option<uuid::Uuid>,
+
+  /// Time when the judgement of the test started.
+  pub started_at: Option<Timestamp>,
+
+  /// Time when the judgement of the test finished.
+  pub finished_at: Option<Timestamp>,
+
   /// Judge status.
   pub status: RecordStatus,
 
-  /// Code run time.
+  /// Code run time (CPU time).
   pub time: time::Duration,
 
+  /// Wall clock time of the run.
+  #[serde(default)]
+  pub wall_time: time::Duration,
+
+  /// Which of `time` and `wall_time` was limited by the time limit.
+  #[serde(default)]
+  pub time_kind: judge::TimeKind,
+
   /// Memory in bytes.
   pub memory: u64,
 
@@ -69,63 +96,457 @@ pub struct Record {
   /// Score.
   pub score: f32,
 
+  /// Points reported by the checker before clamped into `score` (see `checker::Output`),
+  /// None if the checker reported no points.
+  #[serde(default)]
+  pub raw_points: Option<f64>,
+
   /// A message for human reading (like status explanation or checker message).
   pub message: String,
+
+  /// Id of the artifact holding the full checker message (see `artifact::Store`),
+  /// if `message` is only its prefix.
+  #[serde(default)]
+  pub message_artifact: Option<String>,
+
+  /// The status is time limit exceeded, but the run finished within the grace window
+  /// (see `JudgeCfg::time_limit_grace`), so `time` is the actual time used.
+  ///
+  /// It helps to spot borderline solutions when reviewing.
+  #[serde(default)]
+  pub almost_tle: bool,
+
+  /// Number of the signal which terminated the program, if it is signalled.
+  #[serde(default)]
+  pub signal: Option<i32>,
+
+  /// Why the sandbox terminated the program, if it is signalled or calls a dangerous syscall.
+  #[serde(default)]
+  pub detail: Option<Detail>,
+
+  /// Number of times the test was run, more than 1 if it was re-run after flaky failures
+  /// (see `Record::is_flaky`).
+  #[serde(default = "default_attempts")]
+  pub attempts: u32,
+
+  /// Seed passed to the checker (see `checker::Checker::with_seed`),
+  /// None if the checker is not seeded.
+  #[serde(default)]
+  pub checker_seed: Option<u64>,
+
+  /// Why the test is skipped, None if it is not skipped.
+  #[serde(default)]
+  pub skip_reason: Option<SkipReason>,
+
+  /// How the time of the run was measured (like the CPUs it ran on),
+  /// None if nothing was run.
+  #[serde(default)]
+  pub timing: Option<sandbox::Timing>,
+}
+
+/// Why a test is skipped.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SkipReason {
+  /// A subtask which the subtask of the test depends on scored zero.
+  Dependency,
+
+  /// The judge job is cancelled.
+  Cancelled,
+
+  /// The deadline of the judge job or the time budget of the subtask is exceeded
+  /// (see `etc::ProfileCfg::deadline`).
+  DeadlineExceeded,
+}
+
+fn default_attempts() -> u32 {
+  return 1;
 }
 
 lazy_static! {
   pub static ref RECORD_WAITING: Record = Record {
+    test_id: None,
+    correlation_id: None,
+    started_at: None,
+    finished_at: None,
     status: RecordStatus::Waiting,
     time: time::Duration::ZERO,
+    wall_time: time::Duration::ZERO,
+    time_kind: judge::TimeKind::Cpu,
     memory: 0,
     exit_code: -1,
     score: 0.,
+    raw_points: None,
     message: "waiting".to_string(),
+    message_artifact: None,
+    almost_tle: false,
+    signal: None,
+    detail: None,
+    attempts: 1,
+    checker_seed: None,
+    skip_reason: None,
+    timing: None,
   };
   pub static ref RECORD_SKIPPED: Record = Record {
+    test_id: None,
+    correlation_id: None,
+    started_at: None,
+    finished_at: None,
     status: RecordStatus::Skipped,
     time: time::Duration::ZERO,
+    wall_time: time::Duration::ZERO,
+    time_kind: judge::TimeKind::Cpu,
     memory: 0,
     exit_code: -1,
     score: 0.,
+    raw_points: None,
     message: "skipped".to_string(),
+    message_artifact: None,
+    almost_tle: false,
+    signal: None,
+    detail: None,
+    attempts: 1,
+    checker_seed: None,
+    skip_reason: None,
+    timing: None,
   };
 }
 
 impl Record {
+  /// Create a new skipped record.
+  pub fn new_skipped(reason: SkipReason) -> Self {
+    let message = match reason {
+      SkipReason::Dependency => "skipped",
+      SkipReason::Cancelled => "cancelled",
+      SkipReason::DeadlineExceeded => "deadline exceeded",
+    };
+    Self {
+      message: message.to_string(),
+      skip_reason: Some(reason),
+      timing: None,
+      ..RECORD_SKIPPED.clone()
+    }
+  }
+
   /// Create a new system error record.
   pub fn new_system_error(message: &str) -> Self {
     Self {
+      test_id: None,
+      correlation_id: None,
+      started_at: None,
+      finished_at: None,
       status: RecordStatus::SystemError,
       time: time::Duration::ZERO,
+      wall_time: time::Duration::ZERO,
+      time_kind: judge::TimeKind::Cpu,
       memory: 0,
       exit_code: -1,
       score: 0.,
+      raw_points: None,
       message: message.to_string(),
+      message_artifact: None,
+      almost_tle: false,
+      signal: None,
+      detail: None,
+      attempts: 1,
+      checker_seed: None,
+      skip_reason: None,
+      timing: None,
     }
   }
 
   /// Creates a Record from an ExecuteResult that was interrupted (not exited normally).
+  ///
+  /// The signal or the dangerous syscall is described in the message.
   pub fn new_interrupted(result: &sandbox::ExecuteResult) -> Self {
+    let message = error::RuntimeError::from(result.clone()).to_string();
+    let signal = result.signal();
+    let detail = Detail::new(result);
     Self {
+      test_id: None,
+      correlation_id: None,
+      started_at: None,
+      finished_at: None,
       status: result.status.clone().into(),
       time: result.time,
+      wall_time: result.wall_time,
+      time_kind: judge::TimeKind::Cpu,
       memory: result.memory,
       exit_code: result.exit_code,
       score: 0.,
-      message: error::RuntimeError::from(result.clone()).to_string(),
+      raw_points: None,
+      message: match &detail {
+        Some(x) => format!("{}: {}", x, message),
+        None => message,
+      },
+      message_artifact: None,
+      almost_tle: false,
+      signal,
+      detail,
+      attempts: 1,
+      checker_seed: None,
+      skip_reason: None,
+      timing: Some(result.timing.clone()),
     }
   }
 
   /// Combine a JudgeResult and a checker::Output into a Record.
   pub fn new_checked(result: &sandbox::ExecuteResult, checker_output: &checker::Output) -> Self {
     Self {
+      test_id: None,
+      correlation_id: None,
+      started_at: None,
+      finished_at: None,
       status: checker_output.status.clone().into(),
       time: result.time,
+      wall_time: result.wall_time,
+      time_kind: judge::TimeKind::Cpu,
       memory: result.memory,
       exit_code: result.exit_code,
       score: checker_output.score,
+      raw_points: checker_output.raw_points,
       message: checker_output.message.clone(),
+      message_artifact: checker_output.artifact.clone(),
+      almost_tle: false,
+      signal: None,
+      detail: None,
+      attempts: 1,
+      checker_seed: None,
+      skip_reason: None,
+      timing: Some(result.timing.clone()),
     }
   }
+
+  /// Create a record of a submitted answer file checked by checker, nothing was executed.
+  pub fn new_answer_checked(checker_output: &checker::Output) -> Self {
+    Self {
+      test_id: None,
+      correlation_id: None,
+      started_at: None,
+      finished_at: None,
+      status: checker_output.status.clone().into(),
+      time: time::Duration::ZERO,
+      wall_time: time::Duration::ZERO,
+      time_kind: judge::TimeKind::Cpu,
+      memory: 0,
+      exit_code: 0,
+      score: checker_output.score,
+      raw_points: checker_output.raw_points,
+      message: checker_output.message.clone(),
+      message_artifact: checker_output.artifact.clone(),
+      almost_tle: false,
+      signal: None,
+      detail: None,
+      attempts: 1,
+      checker_seed: None,
+      skip_reason: None,
+      timing: None,
+    }
+  }
+
+  /// Create a record of a test whose answer file is not submitted.
+  pub fn new_answer_missing() -> Self {
+    Self {
+      test_id: None,
+      correlation_id: None,
+      started_at: None,
+      finished_at: None,
+      status: RecordStatus::FileError,
+      time: time::Duration::ZERO,
+      wall_time: time::Duration::ZERO,
+      time_kind: judge::TimeKind::Cpu,
+      memory: 0,
+      exit_code: -1,
+      score: 0.,
+      raw_points: None,
+      message: "answer file is not submitted".to_string(),
+      message_artifact: None,
+      almost_tle: false,
+      signal: None,
+      detail: None,
+      attempts: 1,
+      checker_seed: None,
+      skip_reason: None,
+      timing: None,
+    }
+  }
+
+  /// Points of the test in a subtask scored by points (see `problem::ScoringPolicy::Points`),
+  /// the raw points of the checker, or the score if the checker reported no points.
+  pub fn points(&self) -> f32 {
+    return self.raw_points.map_or(self.score, |x| x as f32);
+  }
+
+  /// Attach the test identifier and the job correlation id to the record.
+  pub fn with_id(mut self, test_id: TestId, correlation_id: uuid::Uuid) -> Self {
+    self.test_id = Some(test_id);
+    self.correlation_id = Some(correlation_id);
+    self
+  }
+
+  /// Attach the seed passed to the checker.
+  pub fn with_checker_seed(mut self, seed: Option<u64>) -> Self {
+    self.checker_seed = seed;
+    self
+  }
+
+  /// Tell which time of the run was limited by the time limit.
+  pub fn with_time_kind(mut self, time_kind: judge::TimeKind) -> Self {
+    self.time_kind = time_kind;
+    self
+  }
+
+  /// Time of the run limited by the time limit, `time` or `wall_time` by `time_kind`.
+  pub fn limited_time(&self) -> time::Duration {
+    return match self.time_kind {
+      judge::TimeKind::Cpu => self.time,
+      judge::TimeKind::Wall => self.wall_time,
+    };
+  }
+
+  /// Flag the record as almost TLE if it is time limit exceeded under `time_limit`,
+  /// but the run finished within the grace window instead of being killed.
+  ///
+  /// The time of `time_kind` is compared, so set it (see `with_time_kind`) before.
+  pub fn with_almost_tle(mut self, time_limit: time::Duration) -> Self {
+    self.almost_tle = self.status == RecordStatus::TimeLimitExceeded
+      && self.limited_time() < judge::grace_time_limit(time_limit);
+    self
+  }
+
+  /// Attach the time range of the judgement to the record.
+  pub fn with_time_range(mut self, started_at: Timestamp, finished_at: Timestamp) -> Self {
+    self.started_at = Some(started_at);
+    self.finished_at = Some(finished_at);
+    self
+  }
+
+  /// Whether the record may be caused by a flaky sandbox instead of the solution
+  /// (system error or file error), so the test is worth re-running.
+  pub fn is_flaky(&self) -> bool {
+    return matches!(
+      self.status,
+      RecordStatus::SystemError | RecordStatus::FileError
+    );
+  }
+}
+
+/// A new time is a regression if it exceeds the old time by both this ratio and `TIME_REGRESSION_MIN`.
+pub const TIME_REGRESSION_RATIO: f64 = 1.5;
+
+/// Minimal absolute slowdown of a time regression, so that noise on tiny times is ignored.
+pub const TIME_REGRESSION_MIN: time::Duration = time::Duration::from_millis(50);
+
+/// A change of a single test between two judgements.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Change {
+  /// The status of the test changed.
+  Verdict {
+    test: String,
+    old: RecordStatus,
+    new: RecordStatus,
+  },
+
+  /// The score of the test changed.
+  Score { test: String, old: f32, new: f32 },
+
+  /// The test runs significantly slower.
+  TimeRegression {
+    test: String,
+    old: time::Duration,
+    new: time::Duration,
+  },
+
+  /// The test only exists in the new judgement.
+  Added { test: String },
+
+  /// The test only exists in the old judgement.
+  Removed { test: String },
+}
+
+/// Structured changes between two judgements of the same solution (e.g. before and after a rejudge).
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Default)]
+pub struct Changeset {
+  pub changes: Vec<Change>,
+}
+
+impl Changeset {
+  /// Returns true if nothing changed.
+  pub fn is_empty(&self) -> bool {
+    self.changes.is_empty()
+  }
+
+  /// Sum of the score deltas of all changed tests.
+  pub fn score_delta(&self) -> f32 {
+    self
+      .changes
+      .iter()
+      .map(|x| match x {
+        Change::Score { old, new, .. } => new - old,
+        _ => 0.,
+      })
+      .sum()
+  }
+}
+
+/// Compare the records of two judgements and produce the changeset.
+///
+/// Records are paired by test id if all of them have one, and by position otherwise.
+/// Tests are named by their test ids, or by `#<index>` when paired by position.
+pub fn diff(old: &[Record], new: &[Record]) -> Changeset {
+  let by_id = old.iter().chain(new).all(|x| x.test_id.is_some());
+  let key = |i: usize, x: &Record| match (by_id, x.test_id) {
+    (true, Some(id)) => id.to_string(),
+    _ => format!("#{}", i),
+  };
+  let new_keys: Vec<_> = new.iter().enumerate().map(|(i, x)| key(i, x)).collect();
+
+  let mut changes = vec![];
+  let mut paired = vec![false; new.len()];
+  for (i, old) in old.iter().enumerate() {
+    let test = key(i, old);
+    let j = match new_keys.iter().position(|x| x == &test) {
+      Some(j) => j,
+      None => {
+        changes.push(Change::Removed { test });
+        continue;
+      }
+    };
+    paired[j] = true;
+    let new = &new[j];
+
+    if old.status != new.status {
+      changes.push(Change::Verdict {
+        test: test.clone(),
+        old: old.status.clone(),
+        new: new.status.clone(),
+      });
+    }
+    if (old.score - new.score).abs() > 1e-6 {
+      changes.push(Change::Score {
+        test: test.clone(),
+        old: old.score,
+        new: new.score,
+      });
+    }
+    if new.time > old.time.mul_f64(TIME_REGRESSION_RATIO)
+      && new.time > old.time + TIME_REGRESSION_MIN
+    {
+      changes.push(Change::TimeRegression {
+        test,
+        old: old.time,
+        new: new.time,
+      });
+    }
+  }
+
+  for (j, test) in new_keys.into_iter().enumerate() {
+    if !paired[j] {
+      changes.push(Change::Added { test });
+    }
+  }
+
+  return Changeset { changes };
 }
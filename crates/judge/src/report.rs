@@ -0,0 +1,139 @@
+use std::fmt::Write;
+
+use crate::record;
+
+/// Short verdict name, like the ones shown in Codeforces Polygon invocation tables.
+fn verdict(status: &record::RecordStatus) -> &'static str {
+  match status {
+    record::RecordStatus::Waiting => "WT",
+    record::RecordStatus::Skipped => "SK",
+    record::RecordStatus::Accepted => "OK",
+    record::RecordStatus::WrongAnswer => "WA",
+    record::RecordStatus::PartiallyCorrect => "PC",
+    record::RecordStatus::PresentationError => "PE",
+    record::RecordStatus::TimeLimitExceeded => "TL",
+    record::RecordStatus::IdlenessLimitExceeded => "IL",
+    record::RecordStatus::MemoryLimitExceeded => "ML",
+    record::RecordStatus::OutputLimitExceeded => "OL",
+    record::RecordStatus::FileError => "FE",
+    record::RecordStatus::RuntimeError => "RE",
+    record::RecordStatus::SystemError => "FL",
+  }
+}
+
+/// Background color of a verdict cell in HTML tables.
+fn color(status: &record::RecordStatus) -> &'static str {
+  match status {
+    record::RecordStatus::Accepted => "#c6efce",
+    record::RecordStatus::PartiallyCorrect => "#ffeb9c",
+    record::RecordStatus::Waiting | record::RecordStatus::Skipped => "#eeeeee",
+    record::RecordStatus::SystemError => "#d9b3ff",
+    _ => "#ffc7ce",
+  }
+}
+
+/// Escape a field of CSV.
+fn escape_csv(s: &str) -> String {
+  if s.contains([',', '"', '\n', '\r']) {
+    return format!("\"{}\"", s.replace('"', "\"\""));
+  }
+  return s.to_string();
+}
+
+/// Escape a text of HTML.
+fn escape_html(s: &str) -> String {
+  s.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+/// Name of the test column of the record at `index` in a row.
+fn test_name(record: &record::Record, index: usize) -> String {
+  match &record.test_id {
+    Some(id) => id.to_string(),
+    None => index.to_string(),
+  }
+}
+
+/// Verdict table of several solutions judged on the same tests (solutions × tests).
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+  /// Rows of the table, each is a solution name and its records in test order.
+  pub rows: Vec<(String, Vec<record::Record>)>,
+}
+
+impl Table {
+  /// Append the records of a solution to the table.
+  pub fn push(&mut self, solution: &str, records: Vec<record::Record>) {
+    self.rows.push((solution.to_string(), records));
+  }
+
+  /// Column names of the table, taken from the longest row.
+  fn columns(&self) -> Vec<String> {
+    self
+      .rows
+      .iter()
+      .max_by_key(|r| r.1.len())
+      .map_or(vec![], |r| {
+        r.1
+          .iter()
+          .enumerate()
+          .map(|(i, x)| test_name(x, i))
+          .collect()
+      })
+  }
+
+  /// Render the table as CSV, one line per (solution, test).
+  ///
+  /// Columns: `solution,test,verdict,status,score,time_ms,memory_kb`.
+  pub fn to_csv(&self) -> String {
+    let mut ret = "solution,test,verdict,status,score,time_ms,memory_kb\n".to_string();
+    for (solution, records) in &self.rows {
+      for (i, r) in records.iter().enumerate() {
+        _ = writeln!(
+          ret,
+          "{},{},{},{},{},{},{}",
+          escape_csv(solution),
+          escape_csv(&test_name(r, i)),
+          verdict(&r.status),
+          r.status,
+          r.score,
+          r.time.as_millis(),
+          r.memory / 1024
+        );
+      }
+    }
+    return ret;
+  }
+
+  /// Render the table as a standalone colored HTML table.
+  ///
+  /// Each cell shows the verdict, time and memory, the record message is shown as a tooltip.
+  pub fn to_html(&self) -> String {
+    let mut ret = "<table class=\"verdicts\">\n<tr><th>solution</th>".to_string();
+    for c in self.columns() {
+      _ = write!(ret, "<th>{}</th>", escape_html(&c));
+    }
+    ret += "</tr>\n";
+
+    for (solution, records) in &self.rows {
+      _ = write!(ret, "<tr><th>{}</th>", escape_html(solution));
+      for r in records {
+        _ = write!(
+          ret,
+          "<td style=\"background-color: {}\" title=\"{}\">{}<br>{} ms<br>{} KB</td>",
+          color(&r.status),
+          escape_html(&r.message),
+          verdict(&r.status),
+          r.time.as_millis(),
+          r.memory / 1024
+        );
+      }
+      ret += "</tr>\n";
+    }
+
+    ret += "</table>\n";
+    return ret;
+  }
+}
@@ -0,0 +1,233 @@
+use std::{collections::HashSet, time};
+
+use thiserror::Error;
+
+use crate::etc;
+
+use super::{Cmd, FileHandle, Request};
+
+/// Builder of `Cmd`, which validates options before they are sent to the sandbox.
+///
+/// Constructing a `Cmd` directly makes it easy to build requests which go-judge rejects
+/// (or which never succeed, like a zero time limit),
+/// the builder reports them as `CmdBuildError` instead.
+#[derive(Debug, Clone)]
+pub struct CmdBuilder {
+  cmd: Cmd,
+}
+
+impl CmdBuilder {
+  /// Create a builder, the limits are filled with the defaults of the given judge config.
+  pub fn new(conf: &etc::JudgeCfg) -> Self {
+    Self {
+      cmd: Cmd::with_config(conf),
+    }
+  }
+
+  /// Append a command line argument.
+  pub fn arg(mut self, arg: impl Into<String>) -> Self {
+    self.cmd.args.push(arg.into());
+    self
+  }
+
+  /// Append command line arguments.
+  pub fn args<I, S>(mut self, args: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.cmd.args.extend(args.into_iter().map(Into::into));
+    self
+  }
+
+  /// Append an environment variable in `KEY=value` format.
+  pub fn env(mut self, env: impl Into<String>) -> Self {
+    self.cmd.env.push(env.into());
+    self
+  }
+
+  pub fn time_limit(mut self, time_limit: time::Duration) -> Self {
+    self.cmd.time_limit = time_limit;
+    self
+  }
+
+  pub fn memory_limit(mut self, memory_limit: u64) -> Self {
+    self.cmd.memory_limit = memory_limit;
+    self
+  }
+
+  pub fn stdout_limit(mut self, stdout_limit: i64) -> Self {
+    self.cmd.stdout_limit = stdout_limit;
+    self
+  }
+
+  pub fn stderr_limit(mut self, stderr_limit: i64) -> Self {
+    self.cmd.stderr_limit = stderr_limit;
+    self
+  }
+
+  pub fn stack_limit(mut self, stack_limit: u64) -> Self {
+    self.cmd.stack_limit = Some(stack_limit);
+    self
+  }
+
+  pub fn proc_limit(mut self, proc_limit: u64) -> Self {
+    self.cmd.proc_limit = proc_limit;
+    self
+  }
+
+  /// Limit the CPU usage, in thousandths of a CPU.
+  pub fn cpu_rate_limit(mut self, cpu_rate_limit: u64) -> Self {
+    self.cmd.cpu_rate_limit = cpu_rate_limit;
+    self
+  }
+
+  /// Limit the CPUs to run on, in cpuset format (like `0-3,6`).
+  pub fn cpu_set_limit(mut self, cpu_set_limit: impl Into<String>) -> Self {
+    self.cmd.cpu_set_limit = cpu_set_limit.into();
+    self
+  }
+
+  /// Run on a single CPU of the cpuset not used by other pinned commands (see `Cmd::pin_cpu`).
+  pub fn pin_cpu(mut self, pin: bool) -> Self {
+    self.cmd.pin_cpu = pin;
+    self
+  }
+
+  pub fn strict_memory_limit(mut self, strict: bool) -> Self {
+    self.cmd.strict_memory_limit = strict;
+    self
+  }
+
+  pub fn tty(mut self, tty: bool) -> Self {
+    self.cmd.tty = tty;
+    self
+  }
+
+  pub fn timed(mut self, timed: bool) -> Self {
+    self.cmd.timed = timed;
+    self
+  }
+
+  pub fn clock_limit_ratio(mut self, ratio: f64) -> Self {
+    self.cmd.clock_limit_ratio = ratio;
+    self
+  }
+
+  /// Wall clock time limit, overriding `clock_limit_ratio`.
+  pub fn wall_time_limit(mut self, wall_time_limit: time::Duration) -> Self {
+    self.cmd.wall_time_limit = Some(wall_time_limit);
+    self
+  }
+
+  /// Max size of each copy-out file in bytes.
+  pub fn copy_out_max(mut self, max: u64) -> Self {
+    self.cmd.copy_out_max = max;
+    self
+  }
+
+  pub fn stdin(mut self, file: FileHandle) -> Self {
+    self.cmd.stdin = Some(file);
+    self
+  }
+
+  /// Copy a sandbox file into the container path `name`.
+  pub fn copy_in(mut self, name: impl Into<String>, file: FileHandle) -> Self {
+    self.cmd.copy_in.insert(name.into(), file);
+    self
+  }
+
+  /// Upload the content to sandbox and copy it into the container path `name`.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the content can not be uploaded.
+  pub async fn copy_in_bytes(
+    self,
+    name: impl Into<String>,
+    content: &[u8],
+  ) -> Result<Self, super::Error> {
+    let file = FileHandle::upload(content).await?;
+    Ok(self.copy_in(name, file))
+  }
+
+  /// Copy out a file from the container after execution.
+  ///
+  /// Append '?' after file name will make the file optional.
+  pub fn copy_out(mut self, name: impl Into<String>) -> Self {
+    self.cmd.copy_out.push(name.into());
+    self
+  }
+
+  /// Validate the options and build the `Cmd`.
+  pub fn build(self) -> Result<Cmd, CmdBuildError> {
+    let cmd = self.cmd;
+    if cmd.args.is_empty() {
+      return Err(CmdBuildError::MissingArgs);
+    }
+    if cmd.time_limit.is_zero() {
+      return Err(CmdBuildError::ZeroLimit("time"));
+    }
+    if cmd.memory_limit == 0 {
+      return Err(CmdBuildError::ZeroLimit("memory"));
+    }
+    if cmd.stdout_limit <= 0 {
+      return Err(CmdBuildError::ZeroLimit("stdout"));
+    }
+    if cmd.stderr_limit <= 0 {
+      return Err(CmdBuildError::ZeroLimit("stderr"));
+    }
+    if cmd.stack_limit == Some(0) {
+      return Err(CmdBuildError::ZeroLimit("stack"));
+    }
+    if cmd.proc_limit == 0 {
+      return Err(CmdBuildError::ZeroLimit("process"));
+    }
+    if cmd.wall_time_limit == Some(time::Duration::ZERO) {
+      return Err(CmdBuildError::ZeroLimit("wall time"));
+    }
+    // The wall clock limit must not kill a program before its time limit.
+    if cmd.clock_limit_ratio.is_nan() || cmd.clock_limit_ratio < 1. {
+      return Err(CmdBuildError::ClockLimitRatio(cmd.clock_limit_ratio));
+    }
+    match super::parse_cpu_set(&cmd.cpu_set_limit) {
+      None => return Err(CmdBuildError::InvalidCpuSet(cmd.cpu_set_limit)),
+      Some(x) if x.is_empty() && cmd.pin_cpu => {
+        return Err(CmdBuildError::InvalidCpuSet(cmd.cpu_set_limit));
+      }
+      Some(_) => {}
+    }
+    let mut names = HashSet::new();
+    for name in &cmd.copy_out {
+      if !names.insert(name.trim_end_matches('?')) {
+        return Err(CmdBuildError::DuplicateCopyOut(name.clone()));
+      }
+    }
+    return Ok(cmd);
+  }
+
+  /// Validate the options and build a `Request::Run` of the command.
+  pub fn build_request(self) -> Result<Request, CmdBuildError> {
+    Ok(Request::Run(self.build()?))
+  }
+}
+
+/// Error when a `Cmd` built by `CmdBuilder` is invalid.
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum CmdBuildError {
+  #[error("command line arguments are empty")]
+  MissingArgs,
+
+  #[error("{0} limit must be positive")]
+  ZeroLimit(&'static str),
+
+  #[error("file is copied out more than once: {0}")]
+  DuplicateCopyOut(String),
+
+  #[error("clock limit ratio must be at least 1, got {0}")]
+  ClockLimitRatio(f64),
+
+  /// The cpuset is malformed, or empty while the command is pinned.
+  #[error("invalid cpuset to run on: {0:?}")]
+  InvalidCpuSet(String),
+}
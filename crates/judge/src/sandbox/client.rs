@@ -1,81 +1,80 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, future::Future};
 
-use async_once::AsyncOnce;
-use thiserror::Error;
+use futures::channel::mpsc;
 
+use super::{
+  grpc::GrpcTransport, transport::SandboxTransport, websocket::WebSocketTransport, Error,
+};
 use crate::{etc, sandbox::proto, CONFIG};
 
-/// go-judge client
-#[derive(Clone)]
+/// go-judge client of a sandbox backend (see `Scheduler`).
+///
+/// Calls are sent by the transport selected by `sandbox.transport` in config,
+/// and a call failed because the sandbox is unavailable (e.g. restarting) is retried
+/// with exponential backoff.
 pub struct Client {
-  /// The gRPC client.
-  client: proto::executor_client::ExecutorClient<tonic::transport::Channel>,
+  transport: Box<dyn SandboxTransport>,
 }
 
 impl Client {
-  /// Create a new client from host.
+  /// Create a new client from host, the gRPC transport uses a pool of `pool_size` channels.
   ///
   /// # Panics
   ///
-  /// Panics if the endpoint connect error.
-  async fn connect(conf: &etc::SandboxCfg) -> Self {
+  /// Panics if the host is not a valid uri.
+  pub(super) fn connect(host: &str, pool_size: usize) -> Self {
     return Self {
-      client: proto::executor_client::ExecutorClient::connect(conf.host.clone())
-        .await
-        .unwrap(),
+      transport: match CONFIG.sandbox.transport {
+        etc::Transport::Grpc => Box::new(GrpcTransport::connect(host, pool_size)),
+        etc::Transport::WebSocket => Box::new(WebSocketTransport::connect(host)),
+      },
     };
   }
 
+  /// Call the transport, and retry it with exponential backoff while the sandbox is unavailable,
+  /// at most `sandbox.retries` (in config) times.
+  async fn call<'a, T, F, Fut>(&'a self, name: &str, f: F) -> Result<T, Error>
+  where
+    F: Fn(&'a dyn SandboxTransport) -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+  {
+    let mut backoff = CONFIG.sandbox.retry_backoff;
+    let mut retries = 0;
+    loop {
+      match f(self.transport.as_ref()).await {
+        Err(Error::Unavailable(message)) if retries < CONFIG.sandbox.retries => {
+          log::warn!(
+            "sandbox {} unavailable, retry in {:?}: {}",
+            name,
+            backoff,
+            message
+          );
+          tokio::time::sleep(backoff).await;
+          backoff *= 2;
+          retries += 1;
+        }
+        res => return res,
+      }
+    }
+  }
+
   /// Get a file of sandbox server. and return it's content.
   ///
   /// # Errors
   ///
-  /// This function will return an error if the file is not found or the connect is broken.
-  pub(super) async fn file_get(&self, file_id: &str) -> Result<Vec<u8>, FileGetError> {
-    match self
-      .client
-      .clone()
-      .file_get(proto::FileId {
-        file_id: file_id.to_string(),
-      })
-      .await
-    {
-      Ok(f) => Ok(f.get_ref().content.clone()),
-      Err(err) => match err.code() {
-        tonic::Code::NotFound => Err(FileGetError {
-          id: file_id.to_string(),
-        }),
-        _ => panic!("file get error: {}", err),
-      },
-    }
+  /// This function will return an error if the file is not found or the sandbox is unavailable.
+  pub(super) async fn file_get(&self, file_id: &str) -> Result<Vec<u8>, Error> {
+    return self.call("file get", |x| x.file_get(file_id)).await;
   }
 
   /// Prepare a file in the sandbox, returns file id (can be referenced in `run` parameter).
-  pub(super) async fn file_add(&self, content: &[u8]) -> String {
-    self
-      .client
-      .clone()
-      .file_add(proto::FileContent {
-        content: content.to_vec(),
-        ..Default::default()
-      })
-      .await
-      .unwrap()
-      .get_ref()
-      .file_id
-      .clone()
+  pub(super) async fn file_add(&self, content: &[u8]) -> Result<String, Error> {
+    return self.call("file add", |x| x.file_add(content)).await;
   }
 
   /// Delete a file of sandbox server.
-  pub(super) async fn file_delete(&self, file_id: &str) {
-    self
-      .client
-      .clone()
-      .file_delete(proto::FileId {
-        file_id: file_id.to_string(),
-      })
-      .await
-      .unwrap();
+  pub(super) async fn file_delete(&self, file_id: &str) -> Result<(), Error> {
+    return self.call("file delete", |x| x.file_delete(file_id)).await;
   }
 
   /// List all files of sandbox server.
@@ -83,37 +82,42 @@ impl Client {
   /// - Key of hashmap is file id.
   /// - Value of hashmap is file name.
   #[allow(dead_code)]
-  pub async fn file_list(&self) -> HashMap<String, String> {
-    self
-      .client
-      .clone()
-      .file_list(())
-      .await
-      .unwrap()
-      .get_ref()
-      .file_ids
-      .clone()
+  pub async fn file_list(&self) -> Result<HashMap<String, String>, Error> {
+    return self.call("file list", |x| x.file_list()).await;
+  }
+
+  /// Check whether the sandbox is serving, by listing its files once without retries.
+  pub(super) async fn ping(&self) -> Result<(), Error> {
+    self.transport.file_list().await?;
+    return Ok(());
   }
 
   /// Execute some command (then not wait).
   ///
   /// All the command will be executed parallelly.
-  ///
-  /// Returns the uuid of request and an oneshot result receiver.
-  pub(super) async fn exec(&self, req: proto::Request) -> proto::Response {
-    let client = self.client.clone();
-    let res = client.clone().exec(req).await.unwrap();
-    res.get_ref().clone()
+  pub(super) async fn exec(&self, req: proto::Request) -> Result<proto::Response, Error> {
+    return self.call("exec", |x| x.exec(req.clone())).await;
   }
-}
 
-#[derive(Debug, Error)]
-#[error("file get error: {id}")]
-pub struct FileGetError {
-  pub id: String,
-}
+  /// Whether the transport supports `exec_stream`.
+  pub(super) fn streaming(&self) -> bool {
+    return self.transport.streaming();
+  }
 
-lazy_static! {
-  pub(super) static ref CLIENT: AsyncOnce<Client> =
-    AsyncOnce::new(Client::connect(&CONFIG.sandbox));
+  /// Execute a request by the streaming RPC, and send the inputs to its stream input files
+  /// while it is running.
+  ///
+  /// The stream inputs are closed when all the senders of `inputs` are dropped.
+  /// Contents written to the stream output files are sent to `outputs`,
+  /// the request is cancelled (and None is returned) if `outputs` is closed.
+  ///
+  /// It is not retried, since the inputs can not be sent again.
+  pub(super) async fn exec_stream(
+    &self,
+    req: proto::Request,
+    inputs: mpsc::Receiver<proto::stream_request::Input>,
+    outputs: Option<mpsc::Sender<Vec<u8>>>,
+  ) -> Result<Option<proto::Response>, Error> {
+    return self.transport.exec_stream(req, inputs, outputs).await;
+  }
 }
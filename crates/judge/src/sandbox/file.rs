@@ -1,6 +1,32 @@
-use std::sync::Arc;
+use std::{
+  collections::{hash_map::Entry, HashMap},
+  io,
+  sync::{Arc, Mutex, OnceLock},
+  time,
+};
 
-use super::client::{FileGetError, CLIENT};
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::io::StreamReader;
+
+use super::{
+  proto,
+  request::{pipe_collector, Cmd, Request},
+  scheduler::SCHEDULER,
+  Error, ResponseResult, Status,
+};
+use crate::CONFIG;
+
+/// Program to pipe the streamed files in the sandbox.
+const CAT: &str = "/bin/cat";
+
+/// Program to compute the digest of a file in the sandbox.
+const SHA256SUM: &str = "/usr/bin/sha256sum";
+
+/// CPU time limit of piping a streamed file.
+const STREAM_TIME_LIMIT: time::Duration = time::Duration::from_secs(30);
 
 /// Sandbox file handler.
 ///
@@ -8,6 +34,9 @@ use super::client::{FileGetError, CLIENT};
 /// As such it has a *cheap* `Clone` implementation.
 ///
 /// If the last handler instance of a file is dropped, the file will be deleted in the sandbox.
+///
+/// A file lives on the sandbox backend it is created on, and is copied to another backend
+/// when a request using it runs there (see `Request::exec`).
 #[derive(Debug, Clone)]
 pub struct FileHandle {
   inner: Arc<FileHandleInner>,
@@ -15,41 +44,478 @@ pub struct FileHandle {
 
 #[derive(Debug)]
 struct FileHandleInner {
+  /// Index of the backend holding the file.
+  backend: usize,
+
   /// File id.
   id: String,
+
+  /// Copies of the file on other backends, by backend index, deleted with the file.
+  replicas: Mutex<HashMap<usize, FileHandle>>,
+
+  /// Hex SHA-256 digest of the content, known when uploaded or once computed by `digest`.
+  digest: OnceLock<String>,
 }
 
 impl Drop for FileHandleInner {
   fn drop(&mut self) {
     log::debug!("dropped file {}", &self.id);
+    let backend = self.backend;
     let id = self.id.clone();
-    tokio::spawn(async move { CLIENT.get().await.file_delete(&id).await });
+    tokio::spawn(async move {
+      let scheduler = SCHEDULER.get().await;
+      if let Err(err) = scheduler.backend(backend).client.file_delete(&id).await {
+        log::warn!("file {} can not be deleted: {}", id, err);
+      }
+    });
   }
 }
 
 impl FileHandle {
   /// Upload a file to sandbox and return it's file hander.
-  pub async fn upload(content: &[u8]) -> Self {
-    let id = CLIENT.get().await.file_add(content).await;
-    Self {
-      inner: Arc::new(FileHandleInner { id }),
+  ///
+  /// A content larger than a chunk is streamed by `upload_stream`.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the sandbox is unavailable,
+  /// or the content is larger than `sandbox.upload_limit` (in config).
+  pub async fn upload(content: &[u8]) -> Result<Self, Error> {
+    if content.len() > CONFIG.sandbox.upload_chunk_size {
+      return Self::upload_stream(content).await.map_err(sandbox_error);
+    }
+    let scheduler = SCHEDULER.get().await;
+    let backend = scheduler.pick();
+    let id = scheduler
+      .run(backend, scheduler.backend(backend).client.file_add(content))
+      .await?;
+    return Ok(Self::from_id(backend, id).with_digest(hex::encode(Sha256::digest(content))));
+  }
+
+  /// Upload a file to sandbox from a reader and return it's file hander,
+  /// without holding the whole content in memory or sending it in a single gRPC message.
+  ///
+  /// The content is read in chunks of `sandbox.upload_chunk_size` (in config).
+  /// A content of a single chunk is uploaded at once, larger ones are streamed to the stdin of
+  /// `cat` in the sandbox by the streaming RPC, and its stdout is kept as the file.
+  /// If the transport does not support streaming, the whole content is uploaded at once.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the reader fails, the sandbox fails
+  /// (wrapping a `sandbox::Error`), or the content is larger than `sandbox.upload_limit`
+  /// (in config).
+  pub async fn upload_stream(reader: impl AsyncRead + Unpin) -> io::Result<Self> {
+    let backend = SCHEDULER.get().await.pick();
+    return Self::upload_stream_to(backend, reader).await;
+  }
+
+  /// Upload a file from a reader to a backend like `upload_stream`.
+  async fn upload_stream_to(
+    backend: usize,
+    mut reader: impl AsyncRead + Unpin,
+  ) -> io::Result<Self> {
+    let chunk_size = CONFIG.sandbox.upload_chunk_size;
+    let first = read_chunk(&mut reader, chunk_size).await?;
+    let scheduler = SCHEDULER.get().await;
+    let client = &scheduler.backend(backend).client;
+    if first.len() < chunk_size {
+      let id = scheduler
+        .run(backend, client.file_add(&first))
+        .await
+        .map_err(io::Error::other)?;
+      return Ok(Self::from_id(backend, id).with_digest(hex::encode(Sha256::digest(&first))));
     }
+    if !client.streaming() {
+      // The transport can not stream, so the content is read and uploaded at once.
+      let mut content = first;
+      (&mut reader)
+        .take(CONFIG.sandbox.upload_limit.saturating_add(1) - content.len() as u64)
+        .read_to_end(&mut content)
+        .await?;
+      if content.len() as u64 > CONFIG.sandbox.upload_limit {
+        return Err(io::Error::other(format!(
+          "file is larger than the upload limit of {} bytes",
+          CONFIG.sandbox.upload_limit
+        )));
+      }
+      let id = scheduler
+        .run(backend, client.file_add(&content))
+        .await
+        .map_err(io::Error::other)?;
+      return Ok(Self::from_id(backend, id).with_digest(hex::encode(Sha256::digest(&content))));
+    }
+
+    let cmd = Cmd {
+      args: vec![CAT.to_string()],
+      time_limit: STREAM_TIME_LIMIT,
+      stdout_limit: CONFIG.sandbox.upload_limit as i64,
+      copy_out: vec!["stdout".to_string()],
+      ..Default::default()
+    };
+    let req = proto::Request {
+      cmd: vec![cmd.to_proto_cmd(
+        vec![
+          proto::request::File {
+            file: Some(proto::request::file::File::StreamIn(
+              proto::request::StreamInput {
+                name: "stdin".to_string(),
+              },
+            )),
+          },
+          pipe_collector("stdout", cmd.stdout_limit),
+          pipe_collector("stderr", cmd.stderr_limit),
+        ],
+        vec![],
+        backend,
+      )],
+      ..Default::default()
+    };
+
+    let (mut tx, inputs) = mpsc::channel(1);
+    // Read the chunks while the request is running, the stdin is closed when `tx` is dropped.
+    // The digest is computed on the way, it is only kept if all the chunks are sent.
+    let send = async move {
+      let mut hasher = Sha256::new();
+      let mut chunk = first;
+      while !chunk.is_empty() {
+        hasher.update(&chunk);
+        let input = proto::stream_request::Input {
+          name: "stdin".to_string(),
+          content: chunk,
+        };
+        if tx.send(input).await.is_err() {
+          return io::Result::Ok(None);
+        }
+        chunk = read_chunk(&mut reader, chunk_size).await?;
+      }
+      return io::Result::Ok(Some(hex::encode(hasher.finalize())));
+    };
+    let (sent, resp) = futures::join!(
+      send,
+      scheduler.run(backend, client.exec_stream(req, inputs, None))
+    );
+    // The reader is never dropped, so there is always a response.
+    let resp = resp.map_err(io::Error::other)?.unwrap();
+    if !resp.error.is_empty() {
+      return Err(io::Error::other(Error::Rpc(resp.error)));
+    }
+    let mut res = ResponseResult::new(backend, resp.results.into_iter().next().unwrap());
+    // Take the file first, so it is deleted on errors.
+    let file = res.files.remove("stdout");
+    let digest = sent?;
+    return match (res.result.status, file) {
+      (Status::Accepted, Some(file)) => Ok(match digest {
+        Some(digest) => file.with_digest(digest),
+        None => file,
+      }),
+      (Status::OutputLimitExceeded, _) => Err(io::Error::other(format!(
+        "file is larger than the upload limit of {} bytes",
+        CONFIG.sandbox.upload_limit
+      ))),
+      (status, _) => Err(io::Error::other(Error::Rpc(format!(
+        "streamed upload failed: {}",
+        status
+      )))),
+    };
   }
 
-  /// Create a file handler with file id.
-  pub(super) fn from_id(id: String) -> Self {
+  /// Create a file handler with file id on a backend.
+  pub(crate) fn from_id(backend: usize, id: String) -> Self {
     Self {
-      inner: Arc::new(FileHandleInner { id }),
+      inner: Arc::new(FileHandleInner {
+        backend,
+        id,
+        replicas: Mutex::new(HashMap::new()),
+        digest: OnceLock::new(),
+      }),
     }
   }
 
+  /// Remember the digest of the content, known when it is uploaded.
+  fn with_digest(self, digest: String) -> Self {
+    _ = self.inner.digest.set(digest);
+    self
+  }
+
   /// Get the id of the file corresponding to the FileHandle.
-  pub(super) fn id(&self) -> &String {
+  pub(crate) fn id(&self) -> &String {
     &self.inner.id
   }
 
+  /// Get the index of the backend holding the file.
+  pub(super) fn backend(&self) -> usize {
+    self.inner.backend
+  }
+
+  /// Get the id of the file on a backend.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the file is not copied to the backend by `replicate`.
+  pub(super) fn id_on(&self, backend: usize) -> String {
+    if backend == self.inner.backend {
+      return self.inner.id.clone();
+    }
+    return self.inner.replicas.lock().unwrap()[&backend].id().clone();
+  }
+
+  /// Copy the file to a backend if it is not there, so requests on the backend can use it.
+  ///
+  /// The file is streamed from its backend by `read_stream` and uploaded in chunks
+  /// like `upload_stream`, so it is never held in memory as a whole.
+  /// The copy is deleted with the file.
+  pub(super) async fn replicate(&self, backend: usize) -> Result<(), Error> {
+    if backend == self.inner.backend || self.inner.replicas.lock().unwrap().contains_key(&backend) {
+      return Ok(());
+    }
+    let copy = Self::upload_stream_to(backend, self.read_stream())
+      .await
+      .map_err(sandbox_error)?;
+    log::debug!(
+      "copied file {} to backend {} as {}",
+      self.id(),
+      SCHEDULER.get().await.backend(backend).host,
+      copy.id()
+    );
+    if let Some(digest) = copy.inner.digest.get() {
+      _ = self.inner.digest.set(digest.clone());
+    }
+    // Another request may copy the file at the same time, then the redundant copy is dropped.
+    if let Entry::Vacant(x) = self.inner.replicas.lock().unwrap().entry(backend) {
+      x.insert(copy);
+    }
+    return Ok(());
+  }
+
   /// Get content of file as Vec<u8>.
-  pub async fn context(&self) -> Result<Vec<u8>, FileGetError> {
-    CLIENT.get().await.file_get(&self.id()).await
+  pub async fn context(&self) -> Result<Vec<u8>, Error> {
+    let scheduler = SCHEDULER.get().await;
+    let backend = self.backend();
+    return scheduler
+      .run(
+        backend,
+        scheduler.backend(backend).client.file_get(self.id()),
+      )
+      .await;
+  }
+
+  /// Read the content as a stream, without holding the whole file in memory.
+  ///
+  /// The sandbox only sends whole files by `FileGet`, so the file is piped through `cat`
+  /// in the sandbox, and its stdout is received in chunks by the streaming RPC.
+  /// If the transport does not support streaming, the whole file is received at once.
+  /// Reading fails with `NotFound` at the end if the file does not exist.
+  pub fn read_stream(&self) -> impl AsyncRead + Send + Unpin + 'static {
+    let cmd = Cmd {
+      args: vec![CAT.to_string()],
+      stdin: Some(self.clone()),
+      time_limit: STREAM_TIME_LIMIT,
+      ..Default::default()
+    };
+    let req = proto::Request {
+      cmd: vec![cmd.to_proto_cmd(
+        vec![
+          proto::request::File {
+            file: Some(proto::request::file::File::Cached(
+              proto::request::CachedFile {
+                file_id: self.id().clone(),
+              },
+            )),
+          },
+          proto::request::File {
+            file: Some(proto::request::file::File::StreamOut(
+              proto::request::StreamOutput {
+                name: "stdout".to_string(),
+              },
+            )),
+          },
+          pipe_collector("stderr", cmd.stderr_limit),
+        ],
+        vec![],
+        self.backend(),
+      )],
+      ..Default::default()
+    };
+
+    let backend = self.backend();
+    let id = self.id().clone();
+    let (mut tx, rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+      // Keep the file alive while it is read.
+      let _cmd = cmd;
+      let scheduler = SCHEDULER.get().await;
+      let client = &scheduler.backend(backend).client;
+      if !client.streaming() {
+        // The transport can not stream, so the file is received at once.
+        let res = match scheduler.run(backend, client.file_get(&id)).await {
+          Ok(x) => Ok(io::Cursor::new(x)),
+          Err(Error::NotFound(_)) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("file {}", id),
+          )),
+          Err(err) => Err(io::Error::other(err)),
+        };
+        _ = tx.send(res).await;
+        return;
+      }
+      let (out_tx, out_rx) = mpsc::channel(1);
+      // No stream inputs, so the sender is dropped at once.
+      let (_, inputs) = mpsc::channel(1);
+      let forward = out_rx
+        .map(|x| Ok(Ok(io::Cursor::new(x))))
+        .forward(tx.clone());
+      let (_, resp) = futures::join!(
+        forward,
+        scheduler.run(backend, client.exec_stream(req, inputs, Some(out_tx)))
+      );
+      let status = match resp {
+        Ok(Some(resp)) if resp.error.is_empty() => {
+          ResponseResult::new(backend, resp.results.into_iter().next().unwrap())
+            .result
+            .status
+        }
+        Ok(Some(resp)) => {
+          _ = tx.send(Err(io::Error::other(Error::Rpc(resp.error)))).await;
+          return;
+        }
+        // The reader is dropped.
+        Ok(None) => return,
+        Err(err) => {
+          _ = tx.send(Err(io::Error::other(err))).await;
+          return;
+        }
+      };
+      let err = match status {
+        Status::Accepted => return,
+        Status::FileError => io::Error::new(io::ErrorKind::NotFound, format!("file {}", id)),
+        status => io::Error::other(format!("file {} can not be read: {}", id, status)),
+      };
+      _ = tx.send(Err(err)).await;
+    });
+    return StreamReader::new(rx);
+  }
+
+  /// Get content of file as Vec<u8>, if it is not larger than `max` bytes.
+  ///
+  /// The content is read by `read_stream`, and reading stops after `max` bytes,
+  /// so a huge file (e.g. output of a solution) never runs the judge out of memory.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the file can not be read or is larger than `max`.
+  pub async fn context_limited(&self, max: u64) -> Result<Vec<u8>, FileReadError> {
+    let mut content = vec![];
+    self
+      .read_stream()
+      .take(max.saturating_add(1))
+      .read_to_end(&mut content)
+      .await
+      .map_err(|err| FileReadError::Io {
+        id: self.id().clone(),
+        message: err.to_string(),
+      })?;
+    if content.len() as u64 > max {
+      return Err(FileReadError::TooLarge {
+        id: self.id().clone(),
+        max,
+      });
+    }
+    return Ok(content);
+  }
+
+  /// Get the hex SHA-256 digest of the content of file.
+  pub async fn sha256(&self) -> Result<String, Error> {
+    Ok(hex::encode(Sha256::digest(self.context().await?)))
   }
+
+  /// Get the hex SHA-256 digest of the content of file, computed in the sandbox by `sha256sum`.
+  pub async fn sandbox_sha256(&self) -> Result<String, Error> {
+    let mut res = Request::Run(Cmd {
+      args: vec![SHA256SUM.to_string()],
+      stdin: Some(self.clone()),
+      copy_out: vec!["stdout".to_string()],
+      time_limit: STREAM_TIME_LIMIT,
+      ..Default::default()
+    })
+    .try_exec()
+    .await?
+    .pop()
+    .unwrap();
+    if res.result.status != Status::Accepted {
+      return Err(Error::Rpc(format!(
+        "digest of file {} can not be computed: {}",
+        self.id(),
+        res.result.status
+      )));
+    }
+    let stdout = res.files.remove("stdout").unwrap().context().await?;
+    return Ok(
+      String::from_utf8_lossy(&stdout)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_string(),
+    );
+  }
+
+  /// Get the hex SHA-256 digest of the content of file without reading it from the sandbox.
+  ///
+  /// The digest of an uploaded file is computed when uploading,
+  /// others are computed in the sandbox by `sandbox_sha256` once and remembered.
+  pub async fn digest(&self) -> Result<String, Error> {
+    if let Some(digest) = self.inner.digest.get() {
+      return Ok(digest.clone());
+    }
+    let digest = self.sandbox_sha256().await?;
+    return Ok(self.inner.digest.get_or_init(|| digest).clone());
+  }
+
+  /// Verify that the content read from the sandbox matches the digest computed in the sandbox,
+  /// to detect a file truncated on the way.
+  ///
+  /// # Errors
+  ///
+  /// This function will return `Error::Integrity` if the digests do not match,
+  /// or an error if the file can not be read.
+  pub async fn verify(&self) -> Result<(), Error> {
+    let (expected, actual) = futures::try_join!(self.sandbox_sha256(), self.sha256())?;
+    if expected != actual {
+      return Err(Error::Integrity {
+        id: self.id().clone(),
+        expected,
+        actual,
+      });
+    }
+    return Ok(());
+  }
+}
+
+/// Unwrap the `sandbox::Error` of a failed upload or read of a file.
+fn sandbox_error(err: io::Error) -> Error {
+  let message = err.to_string();
+  if err.kind() == io::ErrorKind::NotFound {
+    return Error::NotFound(message);
+  }
+  return err
+    .into_inner()
+    .and_then(|x| x.downcast::<Error>().ok())
+    .map_or(Error::Rpc(message), |x| *x);
+}
+
+/// Read a chunk of `size` bytes, or less at the end of the reader.
+async fn read_chunk(reader: &mut (impl AsyncRead + Unpin), size: usize) -> io::Result<Vec<u8>> {
+  let mut chunk = Vec::with_capacity(size);
+  reader.take(size as u64).read_to_end(&mut chunk).await?;
+  return Ok(chunk);
+}
+
+/// Error when a file of sandbox can not be read by `FileHandle::context_limited`.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum FileReadError {
+  #[error("file {id} can not be read: {message}")]
+  Io { id: String, message: String },
+
+  #[error("file {id} is larger than {max} bytes")]
+  TooLarge { id: String, max: u64 },
 }
@@ -0,0 +1,118 @@
+use std::{
+  collections::HashMap,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+use async_trait::async_trait;
+use futures::{channel::mpsc, future, stream, SinkExt, StreamExt};
+use tonic::transport::{Channel, Endpoint};
+
+use super::{proto, transport::SandboxTransport, Error};
+
+/// gRPC transport of go-judge.
+///
+/// Calls are spread over a pool of channels, which connect lazily and reconnect automatically.
+pub(crate) struct GrpcTransport {
+  /// The gRPC clients of the pool.
+  clients: Vec<proto::executor_client::ExecutorClient<Channel>>,
+
+  /// Index of the next client to use.
+  next: AtomicUsize,
+}
+
+impl GrpcTransport {
+  /// Create a transport to the host, with a pool of `pool_size` channels.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the host is not a valid uri.
+  pub(crate) fn connect(host: &str, pool_size: usize) -> Self {
+    let endpoint = Endpoint::from_shared(host.to_string()).unwrap();
+    return Self {
+      clients: (0..pool_size.max(1))
+        .map(|_| proto::executor_client::ExecutorClient::new(endpoint.connect_lazy()))
+        .collect(),
+      next: AtomicUsize::new(0),
+    };
+  }
+
+  /// Take a client of the pool in turn.
+  fn client(&self) -> proto::executor_client::ExecutorClient<Channel> {
+    let index = self.next.fetch_add(1, Ordering::Relaxed) % self.clients.len();
+    return self.clients[index].clone();
+  }
+}
+
+#[async_trait]
+impl SandboxTransport for GrpcTransport {
+  async fn file_get(&self, file_id: &str) -> Result<Vec<u8>, Error> {
+    let id = proto::FileId {
+      file_id: file_id.to_string(),
+    };
+    return match self.client().file_get(id).await {
+      Ok(f) => Ok(f.into_inner().content),
+      Err(status) if status.code() == tonic::Code::NotFound => {
+        Err(Error::NotFound(file_id.to_string()))
+      }
+      Err(status) => Err(status.into()),
+    };
+  }
+
+  async fn file_add(&self, content: &[u8]) -> Result<String, Error> {
+    let file = proto::FileContent {
+      content: content.to_vec(),
+      ..Default::default()
+    };
+    return Ok(self.client().file_add(file).await?.into_inner().file_id);
+  }
+
+  async fn file_delete(&self, file_id: &str) -> Result<(), Error> {
+    let id = proto::FileId {
+      file_id: file_id.to_string(),
+    };
+    self.client().file_delete(id).await?;
+    return Ok(());
+  }
+
+  async fn file_list(&self) -> Result<HashMap<String, String>, Error> {
+    return Ok(self.client().file_list(()).await?.into_inner().file_ids);
+  }
+
+  async fn exec(&self, req: proto::Request) -> Result<proto::Response, Error> {
+    return Ok(self.client().exec(req).await?.into_inner());
+  }
+
+  fn streaming(&self) -> bool {
+    return true;
+  }
+
+  async fn exec_stream(
+    &self,
+    req: proto::Request,
+    inputs: mpsc::Receiver<proto::stream_request::Input>,
+    mut outputs: Option<mpsc::Sender<Vec<u8>>>,
+  ) -> Result<Option<proto::Response>, Error> {
+    let requests = stream::once(future::ready(proto::StreamRequest {
+      request: Some(proto::stream_request::Request::ExecRequest(req)),
+    }))
+    .chain(inputs.map(|x| proto::StreamRequest {
+      request: Some(proto::stream_request::Request::ExecInput(x)),
+    }));
+    let mut client = self.client();
+    let mut res = client.exec_stream(requests).await?.into_inner();
+    while let Some(msg) = res.message().await? {
+      match msg.response {
+        Some(proto::stream_response::Response::ExecResponse(x)) => return Ok(Some(x)),
+        Some(proto::stream_response::Response::ExecOutput(x)) => {
+          if let Some(tx) = &mut outputs {
+            if tx.send(x.content).await.is_err() {
+              return Ok(None);
+            }
+          }
+        }
+        None => {}
+      }
+    }
+    return Err(Error::Rpc("stream ends without a response".to_string()));
+  }
+}
@@ -1,14 +1,31 @@
+mod builder;
 mod client;
 mod file;
+mod grpc;
 mod request;
 mod response;
+pub(crate) mod scheduler;
+mod transport;
+pub(crate) mod websocket;
 
-mod proto {
+pub(crate) mod proto {
   tonic::include_proto!("pb");
 }
 
+pub use tokio_util::sync::CancellationToken;
 pub use {
-  file::FileHandle,
-  request::{Cmd, Request},
-  response::{ExecuteResult, ResponseResult, Status},
+  builder::{CmdBuildError, CmdBuilder},
+  file::{FileHandle, FileReadError},
+  request::{parse_cpu_set, Cmd, CpuAffinity, Request, Transcript},
+  response::{describe_signal, Error, ExecuteResult, ResponseResult, Status, Timing},
+  scheduler::Colocation,
 };
+
+/// Check whether any sandbox backend is serving, once without retries.
+///
+/// # Errors
+///
+/// This function will return an error if none of the backends is serving.
+pub async fn ping() -> Result<(), Error> {
+  return scheduler::SCHEDULER.get().await.ping().await;
+}
@@ -1,35 +1,155 @@
 use core::time;
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap};
 
-use crate::CONFIG;
+use serde::{Deserialize, Serialize};
 
-use super::{client, file::FileHandle, proto, ResponseResult};
+use crate::{etc, CONFIG};
+
+use super::{
+  file::FileHandle,
+  proto,
+  scheduler::{Colocation, SCHEDULER},
+  CancellationToken, Error, ExecuteResult, ResponseResult, Timing,
+};
 
 /// A sandbox judge request is a request to run some commands in sandbox.
+///
+/// All commands of a request are sent as a single proto request,
+/// so they are always executed together by the same sandbox backend
+/// (which is required by the pipes of `RunPiped`).
+/// Separate requests which must share a backend are grouped by a `Colocation`
+/// (see `Request::exec_in`).
 #[derive(Debug, Clone)]
 pub enum Request {
   /// Run a single command.
   Run(Cmd),
 
   /// Run two commands, which use pipe to connect input and output streams to each other.
-  RunPiped([Cmd; 2]),
+  ///
+  /// The stdout of each command is connected to the stdin of the other one.
+  RunPiped {
+    cmds: [Cmd; 2],
+
+    /// Capture the data transferred through the pipes, for debugging interactions.
+    transcript: Option<Transcript>,
+  },
+}
+
+/// Options to capture the data transferred through the pipes of a `Request::RunPiped`.
+///
+/// The pipes are proxied by the sandbox, and the data written by `cmds[i]`
+/// will be copied out as a file named `names[i]` in the results.
+#[derive(Debug, Clone)]
+pub struct Transcript {
+  /// Names of the copy-out files.
+  pub names: [String; 2],
+
+  /// Max size of each captured file in bytes, excess data will not be captured.
+  pub max: u64,
+}
+
+/// CPUs to run the timed commands on, to make their timing stable and fair,
+/// e.g. for onsite contests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuAffinity {
+  /// CPUs to run on, in cpuset format (like `0-3,6`).
+  ///
+  /// To keep SMT siblings (hyper-threads of the same core) from disturbing each other,
+  /// list only one logical CPU of each physical core.
+  pub cpus: String,
+
+  /// Run each command alone on a single CPU of `cpus` (see `Cmd::pin_cpu`).
+  #[serde(default)]
+  pub pin: bool,
+}
+
+/// Parse a cpuset (like `0-3,6`) into the sorted CPUs, None if it is malformed.
+///
+/// An empty cpuset is parsed as no CPUs.
+pub fn parse_cpu_set(cpu_set: &str) -> Option<Vec<u32>> {
+  let mut cpus = vec![];
+  for part in cpu_set.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+    let (first, last): (u32, u32) = match part.split_once('-') {
+      Some((first, last)) => (first.trim().parse().ok()?, last.trim().parse().ok()?),
+      None => {
+        let cpu = part.parse().ok()?;
+        (cpu, cpu)
+      }
+    };
+    if first > last {
+      return None;
+    }
+    cpus.extend(first..=last);
+  }
+  cpus.sort_unstable();
+  cpus.dedup();
+  return Some(cpus);
 }
 
 impl Request {
-  /// Convert a wrapped request to sandbox proto request.
-  fn to_proto_request(&self) -> proto::Request {
-    let c = &CONFIG.judge;
+  /// Commands of the request.
+  fn cmds(&self) -> &[Cmd] {
+    return match self {
+      Request::Run(cmd) => std::slice::from_ref(cmd),
+      Request::RunPiped { cmds, .. } => cmds.as_slice(),
+    };
+  }
+
+  /// Mutable commands of the request.
+  fn cmds_mut(&mut self) -> &mut [Cmd] {
+    return match self {
+      Request::Run(cmd) => std::slice::from_mut(cmd),
+      Request::RunPiped { cmds, .. } => cmds.as_mut_slice(),
+    };
+  }
+
+  /// The allowed CPUs of each command to pin (see `Cmd::pin_cpu`), None for a command not to pin.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if a command to pin is allowed no CPUs.
+  fn cpus_to_pin(&self) -> Result<Vec<Option<Vec<u32>>>, Error> {
+    return self
+      .cmds()
+      .iter()
+      .map(|cmd| match cmd.pin_cpu {
+        true => parse_cpu_set(&cmd.cpu_set_limit)
+          .filter(|x| !x.is_empty())
+          .map(Some)
+          .ok_or_else(|| Error::InvalidCpuSet(cmd.cpu_set_limit.clone())),
+        false => Ok(None),
+      })
+      .collect();
+  }
+
+  /// Labels required by the commands of the request (see `Cmd::labels`).
+  fn labels(&self) -> Vec<String> {
+    let mut labels: Vec<_> = self.cmds().iter().flat_map(|x| x.labels.clone()).collect();
+    labels.sort();
+    labels.dedup();
+    return labels;
+  }
+
+  /// Files used by the commands of the request.
+  fn files(&self) -> Vec<&FileHandle> {
+    return self
+      .cmds()
+      .iter()
+      .flat_map(|cmd| cmd.stdin.iter().chain(cmd.copy_in.values()))
+      .collect();
+  }
+
+  /// Convert a wrapped request to sandbox proto request to run on a backend.
+  pub(crate) fn to_proto_request(&self, backend: usize) -> proto::Request {
     match self {
       Request::Run(cmd) => proto::Request {
-        cmd: vec![proto::request::CmdType {
-          args: cmd.args.clone(),
-          env: [c.env.clone(), cmd.env.clone()].concat(),
-          files: vec![
+        cmd: vec![cmd.to_proto_cmd(
+          vec![
             match &cmd.stdin {
               Some(f) => proto::request::File {
                 file: Some(proto::request::file::File::Cached(
                   proto::request::CachedFile {
-                    file_id: f.id().clone(),
+                    file_id: f.id_on(backend),
                   },
                 )),
               },
@@ -41,79 +161,184 @@ impl Request {
                 )),
               },
             },
-            proto::request::File {
-              file: Some(proto::request::file::File::Pipe(
-                proto::request::PipeCollector {
-                  name: "stdout".to_string(),
-                  max: c.stdout_limit,
-                  pipe: false,
-                },
-              )),
-            },
-            proto::request::File {
-              file: Some(proto::request::file::File::Pipe(
-                proto::request::PipeCollector {
-                  name: "stderr".to_string(),
-                  max: c.stderr_limit,
-                  pipe: false,
-                },
-              )),
-            },
+            pipe_collector("stdout", cmd.stdout_limit),
+            pipe_collector("stderr", cmd.stderr_limit),
           ],
-          tty: false,
-          cpu_time_limit: cmd.time_limit.as_nanos().try_into().unwrap(),
-          clock_time_limit: (cmd.time_limit.as_nanos() as f64 * 2.).ceil() as u64,
-          memory_limit: cmd.memory_limit,
-          stack_limit: cmd.memory_limit,
-          proc_limit: c.process_limit,
-          strict_memory_limit: false,
-          copy_in: cmd
-            .copy_in
-            .iter()
-            .map(|f| {
-              {
-                (
-                  f.0.clone(),
-                  proto::request::File {
-                    file: Some(proto::request::file::File::Cached(
-                      proto::request::CachedFile {
-                        file_id: f.1.id().clone(),
-                      },
-                    )),
-                  },
-                )
-              }
-            })
-            .collect(),
-          copy_out: vec![],
-          copy_out_cached: cmd
-            .copy_out
-            .iter()
-            .map(|f| proto::request::CmdCopyOutFile {
-              name: f.to_string(),
-              optional: false,
-            })
-            .collect(),
-          ..Default::default()
-        }],
+          vec![],
+          backend,
+        )],
         pipe_mapping: vec![],
         ..Default::default()
       },
-      // TODO: be used in interactive problems.
-      Request::RunPiped(_) => todo!(),
+      Request::RunPiped { cmds, transcript } => proto::Request {
+        cmd: cmds
+          .iter()
+          .enumerate()
+          .map(|(i, cmd)| {
+            cmd.to_proto_cmd(
+              vec![
+                // stdin and stdout are connected by pipe mapping.
+                proto::request::File { file: None },
+                proto::request::File { file: None },
+                pipe_collector("stderr", cmd.stderr_limit),
+              ],
+              match transcript {
+                Some(t) => vec![proto::request::CmdCopyOutFile {
+                  name: t.names[i].clone(),
+                  optional: true,
+                }],
+                None => vec![],
+              },
+              backend,
+            )
+          })
+          .collect(),
+        pipe_mapping: [(0, 1), (1, 0)]
+          .iter()
+          .map(|&(from, to)| proto::request::PipeMap {
+            r#in: Some(proto::request::pipe_map::PipeIndex { index: from, fd: 1 }),
+            out: Some(proto::request::pipe_map::PipeIndex { index: to, fd: 0 }),
+            proxy: transcript.is_some(),
+            name: transcript
+              .as_ref()
+              .map_or(String::new(), |t| t.names[from as usize].clone()),
+            max: transcript.as_ref().map_or(0, |t| t.max),
+          })
+          .collect(),
+        ..Default::default()
+      },
     }
   }
 
+  /// Execute the request, and return the result of each command.
+  ///
+  /// The request runs on the backend holding most of its files (see `Scheduler::place`)
+  /// among the backends having the labels of its commands (see `Cmd::labels`),
+  /// and the other files are copied there first.
+  /// A request of timed commands waits for a free slot of the backend (see `Cmd::timed`),
+  /// and a request of pinned commands waits for free CPUs (see `Cmd::pin_cpu`).
+  ///
+  /// If the sandbox fails, the error is logged and every command results in `InternalError`,
+  /// which is judged as a system error (and retried, see `problem::retry_flaky`)
+  /// instead of crashing the judge.
+  ///
+  /// Dropping the returned future aborts the request on the sandbox, and kills its commands.
   pub async fn exec(&self) -> Vec<ResponseResult> {
-    let resp = client::CLIENT
-      .get()
-      .await
-      .exec(self.to_proto_request())
-      .await;
+    return self.exec_in(&Colocation::new()).await;
+  }
+
+  /// Execute the request like `exec`, on the backend of a co-location group.
+  ///
+  /// The first request of the group is placed like `exec`, and binds the group to its backend.
+  pub async fn exec_in(&self, group: &Colocation) -> Vec<ResponseResult> {
+    return match self.try_exec_in(group).await {
+      Ok(x) => x,
+      Err(err) => {
+        log::error!("sandbox execute failed: {}", err);
+        let count = match self {
+          Request::Run(_) => 1,
+          Request::RunPiped { .. } => 2,
+        };
+        (0..count)
+          .map(|_| ResponseResult {
+            result: ExecuteResult::internal_error(),
+            files: HashMap::new(),
+          })
+          .collect()
+      }
+    };
+  }
+
+  /// Execute the request, and return the result of each command.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the sandbox is unavailable or rejects the request,
+  /// or no backend has the labels of its commands.
+  pub async fn try_exec(&self) -> Result<Vec<ResponseResult>, Error> {
+    return self.try_exec_in(&Colocation::new()).await;
+  }
+
+  /// Execute the request like `try_exec`, on the backend of a co-location group.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the sandbox is unavailable or rejects the request,
+  /// or no backend has the labels of its commands.
+  pub async fn try_exec_in(&self, group: &Colocation) -> Result<Vec<ResponseResult>, Error> {
+    let scheduler = SCHEDULER.get().await;
+    let cpus = self.cpus_to_pin()?;
+    let files = self.files();
+    let backend = scheduler.place_in(group, &self.labels(), files.iter().map(|x| x.backend()))?;
+    for file in files {
+      file.replicate(backend).await?;
+    }
+    let _slot = match self.cmds().iter().any(|x| x.timed) {
+      true => scheduler.acquire_timed(backend).await,
+      false => None,
+    };
+    let pinned = match cpus.iter().any(Option::is_some) {
+      true => Some(scheduler.pin_cpus(backend, &cpus).await),
+      false => None,
+    };
+    let request = match &pinned {
+      Some(pinned) => {
+        let mut request = self.clone();
+        for (cmd, cpu) in request.cmds_mut().iter_mut().zip(&pinned.cpus) {
+          if let Some(cpu) = cpu {
+            cmd.cpu_set_limit = cpu.to_string();
+          }
+        }
+        Cow::Owned(request)
+      }
+      None => Cow::Borrowed(self),
+    };
+    let resp = scheduler
+      .run(
+        backend,
+        scheduler
+          .backend(backend)
+          .client
+          .exec(request.to_proto_request(backend)),
+      )
+      .await?;
     if !resp.error.is_empty() {
-      panic!("sandbox execute returns an error: {}", resp.error);
+      return Err(Error::Rpc(resp.error));
     }
-    return resp.results.into_iter().map(ResponseResult::from).collect();
+    return Ok(
+      resp
+        .results
+        .into_iter()
+        .zip(request.cmds())
+        .map(|(x, cmd)| {
+          let mut res = ResponseResult::new(backend, x);
+          res.result.check_idleness(cmd.time_limit);
+          res.result.timing = Timing {
+            cpu_set: cmd.cpu_set_limit.clone(),
+            pinned: cmd.pin_cpu,
+            time_factor: CONFIG.sandbox.effective_time_factor(),
+          };
+          res
+        })
+        .collect(),
+    );
+  }
+
+  /// Execute the request like `try_exec`, but abort it on the sandbox once `cancel` is cancelled.
+  ///
+  /// # Errors
+  ///
+  /// This function will return `Error::Cancelled` if the request is cancelled before it finishes,
+  /// or an error of `try_exec`.
+  pub async fn try_exec_cancellable(
+    &self,
+    cancel: &CancellationToken,
+  ) -> Result<Vec<ResponseResult>, Error> {
+    return tokio::select! {
+      biased;
+      _ = cancel.cancelled() => Err(Error::Cancelled),
+      res = self.try_exec() => res,
+    };
   }
 }
 
@@ -132,6 +357,50 @@ pub struct Cmd {
   /// Memory limit in byte.
   pub memory_limit: u64,
 
+  /// Stdout limit in byte.
+  pub stdout_limit: i64,
+
+  /// Stderr limit in byte.
+  pub stderr_limit: i64,
+
+  /// Stack limit in byte, None to use the memory limit.
+  pub stack_limit: Option<u64>,
+
+  /// Max number of processes.
+  pub proc_limit: u64,
+
+  /// CPU usage limit, in thousandths of a CPU (1000 for a whole CPU), 0 for no limit.
+  pub cpu_rate_limit: u64,
+
+  /// CPUs to run on in cpuset format (like `0-3,6`), empty for no limit.
+  pub cpu_set_limit: String,
+
+  /// Run on a single CPU of `cpu_set_limit`, which no other pinned command of the backend
+  /// runs on at the same time, waiting for one to be free.
+  ///
+  /// It keeps the timing from being disturbed by other runs sharing or migrating between CPUs.
+  pub pin_cpu: bool,
+
+  /// Also limit the memory by `RLIMIT_DATA`,
+  /// so that allocations beyond the memory limit fail at once.
+  pub strict_memory_limit: bool,
+
+  /// Run in a TTY, the stdout and stderr are merged into the stdout.
+  pub tty: bool,
+
+  /// The time of the command is judged (e.g. a solution run),
+  /// so it is limited by `sandbox.timed_concurrency` (in config) of the backend.
+  pub timed: bool,
+
+  /// Wall clock time limit, as a ratio of the time limit.
+  pub clock_limit_ratio: f64,
+
+  /// Wall clock time limit, None to use `clock_limit_ratio` of the time limit.
+  ///
+  /// A program exceeding it but not the time limit (e.g. sleeping or waiting for input)
+  /// results in `IdlenessLimitExceeded`.
+  pub wall_time_limit: Option<time::Duration>,
+
   /// Stdin of the file.
   ///
   /// If this command is used in a piped execution, leave this field to None.
@@ -146,19 +415,125 @@ pub struct Cmd {
   ///
   /// Append '?' after file name will make the file optional and do not cause FileError when missing.
   pub copy_out: Vec<String>,
+
+  /// Max size of each copy-out file in bytes, 0 to use the limit of the sandbox.
+  ///
+  /// A larger file is not copied out, and the result is `OutputLimitExceeded`.
+  pub copy_out_max: u64,
+
+  /// Labels the backend running the command must have (see `sandbox.labels` in config),
+  /// empty to run on any backend.
+  pub labels: Vec<String>,
 }
 
 impl Default for Cmd {
   fn default() -> Self {
-    let c = &CONFIG.judge;
+    return Self::with_config(&CONFIG.judge);
+  }
+}
+
+/// A file collects the output of a pipe (like stdout) into a copy-out file named `name`.
+pub(super) fn pipe_collector(name: &str, max: i64) -> proto::request::File {
+  proto::request::File {
+    file: Some(proto::request::file::File::Pipe(
+      proto::request::PipeCollector {
+        name: name.to_string(),
+        max,
+        pipe: false,
+      },
+    )),
+  }
+}
+
+impl Cmd {
+  /// Create an empty command, the limits are filled with the defaults of the given judge config.
+  pub fn with_config(c: &etc::JudgeCfg) -> Self {
     Self {
       args: vec![],
       env: vec![],
       time_limit: c.time_limit,
       memory_limit: c.memory_limit,
+      stdout_limit: c.stdout_limit,
+      stderr_limit: c.stderr_limit,
+      stack_limit: c.stack_limit,
+      proc_limit: c.process_limit,
+      cpu_rate_limit: c.cpu_rate_limit,
+      cpu_set_limit: c.cpu_set_limit.clone(),
+      pin_cpu: false,
+      strict_memory_limit: c.strict_memory_limit,
+      tty: false,
+      timed: false,
+      clock_limit_ratio: c.clock_limit_ratio,
+      wall_time_limit: None,
       stdin: None,
       copy_in: [].into(),
       copy_out: vec![],
+      copy_out_max: 0,
+      labels: vec![],
+    }
+  }
+
+  /// Effective wall clock time limit of the command.
+  pub fn wall_time_limit(&self) -> time::Duration {
+    return self
+      .wall_time_limit
+      .unwrap_or_else(|| self.time_limit.mul_f64(self.clock_limit_ratio));
+  }
+
+  /// Convert the command to sandbox proto command with the given fd files,
+  /// to run on a backend holding all of its copy-in files.
+  ///
+  /// `extra_copy_out` will be copied out besides the `copy_out` field.
+  pub(super) fn to_proto_cmd(
+    &self,
+    files: Vec<proto::request::File>,
+    extra_copy_out: Vec<proto::request::CmdCopyOutFile>,
+    backend: usize,
+  ) -> proto::request::CmdType {
+    let c = &CONFIG.judge;
+    proto::request::CmdType {
+      args: self.args.clone(),
+      env: [c.env.clone(), self.env.clone()].concat(),
+      files,
+      tty: self.tty,
+      cpu_time_limit: self.time_limit.as_nanos().try_into().unwrap(),
+      clock_time_limit: self.wall_time_limit().as_nanos().try_into().unwrap(),
+      memory_limit: self.memory_limit,
+      stack_limit: self.stack_limit.unwrap_or(self.memory_limit),
+      proc_limit: self.proc_limit,
+      cpu_rate_limit: self.cpu_rate_limit,
+      cpu_set_limit: self.cpu_set_limit.clone(),
+      strict_memory_limit: self.strict_memory_limit,
+      copy_in: self
+        .copy_in
+        .iter()
+        .map(|f| {
+          {
+            (
+              f.0.clone(),
+              proto::request::File {
+                file: Some(proto::request::file::File::Cached(
+                  proto::request::CachedFile {
+                    file_id: f.1.id_on(backend),
+                  },
+                )),
+              },
+            )
+          }
+        })
+        .collect(),
+      copy_out: vec![],
+      copy_out_cached: self
+        .copy_out
+        .iter()
+        .map(|f| proto::request::CmdCopyOutFile {
+          name: f.to_string(),
+          optional: false,
+        })
+        .chain(extra_copy_out)
+        .collect(),
+      copy_out_max: self.copy_out_max,
+      ..Default::default()
     }
   }
 }
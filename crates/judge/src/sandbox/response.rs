@@ -1,11 +1,12 @@
 use std::{collections::HashMap, time};
 
 use serde::{Deserialize, Serialize};
-use strum::Display;
 use thiserror::Error;
 
 use super::{proto, FileHandle};
 
+pub use rindag_types::sandbox::Status;
+
 /// Judging result of one `Cmd`, contains the execution result and the copy out files.
 #[derive(Debug, Clone)]
 pub struct ResponseResult {
@@ -17,26 +18,93 @@ pub struct ResponseResult {
 #[derive(Debug, Clone)]
 pub struct ExecuteResult {
   pub status: Status,
+
+  /// CPU time.
   pub time: time::Duration,
+
+  /// Wall clock time.
+  pub wall_time: time::Duration,
+
   pub memory: u64,
   pub exit_code: i32,
+
+  /// Error message of the sandbox about the run, like the syscall of a `DangerousSyscall`,
+  /// empty if there is none.
+  pub error: String,
+
+  /// How the time of the command was measured.
+  pub timing: Timing,
+}
+
+/// How the time of a command was measured, to tell whether the times of runs are comparable.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timing {
+  /// CPUs the command was allowed to run on, in cpuset format, empty for any CPU of the sandbox.
+  pub cpu_set: String,
+
+  /// The command ran alone on a single CPU (see `Cmd::pin_cpu`).
+  pub pinned: bool,
+
+  /// Speed factor of the sandbox (`sandbox.time_factor` in config, or measured at startup),
+  /// which the time limits of solutions were scaled by.
+  pub time_factor: Option<f64>,
+}
+
+impl ExecuteResult {
+  /// Result of a command which can not be run because of a sandbox error.
+  pub fn internal_error() -> Self {
+    return Self {
+      status: Status::InternalError,
+      time: time::Duration::ZERO,
+      wall_time: time::Duration::ZERO,
+      memory: 0,
+      exit_code: 0,
+      error: String::new(),
+      timing: Timing::default(),
+    };
+  }
+
+  /// Tell a program killed by the wall clock time limit from one killed by the time limit,
+  /// which the sandbox reports both as `TimeLimitExceeded`.
+  pub(super) fn check_idleness(&mut self, time_limit: time::Duration) {
+    if self.status == Status::TimeLimitExceeded && self.time < time_limit {
+      self.status = Status::IdlenessLimitExceeded;
+    }
+  }
+
+  /// The syscall reported by the sandbox, if the status is `DangerousSyscall`.
+  pub fn syscall(&self) -> Option<&str> {
+    return match self.status {
+      Status::DangerousSyscall if !self.error.trim().is_empty() => Some(self.error.trim()),
+      _ => None,
+    };
+  }
+
+  /// Number of the signal which terminated the program, if the status is `Signalled`.
+  pub fn signal(&self) -> Option<i32> {
+    match self.status {
+      Status::Signalled => Some(self.exit_code),
+      _ => None,
+    }
+  }
 }
 
-/// Judge result status for a program.
-/// This enum is only used to represent the result after executing the program,
-/// and does not represent the result after the checker checks the correctness of the answer.
-#[derive(Debug, PartialEq, strum::EnumString, Serialize, Deserialize, Clone, Display)]
-#[strum(serialize_all = "snake_case")]
-pub enum Status {
-  Accepted,
-  TimeLimitExceeded,
-  MemoryLimitExceeded,
-  OutputLimitExceeded,
-  FileError,
-  NonZeroExitStatus,
-  DangerousSyscall,
-  Signalled,
-  InternalError,
+/// Human-friendly description of common signals which terminate programs,
+/// None for other signals.
+pub fn describe_signal(signal: i32) -> Option<&'static str> {
+  return match signal {
+    4 => Some("illegal instruction (SIGILL)"),
+    6 => Some("assertion failed or aborted (SIGABRT)"),
+    7 => Some("bus error (SIGBUS)"),
+    8 => Some("integer division by zero or arithmetic error (SIGFPE)"),
+    9 => Some("killed (SIGKILL)"),
+    11 => Some("segmentation fault (SIGSEGV)"),
+    13 => Some("broken pipe (SIGPIPE)"),
+    24 => Some("CPU time limit exceeded (SIGXCPU)"),
+    25 => Some("file size limit exceeded (SIGXFSZ)"),
+    31 => Some("bad system call (SIGSYS)"),
+    _ => None,
+  };
 }
 
 impl From<proto::response::result::StatusType> for Status {
@@ -56,25 +124,71 @@ impl From<proto::response::result::StatusType> for Status {
   }
 }
 
-#[derive(Debug, Clone, Error)]
-#[error("sandbox error: {message}")]
-pub struct SandboxError {
-  pub message: String,
+/// Error when the sandbox can not serve a request.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum Error {
+  /// The sandbox is unavailable (e.g. restarting) after all the retries.
+  #[error("sandbox is unavailable: {0}")]
+  Unavailable(String),
+
+  #[error("file not found in sandbox: {0}")]
+  NotFound(String),
+
+  /// The sandbox rejects the request.
+  #[error("sandbox error: {0}")]
+  Rpc(String),
+
+  /// The request is cancelled before it finishes (see `Request::try_exec_cancellable`).
+  #[error("sandbox request is cancelled")]
+  Cancelled,
+
+  /// No backend to place the request on (the backend of its co-location group, if any)
+  /// has all the labels its commands require (see `Cmd::labels`).
+  #[error("no sandbox backend has labels {0:?}")]
+  NoMatchingBackend(Vec<String>),
+
+  /// A command to pin (see `Cmd::pin_cpu`) is allowed no CPUs to pin.
+  #[error("no CPUs to pin in cpuset {0:?}")]
+  InvalidCpuSet(String),
+
+  /// The content of a file read from the sandbox does not match its digest in the sandbox
+  /// (see `FileHandle::verify`).
+  #[error("file {id} is corrupted: sha256 {actual} does not match {expected} in sandbox")]
+  Integrity {
+    id: String,
+    expected: String,
+    actual: String,
+  },
+}
+
+impl From<tonic::Status> for Error {
+  fn from(status: tonic::Status) -> Self {
+    return match status.code() {
+      tonic::Code::Unavailable => Self::Unavailable(status.message().to_string()),
+      tonic::Code::NotFound => Self::NotFound(status.message().to_string()),
+      tonic::Code::Cancelled => Self::Cancelled,
+      _ => Self::Rpc(status.to_string()),
+    };
+  }
 }
 
-impl From<proto::response::Result> for ResponseResult {
-  fn from(res: proto::response::Result) -> Self {
+impl ResponseResult {
+  /// Convert a sandbox proto result of a request run on a backend.
+  pub(super) fn new(backend: usize, res: proto::response::Result) -> Self {
     Self {
       result: ExecuteResult {
         status: res.status().into(),
         time: time::Duration::from_nanos(res.time),
+        wall_time: time::Duration::from_nanos(res.run_time),
         memory: res.memory,
         exit_code: res.exit_status,
+        error: res.error,
+        timing: Timing::default(),
       },
       files: res
         .file_ids
         .into_iter()
-        .map(|f| (f.0, FileHandle::from_id(f.1)))
+        .map(|f| (f.0, FileHandle::from_id(backend, f.1)))
         .collect(),
     }
   }
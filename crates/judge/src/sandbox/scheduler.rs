@@ -0,0 +1,414 @@
+use std::{
+  collections::{HashMap, HashSet},
+  future::Future,
+  sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Mutex, OnceLock,
+  },
+};
+
+use async_once::AsyncOnce;
+use tokio::sync::{Notify, Semaphore, SemaphorePermit};
+
+use super::{client::Client, Error};
+use crate::{etc, CONFIG};
+
+/// A go-judge server to run requests.
+pub(crate) struct Backend {
+  /// gRPC host address of the server.
+  pub host: String,
+
+  /// Labels of the backend, see `sandbox.labels` in config.
+  labels: HashSet<String>,
+
+  pub(super) client: Client,
+
+  /// Number of requests running on the backend.
+  in_flight: AtomicUsize,
+
+  /// Whether the backend passed the last health check.
+  alive: AtomicBool,
+
+  /// Slots of the timed commands running on the backend, None for no limit.
+  timed_slots: Option<Semaphore>,
+
+  /// CPUs pinned by the commands running on the backend (see `Cmd::pin_cpu`).
+  pinned_cpus: Mutex<HashSet<u32>>,
+
+  /// Notified when pinned CPUs of the backend are released.
+  unpinned: Notify,
+}
+
+impl Backend {
+  /// Pick a CPU not pinned yet for each command, and pin them all,
+  /// None if some command has no free CPU.
+  ///
+  /// `cpus` are the allowed CPUs of each command, None for a command not to pin.
+  fn try_pin(&self, cpus: &[Option<Vec<u32>>]) -> Option<Vec<Option<u32>>> {
+    let mut pinned = self.pinned_cpus.lock().unwrap();
+    let mut picked: Vec<Option<u32>> = vec![];
+    for set in cpus {
+      let cpu = match set {
+        Some(set) => Some(
+          *set
+            .iter()
+            .find(|&&x| !pinned.contains(&x) && !picked.contains(&Some(x)))?,
+        ),
+        None => None,
+      };
+      picked.push(cpu);
+    }
+    pinned.extend(picked.iter().flatten());
+    return Some(picked);
+  }
+}
+
+/// Scheduler to distribute requests over the sandbox backends
+/// (`sandbox.host` and `sandbox.hosts` in config).
+///
+/// A backend is removed from scheduling when it fails a health check or stays unavailable
+/// after all the retries of a request, and is added back once it passes a health check.
+/// If all the backends are dead, all of them are scheduled, so requests still fail or
+/// retry as with a single sandbox.
+pub(crate) struct Scheduler {
+  backends: Vec<Backend>,
+
+  balance: etc::Balance,
+
+  /// Start of the next round-robin pick.
+  next: AtomicUsize,
+}
+
+/// A group of requests which must run on the same sandbox backend,
+/// e.g. the runs of a multi-pass mode sharing the state left by the previous run.
+///
+/// The group is bound to a backend when its first request is placed (see `Scheduler::place_in`),
+/// and the later requests run there even if the backend holds none of their files or is dead,
+/// so they fail together instead of being split over backends.
+#[derive(Debug, Default)]
+pub struct Colocation {
+  backend: OnceLock<usize>,
+}
+
+impl Colocation {
+  /// Create a group not bound to any backend yet.
+  pub fn new() -> Self {
+    return Self::default();
+  }
+
+  /// Index of the backend the group is bound to, None before its first request is placed.
+  pub fn backend(&self) -> Option<usize> {
+    return self.backend.get().copied();
+  }
+}
+
+/// CPUs pinned by a request on a backend, which are released when it drops.
+pub(crate) struct PinnedCpus<'a> {
+  backend: &'a Backend,
+
+  /// The pinned CPU of each command, None for a command not pinned.
+  pub cpus: Vec<Option<u32>>,
+}
+
+impl Drop for PinnedCpus<'_> {
+  fn drop(&mut self) {
+    let mut pinned = self.backend.pinned_cpus.lock().unwrap();
+    for cpu in self.cpus.iter().flatten() {
+      pinned.remove(cpu);
+    }
+    drop(pinned);
+    self.backend.unpinned.notify_waiters();
+  }
+}
+
+/// Decrease the in-flight requests of a backend when a request finishes or is cancelled.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl Drop for InFlight<'_> {
+  fn drop(&mut self) {
+    self.0.fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
+impl Scheduler {
+  /// Create a scheduler of the hosts, the backends connect lazily.
+  ///
+  /// # Panics
+  ///
+  /// Panics if there are no hosts or a host is not a valid uri.
+  pub(crate) fn new(hosts: &[String], pool_size: usize, balance: etc::Balance) -> Self {
+    assert!(!hosts.is_empty(), "no sandbox hosts");
+    return Self {
+      backends: hosts
+        .iter()
+        .map(|host| Backend {
+          host: host.clone(),
+          labels: HashSet::new(),
+          client: Client::connect(host, pool_size),
+          in_flight: AtomicUsize::new(0),
+          alive: AtomicBool::new(true),
+          timed_slots: None,
+          pinned_cpus: Mutex::new(HashSet::new()),
+          unpinned: Notify::new(),
+        })
+        .collect(),
+      balance,
+      next: AtomicUsize::new(0),
+    };
+  }
+
+  /// Limit the timed commands running at the same time on each backend, 0 for no limit.
+  pub(crate) fn with_timed_concurrency(mut self, limit: usize) -> Self {
+    for backend in &mut self.backends {
+      backend.timed_slots = (limit > 0).then(|| Semaphore::new(limit));
+    }
+    self
+  }
+
+  /// Label the backends by host address, hosts not in `labels` have no labels.
+  pub(crate) fn with_labels(mut self, labels: &HashMap<String, Vec<String>>) -> Self {
+    for backend in &mut self.backends {
+      backend.labels = labels
+        .get(&backend.host)
+        .map_or_else(HashSet::new, |x| x.iter().cloned().collect());
+    }
+    self
+  }
+
+  /// Wait for a slot to run timed commands on a backend, which is freed when the permit drops.
+  ///
+  /// Returns None at once if the timed commands are not limited.
+  pub(crate) async fn acquire_timed(&self, index: usize) -> Option<SemaphorePermit<'_>> {
+    let slots = self.backends[index].timed_slots.as_ref()?;
+    // The semaphore is never closed.
+    return Some(slots.acquire().await.unwrap());
+  }
+
+  /// Wait until a distinct CPU can be pinned for each command of a request on a backend,
+  /// and pin them until the returned guard drops.
+  ///
+  /// `cpus` are the allowed CPUs of each command, None for a command not to pin.
+  /// A CPU is pinned by at most one command of the backend at a time,
+  /// so each pinned command runs alone on its CPU.
+  /// Every command to pin must be allowed at least one CPU, or this never returns.
+  pub(crate) async fn pin_cpus(&self, index: usize, cpus: &[Option<Vec<u32>>]) -> PinnedCpus<'_> {
+    let backend = &self.backends[index];
+    loop {
+      let unpinned = backend.unpinned.notified();
+      tokio::pin!(unpinned);
+      // Wait for releases from now on, so a release right after the check is not missed.
+      unpinned.as_mut().enable();
+      if let Some(picked) = backend.try_pin(cpus) {
+        return PinnedCpus {
+          backend,
+          cpus: picked,
+        };
+      }
+      unpinned.await;
+    }
+  }
+
+  /// Get a backend by index.
+  pub(crate) fn backend(&self, index: usize) -> &Backend {
+    &self.backends[index]
+  }
+
+  /// Whether a backend has all the labels.
+  fn has_labels(&self, index: usize, labels: &[String]) -> bool {
+    return labels
+      .iter()
+      .all(|x| self.backends[index].labels.contains(x));
+  }
+
+  /// Indices of the backends having all the labels.
+  ///
+  /// # Errors
+  ///
+  /// This function will return `Error::NoMatchingBackend` if no backend has all the labels.
+  fn matching(&self, labels: &[String]) -> Result<Vec<usize>, Error> {
+    let matching: Vec<_> = (0..self.backends.len())
+      .filter(|&i| self.has_labels(i, labels))
+      .collect();
+    if matching.is_empty() {
+      return Err(Error::NoMatchingBackend(labels.to_vec()));
+    }
+    return Ok(matching);
+  }
+
+  /// Choose a backend for a new request or file by the balance policy.
+  pub(crate) fn pick(&self) -> usize {
+    return self.pick_among((0..self.backends.len()).collect());
+  }
+
+  /// Choose one of the candidate backends by the balance policy,
+  /// or any of them if all the candidates are dead.
+  fn pick_among(&self, candidates: Vec<usize>) -> usize {
+    let mut alive: Vec<_> = candidates
+      .iter()
+      .copied()
+      .filter(|&i| self.is_alive(i))
+      .collect();
+    if alive.is_empty() {
+      alive = candidates;
+    }
+    let start = self.next.fetch_add(1, Ordering::Relaxed);
+    // Rotate the candidates, so ties of least-loaded are broken in turn.
+    let candidates = (0..alive.len()).map(|i| alive[(start + i) % alive.len()]);
+    return match self.balance {
+      etc::Balance::RoundRobin => alive[start % alive.len()],
+      etc::Balance::LeastLoaded => candidates
+        .min_by_key(|&i| self.backends[i].in_flight.load(Ordering::Relaxed))
+        .unwrap(),
+    };
+  }
+
+  /// Choose the backend to run a request which requires `labels` using the files on `backends`:
+  /// among the backends having the labels, the alive one holding most of the files,
+  /// so that fewest files are copied, or one by `pick` if no alive one holds any.
+  ///
+  /// # Errors
+  ///
+  /// This function will return `Error::NoMatchingBackend` if no backend has all the labels.
+  pub(crate) fn place(
+    &self,
+    labels: &[String],
+    backends: impl IntoIterator<Item = usize>,
+  ) -> Result<usize, Error> {
+    return Ok(self.place_among(self.matching(labels)?, backends));
+  }
+
+  /// Choose one of the candidate backends to run a request using the files on `backends`,
+  /// like `place`.
+  fn place_among(
+    &self,
+    candidates: Vec<usize>,
+    backends: impl IntoIterator<Item = usize>,
+  ) -> usize {
+    let mut count = vec![0; self.backends.len()];
+    for i in backends {
+      count[i] += 1;
+    }
+    let holding = candidates
+      .iter()
+      .copied()
+      .filter(|&i| count[i] > 0 && self.is_alive(i))
+      .max_by_key(|&i| count[i]);
+    return holding.unwrap_or_else(|| self.pick_among(candidates));
+  }
+
+  /// Choose the backend to run a request of a co-location group, which requires `labels`,
+  /// using the files on `backends`: the backend of the group,
+  /// or one by `place` to bind the group to if it is not bound yet.
+  ///
+  /// # Errors
+  ///
+  /// This function will return `Error::NoMatchingBackend` if no backend has all the labels,
+  /// or the backend of the group does not.
+  pub(crate) fn place_in(
+    &self,
+    group: &Colocation,
+    labels: &[String],
+    backends: impl IntoIterator<Item = usize>,
+  ) -> Result<usize, Error> {
+    if group.backend().is_none() {
+      // Another request of the group may bind it first, which wins.
+      _ = group.backend.set(self.place(labels, backends)?);
+    }
+    let backend = group.backend().unwrap();
+    if !self.has_labels(backend, labels) {
+      return Err(Error::NoMatchingBackend(labels.to_vec()));
+    }
+    return Ok(backend);
+  }
+
+  /// Whether a backend passed the last health check.
+  pub(crate) fn is_alive(&self, index: usize) -> bool {
+    self.backends[index].alive.load(Ordering::Relaxed)
+  }
+
+  /// Mark a backend alive or dead, and log the change.
+  pub(crate) fn set_alive(&self, index: usize, alive: bool) {
+    let backend = &self.backends[index];
+    if backend.alive.swap(alive, Ordering::Relaxed) != alive {
+      match alive {
+        true => log::info!("sandbox backend {} is back", backend.host),
+        false => log::warn!("sandbox backend {} is dead, removed", backend.host),
+      }
+    }
+  }
+
+  /// Run a call on a backend, counting it as in flight.
+  ///
+  /// The backend is marked dead if it is still unavailable after all the retries.
+  pub(crate) async fn run<T>(
+    &self,
+    index: usize,
+    call: impl Future<Output = Result<T, Error>>,
+  ) -> Result<T, Error> {
+    let backend = &self.backends[index];
+    backend.in_flight.fetch_add(1, Ordering::Relaxed);
+    let _in_flight = InFlight(&backend.in_flight);
+    let res = call.await;
+    if let Err(Error::Unavailable(_)) = &res {
+      self.set_alive(index, false);
+    }
+    return res;
+  }
+
+  /// Check whether any backend is serving, by pinging all of them once without retries,
+  /// and mark them alive or dead.
+  ///
+  /// # Errors
+  ///
+  /// This function will return the error of the last backend if none of them is serving.
+  pub(crate) async fn ping(&self) -> Result<(), Error> {
+    let results = futures::future::join_all(self.backends.iter().map(|x| x.client.ping())).await;
+    let mut serving = false;
+    let mut ret = Ok(());
+    for (i, res) in results.into_iter().enumerate() {
+      self.set_alive(i, res.is_ok());
+      match res {
+        Ok(()) => serving = true,
+        Err(err) => ret = Err(err),
+      }
+    }
+    if serving {
+      return Ok(());
+    }
+    return ret;
+  }
+
+  /// Check the health of all the backends every `sandbox.health_check_interval` (in config),
+  /// by listing their files without retries.
+  async fn health_check(&self) {
+    let mut interval = tokio::time::interval(CONFIG.sandbox.health_check_interval);
+    loop {
+      interval.tick().await;
+      for (i, backend) in self.backends.iter().enumerate() {
+        let res = backend.client.ping().await;
+        if let Err(err) = &res {
+          log::debug!(
+            "sandbox backend {} health check failed: {}",
+            backend.host,
+            err
+          );
+        }
+        self.set_alive(i, res.is_ok());
+      }
+    }
+  }
+}
+
+lazy_static! {
+  pub(super) static ref SCHEDULER: AsyncOnce<Scheduler> = AsyncOnce::new(async {
+    let conf = &CONFIG.sandbox;
+    let scheduler = Scheduler::new(&conf.hosts(), conf.pool_size, conf.balance)
+      .with_timed_concurrency(conf.timed_concurrency)
+      .with_labels(&conf.labels);
+    // A single backend is always scheduled, so it needs no health checks.
+    if scheduler.backends.len() > 1 {
+      tokio::spawn(async { SCHEDULER.get().await.health_check().await });
+    }
+    scheduler
+  });
+}
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use futures::channel::mpsc;
+
+use super::{proto, Error};
+
+/// Transport to talk to a go-judge server, selected by `sandbox.transport` in config.
+///
+/// Each call is sent once, retries while the sandbox is unavailable are done by `Client`.
+#[async_trait]
+pub(crate) trait SandboxTransport: Send + Sync {
+  /// Get the content of a file.
+  async fn file_get(&self, file_id: &str) -> Result<Vec<u8>, Error>;
+
+  /// Add a file to the file store, and return its id.
+  async fn file_add(&self, content: &[u8]) -> Result<String, Error>;
+
+  /// Delete a file of the file store.
+  async fn file_delete(&self, file_id: &str) -> Result<(), Error>;
+
+  /// List all files of the file store, by id to name.
+  async fn file_list(&self) -> Result<HashMap<String, String>, Error>;
+
+  /// Execute a request.
+  async fn exec(&self, req: proto::Request) -> Result<proto::Response, Error>;
+
+  /// Whether the transport supports `exec_stream`.
+  fn streaming(&self) -> bool {
+    return false;
+  }
+
+  /// Execute a request with stream input and output files, see `Client::exec_stream`.
+  async fn exec_stream(
+    &self,
+    _req: proto::Request,
+    _inputs: mpsc::Receiver<proto::stream_request::Input>,
+    _outputs: Option<mpsc::Sender<Vec<u8>>>,
+  ) -> Result<Option<proto::Response>, Error> {
+    return Err(Error::Rpc(
+      "streaming is not supported by the transport".to_string(),
+    ));
+  }
+}
@@ -0,0 +1,681 @@
+use std::{
+  collections::HashMap,
+  io,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+};
+
+use async_trait::async_trait;
+use hyper::{body::Bytes, client::HttpConnector, header, Body, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tokio::{
+  io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, WriteHalf},
+  sync::oneshot,
+};
+
+use super::{proto, transport::SandboxTransport, Error};
+
+/// GUID to compute the accept key of a WebSocket handshake (RFC 6455).
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Max size of a received WebSocket message in bytes.
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+pub(crate) const OP_CONTINUATION: u8 = 0x0;
+pub(crate) const OP_TEXT: u8 = 0x1;
+pub(crate) const OP_BINARY: u8 = 0x2;
+pub(crate) const OP_CLOSE: u8 = 0x8;
+pub(crate) const OP_PING: u8 = 0x9;
+pub(crate) const OP_PONG: u8 = 0xa;
+
+/// WebSocket transport of go-judge, for deployments without its gRPC server.
+///
+/// Requests are sent as JSON over a single WebSocket connection to `<host>/ws`,
+/// which is opened again after it is closed, and files are managed by the REST API
+/// at `<host>/file`.
+/// Streaming is not supported, so streamed files are sent and received at once.
+pub(crate) struct WebSocketTransport {
+  /// HTTP address of the server, without the trailing slash.
+  base: String,
+
+  http: hyper::Client<HttpConnector>,
+
+  /// The open connection, None before it is opened.
+  connection: tokio::sync::Mutex<Option<Arc<Connection>>>,
+
+  /// Id of the next request, to match its response.
+  next_id: AtomicU64,
+}
+
+/// Senders of the responses of the requests waiting on a connection, by request id.
+type Pending = HashMap<String, oneshot::Sender<Result<JsonResponse, Error>>>;
+
+/// An open WebSocket connection.
+struct Connection {
+  writer: tokio::sync::Mutex<WriteHalf<hyper::upgrade::Upgraded>>,
+
+  /// Requests waiting for responses, None after the connection is closed.
+  pending: Mutex<Option<Pending>>,
+}
+
+impl Connection {
+  /// Send a frame of a whole message.
+  async fn send(&self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mask = uuid::Uuid::new_v4().into_bytes();
+    let mut writer = self.writer.lock().await;
+    return write_frame(
+      &mut *writer,
+      opcode,
+      payload,
+      [mask[0], mask[1], mask[2], mask[3]],
+    )
+    .await;
+  }
+
+  /// Mark the connection closed, and fail the waiting requests as unavailable.
+  fn close(&self, message: &str) {
+    if let Some(pending) = self.pending.lock().unwrap().take() {
+      log::warn!("sandbox websocket closed: {}", message);
+      for (_, tx) in pending {
+        _ = tx.send(Err(Error::Unavailable(message.to_string())));
+      }
+    }
+  }
+
+  fn is_closed(&self) -> bool {
+    return self.pending.lock().unwrap().is_none();
+  }
+}
+
+/// Cancel a request on the sandbox if its call is dropped before the response is received.
+struct CancelOnDrop {
+  connection: Arc<Connection>,
+
+  /// Id of the request, None after the response is received.
+  request_id: Option<String>,
+}
+
+impl Drop for CancelOnDrop {
+  fn drop(&mut self) {
+    let request_id = match self.request_id.take() {
+      Some(x) => x,
+      None => return,
+    };
+    match self.connection.pending.lock().unwrap().as_mut() {
+      Some(x) => x.remove(&request_id),
+      None => return,
+    };
+    let connection = self.connection.clone();
+    tokio::spawn(async move {
+      let body = serde_json::json!({ "cancelRequestId": request_id }).to_string();
+      if let Err(err) = connection.send(OP_TEXT, body.as_bytes()).await {
+        connection.close(&err.to_string());
+      }
+    });
+  }
+}
+
+impl WebSocketTransport {
+  /// Create a transport to the HTTP address of the server, the connection is opened lazily.
+  pub(crate) fn connect(host: &str) -> Self {
+    return Self {
+      base: host.trim_end_matches('/').to_string(),
+      http: hyper::Client::new(),
+      connection: tokio::sync::Mutex::new(None),
+      next_id: AtomicU64::new(0),
+    };
+  }
+
+  /// Get the open connection, or open a new one.
+  async fn connection(&self) -> Result<Arc<Connection>, Error> {
+    let mut connection = self.connection.lock().await;
+    if let Some(x) = connection.as_ref().filter(|x| !x.is_closed()) {
+      return Ok(x.clone());
+    }
+    let x = self.open().await?;
+    *connection = Some(x.clone());
+    return Ok(x);
+  }
+
+  /// Open a connection by the WebSocket handshake,
+  /// and receive its messages in background until it is closed.
+  async fn open(&self) -> Result<Arc<Connection>, Error> {
+    let key = base64::encode(uuid::Uuid::new_v4().as_bytes());
+    let req = hyper::Request::get(format!("{}/ws", self.base))
+      .header(header::CONNECTION, "Upgrade")
+      .header(header::UPGRADE, "websocket")
+      .header(header::SEC_WEBSOCKET_VERSION, "13")
+      .header(header::SEC_WEBSOCKET_KEY, &key)
+      .body(Body::empty())
+      .map_err(|err| Error::Rpc(err.to_string()))?;
+    let res = self.http.request(req).await.map_err(http_error)?;
+    if res.status() != StatusCode::SWITCHING_PROTOCOLS {
+      return Err(Error::Rpc(format!(
+        "websocket handshake failed: {}",
+        res.status()
+      )));
+    }
+    if res
+      .headers()
+      .get(header::SEC_WEBSOCKET_ACCEPT)
+      .map(|x| x.as_bytes())
+      != Some(accept_key(&key).as_bytes())
+    {
+      return Err(Error::Rpc(
+        "websocket handshake failed: wrong accept key".to_string(),
+      ));
+    }
+    let (reader, writer) = tokio::io::split(hyper::upgrade::on(res).await.map_err(http_error)?);
+
+    let connection = Arc::new(Connection {
+      writer: tokio::sync::Mutex::new(writer),
+      pending: Mutex::new(Some(HashMap::new())),
+    });
+    let conn = connection.clone();
+    tokio::spawn(async move {
+      let mut reader = MessageReader::new(reader);
+      let message = loop {
+        match reader.read_message().await {
+          Ok((OP_TEXT | OP_BINARY, payload)) => {
+            let res = match serde_json::from_slice::<JsonResponse>(&payload) {
+              Ok(x) => x,
+              Err(err) => {
+                log::warn!("invalid message from sandbox websocket: {}", err);
+                continue;
+              }
+            };
+            let tx = match conn.pending.lock().unwrap().as_mut() {
+              Some(x) => x.remove(&res.request_id),
+              None => None,
+            };
+            if let Some(tx) = tx {
+              _ = tx.send(Ok(res));
+            }
+          }
+          Ok((OP_PING, payload)) => {
+            if let Err(err) = conn.send(OP_PONG, &payload).await {
+              break err.to_string();
+            }
+          }
+          Ok((OP_CLOSE, _)) => break "closed by the sandbox".to_string(),
+          Ok(_) => {}
+          Err(err) => break err.to_string(),
+        }
+      };
+      conn.close(&message);
+    });
+    return Ok(connection);
+  }
+
+  /// Send a request to the REST API, and return the body of a successful response.
+  async fn rest(
+    &self,
+    method: Method,
+    path: &str,
+    content_type: Option<String>,
+    body: Body,
+  ) -> Result<Bytes, Error> {
+    let mut req = hyper::Request::builder()
+      .method(method)
+      .uri(format!("{}{}", self.base, path));
+    if let Some(x) = content_type {
+      req = req.header(header::CONTENT_TYPE, x);
+    }
+    let req = req.body(body).map_err(|err| Error::Rpc(err.to_string()))?;
+    let res = self.http.request(req).await.map_err(http_error)?;
+    let status = res.status();
+    let body = hyper::body::to_bytes(res.into_body())
+      .await
+      .map_err(http_error)?;
+    return match status {
+      x if x.is_success() => Ok(body),
+      StatusCode::NOT_FOUND => Err(Error::NotFound(path.to_string())),
+      StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => {
+        Err(Error::Unavailable(status.to_string()))
+      }
+      _ => Err(Error::Rpc(format!(
+        "{}: {}",
+        status,
+        String::from_utf8_lossy(&body)
+      ))),
+    };
+  }
+}
+
+#[async_trait]
+impl SandboxTransport for WebSocketTransport {
+  async fn file_get(&self, file_id: &str) -> Result<Vec<u8>, Error> {
+    return match self
+      .rest(
+        Method::GET,
+        &format!("/file/{}", file_id),
+        None,
+        Body::empty(),
+      )
+      .await
+    {
+      Ok(x) => Ok(x.to_vec()),
+      Err(Error::NotFound(_)) => Err(Error::NotFound(file_id.to_string())),
+      Err(err) => Err(err),
+    };
+  }
+
+  async fn file_add(&self, content: &[u8]) -> Result<String, Error> {
+    let boundary = format!("rindag-{}", uuid::Uuid::new_v4().simple());
+    let mut body = format!(
+      "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"file\"\r\n\
+       Content-Type: application/octet-stream\r\n\r\n",
+      boundary
+    )
+    .into_bytes();
+    body.extend_from_slice(content);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    let res = self
+      .rest(
+        Method::POST,
+        "/file",
+        Some(format!("multipart/form-data; boundary={}", boundary)),
+        body.into(),
+      )
+      .await?;
+    return serde_json::from_slice(&res).map_err(|err| Error::Rpc(err.to_string()));
+  }
+
+  async fn file_delete(&self, file_id: &str) -> Result<(), Error> {
+    return match self
+      .rest(
+        Method::DELETE,
+        &format!("/file/{}", file_id),
+        None,
+        Body::empty(),
+      )
+      .await
+    {
+      Ok(_) => Ok(()),
+      Err(Error::NotFound(_)) => Err(Error::NotFound(file_id.to_string())),
+      Err(err) => Err(err),
+    };
+  }
+
+  async fn file_list(&self) -> Result<HashMap<String, String>, Error> {
+    let res = self.rest(Method::GET, "/file", None, Body::empty()).await?;
+    return serde_json::from_slice(&res).map_err(|err| Error::Rpc(err.to_string()));
+  }
+
+  async fn exec(&self, req: proto::Request) -> Result<proto::Response, Error> {
+    let id = self.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+    let body = serde_json::to_vec(&JsonRequest::new(id.clone(), req)?)
+      .map_err(|err| Error::Rpc(err.to_string()))?;
+    let connection = self.connection().await?;
+    let (tx, rx) = oneshot::channel();
+    match connection.pending.lock().unwrap().as_mut() {
+      Some(x) => x.insert(id.clone(), tx),
+      None => return Err(Error::Unavailable("connection closed".to_string())),
+    };
+    let mut waiting = CancelOnDrop {
+      connection: connection.clone(),
+      request_id: Some(id),
+    };
+    if let Err(err) = connection.send(OP_TEXT, &body).await {
+      connection.close(&err.to_string());
+    }
+    let res = rx
+      .await
+      .map_err(|_| Error::Unavailable("connection closed".to_string()))?;
+    waiting.request_id = None;
+    return Ok(res?.into());
+  }
+}
+
+/// Map an HTTP error to a sandbox error, connection failures are unavailable.
+fn http_error(err: hyper::Error) -> Error {
+  if err.is_connect() || err.is_closed() || err.is_incomplete_message() {
+    return Error::Unavailable(err.to_string());
+  }
+  return Error::Rpc(err.to_string());
+}
+
+/// Accept key of a WebSocket handshake for the client key.
+pub(crate) fn accept_key(key: &str) -> String {
+  return base64::encode(Sha1::digest(format!("{}{}", key, WEBSOCKET_GUID)));
+}
+
+/// Write a whole message as a single WebSocket frame, masked by `mask` as clients must do.
+pub(crate) async fn write_frame(
+  writer: &mut (impl AsyncWrite + Unpin),
+  opcode: u8,
+  payload: &[u8],
+  mask: [u8; 4],
+) -> io::Result<()> {
+  let mut frame = vec![0x80 | opcode];
+  match payload.len() {
+    len @ 0..=125 => frame.push(0x80 | len as u8),
+    len @ 126..=0xffff => {
+      frame.push(0x80 | 126);
+      frame.extend((len as u16).to_be_bytes());
+    }
+    len => {
+      frame.push(0x80 | 127);
+      frame.extend((len as u64).to_be_bytes());
+    }
+  }
+  frame.extend(mask);
+  frame.extend(payload.iter().enumerate().map(|(i, x)| x ^ mask[i % 4]));
+  writer.write_all(&frame).await?;
+  return writer.flush().await;
+}
+
+/// Reader of WebSocket messages, joining fragmented messages.
+pub(crate) struct MessageReader<R> {
+  reader: R,
+
+  /// Opcode and payload of the fragments received of a message.
+  partial: Option<(u8, Vec<u8>)>,
+}
+
+impl<R: AsyncRead + Unpin> MessageReader<R> {
+  pub(crate) fn new(reader: R) -> Self {
+    return Self {
+      reader,
+      partial: None,
+    };
+  }
+
+  /// Read the next message and return its opcode and payload.
+  ///
+  /// Control frames (e.g. ping) are returned as soon as they are received,
+  /// even between the fragments of a message.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the reader fails, the frames are invalid,
+  /// or the message is larger than 64 MiB.
+  pub(crate) async fn read_message(&mut self) -> io::Result<(u8, Vec<u8>)> {
+    loop {
+      let mut head = [0; 2];
+      self.reader.read_exact(&mut head).await?;
+      let fin = head[0] & 0x80 != 0;
+      let opcode = head[0] & 0x0f;
+      let len = match head[1] & 0x7f {
+        126 => self.reader.read_u16().await? as u64,
+        127 => self.reader.read_u64().await?,
+        x => x as u64,
+      };
+      if len + self.partial.as_ref().map_or(0, |x| x.1.len() as u64) > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidData,
+          "websocket message is too large",
+        ));
+      }
+      let mut mask = None;
+      if head[1] & 0x80 != 0 {
+        let mut x = [0; 4];
+        self.reader.read_exact(&mut x).await?;
+        mask = Some(x);
+      }
+      let mut payload = vec![0; len as usize];
+      self.reader.read_exact(&mut payload).await?;
+      if let Some(mask) = mask {
+        for (i, x) in payload.iter_mut().enumerate() {
+          *x ^= mask[i % 4];
+        }
+      }
+
+      if opcode >= OP_CLOSE {
+        return Ok((opcode, payload));
+      }
+      let (opcode, mut message) = match (opcode, self.partial.take()) {
+        (OP_CONTINUATION, Some(x)) => x,
+        (OP_CONTINUATION, None) => {
+          return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected websocket continuation frame",
+          ))
+        }
+        (opcode, _) => (opcode, vec![]),
+      };
+      message.extend(payload);
+      if fin {
+        return Ok((opcode, message));
+      }
+      self.partial = Some((opcode, message));
+    }
+  }
+}
+
+/// A request in the JSON model of go-judge.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JsonRequest {
+  request_id: String,
+  cmd: Vec<JsonCmd>,
+  pipe_mapping: Vec<JsonPipeMap>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonCmd {
+  args: Vec<String>,
+  env: Vec<String>,
+  files: Vec<Option<JsonFile>>,
+  tty: bool,
+  cpu_limit: u64,
+  clock_limit: u64,
+  memory_limit: u64,
+  stack_limit: u64,
+  proc_limit: u64,
+  cpu_rate_limit: u64,
+  #[serde(skip_serializing_if = "String::is_empty")]
+  cpu_set_limit: String,
+  strict_memory_limit: bool,
+  copy_in: HashMap<String, JsonFile>,
+  copy_out: Vec<String>,
+  copy_out_cached: Vec<String>,
+  #[serde(skip_serializing_if = "String::is_empty")]
+  copy_out_dir: String,
+  copy_out_max: u64,
+}
+
+#[derive(Debug, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct JsonFile {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  src: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  content: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  file_id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  max: Option<i64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pipe: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonPipeMap {
+  r#in: JsonPipeIndex,
+  out: JsonPipeIndex,
+  proxy: bool,
+  name: String,
+  max: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonPipeIndex {
+  index: i32,
+  fd: i32,
+}
+
+impl JsonRequest {
+  /// Convert a proto request.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the request has stream files,
+  /// which are only valid in the streaming RPC.
+  pub(crate) fn new(request_id: String, req: proto::Request) -> Result<Self, Error> {
+    let mut cmd = vec![];
+    for x in req.cmd {
+      let copy_out_names = |files: Vec<proto::request::CmdCopyOutFile>| -> Vec<String> {
+        files
+          .into_iter()
+          .map(|f| match f.optional {
+            true => format!("{}?", f.name),
+            false => f.name,
+          })
+          .collect()
+      };
+      cmd.push(JsonCmd {
+        args: x.args,
+        env: x.env,
+        files: x
+          .files
+          .into_iter()
+          .map(|f| f.file.map(json_file).transpose())
+          .collect::<Result<_, _>>()?,
+        tty: x.tty,
+        cpu_limit: x.cpu_time_limit,
+        clock_limit: x.clock_time_limit,
+        memory_limit: x.memory_limit,
+        stack_limit: x.stack_limit,
+        proc_limit: x.proc_limit,
+        cpu_rate_limit: x.cpu_rate_limit,
+        cpu_set_limit: x.cpu_set_limit,
+        strict_memory_limit: x.strict_memory_limit,
+        copy_in: x
+          .copy_in
+          .into_iter()
+          .filter_map(|(name, f)| Some((name, f.file?)))
+          .map(|(name, f)| Ok((name, json_file(f)?)))
+          .collect::<Result<_, Error>>()?,
+        copy_out: copy_out_names(x.copy_out),
+        copy_out_cached: copy_out_names(x.copy_out_cached),
+        copy_out_dir: x.copy_out_dir,
+        copy_out_max: x.copy_out_max,
+      });
+    }
+    let index = |x: Option<proto::request::pipe_map::PipeIndex>| {
+      let x = x.unwrap_or_default();
+      JsonPipeIndex {
+        index: x.index,
+        fd: x.fd,
+      }
+    };
+    return Ok(Self {
+      request_id,
+      cmd,
+      pipe_mapping: req
+        .pipe_mapping
+        .into_iter()
+        .map(|x| JsonPipeMap {
+          r#in: index(x.r#in),
+          out: index(x.out),
+          proxy: x.proxy,
+          name: x.name,
+          max: x.max,
+        })
+        .collect(),
+    });
+  }
+}
+
+/// Convert a proto file.
+fn json_file(file: proto::request::file::File) -> Result<JsonFile, Error> {
+  use proto::request::file::File;
+  return Ok(match file {
+    File::Local(x) => JsonFile {
+      src: Some(x.src),
+      ..Default::default()
+    },
+    File::Memory(x) => JsonFile {
+      content: Some(String::from_utf8_lossy(&x.content).into_owned()),
+      ..Default::default()
+    },
+    File::Cached(x) => JsonFile {
+      file_id: Some(x.file_id),
+      ..Default::default()
+    },
+    File::Pipe(x) => JsonFile {
+      name: Some(x.name),
+      max: Some(x.max),
+      pipe: Some(x.pipe),
+      ..Default::default()
+    },
+    File::StreamIn(_) | File::StreamOut(_) => {
+      return Err(Error::Rpc(
+        "stream files are not supported by the websocket transport".to_string(),
+      ))
+    }
+  });
+}
+
+/// A response in the JSON model of go-judge.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JsonResponse {
+  request_id: String,
+
+  #[serde(default)]
+  results: Vec<JsonResult>,
+
+  #[serde(default)]
+  error: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct JsonResult {
+  status: String,
+  exit_status: i32,
+  error: String,
+  time: u64,
+  run_time: u64,
+  memory: u64,
+  files: HashMap<String, String>,
+  file_ids: HashMap<String, String>,
+}
+
+impl From<JsonResponse> for proto::Response {
+  fn from(res: JsonResponse) -> Self {
+    use proto::response::result::StatusType;
+    return Self {
+      request_id: res.request_id,
+      results: res
+        .results
+        .into_iter()
+        .map(|x| proto::response::Result {
+          status: match x.status.as_str() {
+            "Accepted" => StatusType::Accepted,
+            "Memory Limit Exceeded" => StatusType::MemoryLimitExceeded,
+            "Time Limit Exceeded" => StatusType::TimeLimitExceeded,
+            "Output Limit Exceeded" => StatusType::OutputLimitExceeded,
+            "File Error" => StatusType::FileError,
+            "Nonzero Exit Status" => StatusType::NonZeroExitStatus,
+            "Signalled" => StatusType::Signalled,
+            "Dangerous Syscall" => StatusType::DangerousSyscall,
+            "Internal Error" => StatusType::InternalError,
+            _ => StatusType::Invalid,
+          } as i32,
+          exit_status: x.exit_status,
+          error: x.error,
+          time: x.time,
+          run_time: x.run_time,
+          memory: x.memory,
+          files: x
+            .files
+            .into_iter()
+            .map(|(name, content)| (name, content.into_bytes()))
+            .collect(),
+          file_ids: x.file_ids,
+          file_error: vec![],
+        })
+        .collect(),
+      error: res.error,
+    };
+  }
+}
@@ -0,0 +1,88 @@
+use thiserror::Error;
+
+use crate::problem;
+
+/// Current version of the schema of the judging events (`problem::Response`)
+/// sent to external consumers.
+///
+/// Bump it whenever a field of the records or an event type is added,
+/// and register the additions in `ADDITIONS`, so consumers asking for an older version
+/// keep receiving the payloads they know.
+pub const VERSION: u32 = 3;
+
+/// Oldest version the events can be emitted in.
+///
+/// Version 1 is the schema before versioning, whose payloads have no `schema_version` field.
+pub const MIN_VERSION: u32 = 1;
+
+/// A part added to the payloads in a schema version.
+enum Addition {
+  /// A field of `record::Record`.
+  RecordField(&'static str),
+
+  /// An event type, the `type` tag of `problem::Response`.
+  Event(&'static str),
+}
+
+/// Parts added in each version, which are dropped when emitting an older version.
+const ADDITIONS: &[(u32, Addition)] = &[
+  (2, Addition::Event("CompileError")),
+  (2, Addition::RecordField("skip_reason")),
+  (2, Addition::RecordField("timing")),
+  (3, Addition::Event("Compiled")),
+  (3, Addition::RecordField("raw_points")),
+  (3, Addition::RecordField("time_kind")),
+];
+
+/// Error when a schema version is not supported.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("unsupported schema version {0}, the supported versions are {MIN_VERSION} to {VERSION}")]
+pub struct UnsupportedVersion(pub u32);
+
+/// Check that the events can be emitted in a schema version.
+pub fn check(version: u32) -> Result<(), UnsupportedVersion> {
+  if !(MIN_VERSION..=VERSION).contains(&version) {
+    return Err(UnsupportedVersion(version));
+  }
+  return Ok(());
+}
+
+/// Encode an event in a schema version, tagged with a `schema_version` field
+/// (except for version 1).
+///
+/// Returns Ok(None) if the event type does not exist in the version,
+/// so it should not be sent to the consumer.
+///
+/// # Errors
+///
+/// This function will return an error if the version is not supported.
+pub fn encode(
+  event: &problem::Response,
+  version: u32,
+) -> Result<Option<serde_json::Value>, UnsupportedVersion> {
+  check(version)?;
+  let mut value = serde_json::to_value(event).unwrap();
+  for (_, addition) in ADDITIONS.iter().filter(|x| x.0 > version) {
+    match addition {
+      Addition::Event(name) => {
+        if value["type"] == *name {
+          return Ok(None);
+        }
+      }
+      Addition::RecordField(name) => {
+        if let Some(record) = value.get_mut("record").and_then(|x| x.as_object_mut()) {
+          record.remove(*name);
+        }
+        if let Some(records) = value.get_mut("records").and_then(|x| x.as_array_mut()) {
+          for record in records.iter_mut().filter_map(|x| x.as_object_mut()) {
+            record.remove(*name);
+          }
+        }
+      }
+    }
+  }
+  if version > 1 {
+    value["schema_version"] = version.into();
+  }
+  return Ok(Some(value));
+}
@@ -0,0 +1,198 @@
+use std::{collections::HashMap, time};
+
+use thiserror::Error;
+
+use crate::{checker, error, generator, judge, program, record, sandbox, validator, CONFIG};
+
+/// Stress testing (a.k.a. duel) of a candidate solution against a reference solution.
+///
+/// Each round generates an input with a different seed, validates it,
+/// runs both solutions on it and checks the candidate's output with the checker,
+/// using the reference's output as the answer.
+#[derive(Debug, Clone)]
+pub struct Stress {
+  pub generator: generator::Generator,
+
+  /// Generator arguments, the seed of each round will be appended after them.
+  pub args: Vec<String>,
+
+  /// Validator for the generated inputs, None to skip validation.
+  pub validator: Option<validator::Validator>,
+
+  /// The solution which is believed to be correct (e.g. a brute force solution).
+  pub reference: program::Executable,
+
+  /// The solution under test.
+  pub candidate: program::Executable,
+
+  pub checker: checker::Checker,
+
+  pub time_limit: time::Duration,
+
+  pub memory_limit: u64,
+
+  /// Extra files when running generator, validator and checker.
+  pub user_copy_in: HashMap<String, sandbox::FileHandle>,
+
+  /// Extra files when running solutions.
+  pub judge_copy_in: HashMap<String, sandbox::FileHandle>,
+}
+
+/// A test on which the candidate solution does not agree with the reference solution.
+#[derive(Debug, Clone)]
+pub struct Counterexample {
+  /// Seed passed to the generator.
+  pub seed: u64,
+
+  pub input: sandbox::FileHandle,
+
+  pub reference_output: sandbox::FileHandle,
+
+  /// Output of the candidate, None if it exits abnormally.
+  pub candidate_output: Option<sandbox::FileHandle>,
+
+  /// Record of the candidate, contains the checker message.
+  pub record: record::Record,
+}
+
+/// Result of a stress testing.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+  /// All rounds passed.
+  Passed { rounds: usize },
+
+  /// A counterexample was found, and the stress testing stopped.
+  Found(Counterexample),
+}
+
+impl Stress {
+  /// Run at most `rounds` rounds, round `i` uses `seed + i` as the generator seed.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the generator, the validator, the reference solution
+  /// or the checker fails, which means the stress testing itself is broken.
+  pub async fn run(&self, rounds: usize, seed: u64) -> Result<Outcome, StressError> {
+    for i in 0..rounds {
+      let seed = seed.wrapping_add(i as u64);
+      if let Some(counterexample) = self.round(seed).await? {
+        log::debug!("stress testing found a counterexample at seed {}", seed);
+        return Ok(Outcome::Found(counterexample));
+      }
+    }
+    return Ok(Outcome::Passed { rounds });
+  }
+
+  /// Run a single round, returns the counterexample if found.
+  async fn round(&self, seed: u64) -> Result<Option<Counterexample>, StressError> {
+    let input = self
+      .generator
+      .generate(
+        [self.args.clone(), vec![seed.to_string()]].concat(),
+        self.user_copy_in.clone(),
+      )
+      .await
+      .map_err(|err| StressError::Generator { seed, err })?;
+
+    if let Some(validator) = &self.validator {
+      validator
+        .validate(vec![], input.clone(), self.user_copy_in.clone())
+        .await
+        .map_err(|err| StressError::Validator { seed, err })?;
+    }
+
+    let (reference, candidate) = futures::join!(
+      self.reference.judge_batch(
+        vec![],
+        input.clone(),
+        self.judge_copy_in.clone(),
+        self.time_limit,
+        self.memory_limit,
+        None,
+        judge::TimeKind::Cpu,
+        None,
+        CONFIG.judge.stdout_limit,
+        &judge::OutputSource::Stdout,
+      ),
+      self.candidate.judge_batch(
+        vec![],
+        input.clone(),
+        self.judge_copy_in.clone(),
+        self.time_limit,
+        self.memory_limit,
+        None,
+        judge::TimeKind::Cpu,
+        None,
+        CONFIG.judge.stdout_limit,
+        &judge::OutputSource::Stdout,
+      ),
+    );
+
+    let reference_output = match reference {
+      (_, Some(f)) => f,
+      (res, None) => {
+        return Err(StressError::Reference {
+          seed,
+          err: res.into(),
+        })
+      }
+    };
+
+    let candidate_output = match candidate {
+      (_, Some(f)) => f,
+      (res, None) => {
+        return Ok(Some(Counterexample {
+          seed,
+          input,
+          reference_output,
+          candidate_output: None,
+          record: record::Record::new_interrupted(&res),
+        }))
+      }
+    };
+
+    let output = self
+      .checker
+      .check(
+        vec![],
+        input.clone(),
+        candidate_output.clone(),
+        reference_output.clone(),
+        self.user_copy_in.clone(),
+      )
+      .await
+      .map_err(|err| StressError::Checker { seed, err })?
+      .output;
+
+    if output.status == checker::Status::Accepted {
+      return Ok(None);
+    }
+
+    return Ok(Some(Counterexample {
+      seed,
+      input,
+      reference_output,
+      candidate_output: Some(candidate_output),
+      record: record::Record::new_checked(&candidate.0, &output),
+    }));
+  }
+}
+
+/// Error when the stress testing itself is broken.
+#[derive(Debug, Error, Clone)]
+pub enum StressError {
+  #[error("generator failed at seed {seed}: {err}")]
+  Generator { seed: u64, err: error::RuntimeError },
+
+  #[error("generated input is invalid at seed {seed}: {err}")]
+  Validator {
+    seed: u64,
+    err: error::ValidateError,
+  },
+
+  #[error("reference solution failed at seed {seed}: {err}")]
+  Reference { seed: u64, err: error::RuntimeError },
+
+  #[error("checker failed at seed {seed}: {err}")]
+  Checker { seed: u64, err: error::RuntimeError },
+}
@@ -0,0 +1,38 @@
+use crate::{artifact, checker, CONFIG};
+
+/// A test for storing and loading artifacts by their content hashes.
+#[test]
+fn test_artifact_store() {
+  super::async_test(async {
+    let dir = std::env::temp_dir().join(format!("rindag-artifacts-{}", uuid::Uuid::new_v4()));
+    let store = artifact::Store::open(&dir).unwrap();
+
+    let id = store.save(b"wrong answer").await.unwrap();
+    assert_eq!(store.save(b"wrong answer").await.unwrap(), id);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    // Reopen like after a restart.
+    let store = artifact::Store::open(&dir).unwrap();
+    assert_eq!(
+      store.load(&id).await.unwrap().as_deref(),
+      Some(&b"wrong answer"[..])
+    );
+    assert_eq!(store.load(&"0".repeat(64)).await.unwrap(), None);
+    assert_eq!(store.load("../artifacts").await.unwrap(), None);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  });
+}
+
+/// A test for cutting a long checker message to the configured prefix.
+#[test]
+fn test_long_checker_message() {
+  let limit = CONFIG.judge.message_limit;
+  let output = format!("wrong answer {}", "x".repeat(limit * 4));
+  let parsed = checker::Output::parse(&output);
+  assert_eq!(parsed.status, checker::Status::WrongAnswer);
+  assert_eq!(parsed.message.len(), limit);
+  assert!(parsed.message.ends_with("..."));
+  assert!(output.starts_with(parsed.message.trim_end_matches("...")));
+  assert_eq!(parsed.artifact, None);
+}
@@ -0,0 +1,77 @@
+use std::str::FromStr;
+
+use crate::builtin;
+
+#[test]
+fn test_list() {
+  let checkers = builtin::list("checker").unwrap();
+  assert!(checkers.contains(&"ncmp.cpp".to_string()));
+  assert!(checkers.windows(2).all(|x| x[0] < x[1]));
+
+  for pool in ["testlib", "checker", "validator", "generator"] {
+    for path in builtin::list(pool).unwrap() {
+      let file = builtin::File::from_str(&format!("{}:{}", pool, path)).unwrap();
+      assert!(!file.as_bytes().is_empty());
+    }
+  }
+
+  assert!(matches!(
+    builtin::list("interactor"),
+    Err(builtin::FileNotExistError::Pool(_))
+  ));
+  assert!(builtin::File::new("validator", "missing.cpp").is_err());
+}
+
+#[test]
+fn test_checker_info() {
+  let info = builtin::CheckerInfo::parse(
+    "rcmp9.cpp",
+    r#"#include "testlib.h"
+
+using namespace std;
+
+const double EPS = 1E-9;
+
+int main(int argc, char *argv[]) {
+    setName("compare two sequences of doubles, max absolute or relative error = %.10f", EPS);
+    registerTestlibCmd(argc, argv);
+}
+"#,
+  );
+  assert_eq!(info.name, "rcmp9");
+  assert_eq!(info.path, "rcmp9.cpp");
+  assert_eq!(
+    info.description.as_deref(),
+    Some("compare two sequences of doubles, max absolute or relative error = %.10f")
+  );
+  assert_eq!(info.arity, Some(builtin::Arity::Sequence));
+  assert_eq!(info.tolerance, Some(1e-9));
+
+  let info = builtin::CheckerInfo::parse(
+    "yesno.cpp",
+    r#"#include "testlib.h"
+int main(int argc, char *argv[]) {
+    setName("%s", ("YES or NO (case insensitive)"));
+}
+"#,
+  );
+  assert_eq!(
+    info.description.as_deref(),
+    Some("YES or NO (case insensitive)")
+  );
+  assert_eq!(info.arity, None);
+  assert_eq!(info.tolerance, None);
+
+  let info = builtin::CheckerInfo::parse(
+    "icmp.cpp",
+    "/*\n * Compares two signed 32-bit integers.\n */\n#include \"testlib.h\"\nint main() {}\n",
+  );
+  assert_eq!(
+    info.description.as_deref(),
+    Some("Compares two signed 32-bit integers.")
+  );
+  assert_eq!(info.arity, Some(builtin::Arity::Single));
+
+  let checkers = builtin::checkers();
+  assert!(checkers.iter().any(|x| x.name == "ncmp"));
+}
@@ -1,9 +1,9 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
 
 use crate::{
   builtin,
   checker::{self, Output},
-  lang, program, sandbox,
+  data, lang, program, sandbox, CONFIG,
 };
 
 #[test]
@@ -14,7 +14,9 @@ fn test_parse_output() {
       Output {
         status: checker::Status::Accepted,
         score: 1.0f32,
-        message: "ok you win\n3 steps.".to_string()
+        raw_points: None,
+        message: "ok you win\n3 steps.".to_string(),
+        artifact: None,
       }
     );
 
@@ -23,7 +25,9 @@ fn test_parse_output() {
       Output {
         status: checker::Status::WrongAnswer,
         score: 0.0f32,
-        message: "wrong answer you lose\n12 steps.".to_string()
+        raw_points: None,
+        message: "wrong answer you lose\n12 steps.".to_string(),
+        artifact: None,
       }
     );
 
@@ -32,16 +36,26 @@ fn test_parse_output() {
       Output {
         status: checker::Status::PartiallyCorrect,
         score: 0.12f32,
-        message: "points 0.12 you used 12 / 100 moves".to_string()
+        raw_points: Some(0.12),
+        message: "points 0.12 you used 12 / 100 moves".to_string(),
+        artifact: None,
       }
     );
 
+    // Points out of range are clamped into the score, and kept in the raw points.
+    let points = Output::parse("points 35");
+    assert_eq!(points.status, checker::Status::Accepted);
+    assert_eq!(points.score, 1.);
+    assert_eq!(points.raw_points, Some(35.));
+
     assert_eq!(
       Output::parse("wrong output format \t \textra spaces\n\t\t"),
       Output {
         status: checker::Status::PresentationError,
         score: 0.0f32,
-        message: "wrong output format \t \textra spaces\n\t\t".to_string()
+        raw_points: None,
+        message: "wrong output format \t \textra spaces\n\t\t".to_string(),
+        artifact: None,
       }
     );
 
@@ -50,7 +64,9 @@ fn test_parse_output() {
       Output {
         status: checker::Status::Accepted,
         score: 0.1f32,
-        message: "status(accepted)\nscore(0.1)".to_string()
+        raw_points: Some(0.1),
+        message: "status(accepted)\nscore(0.1)".to_string(),
+        artifact: None,
       }
     );
   });
@@ -75,7 +91,8 @@ fn test_builtin_checker() {
                 .unwrap()
                 .as_bytes(),
             )
-            .await,
+            .await
+            .unwrap(),
           )]
           .into(),
         )
@@ -86,14 +103,184 @@ fn test_builtin_checker() {
     let res = chk
       .check(
         vec![],
-        sandbox::FileHandle::upload("hello\n".as_bytes()).await,
-        sandbox::FileHandle::upload("9 9   8\n2\n  4 4\t3 5\n3".as_bytes()).await,
-        sandbox::FileHandle::upload("9 9 8 2 4 4 3 5 3\n".as_bytes()).await,
+        sandbox::FileHandle::upload("hello\n".as_bytes())
+          .await
+          .unwrap(),
+        sandbox::FileHandle::upload("9 9   8\n2\n  4 4\t3 5\n3".as_bytes())
+          .await
+          .unwrap(),
+        sandbox::FileHandle::upload("9 9 8 2 4 4 3 5 3\n".as_bytes())
+          .await
+          .unwrap(),
         HashMap::new(),
       )
       .await
       .unwrap();
 
-    assert_eq!(res.status, checker::Status::Accepted);
+    assert_eq!(res.output.status, checker::Status::Accepted);
+    assert_eq!(res.result.status, sandbox::Status::Accepted);
+
+    let cache = Arc::new(checker::Cache::default());
+    let chk = chk.with_cache(cache.clone());
+    for _ in 0..2 {
+      let res = chk
+        .check(
+          vec![],
+          sandbox::FileHandle::upload("hello\n".as_bytes())
+            .await
+            .unwrap(),
+          sandbox::FileHandle::upload("1 2".as_bytes()).await.unwrap(),
+          sandbox::FileHandle::upload("1 3".as_bytes()).await.unwrap(),
+          HashMap::new(),
+        )
+        .await
+        .unwrap();
+      assert_eq!(res.output.status, checker::Status::WrongAnswer);
+    }
+    assert_eq!(cache.stats(), checker::CacheStats { hits: 1, misses: 1 });
+  });
+}
+
+/// A test for a checker running out of time, whose execution result is reported in the error.
+#[test]
+fn test_checker_time_limit() {
+  super::async_test(async {
+    let chk = checker::Checker::from(
+      program::Source {
+        lang: lang::Lang::from_str("c").unwrap(),
+        data: data::Provider::Memory(
+          "int main(){for(volatile int i=0;;i++);}"
+            .as_bytes()
+            .to_vec(),
+        ),
+      }
+      .compile(vec![], HashMap::new())
+      .await
+      .unwrap(),
+    );
+    let file = sandbox::FileHandle::upload("1".as_bytes()).await.unwrap();
+    let err = chk
+      .check(vec![], file.clone(), file.clone(), file, HashMap::new())
+      .await
+      .unwrap_err();
+    assert_eq!(err.result.status, sandbox::Status::TimeLimitExceeded);
+    assert!(err.result.time >= CONFIG.judge.time_limit);
+    assert!(err.result.memory > 0);
   });
 }
+
+#[test]
+fn test_presentation_error_policy() {
+  let output = checker::Output::parse("wrong output format Extra spaces");
+  assert_eq!(output.status, checker::Status::PresentationError);
+
+  assert_eq!(
+    checker::PresentationErrorPolicy::AsIs.apply(output.clone()),
+    output
+  );
+
+  let wa = checker::PresentationErrorPolicy::WrongAnswer.apply(output.clone());
+  assert_eq!(wa.status, checker::Status::WrongAnswer);
+  assert_eq!(wa.score, 0.);
+
+  let penalized = checker::PresentationErrorPolicy::Penalized { score: 0.5 }.apply(output);
+  assert_eq!(penalized.status, checker::Status::PresentationError);
+  assert_eq!(penalized.score, 0.5);
+
+  let ok = checker::Output::parse("ok");
+  assert_eq!(
+    checker::PresentationErrorPolicy::WrongAnswer.apply(ok.clone()),
+    ok
+  );
+}
+
+#[test]
+fn test_builtin_compare() {
+  let compare = |builtin: checker::Builtin, output: &str, answer: &str| {
+    builtin.compare(output.as_bytes(), answer.as_bytes())
+  };
+
+  let ok = compare(checker::Builtin::Tokens, "1  2\n3\n", "1 2 3");
+  assert_eq!(ok.status, checker::Status::Accepted);
+  assert_eq!(ok.message, "ok 3 words");
+  let wa = compare(checker::Builtin::Tokens, "1 2 4", "1 2 3");
+  assert_eq!(wa.status, checker::Status::WrongAnswer);
+  assert_eq!(
+    wa.message,
+    "wrong answer 3rd words differ - expected: '3', found: '4'"
+  );
+  assert_eq!(
+    compare(checker::Builtin::Tokens, "1 2", "1 2 3").status,
+    checker::Status::WrongAnswer
+  );
+  assert_eq!(
+    compare(checker::Builtin::Tokens, "1 2 3 4", "1 2 3").status,
+    checker::Status::WrongAnswer
+  );
+
+  let lines = |output| compare(checker::Builtin::Lines, output, "a b\nc\n");
+  assert_eq!(lines("a b  \r\nc\n\n").status, checker::Status::Accepted);
+  assert_eq!(lines("a b c\n").status, checker::Status::WrongAnswer);
+  assert_eq!(lines("a  b\nc\n").status, checker::Status::WrongAnswer);
+
+  let floats = |output| {
+    compare(
+      checker::Builtin::Floats { eps: 1e-6 },
+      output,
+      "1000000 0.5 x",
+    )
+  };
+  assert_eq!(
+    floats("1000000.5 0.5000001 x").status,
+    checker::Status::Accepted
+  );
+  assert_eq!(floats("1000002 0.5 x").status, checker::Status::WrongAnswer);
+  assert_eq!(floats("1e6 0.5 y").status, checker::Status::WrongAnswer);
+  assert_eq!(floats("1e6 nan x").status, checker::Status::WrongAnswer);
+}
+
+#[test]
+fn test_parse_appes() {
+  let parse = |outcome: &str, message: &str| {
+    checker::Output::parse(&format!(
+      "<?xml version=\"1.0\" encoding=\"windows-1251\"?><result {}>{}</result>\n",
+      outcome, message
+    ))
+  };
+
+  let ok = parse("outcome = \"accepted\"", "3 numbers &amp; more");
+  assert_eq!(ok.status, checker::Status::Accepted);
+  assert_eq!(ok.score, 1.);
+  assert_eq!(ok.message, "3 numbers & more");
+
+  let points = parse("outcome = \"points\" points = \"0.123456\"", "");
+  assert_eq!(points.status, checker::Status::PartiallyCorrect);
+  assert_eq!(points.score, 0.123456);
+  assert_eq!(points.raw_points, Some(0.123456));
+  assert_eq!(points.message, "");
+  assert_eq!(
+    parse("outcome = \"points\" points = \"2\"", "").status,
+    checker::Status::Accepted
+  );
+  let pc = parse("outcome = \"partially-correct\" pctype = \"25\"", "");
+  assert_eq!(pc.status, checker::Status::PartiallyCorrect);
+  assert_eq!(pc.score, 0.25);
+
+  for (outcome, status) in [
+    ("wrong-answer", checker::Status::WrongAnswer),
+    ("presentation-error", checker::Status::PresentationError),
+    ("unexpected-eof", checker::Status::PresentationError),
+    ("fail", checker::Status::SystemError),
+  ] {
+    let output = parse(&format!("outcome = \"{}\"", outcome), "x");
+    assert_eq!(output.status, status);
+    assert_eq!(output.score, 0.);
+  }
+
+  assert_eq!(checker::Output::parse_appes("ok 3 numbers"), None);
+  assert_eq!(checker::Output::parse_appes("<result>x</result>"), None);
+  assert_eq!(
+    checker::Output::parse_appes("<result outcome = \"unknown\">x</result>"),
+    None
+  );
+}
@@ -0,0 +1,346 @@
+use crate::{checker, judge, problem, record};
+
+#[test]
+fn test_parse() {
+  let config = problem::config::ProblemConfig::parse(
+    r#"
+    time_limit_ms = 1000
+    memory_limit_mb = 256
+    checker = { builtin = "ncmp.cpp" }
+    standard_solution = { path = "std.cpp" }
+
+    [[subtasks]]
+    score = 40
+    tests = [{ input = "1.in" }]
+
+    [[subtasks]]
+    score = 60
+    dependences = [0]
+    scoring = "sum"
+    time_limit_ms = 2000
+    time_kind = "wall"
+    tests = [{ generate = "gen 1 2", answer = "2.ans" }]
+
+    [[solutions]]
+    path = "brute.cpp"
+    expected = "TimeLimitExceeded"
+    "#,
+  )
+  .unwrap();
+
+  assert!(matches!(
+    config.checker,
+    problem::config::CheckerConfig::Builtin { ref builtin } if builtin == "ncmp.cpp"
+  ));
+  assert_eq!(config.subtasks[1].dependences, vec![0]);
+  assert_eq!(
+    config.subtasks[1].scoring,
+    problem::config::ScoringConfig::Sum
+  );
+  assert_eq!(config.subtasks[1].testset, problem::Testset::Main);
+  assert_eq!(config.time_kind, None);
+  assert_eq!(config.subtasks[1].time_kind, Some(judge::TimeKind::Wall));
+  assert_eq!(
+    config.solutions[0].expected,
+    record::RecordStatus::TimeLimitExceeded
+  );
+
+  let config = problem::config::ProblemConfig::parse(
+    r#"
+    time_limit_ms = 1000
+    memory_limit_mb = 256
+    checker = { compare = "floats", eps = 1e-6 }
+    standard_solution = { path = "std.cpp" }
+    subtasks = []
+    "#,
+  )
+  .unwrap();
+  assert!(matches!(
+    config.checker,
+    problem::config::CheckerConfig::Compare(checker::Builtin::Floats { eps }) if eps == 1e-6
+  ));
+
+  assert!(matches!(
+    problem::config::ProblemConfig::parse("time_limit_ms = 1000\nunknown = 1"),
+    Err(problem::config::LoadError::Parse(_))
+  ));
+}
+
+#[test]
+fn test_load() {
+  super::async_test(async {
+    let root = std::env::temp_dir().join(format!("rindag-config-{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&root).await.unwrap();
+    let write = |path: &str, content: &str| tokio::fs::write(root.join(path), content.to_string());
+    write(
+      "problem.toml",
+      r#"
+      time_limit_ms = 1000
+      memory_limit_mb = 256
+      checker = { builtin = "ncmp.cpp" }
+      validator = { path = "val.cpp" }
+      standard_solution = { path = "std.c" }
+
+      [[subtasks]]
+      score = 100
+      memory_limit_mb = 64
+      tests = [{ input = "1.in", answer = "1.ans" }, { input = "2.in" }]
+
+      [[solutions]]
+      path = "wa.c"
+      expected = "WrongAnswer"
+      "#,
+    )
+    .await
+    .unwrap();
+    for (path, content) in [
+      ("val.cpp", "int main(){}"),
+      ("std.c", "int main(){}"),
+      ("wa.c", "int main(){}"),
+      ("1.in", "1 2\n"),
+      ("1.ans", "3\n"),
+      ("2.in", "3 4\n"),
+    ] {
+      write(path, content).await.unwrap();
+    }
+
+    let (problem, checks) = problem::config::load(&root).await.unwrap();
+
+    assert_eq!(problem.kind, problem::Kind::Batch);
+    assert!(problem.user_copy_in.contains_key("testlib.h"));
+    let subtask = &problem.subtasks[0];
+    assert_eq!(subtask.time_limit, std::time::Duration::from_secs(1));
+    assert_eq!(subtask.memory_limit, 64 * 1024 * 1024);
+    assert!(
+      matches!(&subtask.tests[0].answer, problem::Answer::Plain { context } if context == b"3\n")
+    );
+    assert!(matches!(
+      subtask.tests[1].answer,
+      problem::Answer::Generated
+    ));
+    assert_eq!(problem.standard_solution.lang.to_string(), "c");
+    assert!(checks.validator.is_some());
+    assert_eq!(checks.solutions[0].name, "wa.c");
+    assert_eq!(
+      checks.solutions[0].expected,
+      record::RecordStatus::WrongAnswer
+    );
+
+    write(
+      "problem.toml",
+      r#"
+      time_limit_ms = 1000
+      memory_limit_mb = 256
+      checker = { builtin = "ncmp.cpp" }
+      standard_solution = { path = "std.c" }
+
+      [[subtasks]]
+      score = 100
+      dependences = [0]
+      tests = [{ input = "1.in" }]
+      "#,
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+      problem::config::load(&root).await,
+      Err(problem::config::LoadError::Invalid(_))
+    ));
+
+    tokio::fs::remove_dir_all(&root).await.unwrap();
+  });
+}
+
+#[test]
+fn test_build_answer_mismatch() {
+  super::async_test(async {
+    let root = std::env::temp_dir().join(format!("rindag-config-{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&root).await.unwrap();
+    for (path, content) in [
+      (
+        "problem.toml",
+        r#"
+        time_limit_ms = 1000
+        memory_limit_mb = 256
+        checker = { builtin = "ncmp.cpp" }
+        standard_solution = { path = "std.c" }
+
+        [[subtasks]]
+        score = 100
+        tests = [{ input = "1.in", answer = "1.ans" }, { input = "2.in", answer = "2.ans" }]
+        "#,
+      ),
+      (
+        "std.c",
+        "#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}",
+      ),
+      ("1.in", "1 2\n"),
+      ("1.ans", "3\n"),
+      ("2.in", "3 4\n"),
+      ("2.ans", "8\n"),
+    ] {
+      tokio::fs::write(root.join(path), content).await.unwrap();
+    }
+
+    let (problem, checks) = problem::config::load(&root).await.unwrap();
+    let (issues, usage) = checks.run(&problem).await.unwrap();
+
+    assert_eq!(usage, problem::config::Usage::default());
+    assert_eq!(issues.len(), 1);
+    assert!(matches!(
+      &issues[0],
+      problem::config::Issue::AnswerMismatch { test_id, .. } if test_id.index == 1
+    ));
+
+    tokio::fs::remove_dir_all(&root).await.unwrap();
+  });
+}
+
+#[test]
+fn test_constants_header() {
+  let config = problem::config::ProblemConfig::parse(
+    r#"
+    time_limit_ms = 1000
+    memory_limit_mb = 256
+    checker = { builtin = "ncmp.cpp" }
+    standard_solution = { path = "std.cpp" }
+    constants = { MOD = 998244353, EPS = 1e-9, ONE = 1.0, STRICT = true, NAME = "a \"b\"" }
+
+    [[subtasks]]
+    score = 100
+    tests = [{ input = "1.in" }]
+    "#,
+  )
+  .unwrap();
+  assert_eq!(
+    problem::config::constants_header(&config.constants).unwrap(),
+    "#ifndef RINDAG_CONSTANTS_H\n#define RINDAG_CONSTANTS_H\n\
+     #define EPS 1e-9\n#define MOD 998244353\n#define NAME \"a \\\"b\\\"\"\n\
+     #define ONE 1.0\n#define STRICT true\n#endif\n"
+  );
+
+  let invalid = std::collections::BTreeMap::from([(
+    "N-MAX".to_string(),
+    problem::config::Constant::Integer(1),
+  )]);
+  assert!(matches!(
+    problem::config::constants_header(&invalid),
+    Err(problem::config::LoadError::Invalid(_))
+  ));
+}
+
+#[test]
+fn test_test_expectations() {
+  super::async_test(async {
+    let root = std::env::temp_dir().join(format!("rindag-config-{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&root).await.unwrap();
+    let write = |path: &str, content: &str| tokio::fs::write(root.join(path), content.to_string());
+    let config = |tests: &str| {
+      format!(
+        r#"
+        time_limit_ms = 1000
+        memory_limit_mb = 256
+        checker = {{ builtin = "ncmp.cpp" }}
+        standard_solution = {{ path = "std.c" }}
+
+        [[subtasks]]
+        score = 100
+        tests = [{{ input = "1.in" }}, {{ input = "1.in", tags = ["max_n"] }}]
+
+        [[solutions]]
+        path = "brute.c"
+        expected = "TimeLimitExceeded"
+        tests = [{}]
+        "#,
+        tests
+      )
+    };
+    for (path, content) in [
+      ("std.c", "int main(){}"),
+      ("brute.c", "int main(){}"),
+      ("1.in", "1\n"),
+    ] {
+      write(path, content).await.unwrap();
+    }
+
+    write(
+      "problem.toml",
+      &config(r#"{ tags = ["max_n"], expected = "TimeLimitExceeded", exactly = true }"#),
+    )
+    .await
+    .unwrap();
+    let (_, checks) = problem::config::load(&root).await.unwrap();
+    let solution = &checks.solutions[0];
+    let test_id = |index| record::TestId {
+      testset: problem::Testset::Main,
+      subtask: 0,
+      index,
+    };
+    assert_eq!(
+      solution.tests[0].tests,
+      std::collections::HashSet::from([test_id(1)])
+    );
+
+    let records = |statuses: [record::RecordStatus; 2]| {
+      statuses
+        .into_iter()
+        .enumerate()
+        .map(|(index, status)| record::Record {
+          test_id: Some(test_id(index)),
+          status,
+          ..record::RECORD_WAITING.clone()
+        })
+        .collect::<Vec<_>>()
+    };
+    assert!(solution
+      .check_tests(&records([
+        record::RecordStatus::Accepted,
+        record::RecordStatus::TimeLimitExceeded,
+      ]))
+      .is_empty());
+    let issues = solution.check_tests(&records([
+      record::RecordStatus::TimeLimitExceeded,
+      record::RecordStatus::WrongAnswer,
+    ]));
+    assert_eq!(issues.len(), 2);
+    assert!(matches!(
+      &issues[0],
+      problem::config::Issue::UnexpectedTestVerdict { test_id, excluded: true, .. }
+        if test_id.index == 0
+    ));
+    assert!(matches!(
+      &issues[1],
+      problem::config::Issue::UnexpectedTestVerdict {
+        test_id,
+        excluded: false,
+        actual: record::RecordStatus::WrongAnswer,
+        ..
+      } if test_id.index == 1
+    ));
+
+    write(
+      "problem.toml",
+      &config(r#"{ ids = ["main/0/0"], expected = "Accepted" }"#),
+    )
+    .await
+    .unwrap();
+    let (_, checks) = problem::config::load(&root).await.unwrap();
+    assert_eq!(
+      checks.solutions[0].tests[0].tests,
+      std::collections::HashSet::from([test_id(0)])
+    );
+
+    for tests in [
+      r#"{ tags = ["min_n"], expected = "Accepted" }"#,
+      r#"{ ids = ["main/0/2"], expected = "Accepted" }"#,
+    ] {
+      write("problem.toml", &config(tests)).await.unwrap();
+      assert!(matches!(
+        problem::config::load(&root).await,
+        Err(problem::config::LoadError::Invalid(_))
+      ));
+    }
+
+    tokio::fs::remove_dir_all(&root).await.unwrap();
+  });
+}
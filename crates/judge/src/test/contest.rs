@@ -0,0 +1,168 @@
+use std::{collections::HashMap, str::FromStr, time};
+
+use crate::{contest, judge, lang, problem, program};
+
+fn make_problem(time_limit: u64, score: f32) -> problem::Problem {
+  let source = program::Source {
+    lang: lang::Lang::from_str("cpp").unwrap(),
+    data: b"int main() {}".to_vec().into(),
+  };
+  problem::Problem {
+    subtasks: vec![problem::Subtask {
+      id: 0,
+      score,
+      dependences: vec![],
+      testset: problem::Testset::Main,
+      tests: vec![],
+      time_limit: time::Duration::from_millis(time_limit),
+      memory_limit: 256 << 20,
+      wall_time_limit: None,
+      time_kind: judge::TimeKind::Cpu,
+      cpu_affinity: None,
+      scoring: problem::ScoringPolicy::Min,
+    }],
+    kind: problem::Kind::Batch,
+    checker: source.clone().into(),
+    interactor: None,
+    validator: None,
+    output: Default::default(),
+    presentation_error: Default::default(),
+    cache_checker: false,
+    seed_checker: false,
+    labels: vec![],
+    answer_limits: problem::AnswerLimits::default(),
+    submission_limits: problem::SubmissionLimits::default(),
+    standard_solution: source,
+    user_copy_in: HashMap::new(),
+    judge_copy_in: HashMap::new(),
+  }
+}
+
+#[test]
+fn test_validate_policy() {
+  let mut contest = contest::Contest {
+    name: "test".to_string(),
+    problems: vec![
+      contest::ContestProblem {
+        label: "A".to_string(),
+        problem: make_problem(1000, 100.),
+      },
+      contest::ContestProblem {
+        label: "B".to_string(),
+        problem: make_problem(1000, 100.),
+      },
+    ],
+    policy: contest::Policy {
+      max_time_limit: Some(time::Duration::from_secs(2)),
+      same_limits: true,
+      total_score: Some(100.),
+      ..Default::default()
+    },
+  };
+  assert!(contest.validate().is_empty());
+
+  contest.problems[1].problem = make_problem(3000, 50.);
+  contest.policy.checker_langs = vec!["c".to_string()];
+  let violations = contest.validate();
+  assert_eq!(violations.len(), 5);
+  assert!(
+    violations.contains(&contest::PolicyViolation::InconsistentLimits {
+      label: "B".to_string(),
+      subtask: 0,
+    })
+  );
+  assert!(violations.contains(&contest::PolicyViolation::TotalScore {
+    label: "B".to_string(),
+    score: 50.,
+    expected: 100.,
+  }));
+}
+
+/// Index of a contest as if it was built, for planning rejudges.
+fn make_index(contest: &contest::Contest) -> contest::Index {
+  contest::Index {
+    name: contest.name.clone(),
+    problems: contest
+      .problems
+      .iter()
+      .map(|x| contest::IndexEntry {
+        label: x.label.clone(),
+        kind: x.problem.kind,
+        score: 100.,
+        subtasks: x.problem.subtasks.len(),
+        tests: 0,
+        time_limit: time::Duration::from_secs(1),
+        memory_limit: 256 << 20,
+        revision: x.problem.revision(),
+        revisions: x.problem.revisions(),
+      })
+      .collect(),
+  }
+}
+
+#[test]
+fn test_plan_rejudge() {
+  let problem = |time_limits: &[u64]| {
+    let mut problem = make_problem(time_limits[0], 100.);
+    for (id, &time_limit) in time_limits.iter().enumerate().skip(1) {
+      problem.subtasks.push(problem::Subtask {
+        id,
+        dependences: vec![id - 1],
+        time_limit: time::Duration::from_millis(time_limit),
+        ..make_problem(0, 0.).subtasks.pop().unwrap()
+      });
+    }
+    problem
+  };
+  let mut contest = contest::Contest {
+    name: "test".to_string(),
+    problems: ["A", "B", "C"]
+      .into_iter()
+      .map(|label| contest::ContestProblem {
+        label: label.to_string(),
+        problem: problem(&[1000, 1000, 1000]),
+      })
+      .collect(),
+    policy: Default::default(),
+  };
+  let previous = make_index(&contest);
+  assert!(contest
+    .plan_rejudge(&previous)
+    .problems
+    .iter()
+    .all(|x| x.action == contest::RejudgeAction::Skip));
+
+  // Subtask 1 of B changes, and subtask 2 depends on it.
+  contest.problems[1].problem = problem(&[1000, 2000, 1000]);
+  contest.problems[2].problem.checker = program::Source {
+    lang: lang::Lang::from_str("cpp").unwrap(),
+    data: b"int main() { return 0; }".to_vec().into(),
+  }
+  .into();
+  contest.problems.push(contest::ContestProblem {
+    label: "D".to_string(),
+    problem: problem(&[1000]),
+  });
+  let plan = contest.plan_rejudge(&previous);
+  let actions: Vec<_> = plan.problems.iter().map(|x| &x.action).collect();
+  assert_eq!(actions[0], &contest::RejudgeAction::Skip);
+  assert_eq!(
+    actions[1],
+    &contest::RejudgeAction::Subtasks {
+      subtasks: vec![1, 2]
+    }
+  );
+  assert!(matches!(actions[2], contest::RejudgeAction::All { .. }));
+  assert!(matches!(actions[3], contest::RejudgeAction::All { .. }));
+
+  assert!(!plan.should_rejudge("A", 0));
+  assert!(!plan.should_rejudge("B", 0));
+  assert!(plan.should_rejudge("B", 2));
+  assert!(plan.should_rejudge("C", 0));
+  assert!(plan.should_rejudge("E", 0));
+  assert_eq!(
+    plan.to_string(),
+    "rejudge:\n- A: skipped, unchanged\n- B: subtasks 1, 2\n\
+     - C: all, programs, settings or extra files changed\n- D: all, new problem"
+  );
+}
@@ -0,0 +1,38 @@
+use tokio::io::AsyncReadExt;
+
+use crate::data;
+
+#[test]
+fn test_local_provider() {
+  super::async_test(async {
+    let path = std::env::temp_dir().join(format!("rindag-data-{}", uuid::Uuid::new_v4()));
+    tokio::fs::write(&path, b"local data").await.unwrap();
+    let provider = data::Provider::Local { path: path.clone() };
+
+    assert_eq!(provider.memory_size(), 0);
+    assert_eq!(&*provider.read().await.unwrap(), b"local data");
+    let mut streamed = vec![];
+    provider
+      .open()
+      .await
+      .unwrap()
+      .read_to_end(&mut streamed)
+      .await
+      .unwrap();
+    assert_eq!(streamed, b"local data");
+
+    // The fingerprint follows the file without reading it.
+    let fingerprint = provider.fingerprint().into_owned();
+    assert_eq!(fingerprint, provider.fingerprint().into_owned());
+    tokio::fs::write(&path, b"longer local data").await.unwrap();
+    assert_ne!(fingerprint, provider.fingerprint().into_owned());
+
+    // Local files can not be (de)serialized.
+    assert!(serde_json::to_string(&provider).is_err());
+    let memory = data::Provider::Memory(b"abc".to_vec());
+    assert_eq!(memory.memory_size(), 3);
+    assert_eq!(&*memory.read().await.unwrap(), b"abc");
+
+    tokio::fs::remove_file(&path).await.unwrap();
+  });
+}
@@ -0,0 +1,46 @@
+use crate::doctor;
+
+#[test]
+fn test_report() {
+  let report = doctor::Report {
+    langs: vec![
+      doctor::LangReport {
+        lang: "c".to_string(),
+        probes: vec![
+          doctor::ProbeResult {
+            probe: doctor::Probe::Hello,
+            failure: None,
+          },
+          doctor::ProbeResult {
+            probe: doctor::Probe::TimeLimit,
+            failure: Some("unexpected result".to_string()),
+          },
+        ],
+      },
+      doctor::LangReport {
+        lang: "py".to_string(),
+        probes: vec![],
+      },
+    ],
+  };
+
+  assert!(!report.is_ok());
+  assert_eq!(
+    report.to_string(),
+    "c hello: ok\nc time_limit: FAILED: unexpected result\npy: skipped, no sample programs\n"
+  );
+}
+
+#[test]
+fn test_diagnose() {
+  super::async_test(async {
+    let report = doctor::diagnose().await;
+
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(
+      report.langs.iter().map(|x| &x.lang).collect::<Vec<_>>(),
+      ["c", "cpp"]
+    );
+    assert!(report.langs.iter().all(|x| x.probes.len() == 4));
+  });
+}
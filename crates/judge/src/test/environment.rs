@@ -0,0 +1,11 @@
+use crate::{environment, CONFIG};
+
+#[test]
+fn test_capture() {
+  let env = environment::Environment::capture();
+
+  assert_eq!(env, *environment::ENVIRONMENT);
+  assert_eq!(env.node, CONFIG.sandbox.host);
+  assert_eq!(env.lang_hash.len(), 64);
+  assert_eq!(env.judge_version, env!("CARGO_PKG_VERSION"));
+}
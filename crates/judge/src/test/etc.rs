@@ -0,0 +1,46 @@
+use crate::etc;
+
+/// Parse a config in YAML like `etc::Cfg::load`.
+fn parse(content: &str) -> etc::Cfg {
+  return config::Config::builder()
+    .add_source(config::File::from_str(content, config::FileFormat::Yaml))
+    .build()
+    .unwrap()
+    .try_deserialize()
+    .unwrap();
+}
+
+#[test]
+fn test_load_partial_sections() {
+  // Sections of a config written before the newer fields were added.
+  let cfg = parse(
+    r#"
+sandbox:
+  host: "http://sandbox:5051"
+judge:
+  env: ["PATH=/usr/bin"]
+  time_limit: { secs: 5, nanos: 0 }
+  memory_limit: 268435456
+  process_limit: 8
+  stdout_limit: 1048576
+  stderr_limit: 1024
+"#,
+  );
+
+  assert_eq!(cfg.judge.env, vec!["PATH=/usr/bin".to_string()]);
+  assert_eq!(cfg.judge.time_limit, std::time::Duration::from_secs(5));
+  assert_eq!(cfg.judge.process_limit, 8);
+  assert_eq!(cfg.judge.stdout_limit, 1048576);
+  let default = etc::JudgeCfg::default();
+  assert_eq!(cfg.judge.message_limit, default.message_limit);
+  assert_eq!(cfg.judge.max_attempts, default.max_attempts);
+  assert_eq!(cfg.judge.lazy_answers, default.lazy_answers);
+
+  assert_eq!(cfg.sandbox.host, "http://sandbox:5051");
+  let default = etc::SandboxCfg::default();
+  assert_eq!(cfg.sandbox.hosts(), vec!["http://sandbox:5051".to_string()]);
+  assert_eq!(cfg.sandbox.pool_size, default.pool_size);
+  assert_eq!(cfg.sandbox.retries, default.retries);
+  assert_eq!(cfg.sandbox.balance, default.balance);
+  assert_eq!(cfg.sandbox.transport, default.transport);
+}
@@ -1,6 +1,6 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
 
-use crate::{builtin, data, generator, lang, program, sandbox};
+use crate::{builtin, cache, data, generator, lang, program, sandbox};
 
 #[test]
 fn test_simple() {
@@ -33,7 +33,8 @@ fn test_simple() {
                 .unwrap()
                 .as_bytes(),
             )
-            .await,
+            .await
+            .unwrap(),
           )]
           .into(),
         )
@@ -53,3 +54,50 @@ fn test_simple() {
     );
   });
 }
+
+#[test]
+fn test_cached() {
+  super::async_test(async {
+    let src = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(
+        "#include<stdio.h>\nint main(int argc,char**argv){puts(argv[1]);}"
+          .as_bytes()
+          .to_vec(),
+      ),
+    };
+
+    let compile_cache = program::CompileCache::default();
+    src
+      .compile_cached(vec![], HashMap::new(), &compile_cache)
+      .await
+      .unwrap();
+    let exec = src
+      .compile_cached(vec![], HashMap::new(), &compile_cache)
+      .await
+      .unwrap();
+    assert_eq!(
+      compile_cache.stats(),
+      cache::CacheStats { hits: 1, misses: 1 }
+    );
+
+    let generate_cache = Arc::new(generator::Cache::default());
+    let gen = generator::Generator::from(exec).with_cache(generate_cache.clone());
+    for _ in 0..2 {
+      assert_eq!(
+        gen
+          .generate(vec!["42".to_string()], HashMap::new())
+          .await
+          .unwrap()
+          .context()
+          .await
+          .unwrap(),
+        "42\n".as_bytes()
+      );
+    }
+    assert_eq!(
+      generate_cache.stats(),
+      cache::CacheStats { hits: 1, misses: 1 }
+    );
+  });
+}
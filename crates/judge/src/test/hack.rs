@@ -0,0 +1,89 @@
+use std::{collections::HashMap, str::FromStr, time};
+
+use crate::{builtin, data, hack, lang, problem, program, sandbox};
+
+#[test]
+fn test_hack() {
+  super::async_test(async {
+    let user_copy_in = HashMap::from([(
+      "testlib.h".to_string(),
+      sandbox::FileHandle::upload(
+        builtin::File::from_str("testlib:testlib.h")
+          .unwrap()
+          .as_bytes(),
+      )
+      .await
+      .unwrap(),
+    )]);
+
+    let source = |lang: &str, code: &str| program::Source {
+      lang: lang::Lang::from_str(lang).unwrap(),
+      data: data::Provider::Memory(code.as_bytes().to_vec()),
+    };
+
+    let validator = source(
+      "cpp",
+      "
+      #include\"testlib.h\"
+      signed main(signed argc,char**argv){
+        registerValidation(argc,argv);
+        inf.readInt(0,2000000000,\"a\");inf.readSpace();
+        inf.readInt(0,2000000000,\"b\");inf.readEoln();
+        inf.readEof();
+      }
+      ",
+    )
+    .compile(vec![], user_copy_in.clone())
+    .await
+    .unwrap();
+    let std = source(
+      "c",
+      "#include<stdio.h>\nint main(){long long a,b;scanf(\"%lld%lld\",&a,&b);printf(\"%lld\\n\",a+b);}",
+    )
+    .compile(vec![], HashMap::new())
+    .await
+    .unwrap();
+    let defender = source(
+      "c",
+      "#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}",
+    )
+    .compile(vec![], HashMap::new())
+    .await
+    .unwrap();
+    let checker = program::Source {
+      lang: lang::Lang::from_str("cpp").unwrap(),
+      data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+    }
+    .compile(vec![], user_copy_in.clone())
+    .await
+    .unwrap();
+
+    let hack = |input: &str| hack::Hack {
+      input: problem::Input::Plain {
+        context: input.as_bytes().to_vec(),
+      },
+      validator: validator.clone().into(),
+      standard_solution: std.clone(),
+      defender: defender.clone(),
+      checker: checker.clone().into(),
+      time_limit: time::Duration::from_secs(1),
+      memory_limit: 64 * 1024 * 1024,
+      output: Default::default(),
+      user_copy_in: user_copy_in.clone(),
+      judge_copy_in: HashMap::new(),
+    };
+
+    assert!(matches!(
+      hack("2000000000 2000000000\n").run().await.unwrap(),
+      hack::Outcome::Success { .. }
+    ));
+    assert!(matches!(
+      hack("1 2\n").run().await.unwrap(),
+      hack::Outcome::Defended { .. }
+    ));
+    assert!(matches!(
+      hack("-1 2\n").run().await.unwrap(),
+      hack::Outcome::Invalid(hack::InvalidReason::Validator { .. })
+    ));
+  });
+}
@@ -1,11 +1,28 @@
 use std::time;
 
+mod artifact;
+mod builtin;
 mod checker;
+mod config;
+mod contest;
+mod data;
+mod doctor;
+mod environment;
+mod etc;
 mod generator;
+mod hack;
+mod package;
+mod preview;
 mod problem;
 mod program;
+mod record;
+mod report;
 mod sandbox;
+mod schema;
+mod stress;
+mod testplan;
 mod validator;
+mod workflow;
 
 pub fn async_test<F: std::future::Future>(f: F) -> F::Output {
   lazy_static! {
@@ -0,0 +1,235 @@
+use crate::problem::package;
+
+#[test]
+fn test_parse_checksums() {
+  let sums = package::Checksums::parse(
+    "# tests\n\
+    2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  tests/01\n\
+    486EA46224D1BB4FB680F34F7C9AD96A8F24EC88BE73EA8E5A6C65260E9CB8A7 *tests/02\n",
+  )
+  .unwrap();
+  assert_eq!(sums.0.len(), 2);
+  assert_eq!(
+    sums.0["tests/02"],
+    "486ea46224d1bb4fb680f34f7c9ad96a8f24ec88be73ea8e5a6c65260e9cb8a7"
+  );
+
+  assert!(package::Checksums::parse("deadbeef tests/01").is_err());
+  for path in ["/etc/passwd", "../secret", "tests/../../secret"] {
+    assert!(package::Checksums::parse(&format!(
+      "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824 {}",
+      path
+    ))
+    .is_err());
+  }
+}
+
+#[test]
+fn test_verify_dir() {
+  super::async_test(async {
+    let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    tokio::fs::create_dir_all(root.join("tests")).await.unwrap();
+    tokio::fs::write(root.join("tests/01"), "hello")
+      .await
+      .unwrap();
+    tokio::fs::write(root.join("tests/02"), "worl")
+      .await
+      .unwrap();
+
+    let sums = package::Checksums::parse(
+      "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824 tests/01\n\
+      486ea46224d1bb4fb680f34f7c9ad96a8f24ec88be73ea8e5a6c65260e9cb8a7 tests/02\n\
+      486ea46224d1bb4fb680f34f7c9ad96a8f24ec88be73ea8e5a6c65260e9cb8a7 tests/03\n",
+    )
+    .unwrap();
+    let report = sums.verify_dir(&root).await;
+    tokio::fs::remove_dir_all(&root).await.unwrap();
+
+    assert_eq!(report.checked, 3);
+    assert_eq!(report.failures.len(), 2);
+    assert_eq!(report.failures[0].path, "tests/02");
+    assert!(matches!(
+      report.failures[0].kind,
+      package::FailureKind::Mismatch { size: 4, .. }
+    ));
+    assert_eq!(report.failures[1].kind, package::FailureKind::Missing);
+  });
+}
+
+#[test]
+fn test_import_polygon() {
+  super::async_test(async {
+    let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    tokio::fs::create_dir_all(root.join("tests")).await.unwrap();
+    tokio::fs::create_dir_all(root.join("files")).await.unwrap();
+    tokio::fs::create_dir_all(root.join("solutions"))
+      .await
+      .unwrap();
+    for (path, content) in [
+      ("tests/01", "1 2\n"),
+      ("tests/01.a", "3\n"),
+      ("tests/02", "3 4\n"),
+      ("tests/03", "5 6\n"),
+      ("files/check.cpp", "int main() {}\n"),
+      ("files/testlib.h", "\n"),
+      ("solutions/main.cpp", "int main() {}\n"),
+    ] {
+      tokio::fs::write(root.join(path), content).await.unwrap();
+    }
+    tokio::fs::write(
+      root.join("problem.xml"),
+      r#"<?xml version="1.0" encoding="utf-8"?>
+<problem revision="1" short-name="a-plus-b">
+  <judging input-file="" output-file="">
+    <testset name="tests">
+      <time-limit>1000</time-limit>
+      <memory-limit>268435456</memory-limit>
+      <test-count>3</test-count>
+      <input-path-pattern>tests/%02d</input-path-pattern>
+      <answer-path-pattern>tests/%02d.a</answer-path-pattern>
+      <tests>
+        <test method="manual" points="10" group="0"/>
+        <test method="manual" points="30" group="1"/>
+        <test method="manual" points="60" group="1"/>
+      </tests>
+      <groups>
+        <group name="0" points-policy="complete-group"/>
+        <group name="1" points-policy="complete-group">
+          <dependencies><dependency group="0"/></dependencies>
+        </group>
+      </groups>
+    </testset>
+  </judging>
+  <files>
+    <resources><file path="files/testlib.h"/></resources>
+  </files>
+  <assets>
+    <checker type="testlib">
+      <source path="files/check.cpp" type="cpp.g++17"/>
+    </checker>
+    <solutions>
+      <solution tag="main"><source path="solutions/main.cpp" type="cpp.g++17"/></solution>
+    </solutions>
+  </assets>
+</problem>
+"#,
+    )
+    .await
+    .unwrap();
+
+    let problem = package::import_polygon(&root).await;
+    tokio::fs::remove_dir_all(&root).await.unwrap();
+    let problem = problem.unwrap();
+
+    assert_eq!(problem.subtasks.len(), 2);
+    assert_eq!(problem.subtasks[0].score, 10.);
+    assert_eq!(problem.subtasks[1].score, 90.);
+    assert_eq!(problem.subtasks[1].dependences, vec![0]);
+    assert_eq!(problem.subtasks[1].tests.len(), 2);
+    assert_eq!(problem.subtasks[0].memory_limit, 268435456);
+    assert!(matches!(
+      problem.subtasks[0].tests[0].answer,
+      crate::problem::Answer::Plain { .. }
+    ));
+    assert!(matches!(
+      problem.subtasks[1].tests[0].answer,
+      crate::problem::Answer::Generated
+    ));
+    assert!(problem.user_copy_in.contains_key("testlib.h"));
+  });
+}
+
+#[test]
+fn test_export_package() {
+  use std::{collections::HashMap, io::Read, str::FromStr};
+
+  use crate::{builtin, data, judge, lang, problem, program};
+
+  super::async_test(async {
+    let problem = problem::Problem {
+      subtasks: vec![problem::Subtask {
+        id: 0,
+        score: 100.,
+        dependences: vec![],
+        testset: problem::Testset::Main,
+        tests: vec![
+          problem::Test {
+            input: problem::Input::Plain {
+              context: b"1 2\n".to_vec(),
+            },
+            answer: problem::Answer::Generated,
+            time_limit: None,
+            memory_limit: None,
+          },
+          problem::Test {
+            input: problem::Input::Plain {
+              context: b"3 4\n".to_vec(),
+            },
+            answer: problem::Answer::Plain {
+              context: b"7\n".to_vec(),
+            },
+            time_limit: None,
+            memory_limit: None,
+          },
+        ],
+        time_limit: std::time::Duration::from_secs(1),
+        memory_limit: 64 << 20,
+        wall_time_limit: None,
+        time_kind: judge::TimeKind::Cpu,
+        cpu_affinity: None,
+        scoring: problem::ScoringPolicy::Min,
+      }],
+      kind: problem::Kind::Batch,
+      checker: program::Source {
+        lang: lang::Lang::from_str("cpp").unwrap(),
+        data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+      }
+      .into(),
+      interactor: None,
+      validator: None,
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: false,
+      seed_checker: false,
+      labels: vec![],
+      answer_limits: problem::AnswerLimits::default(),
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: program::Source {
+        lang: lang::Lang::from_str("c").unwrap(),
+        data: data::Provider::Memory(
+          "
+          #include<stdio.h>
+          int main() { int a, b; scanf(\"%d%d\", &a, &b); printf(\"%d\\n\", a + b); }
+          "
+          .into(),
+        ),
+      },
+      user_copy_in: HashMap::new(),
+      judge_copy_in: HashMap::new(),
+    };
+
+    let path = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string() + ".tar");
+    let manifest = problem.export_package(&path).await.unwrap();
+    let archive = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(manifest.subtasks[0].tests, vec!["tests/01", "tests/02"]);
+    assert!(matches!(
+      &manifest.checker,
+      package::ManifestChecker::Source(x) if x.path == "checker.cpp"
+    ));
+
+    let mut files = HashMap::new();
+    for entry in tar::Archive::new(archive.as_slice()).entries().unwrap() {
+      let mut entry = entry.unwrap();
+      let mut content = vec![];
+      entry.read_to_end(&mut content).unwrap();
+      files.insert(entry.path().unwrap().to_string_lossy().to_string(), content);
+    }
+    assert_eq!(files["tests/01.a"], b"3\n");
+    assert_eq!(files["tests/02.a"], b"7\n");
+    let sums =
+      package::Checksums::parse(&String::from_utf8_lossy(&files[package::CHECKSUMS_FILE])).unwrap();
+    assert_eq!(sums.0.len(), files.len() - 1);
+  });
+}
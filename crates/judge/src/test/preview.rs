@@ -0,0 +1,28 @@
+use crate::preview::{Charset, Preview};
+
+#[test]
+fn test_preview() {
+  let preview = Preview::new(b"1 2\n3 4 5", 4);
+  assert_eq!(
+    preview,
+    Preview {
+      head: "1 2\n".to_string(),
+      truncated: true,
+      size: 9,
+      lines: 2,
+      tokens: 5,
+      charset: Charset::Ascii,
+    }
+  );
+
+  // The head is cut before the incomplete character.
+  let preview = Preview::new("a你好\n".as_bytes(), 5);
+  assert_eq!(preview.head, "a你");
+  assert_eq!(preview.charset, Charset::Utf8);
+  assert_eq!(preview.lines, 1);
+
+  let preview = Preview::new(b"\x1b[31m\xff\n", 1024);
+  assert_eq!(preview.head, "\u{fffd}[31m\u{fffd}\n");
+  assert!(!preview.truncated);
+  assert_eq!(preview.charset, Charset::Binary);
+}
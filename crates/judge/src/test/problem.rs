@@ -1,6 +1,8 @@
 use std::{collections::HashMap, str::FromStr, time};
 
-use crate::{builtin, data, generator, lang, problem, program, record, sandbox};
+use crate::{
+  builtin, checker, data, etc, generator, judge, lang, problem, program, record, sandbox, CONFIG,
+};
 
 #[test]
 fn test_judge_a_plus_b() {
@@ -44,6 +46,8 @@ fn test_judge_a_plus_b() {
             context: "12 34\n".as_bytes().to_vec(),
           },
           answer: problem::Answer::Generated,
+          time_limit: None,
+          memory_limit: None,
         },
         problem::Test {
           input: problem::Input::Generated {
@@ -73,7 +77,8 @@ fn test_judge_a_plus_b() {
                       .unwrap()
                       .as_bytes(),
                   )
-                  .await,
+                  .await
+                  .unwrap(),
                 )]
                 .into(),
               )
@@ -83,10 +88,16 @@ fn test_judge_a_plus_b() {
             args: vec!["-n".to_string(), "100".to_string()],
           },
           answer: problem::Answer::Generated,
+          time_limit: None,
+          memory_limit: None,
         },
       ],
       time_limit: time::Duration::from_secs(1),
       memory_limit: 64 * 1024 * 1024,
+      wall_time_limit: None,
+      time_kind: judge::TimeKind::Cpu,
+      cpu_affinity: None,
+      scoring: problem::ScoringPolicy::Min,
     };
 
     let chk = program::Source {
@@ -101,11 +112,14 @@ fn test_judge_a_plus_b() {
           .unwrap()
           .as_bytes(),
       )
-      .await,
+      .await
+      .unwrap(),
     )]);
 
     let (score, records) = subtask
       .judge(
+        uuid::Uuid::new_v4(),
+        &etc::ProfileCfg::default(),
         &sol_c.compile(vec![], user_copy_in.clone()).await.unwrap(),
         &sol_cpp.compile(vec![], user_copy_in.clone()).await.unwrap(),
         &chk
@@ -113,15 +127,1642 @@ fn test_judge_a_plus_b() {
           .await
           .unwrap()
           .into(),
+        None,
+        &Default::default(),
         &user_copy_in,
         &HashMap::new(),
         None,
+        None,
+        None,
       )
       .await;
 
     assert_eq!(score, 1.);
-    for record in &records {
+    for (i, record) in records.iter().enumerate() {
       assert_eq!(record.status, record::RecordStatus::Accepted);
+      assert_eq!(
+        record.test_id,
+        Some(record::TestId {
+          testset: problem::Testset::Main,
+          subtask: 1,
+          index: i,
+        })
+      );
+    }
+  });
+}
+
+#[test]
+fn test_answer_limits() {
+  let limits = problem::AnswerLimits {
+    max_size: Some(16),
+    max_lines: Some(2),
+    max_tokens_per_line: Some(3),
+  };
+
+  assert!(limits.check("1 2 3\n4 5\n".as_bytes()).is_ok());
+  assert_eq!(
+    limits.check("1 2 3 4 5 6 7 8 9".as_bytes()),
+    Err(problem::AnswerLimitError::Size {
+      size: 17,
+      limit: 16
+    })
+  );
+  assert_eq!(
+    limits.check("1\n2\n3\n".as_bytes()),
+    Err(problem::AnswerLimitError::Lines { lines: 3, limit: 2 })
+  );
+  assert_eq!(
+    limits.check("1 2\n3 4 5 6\n".as_bytes()),
+    Err(problem::AnswerLimitError::TokensPerLine {
+      line: 2,
+      tokens: 4,
+      limit: 3
+    })
+  );
+}
+
+#[test]
+fn test_submission_limits() {
+  let subtask = problem::Subtask {
+    id: 1,
+    score: 100.,
+    dependences: vec![],
+    testset: problem::Testset::Main,
+    tests: (0..2)
+      .map(|_| problem::Test {
+        input: problem::Input::Plain { context: vec![] },
+        answer: problem::Answer::Generated,
+        time_limit: None,
+        memory_limit: None,
+      })
+      .collect(),
+    time_limit: time::Duration::from_secs(1),
+    memory_limit: 64 << 20,
+    wall_time_limit: None,
+    time_kind: judge::TimeKind::Cpu,
+    cpu_affinity: None,
+    scoring: problem::ScoringPolicy::Min,
+  };
+  let limits = problem::SubmissionLimits {
+    max_size: Some(4),
+    ..Default::default()
+  };
+
+  let (accepted, rejections) = limits.check(
+    HashMap::from([
+      ("1-1.out".to_string(), b"3\n".to_vec()),
+      ("1-2.out".to_string(), vec![0xff, 0xfe]),
+      ("1-3.out".to_string(), b"7\n".to_vec()),
+    ]),
+    &[subtask],
+  );
+  assert_eq!(accepted.len(), 1);
+  assert!(accepted.contains_key(&record::TestId {
+    testset: problem::Testset::Main,
+    subtask: 1,
+    index: 0,
+  }));
+  assert_eq!(
+    rejections,
+    vec![
+      problem::Rejection {
+        file: "1-2.out".to_string(),
+        reason: problem::RejectReason::Binary,
+      },
+      problem::Rejection {
+        file: "1-3.out".to_string(),
+        reason: problem::RejectReason::UnknownName,
+      },
+    ]
+  );
+}
+
+#[test]
+fn test_judge_submit_answer() {
+  super::async_test(async {
+    let user_copy_in = HashMap::from([(
+      "testlib.h".to_string(),
+      sandbox::FileHandle::upload(
+        builtin::File::from_str("testlib:testlib.h")
+          .unwrap()
+          .as_bytes(),
+      )
+      .await
+      .unwrap(),
+    )]);
+
+    let std = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(
+        "#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}"
+          .as_bytes()
+          .to_vec(),
+      ),
+    };
+
+    let chk = program::Source {
+      lang: lang::Lang::from_str("cpp").unwrap(),
+      data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+    };
+
+    let subtask = problem::Subtask {
+      id: 1,
+      score: 100.,
+      dependences: vec![],
+      testset: problem::Testset::Main,
+      tests: vec![
+        problem::Test {
+          input: problem::Input::Plain {
+            context: "1 2\n".as_bytes().to_vec(),
+          },
+          answer: problem::Answer::Generated,
+          time_limit: None,
+          memory_limit: None,
+        },
+        problem::Test {
+          input: problem::Input::Plain {
+            context: "3 4\n".as_bytes().to_vec(),
+          },
+          answer: problem::Answer::Generated,
+          time_limit: None,
+          memory_limit: None,
+        },
+      ],
+      time_limit: time::Duration::from_secs(1),
+      memory_limit: 64 * 1024 * 1024,
+      wall_time_limit: None,
+      time_kind: judge::TimeKind::Cpu,
+      cpu_affinity: None,
+      scoring: problem::ScoringPolicy::Min,
+    };
+
+    let answers = HashMap::from([(
+      subtask.test_id(0),
+      sandbox::FileHandle::upload("3\n".as_bytes()).await.unwrap(),
+    )]);
+
+    let (score, records) = subtask
+      .judge_submit_answer(
+        uuid::Uuid::new_v4(),
+        &answers,
+        &std.compile(vec![], HashMap::new()).await.unwrap(),
+        &chk
+          .compile(vec![], user_copy_in.clone())
+          .await
+          .unwrap()
+          .into(),
+        &Default::default(),
+        &user_copy_in,
+        &HashMap::new(),
+        None,
+        None,
+      )
+      .await;
+
+    assert_eq!(score, 0.);
+    assert_eq!(records[0].status, record::RecordStatus::Accepted);
+    assert_eq!(records[1].status, record::RecordStatus::FileError);
+  });
+}
+
+#[test]
+fn test_judge_milestones() {
+  super::async_test(async {
+    let sol = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(
+        "#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}"
+          .as_bytes()
+          .to_vec(),
+      ),
+    };
+    let chk = program::Source {
+      lang: lang::Lang::from_str("cpp").unwrap(),
+      data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+    };
+    let make_subtask = |id: usize, dependences: Vec<usize>, answer: &str| problem::Subtask {
+      id,
+      score: 10.,
+      dependences,
+      testset: problem::Testset::Main,
+      tests: vec![problem::Test {
+        input: problem::Input::Plain {
+          context: b"1 2\n".to_vec(),
+        },
+        answer: problem::Answer::Plain {
+          context: answer.as_bytes().to_vec(),
+        },
+        time_limit: None,
+        memory_limit: None,
+      }],
+      time_limit: time::Duration::from_secs(1),
+      memory_limit: 64 * 1024 * 1024,
+      wall_time_limit: None,
+      time_kind: judge::TimeKind::Cpu,
+      cpu_affinity: None,
+      scoring: problem::ScoringPolicy::Min,
+    };
+    let problem = problem::Problem {
+      subtasks: vec![
+        make_subtask(0, vec![], "3\n"),
+        make_subtask(1, vec![0], "4\n"),
+        make_subtask(2, vec![1], "3\n"),
+      ],
+      kind: problem::Kind::Batch,
+      checker: chk.clone().into(),
+      interactor: None,
+      validator: None,
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: false,
+      seed_checker: false,
+      labels: vec![],
+      answer_limits: problem::AnswerLimits::default(),
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: sol.clone(),
+      user_copy_in: HashMap::new(),
+      judge_copy_in: HashMap::new(),
+    };
+
+    let user_copy_in = HashMap::from([(
+      "testlib.h".to_string(),
+      sandbox::FileHandle::upload(
+        builtin::File::from_str("testlib:testlib.h")
+          .unwrap()
+          .as_bytes(),
+      )
+      .await
+      .unwrap(),
+    )]);
+    let sol = sol.compile(vec![], HashMap::new()).await.unwrap();
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+    let (status_tx, status_rx) = futures::channel::mpsc::unbounded();
+
+    let (score, records) = problem
+      .judge(
+        uuid::Uuid::new_v4(),
+        &etc::ProfileCfg::default(),
+        &[problem::Testset::Main],
+        &sol,
+        &sol,
+        &chk
+          .compile(vec![], user_copy_in.clone())
+          .await
+          .unwrap()
+          .into(),
+        None,
+        &user_copy_in,
+        &HashMap::new(),
+        Some(status_tx),
+        Some(tx),
+        None,
+      )
+      .await;
+
+    assert_eq!(score, 10.);
+    assert_eq!(records[2].1[0].status, record::RecordStatus::Skipped);
+    let responses: Vec<_> = futures::StreamExt::collect(status_rx).await;
+    assert!(responses.iter().any(|x| matches!(
+      x,
+      problem::Response::CompleteOne { record, .. }
+        if record.test_id.unwrap().subtask == 2 && record.status == record::RecordStatus::Skipped
+    )));
+    assert!(responses
+      .iter()
+      .any(|x| matches!(x, problem::Response::SubtaskFinished { subtask: 2, .. })));
+    assert_eq!(
+      records[2].1[0].skip_reason,
+      Some(record::SkipReason::Dependency)
+    );
+
+    let milestones: Vec<_> = futures::StreamExt::collect(rx).await;
+    assert_eq!(milestones.len(), 5);
+    assert!(matches!(
+      milestones[0],
+      problem::Milestone::FirstAccepted { test_id, .. } if test_id.subtask == 0
+    ));
+    assert!(matches!(
+      &milestones[4],
+      problem::Milestone::Verdict { status: record::RecordStatus::WrongAnswer, score, .. }
+        if *score == 10.
+    ));
+  });
+}
+
+#[test]
+fn test_judge_deadline() {
+  super::async_test(async {
+    let sol = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(
+        "#include<unistd.h>\nint main(){sleep(1);}"
+          .as_bytes()
+          .to_vec(),
+      ),
+    };
+    let make_subtask = |id: usize| problem::Subtask {
+      id,
+      score: 50.,
+      dependences: vec![],
+      testset: problem::Testset::Main,
+      tests: vec![problem::Test {
+        input: problem::Input::Plain { context: vec![] },
+        answer: problem::Answer::Plain { context: vec![] },
+        time_limit: None,
+        memory_limit: None,
+      }],
+      time_limit: time::Duration::from_secs(1),
+      memory_limit: 64 * 1024 * 1024,
+      wall_time_limit: Some(time::Duration::from_secs(3)),
+      time_kind: judge::TimeKind::Cpu,
+      cpu_affinity: None,
+      scoring: problem::ScoringPolicy::Min,
+    };
+    let chk = program::Source {
+      lang: lang::Lang::from_str("cpp").unwrap(),
+      data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+    };
+    let problem = problem::Problem {
+      subtasks: vec![make_subtask(0), make_subtask(1)],
+      kind: problem::Kind::Batch,
+      checker: chk.clone().into(),
+      interactor: None,
+      validator: None,
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: false,
+      seed_checker: false,
+      labels: vec![],
+      answer_limits: problem::AnswerLimits::default(),
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: sol.clone(),
+      user_copy_in: HashMap::new(),
+      judge_copy_in: HashMap::new(),
+    };
+
+    let user_copy_in = HashMap::from([(
+      "testlib.h".to_string(),
+      sandbox::FileHandle::upload(
+        builtin::File::from_str("testlib:testlib.h")
+          .unwrap()
+          .as_bytes(),
+      )
+      .await
+      .unwrap(),
+    )]);
+    let sol = sol.compile(vec![], HashMap::new()).await.unwrap();
+    let profile = etc::ProfileCfg {
+      deadline: Some(time::Duration::from_millis(300)),
+      subtask_budget: Some(time::Duration::from_millis(200)),
+      ..Default::default()
+    };
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+
+    let started = std::time::Instant::now();
+    let (score, records) = problem
+      .judge(
+        uuid::Uuid::new_v4(),
+        &profile,
+        &[problem::Testset::Main],
+        &sol,
+        &sol,
+        &chk
+          .compile(vec![], user_copy_in.clone())
+          .await
+          .unwrap()
+          .into(),
+        None,
+        &user_copy_in,
+        &HashMap::new(),
+        Some(tx),
+        None,
+        None,
+      )
+      .await;
+    assert!(started.elapsed() < time::Duration::from_secs(1));
+
+    assert_eq!(score, 0.);
+    for (_, records) in &records {
+      assert_eq!(records[0].status, record::RecordStatus::Skipped);
+      assert_eq!(
+        records[0].skip_reason,
+        Some(record::SkipReason::DeadlineExceeded)
+      );
     }
+
+    // The job still finishes with the partial result.
+    let responses: Vec<_> = futures::StreamExt::collect(rx).await;
+    assert!(matches!(
+      responses.last(),
+      Some(problem::Response::Finished { records, .. }) if records.len() == 2
+    ));
+  });
+}
+
+#[test]
+fn test_add_hack() {
+  let source = program::Source {
+    lang: lang::Lang::from_str("c").unwrap(),
+    data: data::Provider::Memory(vec![]),
+  };
+  let mut problem = problem::Problem {
+    subtasks: vec![problem::Subtask {
+      id: 1,
+      score: 100.,
+      dependences: vec![],
+      testset: problem::Testset::Main,
+      tests: vec![],
+      time_limit: time::Duration::from_secs(2),
+      memory_limit: 256 << 20,
+      wall_time_limit: None,
+      time_kind: judge::TimeKind::Cpu,
+      cpu_affinity: None,
+      scoring: problem::ScoringPolicy::Min,
+    }],
+    kind: problem::Kind::Batch,
+    checker: source.clone().into(),
+    interactor: None,
+    validator: None,
+    output: Default::default(),
+    presentation_error: Default::default(),
+    cache_checker: false,
+    seed_checker: false,
+    labels: vec![],
+    answer_limits: problem::AnswerLimits::default(),
+    submission_limits: problem::SubmissionLimits::default(),
+    standard_solution: source,
+    user_copy_in: HashMap::new(),
+    judge_copy_in: HashMap::new(),
+  };
+
+  for index in 0..2 {
+    assert_eq!(
+      problem.add_hack(problem::Input::Plain { context: vec![] }),
+      record::TestId {
+        testset: problem::Testset::Hack,
+        subtask: 2,
+        index,
+      }
+    );
+  }
+  assert_eq!(problem.subtasks.len(), 2);
+  assert_eq!(problem.subtasks[1].score, 0.);
+  assert_eq!(problem.subtasks[1].time_limit, time::Duration::from_secs(2));
+  assert_eq!(problem.subtasks[1].tests.len(), 2);
+}
+
+#[test]
+fn test_limits_override() {
+  let subtask = problem::Subtask {
+    id: 1,
+    score: 100.,
+    dependences: vec![],
+    testset: problem::Testset::Main,
+    tests: vec![
+      problem::Test {
+        input: problem::Input::Plain { context: vec![] },
+        answer: problem::Answer::Generated,
+        time_limit: None,
+        memory_limit: None,
+      },
+      problem::Test {
+        input: problem::Input::Plain { context: vec![] },
+        answer: problem::Answer::Generated,
+        time_limit: Some(time::Duration::from_secs(3)),
+        memory_limit: None,
+      },
+    ],
+    time_limit: time::Duration::from_secs(1),
+    memory_limit: 64 << 20,
+    wall_time_limit: None,
+    time_kind: judge::TimeKind::Cpu,
+    cpu_affinity: None,
+    scoring: problem::ScoringPolicy::Min,
+  };
+
+  assert_eq!(
+    subtask.limits(&subtask.tests[0]),
+    (time::Duration::from_secs(1), 64 << 20)
+  );
+  assert_eq!(
+    subtask.limits(&subtask.tests[1]),
+    (time::Duration::from_secs(3), 64 << 20)
+  );
+}
+
+#[test]
+fn test_scoring_policy() {
+  let scores = [1., 0.5, 0.8];
+
+  assert_eq!(problem::ScoringPolicy::Min.aggregate(&scores), 0.5);
+  assert_eq!(problem::ScoringPolicy::Sum.aggregate(&scores), 2.3 / 3.);
+  assert_eq!(problem::ScoringPolicy::Product.aggregate(&scores), 0.4);
+  assert_eq!(problem::ScoringPolicy::LastTest.aggregate(&scores), 0.8);
+  assert_eq!(
+    problem::ScoringPolicy::Custom(std::sync::Arc::new(|x| x
+      .iter()
+      .cloned()
+      .fold(0., f32::max)))
+    .aggregate(&scores),
+    1.
+  );
+  assert_eq!(problem::ScoringPolicy::Sum.aggregate(&[]), 1.);
+
+  // Tests scored by points count the raw points of the checker.
+  let records = ["points 35", "points 0.5", "ok"]
+    .map(|x| record::Record::new_answer_checked(&checker::Output::parse(x)));
+  assert_eq!(records[0].score, 1.);
+  let points: Vec<_> = records.iter().map(|x| x.points()).collect();
+  assert_eq!(points, [35., 0.5, 1.]);
+  assert_eq!(problem::ScoringPolicy::Points.aggregate(&points), 36.5);
+  assert_eq!(problem::ScoringPolicy::Points.aggregate(&[]), 0.);
+}
+
+#[test]
+fn test_judge_cancelled() {
+  super::async_test(async {
+    let sol = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(
+        "#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}"
+          .as_bytes()
+          .to_vec(),
+      ),
+    }
+    .compile(vec![], HashMap::new())
+    .await
+    .unwrap();
+    let subtask = problem::Subtask {
+      id: 1,
+      score: 100.,
+      dependences: vec![],
+      testset: problem::Testset::Main,
+      tests: vec![problem::Test {
+        input: problem::Input::Plain {
+          context: b"1 2\n".to_vec(),
+        },
+        answer: problem::Answer::Generated,
+        time_limit: None,
+        memory_limit: None,
+      }],
+      time_limit: time::Duration::from_secs(1),
+      memory_limit: 64 * 1024 * 1024,
+      wall_time_limit: None,
+      time_kind: judge::TimeKind::Cpu,
+      cpu_affinity: None,
+      scoring: problem::ScoringPolicy::Min,
+    };
+
+    let (cancel_tx, cancel) = problem::cancel_channel();
+    cancel_tx
+      .send(Some(problem::Cancellation {
+        reason: problem::CancelReason::Admin,
+        by: Some("root".to_string()),
+      }))
+      .unwrap();
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+
+    let (score, records) = subtask
+      .judge(
+        uuid::Uuid::new_v4(),
+        &etc::ProfileCfg::default(),
+        &sol,
+        &sol,
+        &sol.clone().into(),
+        None,
+        &Default::default(),
+        &HashMap::new(),
+        &HashMap::new(),
+        Some(tx),
+        Some(cancel),
+        None,
+      )
+      .await;
+
+    assert_eq!(score, 0.);
+    assert_eq!(records[0].status, record::RecordStatus::Skipped);
+    let responses: Vec<_> = futures::StreamExt::collect(rx).await;
+    assert!(matches!(
+      responses.as_slice(),
+      [problem::Response::Cancelled { reason: problem::CancelReason::Admin, by: Some(_), records, .. }]
+        if records.is_empty()
+    ));
+  });
+}
+
+#[test]
+fn test_schedule() {
+  let source = program::Source {
+    lang: lang::Lang::from_str("c").unwrap(),
+    data: data::Provider::Memory(vec![]),
+  };
+  let make_subtask = |id: usize, dependences: Vec<usize>, testset| problem::Subtask {
+    id,
+    score: 10.,
+    dependences,
+    testset,
+    tests: vec![],
+    time_limit: time::Duration::from_secs(1),
+    memory_limit: 64 << 20,
+    wall_time_limit: None,
+    time_kind: judge::TimeKind::Cpu,
+    cpu_affinity: None,
+    scoring: problem::ScoringPolicy::Min,
+  };
+  let problem = problem::Problem {
+    subtasks: vec![
+      make_subtask(0, vec![2], problem::Testset::Main),
+      make_subtask(1, vec![3], problem::Testset::Main),
+      make_subtask(2, vec![1], problem::Testset::Main),
+      make_subtask(3, vec![], problem::Testset::Sample),
+    ],
+    kind: problem::Kind::Batch,
+    checker: source.clone().into(),
+    interactor: None,
+    validator: None,
+    output: Default::default(),
+    presentation_error: Default::default(),
+    cache_checker: false,
+    seed_checker: false,
+    labels: vec![],
+    answer_limits: problem::AnswerLimits::default(),
+    submission_limits: problem::SubmissionLimits::default(),
+    standard_solution: source,
+    user_copy_in: HashMap::new(),
+    judge_copy_in: HashMap::new(),
+  };
+
+  let ids = |testsets: &[problem::Testset]| -> Vec<usize> {
+    problem.schedule(testsets).iter().map(|x| x.id).collect()
+  };
+  assert_eq!(ids(&[problem::Testset::Main]), vec![1, 2, 0]);
+  assert_eq!(
+    ids(&[problem::Testset::Sample, problem::Testset::Main]),
+    vec![3, 1, 2, 0]
+  );
+}
+
+#[test]
+fn test_judge_source() {
+  super::async_test(async {
+    let sol = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(
+        "#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}"
+          .as_bytes()
+          .to_vec(),
+      ),
+    };
+    let mut problem = problem::Problem {
+      subtasks: vec![problem::Subtask {
+        id: 0,
+        score: 100.,
+        dependences: vec![],
+        testset: problem::Testset::Main,
+        tests: vec![problem::Test {
+          input: problem::Input::Plain {
+            context: b"1 2\n".to_vec(),
+          },
+          answer: problem::Answer::Generated,
+          time_limit: None,
+          memory_limit: None,
+        }],
+        time_limit: time::Duration::from_secs(1),
+        memory_limit: 64 * 1024 * 1024,
+        wall_time_limit: None,
+        time_kind: judge::TimeKind::Cpu,
+        cpu_affinity: None,
+        scoring: problem::ScoringPolicy::Min,
+      }],
+      kind: problem::Kind::SubmitAnswer,
+      checker: program::Source {
+        lang: lang::Lang::from_str("cpp").unwrap(),
+        data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+      }
+      .into(),
+      interactor: None,
+      validator: None,
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: true,
+      seed_checker: false,
+      labels: vec![],
+      answer_limits: problem::AnswerLimits::default(),
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: sol.clone(),
+      user_copy_in: HashMap::from([(
+        "testlib.h".to_string(),
+        builtin::File::from_str("testlib:testlib.h").unwrap().into(),
+      )]),
+      judge_copy_in: HashMap::new(),
+    };
+
+    assert!(matches!(
+      problem
+        .judge_source(
+          uuid::Uuid::new_v4(),
+          &etc::ProfileCfg::default(),
+          &[problem::Testset::Main],
+          &sol,
+          None,
+          None,
+          None,
+        )
+        .await,
+      Err(problem::JudgeError::Kind(problem::Kind::SubmitAnswer))
+    ));
+    assert!(matches!(
+      problem.check_judgeable(),
+      Err(problem::JudgeError::Kind(problem::Kind::SubmitAnswer))
+    ));
+
+    problem.kind = problem::Kind::Batch;
+    assert!(problem.check_judgeable().is_ok());
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+    let (score, records) = problem
+      .judge_source(
+        uuid::Uuid::new_v4(),
+        &etc::ProfileCfg::default(),
+        &[problem::Testset::Main],
+        &sol,
+        Some(tx),
+        None,
+        None,
+      )
+      .await
+      .unwrap();
+    assert_eq!(score, 100.);
+    assert_eq!(records[0].1[0].status, record::RecordStatus::Accepted);
+
+    let responses: Vec<_> = futures::StreamExt::collect(rx).await;
+    assert!(matches!(
+      responses[0],
+      problem::Response::Compiling {
+        program: problem::CompileTarget::Solution,
+        ..
+      }
+    ));
+    assert_eq!(
+      responses
+        .iter()
+        .filter(|x| matches!(x, problem::Response::CompileFinished { error: None, .. }))
+        .count(),
+      3
+    );
+    assert!(responses
+      .iter()
+      .any(|x| matches!(x, problem::Response::TestStarted { .. })));
+    assert!(matches!(
+      responses.last(),
+      Some(problem::Response::Finished { score, .. }) if *score == 100.
+    ));
+
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+    let broken = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory("ERROR".as_bytes().to_vec()),
+    };
+    assert!(matches!(
+      problem
+        .judge_source(
+          uuid::Uuid::new_v4(),
+          &etc::ProfileCfg::default(),
+          &[problem::Testset::Main],
+          &broken,
+          Some(tx),
+          None,
+          None,
+        )
+        .await,
+      Err(problem::JudgeError::Solution(_))
+    ));
+    let responses: Vec<_> = futures::StreamExt::collect(rx).await;
+    assert!(matches!(
+      responses.last(),
+      Some(problem::Response::CompileError {
+        program: problem::CompileTarget::Solution,
+        message,
+        result,
+        ..
+      }) if message.contains("ERROR") && result.status == sandbox::Status::NonZeroExitStatus
+    ));
+  });
+}
+
+#[test]
+fn test_generate_answers() {
+  super::async_test(async {
+    let std = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(
+        "#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}"
+          .as_bytes()
+          .to_vec(),
+      ),
+    };
+    let test = |input: &str| problem::Test {
+      input: problem::Input::Plain {
+        context: input.as_bytes().to_vec(),
+      },
+      answer: problem::Answer::Generated,
+      time_limit: None,
+      memory_limit: None,
+    };
+    let mut problem = problem::Problem {
+      subtasks: vec![problem::Subtask {
+        id: 0,
+        score: 100.,
+        dependences: vec![],
+        testset: problem::Testset::Main,
+        tests: vec![test("1 2\n"), test("3 4\n"), test("5 6\n")],
+        time_limit: time::Duration::from_secs(1),
+        memory_limit: 64 * 1024 * 1024,
+        wall_time_limit: None,
+        time_kind: judge::TimeKind::Cpu,
+        cpu_affinity: None,
+        scoring: problem::ScoringPolicy::Min,
+      }],
+      kind: problem::Kind::Batch,
+      checker: std.clone().into(),
+      interactor: None,
+      validator: None,
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: false,
+      seed_checker: false,
+      labels: vec![],
+      answer_limits: problem::AnswerLimits {
+        max_size: Some(2),
+        ..Default::default()
+      },
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: std.clone(),
+      user_copy_in: HashMap::new(),
+      judge_copy_in: HashMap::new(),
+    };
+
+    let std = std.compile(vec![], HashMap::new()).await.unwrap();
+    let errors = problem
+      .generate_answers(&std, &HashMap::new(), &HashMap::new())
+      .await;
+
+    // "11\n" exceeds the max size of answers.
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0.index, 2);
+    let tests = &problem.subtasks[0].tests;
+    assert!(matches!(&tests[0].answer, problem::Answer::Plain { context } if context == b"3\n"));
+    assert!(matches!(&tests[1].answer, problem::Answer::Plain { context } if context == b"7\n"));
+    assert!(matches!(tests[2].answer, problem::Answer::Generated));
+  });
+}
+
+#[test]
+fn test_retry_flaky() {
+  super::async_test(async {
+    let runs = std::sync::atomic::AtomicU32::new(0);
+    let record = problem::retry_flaky(uuid::Uuid::new_v4(), || async {
+      if runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+        return record::Record::new_system_error("sandbox hiccup");
+      }
+      return record::RECORD_SKIPPED.clone();
+    })
+    .await;
+    assert_eq!(record.status, record::RecordStatus::Skipped);
+    assert_eq!(record.attempts, 2);
+
+    let record = problem::retry_flaky(uuid::Uuid::new_v4(), || async {
+      let mut record = record::RECORD_SKIPPED.clone();
+      record.status = record::RecordStatus::FileError;
+      record.message = "copy out failed".to_string();
+      return record;
+    })
+    .await;
+    assert_eq!(record.status, record::RecordStatus::SystemError);
+    assert_eq!(record.attempts, CONFIG.judge.max_attempts);
+    assert!(record.message.starts_with("copy out failed"));
+  });
+}
+
+#[test]
+fn test_spot_check_sampling() {
+  let test_id = |index| record::TestId {
+    testset: problem::Testset::Main,
+    subtask: 0,
+    index,
+  };
+  assert!((0..100).all(|i| !problem::sampled(b"seed", &test_id(i), 0.)));
+  assert!((0..100).all(|i| problem::sampled(b"seed", &test_id(i), 1.)));
+
+  let sample = |seed: &[u8]| {
+    (0..100)
+      .filter(|&i| problem::sampled(seed, &test_id(i), 0.5))
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(sample(b"seed"), sample(b"seed"));
+  assert!(!sample(b"seed").is_empty() && sample(b"seed").len() < 100);
+}
+
+#[test]
+fn test_spot_check_answers() {
+  super::async_test(async {
+    let std = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(
+        "#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}"
+          .as_bytes()
+          .to_vec(),
+      ),
+    };
+    let checker_src = program::Source {
+      lang: lang::Lang::from_str("cpp").unwrap(),
+      data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+    };
+    let test = |input: &str, answer: &str| problem::Test {
+      input: problem::Input::Plain {
+        context: input.as_bytes().to_vec(),
+      },
+      answer: problem::Answer::Plain {
+        context: answer.as_bytes().to_vec(),
+      },
+      time_limit: None,
+      memory_limit: None,
+    };
+    let problem = problem::Problem {
+      subtasks: vec![problem::Subtask {
+        id: 0,
+        score: 100.,
+        dependences: vec![],
+        testset: problem::Testset::Main,
+        tests: vec![test("1 2\n", "3\n"), test("3 4\n", "8\n")],
+        time_limit: time::Duration::from_secs(1),
+        memory_limit: 64 * 1024 * 1024,
+        wall_time_limit: None,
+        time_kind: judge::TimeKind::Cpu,
+        cpu_affinity: None,
+        scoring: problem::ScoringPolicy::Min,
+      }],
+      kind: problem::Kind::Batch,
+      checker: checker_src.clone().into(),
+      interactor: None,
+      validator: None,
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: false,
+      seed_checker: false,
+      labels: vec![],
+      answer_limits: Default::default(),
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: std.clone(),
+      user_copy_in: HashMap::new(),
+      judge_copy_in: HashMap::new(),
+    };
+
+    let std = std.compile(vec![], HashMap::new()).await.unwrap();
+    let chk = checker::Checker::from(
+      checker_src
+        .compile(
+          vec![],
+          [(
+            "testlib.h".to_string(),
+            sandbox::FileHandle::upload(
+              &builtin::File::from_str("testlib:testlib.h")
+                .unwrap()
+                .as_bytes(),
+            )
+            .await
+            .unwrap(),
+          )]
+          .into(),
+        )
+        .await
+        .unwrap(),
+    );
+
+    let drifts = problem
+      .spot_check_answers(b"seed", 1., &std, &chk, &HashMap::new(), &HashMap::new())
+      .await;
+
+    // The stored answer "8" of "3 4" drifted from the standard solution.
+    assert_eq!(drifts.len(), 1);
+    assert_eq!(drifts[0].test_id.index, 1);
+  });
+}
+
+#[test]
+fn test_seed_checker() {
+  super::async_test(async {
+    let source = |lang: &str, code: &str| program::Source {
+      lang: lang::Lang::from_str(lang).unwrap(),
+      data: data::Provider::Memory(code.as_bytes().to_vec()),
+    };
+    let sol = source(
+      "c",
+      "#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}",
+    );
+    let test = |input: &str| problem::Test {
+      input: problem::Input::Plain {
+        context: input.as_bytes().to_vec(),
+      },
+      answer: problem::Answer::Generated,
+      time_limit: None,
+      memory_limit: None,
+    };
+    let problem = problem::Problem {
+      subtasks: vec![problem::Subtask {
+        id: 0,
+        score: 100.,
+        dependences: vec![],
+        testset: problem::Testset::Main,
+        tests: vec![test("1 2\n"), test("3 4\n")],
+        time_limit: time::Duration::from_secs(1),
+        memory_limit: 64 * 1024 * 1024,
+        wall_time_limit: None,
+        time_kind: judge::TimeKind::Cpu,
+        cpu_affinity: None,
+        scoring: problem::ScoringPolicy::Min,
+      }],
+      kind: problem::Kind::Batch,
+      // Reports the seed it is given.
+      checker: source(
+        "c",
+        "#include<stdio.h>\n#include<stdlib.h>\n\
+        int main(){fprintf(stderr,\"ok %s\",getenv(\"RINDAG_CHECKER_SEED\"));}",
+      )
+      .into(),
+      interactor: None,
+      validator: None,
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: false,
+      seed_checker: true,
+      labels: vec![],
+      answer_limits: problem::AnswerLimits::default(),
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: sol.clone(),
+      user_copy_in: HashMap::new(),
+      judge_copy_in: HashMap::new(),
+    };
+
+    let other = source("c", "int main(){}");
+    assert_eq!(
+      problem.checker_seed_key(&sol),
+      problem.checker_seed_key(&sol)
+    );
+    assert_ne!(
+      problem.checker_seed_key(&sol),
+      problem.checker_seed_key(&other)
+    );
+
+    let judge = || async {
+      let (_, records) = problem
+        .judge_source(
+          uuid::Uuid::new_v4(),
+          &etc::ProfileCfg::default(),
+          &[problem::Testset::Main],
+          &sol,
+          None,
+          None,
+          None,
+        )
+        .await
+        .unwrap();
+      return records[0].1.clone();
+    };
+    let (first, second) = (judge().await, judge().await);
+
+    for record in &first {
+      let seed = record.checker_seed.unwrap();
+      assert_eq!(record.message, format!("ok {}", seed));
+    }
+    assert_ne!(first[0].checker_seed, first[1].checker_seed);
+    // Re-judging passes the same seeds.
+    assert_eq!(
+      first.iter().map(|x| x.checker_seed).collect::<Vec<_>>(),
+      second.iter().map(|x| x.checker_seed).collect::<Vec<_>>()
+    );
+  });
+}
+
+#[test]
+fn test_prepare() {
+  super::async_test(async {
+    let source = |code: &str| program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(code.as_bytes().to_vec()),
+    };
+    let sol =
+      source("#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}");
+    let problem = problem::Problem {
+      subtasks: vec![problem::Subtask {
+        id: 0,
+        score: 100.,
+        dependences: vec![],
+        testset: problem::Testset::Main,
+        tests: vec![problem::Test {
+          input: problem::Input::Plain {
+            context: b"1 2\n".to_vec(),
+          },
+          answer: problem::Answer::Plain {
+            context: b"3\n".to_vec(),
+          },
+          time_limit: None,
+          memory_limit: None,
+        }],
+        time_limit: time::Duration::from_secs(1),
+        memory_limit: 64 * 1024 * 1024,
+        wall_time_limit: None,
+        time_kind: judge::TimeKind::Cpu,
+        cpu_affinity: None,
+        scoring: problem::ScoringPolicy::Min,
+      }],
+      kind: problem::Kind::Batch,
+      checker: program::Source {
+        lang: lang::Lang::from_str("cpp").unwrap(),
+        data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+      }
+      .into(),
+      interactor: None,
+      validator: None,
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: false,
+      seed_checker: false,
+      labels: vec![],
+      answer_limits: problem::AnswerLimits::default(),
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: sol.clone(),
+      user_copy_in: HashMap::from([(
+        "testlib.h".to_string(),
+        builtin::File::from_str("testlib:testlib.h").unwrap().into(),
+      )]),
+      judge_copy_in: HashMap::new(),
+    };
+    let size = "1 2\n3\n".len() + sol.data.as_bytes().len() * 2;
+
+    let prepared = problem.prepare(uuid::Uuid::new_v4(), None).await.unwrap();
+    assert!(prepared.size() > size as u64);
+
+    // Only the solution is compiled for each judgement.
+    for (code, score) in [
+      (sol.clone(), 100.),
+      (source("#include<stdio.h>\nint main(){puts(\"4\");}"), 0.),
+    ] {
+      let (tx, rx) = futures::channel::mpsc::unbounded();
+      let (total, _) = prepared
+        .judge_source(
+          uuid::Uuid::new_v4(),
+          &etc::ProfileCfg::default(),
+          &[problem::Testset::Main],
+          &code,
+          Some(tx),
+          None,
+          None,
+        )
+        .await
+        .unwrap();
+      assert_eq!(total, score);
+
+      let responses: Vec<_> = futures::StreamExt::collect(rx).await;
+      assert_eq!(
+        responses
+          .iter()
+          .filter(|x| matches!(x, problem::Response::CompileFinished { .. }))
+          .count(),
+        1
+      );
+    }
+  });
+}
+
+#[test]
+fn test_validate_generated_inputs() {
+  super::async_test(async {
+    let source = |code: &str| program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(code.as_bytes().to_vec()),
+    };
+    let sol =
+      source("#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}");
+    let gen = generator::Generator::from(
+      source(
+        "#include<stdio.h>\nint main(int argc,char**argv){printf(\"%s %s\\n\",argv[1],argv[2]);}",
+      )
+      .compile(vec![], HashMap::new())
+      .await
+      .unwrap(),
+    );
+    let generated = |a: &str, b: &str| problem::Test {
+      input: problem::Input::Generated {
+        generator: gen.clone(),
+        args: vec![a.to_string(), b.to_string()],
+      },
+      answer: problem::Answer::Generated,
+      time_limit: None,
+      memory_limit: None,
+    };
+    let problem = problem::Problem {
+      subtasks: vec![problem::Subtask {
+        id: 0,
+        score: 100.,
+        dependences: vec![],
+        testset: problem::Testset::Main,
+        tests: vec![
+          generated("1", "2"),
+          generated("-1", "2"),
+          // Plain inputs are not validated at judge time.
+          problem::Test {
+            input: problem::Input::Plain {
+              context: b"-3 4\n".to_vec(),
+            },
+            answer: problem::Answer::Generated,
+            time_limit: None,
+            memory_limit: None,
+          },
+        ],
+        time_limit: time::Duration::from_secs(1),
+        memory_limit: 64 * 1024 * 1024,
+        wall_time_limit: None,
+        time_kind: judge::TimeKind::Cpu,
+        cpu_affinity: None,
+        scoring: problem::ScoringPolicy::Sum,
+      }],
+      kind: problem::Kind::Batch,
+      checker: program::Source {
+        lang: lang::Lang::from_str("cpp").unwrap(),
+        data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+      }
+      .into(),
+      interactor: None,
+      validator: Some(source(
+        "#include<stdio.h>\n\
+        int main(){int a,b;scanf(\"%d%d\",&a,&b);\
+        if(a<0){fprintf(stderr,\"a is negative\");return 3;}}",
+      )),
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: false,
+      seed_checker: false,
+      labels: vec![],
+      answer_limits: problem::AnswerLimits::default(),
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: sol.clone(),
+      user_copy_in: HashMap::from([(
+        "testlib.h".to_string(),
+        builtin::File::from_str("testlib:testlib.h").unwrap().into(),
+      )]),
+      judge_copy_in: HashMap::new(),
+    };
+
+    let (_, records) = problem
+      .judge_source(
+        uuid::Uuid::new_v4(),
+        &etc::ProfileCfg::default(),
+        &[problem::Testset::Main],
+        &sol,
+        None,
+        None,
+        None,
+      )
+      .await
+      .unwrap();
+
+    let records = &records[0].1;
+    assert_eq!(records[0].status, record::RecordStatus::Accepted);
+    assert_eq!(records[1].status, record::RecordStatus::SystemError);
+    assert!(records[1]
+      .message
+      .starts_with("generated input is invalid: a is negative"));
+    assert_eq!(records[2].status, record::RecordStatus::Accepted);
+  });
+}
+
+#[test]
+fn test_revision() {
+  let make = |answer: &str| problem::Problem {
+    subtasks: vec![problem::Subtask {
+      id: 0,
+      score: 100.,
+      dependences: vec![],
+      testset: problem::Testset::Main,
+      tests: vec![problem::Test {
+        input: problem::Input::Plain {
+          context: b"1 2\n".to_vec(),
+        },
+        answer: problem::Answer::Plain {
+          context: answer.as_bytes().to_vec(),
+        },
+        time_limit: None,
+        memory_limit: None,
+      }],
+      time_limit: time::Duration::from_secs(1),
+      memory_limit: 64 * 1024 * 1024,
+      wall_time_limit: None,
+      time_kind: judge::TimeKind::Cpu,
+      cpu_affinity: None,
+      scoring: problem::ScoringPolicy::Min,
+    }],
+    kind: problem::Kind::Batch,
+    checker: program::Source {
+      lang: lang::Lang::from_str("cpp").unwrap(),
+      data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+    }
+    .into(),
+    interactor: None,
+    validator: None,
+    output: Default::default(),
+    presentation_error: Default::default(),
+    cache_checker: false,
+    seed_checker: false,
+    labels: vec![],
+    answer_limits: problem::AnswerLimits::default(),
+    submission_limits: problem::SubmissionLimits::default(),
+    standard_solution: program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(b"int main(){}".to_vec()),
+    },
+    user_copy_in: HashMap::new(),
+    judge_copy_in: HashMap::new(),
+  };
+
+  assert_eq!(make("3\n").revision(), make("3\n").revision());
+  assert_ne!(make("3\n").revision(), make("4\n").revision());
+
+  let mut problem = make("3\n");
+  problem.subtasks[0].time_limit = time::Duration::from_secs(2);
+  assert_ne!(problem.revision(), make("3\n").revision());
+}
+
+#[test]
+fn test_bundle() {
+  super::async_test(async {
+    let source = |code: &str| program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(code.as_bytes().to_vec()),
+    };
+    let sol =
+      source("#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}");
+    let problem = problem::Problem {
+      subtasks: vec![problem::Subtask {
+        id: 0,
+        score: 100.,
+        dependences: vec![],
+        testset: problem::Testset::Main,
+        tests: vec![problem::Test {
+          input: problem::Input::Plain {
+            context: b"1 2\n".to_vec(),
+          },
+          answer: problem::Answer::Plain {
+            context: b"3\n".to_vec(),
+          },
+          time_limit: None,
+          memory_limit: None,
+        }],
+        time_limit: time::Duration::from_secs(1),
+        memory_limit: 64 * 1024 * 1024,
+        wall_time_limit: None,
+        time_kind: judge::TimeKind::Cpu,
+        cpu_affinity: None,
+        scoring: problem::ScoringPolicy::Min,
+      }],
+      kind: problem::Kind::Batch,
+      checker: program::Source {
+        lang: lang::Lang::from_str("cpp").unwrap(),
+        data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+      }
+      .into(),
+      interactor: None,
+      validator: None,
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: false,
+      seed_checker: false,
+      labels: vec![],
+      answer_limits: problem::AnswerLimits::default(),
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: sol,
+      user_copy_in: HashMap::new(),
+      judge_copy_in: HashMap::new(),
+    };
+    let prepared = problem.prepare(uuid::Uuid::new_v4(), None).await.unwrap();
+
+    let wrong = source("#include<stdio.h>\nint main(){puts(\"4\");}");
+    let mut rec = record::RECORD_WAITING.clone().with_id(
+      record::TestId {
+        testset: problem::Testset::Main,
+        subtask: 0,
+        index: 0,
+      },
+      uuid::Uuid::new_v4(),
+    );
+    rec.status = record::RecordStatus::WrongAnswer;
+
+    let bundle = prepared
+      .bundle(&etc::ProfileCfg::default(), &wrong, &rec, 2)
+      .await
+      .unwrap();
+    assert_eq!(bundle.input, b"1 ");
+    assert_eq!(bundle.output, Some(b"4\n".to_vec()));
+    assert_eq!(bundle.answer, b"3\n");
+    assert_eq!(bundle.truncated, vec!["input.txt".to_string()]);
+
+    let tar = bundle.to_tar().unwrap();
+    let mut archive = tar::Archive::new(tar.as_slice());
+    let names: Vec<_> = archive
+      .entries()
+      .unwrap()
+      .map(|x| x.unwrap().path().unwrap().display().to_string())
+      .collect();
+    assert_eq!(
+      names,
+      ["input.txt", "output.txt", "answer.txt", "record.json"]
+    );
+
+    // A record of a test not in the problem.
+    rec.test_id.as_mut().unwrap().index = 1;
+    assert!(matches!(
+      prepared
+        .bundle(&etc::ProfileCfg::default(), &wrong, &rec, 2)
+        .await,
+      Err(problem::BundleError::NoTest)
+    ));
+  });
+}
+
+/// A test for running a solution on the samples only, with the outputs returned.
+#[test]
+fn test_run_samples() {
+  super::async_test(async {
+    let source = |code: &str| program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(code.as_bytes().to_vec()),
+    };
+    let test = |input: &[u8], answer: &[u8]| problem::Test {
+      input: problem::Input::Plain {
+        context: input.to_vec(),
+      },
+      answer: problem::Answer::Plain {
+        context: answer.to_vec(),
+      },
+      time_limit: None,
+      memory_limit: None,
+    };
+    let subtask = |id, testset, tests| problem::Subtask {
+      id,
+      score: 50.,
+      dependences: vec![],
+      testset,
+      tests,
+      time_limit: time::Duration::from_secs(1),
+      memory_limit: 64 * 1024 * 1024,
+      wall_time_limit: None,
+      time_kind: judge::TimeKind::Cpu,
+      cpu_affinity: None,
+      scoring: problem::ScoringPolicy::Min,
+    };
+    let sol =
+      source("#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}");
+    let problem = problem::Problem {
+      subtasks: vec![
+        subtask(
+          0,
+          problem::Testset::Sample,
+          vec![test(b"1 2\n", b"3\n"), test(b"20 22\n", b"42\n")],
+        ),
+        subtask(1, problem::Testset::Main, vec![test(b"5 5\n", b"10\n")]),
+      ],
+      kind: problem::Kind::Batch,
+      checker: program::Source {
+        lang: lang::Lang::from_str("cpp").unwrap(),
+        data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+      }
+      .into(),
+      interactor: None,
+      validator: None,
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: false,
+      seed_checker: false,
+      labels: vec![],
+      answer_limits: problem::AnswerLimits::default(),
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: sol,
+      user_copy_in: HashMap::new(),
+      judge_copy_in: HashMap::new(),
+    };
+    let prepared = problem.prepare(uuid::Uuid::new_v4(), None).await.unwrap();
+
+    let wrong = source("#include<stdio.h>\nint main(){puts(\"3\");}");
+    let runs = prepared
+      .run_samples(&etc::ProfileCfg::default(), &wrong, 1024)
+      .await
+      .unwrap();
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[0].record.status, record::RecordStatus::Accepted);
+    assert_eq!(runs[1].record.status, record::RecordStatus::WrongAnswer);
+    assert_eq!(runs[1].input, "20 22\n");
+    assert_eq!(runs[1].output.as_deref(), Some("3\n"));
+    assert_eq!(runs[1].answer, "42\n");
+    assert!(runs[1].truncated.is_empty());
+
+    let broken = source("int main(){return}");
+    assert!(matches!(
+      prepared
+        .run_samples(&etc::ProfileCfg::default(), &broken, 1024)
+        .await,
+      Err(problem::SampleRunError::Compile(_))
+    ));
+  });
+}
+
+#[test]
+fn test_score_weights() {
+  super::async_test(async {
+    let source = |code: &str| program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(code.as_bytes().to_vec()),
+    };
+    let sol =
+      source("#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}");
+    let subtask = |id: usize, score: f32, testset: problem::Testset| problem::Subtask {
+      id,
+      score,
+      dependences: vec![],
+      testset,
+      tests: vec![problem::Test {
+        input: problem::Input::Plain {
+          context: b"1 2\n".to_vec(),
+        },
+        answer: problem::Answer::Plain {
+          context: b"3\n".to_vec(),
+        },
+        time_limit: None,
+        memory_limit: None,
+      }],
+      time_limit: time::Duration::from_secs(1),
+      memory_limit: 64 * 1024 * 1024,
+      wall_time_limit: None,
+      time_kind: judge::TimeKind::Cpu,
+      cpu_affinity: None,
+      scoring: problem::ScoringPolicy::Min,
+    };
+    let problem = problem::Problem {
+      subtasks: vec![
+        subtask(0, 30., problem::Testset::Pretests),
+        subtask(1, 70., problem::Testset::Main),
+      ],
+      kind: problem::Kind::Batch,
+      checker: program::Source {
+        lang: lang::Lang::from_str("cpp").unwrap(),
+        data: builtin::File::from_str("checker:ncmp.cpp").unwrap().into(),
+      }
+      .into(),
+      interactor: None,
+      validator: None,
+      output: Default::default(),
+      presentation_error: Default::default(),
+      cache_checker: false,
+      seed_checker: false,
+      labels: vec![],
+      answer_limits: problem::AnswerLimits::default(),
+      submission_limits: problem::SubmissionLimits::default(),
+      standard_solution: sol.clone(),
+      user_copy_in: HashMap::new(),
+      judge_copy_in: HashMap::new(),
+    };
+    let prepared = problem.prepare(uuid::Uuid::new_v4(), None).await.unwrap();
+
+    let profile = etc::ProfileCfg {
+      weights: etc::ScoreWeights {
+        provisional: HashMap::from([(problem::Testset::Main, 0.)]),
+        final_: HashMap::from([(problem::Testset::Pretests, 0.)]),
+      },
+      ..Default::default()
+    };
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+    let (total, _) = prepared
+      .judge_source(
+        uuid::Uuid::new_v4(),
+        &profile,
+        &[problem::Testset::Pretests, problem::Testset::Main],
+        &sol,
+        Some(tx),
+        None,
+        None,
+      )
+      .await
+      .unwrap();
+    assert_eq!(total, 100.);
+
+    let responses: Vec<_> = futures::StreamExt::collect(rx).await;
+    assert!(matches!(
+      responses.last(),
+      Some(problem::Response::Finished {
+        score,
+        provisional_score,
+        final_score,
+        ..
+      }) if *score == 100. && *provisional_score == 30. && *final_score == 70.
+    ));
   });
 }
@@ -1,6 +1,6 @@
 use std::{collections::HashMap, str::FromStr, time};
 
-use crate::{data, lang, program, sandbox};
+use crate::{calibrate, checker, data, error, interactor, judge, lang, program, sandbox, CONFIG};
 
 #[test]
 fn test_ce() {
@@ -16,6 +16,39 @@ fn test_ce() {
   });
 }
 
+#[test]
+fn test_compile_diagnostics() {
+  let err = error::CompileError {
+    result: sandbox::ExecuteResult::internal_error(),
+    message: "error: ".repeat(CONFIG.judge.compile_message_limit),
+  };
+  let diagnostics = err.diagnostics();
+  assert_eq!(diagnostics.len(), CONFIG.judge.compile_message_limit);
+  assert!(diagnostics.starts_with("error: error: "));
+  assert!(diagnostics.ends_with("..."));
+
+  let err = error::CompileError {
+    message: "error: x".to_string(),
+    ..err
+  };
+  assert_eq!(err.diagnostics(), "error: x");
+}
+
+#[test]
+fn test_compile_report() {
+  super::async_test(async {
+    let src = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory("int main(){return 0;}".as_bytes().to_vec()),
+    };
+
+    let exec = src.compile(vec![], HashMap::new()).await.unwrap();
+
+    assert!(exec.report.time > time::Duration::ZERO);
+    assert!(exec.report.memory > 0);
+  });
+}
+
 #[test]
 fn test_ok() {
   super::async_test(async {
@@ -37,7 +70,8 @@ fn test_ok() {
             "#include<stdio.h>\nvoid func(){int x;scanf(\"%d\",&x);printf(\"func: %d\\n\",x);}"
               .as_bytes(),
           )
-          .await,
+          .await
+          .unwrap(),
         )]
         .into(),
       )
@@ -47,10 +81,17 @@ fn test_ok() {
     let res = exec
       .judge_batch(
         vec![],
-        sandbox::FileHandle::upload("998244353".as_bytes()).await,
+        sandbox::FileHandle::upload("998244353".as_bytes())
+          .await
+          .unwrap(),
         [].into(),
         time::Duration::from_secs(1),
         64 * 1024 * 1024,
+        None,
+        judge::TimeKind::Cpu,
+        None,
+        CONFIG.judge.stdout_limit,
+        &judge::OutputSource::Stdout,
       )
       .await;
 
@@ -62,3 +103,215 @@ fn test_ok() {
     );
   });
 }
+
+#[test]
+fn test_interactive() {
+  super::async_test(async {
+    let sol = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(
+        "#include<stdio.h>\nint main(){int x;scanf(\"%d\",&x);printf(\"%d\\n\",x*2);}"
+          .as_bytes()
+          .to_vec(),
+      ),
+    }
+    .compile(vec![], HashMap::new())
+    .await
+    .unwrap();
+
+    let interactor = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(
+        "
+        #include<stdio.h>
+        int main(int argc,char**argv){
+          int x,y;FILE*inf=fopen(argv[1],\"r\");fscanf(inf,\"%d\",&x);
+          printf(\"%d\\n\",x);fflush(stdout);scanf(\"%d\",&y);
+          fputs(y==x*2?\"ok\":\"wrong answer\",stderr);
+          fclose(fopen(argv[2],\"w\"));
+          return y!=x*2;
+        }
+        "
+        .as_bytes()
+        .to_vec(),
+      ),
+    }
+    .compile(vec![], HashMap::new())
+    .await
+    .unwrap();
+
+    let res = interactor::Interactor::from(interactor)
+      .interact(
+        &sol,
+        vec![],
+        sandbox::FileHandle::upload("21".as_bytes()).await.unwrap(),
+        HashMap::new(),
+        HashMap::new(),
+        time::Duration::from_secs(1),
+        judge::TimeKind::Cpu,
+        64 * 1024 * 1024,
+      )
+      .await;
+
+    assert_eq!(res.solution.status, sandbox::Status::Accepted);
+    assert_eq!(
+      res.interactor_output.unwrap().status,
+      checker::Status::Accepted
+    );
+    assert_eq!(
+      res.transcript[0].as_ref().unwrap().context().await.unwrap(),
+      "42\n".as_bytes()
+    );
+  });
+}
+
+#[test]
+fn test_calibrate() {
+  super::async_test(async {
+    let calibration = calibrate::calibrate().await.unwrap();
+    assert_eq!(calibration.times.len(), 3);
+    assert!(calibration.factor > 0.);
+  });
+}
+
+#[test]
+fn test_calibration_report() {
+  let calibration = calibrate::Calibration {
+    times: HashMap::from([
+      ("int.c".to_string(), time::Duration::from_millis(600)),
+      ("float.c".to_string(), time::Duration::from_millis(420)),
+    ]),
+    factor: 1.2,
+  };
+  assert_eq!(
+    calibration.to_string(),
+    "float.c: 420 ms\nint.c: 600 ms\nspeed factor: 1.200\n"
+  );
+
+  // Nothing is measured unless enabled in config.
+  assert!(super::async_test(calibrate::auto_calibrate())
+    .unwrap()
+    .is_none());
+  assert_eq!(calibrate::measured_factor(), None);
+}
+
+#[test]
+fn test_denied_compile_args() {
+  super::async_test(async {
+    let src = program::Source {
+      lang: lang::Lang::from_str("cpp").unwrap(),
+      data: data::Provider::Memory("int main() {}".as_bytes().to_vec()),
+    };
+    let lang = &src.lang;
+    assert!(lang
+      .check_compile_args(&["-DLOCAL".to_string(), "-std=c++17".to_string()])
+      .is_ok());
+    assert!(lang
+      .check_compile_args(&["-include/etc/passwd".to_string()])
+      .is_err());
+
+    let err = src
+      .compile(
+        vec!["-include".to_string(), "/etc/passwd".to_string()],
+        HashMap::new(),
+      )
+      .await
+      .unwrap_err();
+    assert!(err.message.contains("-include"));
+  });
+}
+
+#[test]
+fn test_output_source() {
+  super::async_test(async {
+    let exec = program::Source {
+      lang: lang::Lang::from_str("c").unwrap(),
+      data: data::Provider::Memory(
+        "#include<stdio.h>\nint main(){puts(\"out\");fputs(\"err\",stderr);fclose(fopen(\"a.txt\",\"w\"));}"
+          .as_bytes()
+          .to_vec(),
+      ),
+    }
+    .compile(vec![], HashMap::new())
+    .await
+    .unwrap();
+
+    for (output, expected) in [
+      (judge::OutputSource::Stdout, "out\n"),
+      (judge::OutputSource::Stderr, "err"),
+      (judge::OutputSource::File("a.txt".to_string()), ""),
+    ] {
+      let (res, file) = exec
+        .judge_batch(
+          vec![],
+          sandbox::FileHandle::upload(&[]).await.unwrap(),
+          HashMap::new(),
+          time::Duration::from_secs(1),
+          64 * 1024 * 1024,
+          None,
+          judge::TimeKind::Cpu,
+          None,
+          CONFIG.judge.stdout_limit,
+          &output,
+        )
+        .await;
+      assert_eq!(res.status, sandbox::Status::Accepted);
+      assert_eq!(file.unwrap().context().await.unwrap(), expected.as_bytes());
+    }
+
+    let (res, _) = exec
+      .judge_batch(
+        vec![],
+        sandbox::FileHandle::upload(&[]).await.unwrap(),
+        HashMap::new(),
+        time::Duration::from_secs(1),
+        64 * 1024 * 1024,
+        None,
+        judge::TimeKind::Cpu,
+        None,
+        CONFIG.judge.stdout_limit,
+        &judge::OutputSource::File("b.txt".to_string()),
+      )
+      .await;
+    assert_eq!(res.status, sandbox::Status::FileError);
+  });
+}
+
+#[test]
+fn test_apply_time_limit() {
+  let limit = time::Duration::from_secs(1);
+  let result = sandbox::ExecuteResult {
+    time: time::Duration::from_millis(100),
+    wall_time: time::Duration::from_millis(1050),
+    ..sandbox::ExecuteResult::internal_error()
+  };
+  let apply = |status: sandbox::Status, time_kind: judge::TimeKind| {
+    let mut result = sandbox::ExecuteResult {
+      status,
+      ..result.clone()
+    };
+    judge::apply_time_limit(&mut result, limit, time_kind);
+    result.status
+  };
+
+  assert_eq!(
+    apply(sandbox::Status::Accepted, judge::TimeKind::Cpu),
+    sandbox::Status::Accepted
+  );
+  assert_eq!(
+    apply(sandbox::Status::Accepted, judge::TimeKind::Wall),
+    sandbox::Status::TimeLimitExceeded
+  );
+  // Killed by the wall clock time limit, which is the time limit of `Wall`.
+  assert_eq!(
+    apply(sandbox::Status::IdlenessLimitExceeded, judge::TimeKind::Cpu),
+    sandbox::Status::IdlenessLimitExceeded
+  );
+  assert_eq!(
+    apply(
+      sandbox::Status::IdlenessLimitExceeded,
+      judge::TimeKind::Wall
+    ),
+    sandbox::Status::TimeLimitExceeded
+  );
+}
@@ -0,0 +1,198 @@
+use std::time;
+
+use crate::{problem, record, sandbox};
+
+fn make_record(
+  index: usize,
+  status: record::RecordStatus,
+  score: f32,
+  time: u64,
+) -> record::Record {
+  let mut ret = record::RECORD_WAITING.clone().with_id(
+    record::TestId {
+      testset: problem::Testset::Main,
+      subtask: 0,
+      index,
+    },
+    uuid::Uuid::new_v4(),
+  );
+  ret.status = status;
+  ret.score = score;
+  ret.time = time::Duration::from_millis(time);
+  return ret;
+}
+
+#[test]
+fn test_diff() {
+  use record::RecordStatus::*;
+
+  let old = [
+    make_record(0, Accepted, 1., 100),
+    make_record(1, WrongAnswer, 0., 100),
+    make_record(2, Accepted, 1., 10),
+  ];
+  let new = [
+    make_record(1, Accepted, 1., 100),
+    make_record(0, Accepted, 1., 400),
+    make_record(3, Accepted, 1., 100),
+  ];
+
+  let changeset = record::diff(&old, &new);
+  assert_eq!(
+    changeset.changes,
+    vec![
+      record::Change::TimeRegression {
+        test: "main/0/0".to_string(),
+        old: time::Duration::from_millis(100),
+        new: time::Duration::from_millis(400),
+      },
+      record::Change::Verdict {
+        test: "main/0/1".to_string(),
+        old: WrongAnswer,
+        new: Accepted,
+      },
+      record::Change::Score {
+        test: "main/0/1".to_string(),
+        old: 0.,
+        new: 1.,
+      },
+      record::Change::Removed {
+        test: "main/0/2".to_string(),
+      },
+      record::Change::Added {
+        test: "main/0/3".to_string(),
+      },
+    ]
+  );
+  assert_eq!(changeset.score_delta(), 1.);
+  assert!(record::diff(&old, &old).is_empty());
+}
+
+#[test]
+fn test_almost_tle() {
+  use record::RecordStatus::*;
+
+  let limit = time::Duration::from_secs(1);
+  let grace = crate::judge::grace_time_limit(limit);
+  assert!(
+    make_record(0, TimeLimitExceeded, 0., 1001)
+      .with_almost_tle(limit)
+      .almost_tle
+  );
+  assert!(
+    !make_record(0, TimeLimitExceeded, 0., grace.as_millis() as u64)
+      .with_almost_tle(limit)
+      .almost_tle
+  );
+  assert!(
+    !make_record(0, Accepted, 1., 900)
+      .with_almost_tle(limit)
+      .almost_tle
+  );
+
+  // The wall clock time is compared if it is limited.
+  let mut record = make_record(0, TimeLimitExceeded, 0., 10);
+  record.wall_time = grace;
+  assert!(record.clone().with_almost_tle(limit).almost_tle);
+  assert!(
+    !record
+      .with_time_kind(crate::judge::TimeKind::Wall)
+      .with_almost_tle(limit)
+      .almost_tle
+  );
+}
+
+#[test]
+fn test_signalled() {
+  let result = sandbox::ExecuteResult {
+    status: sandbox::Status::Signalled,
+    time: time::Duration::from_millis(10),
+    wall_time: time::Duration::from_millis(12),
+    memory: 1024,
+    exit_code: 11,
+    error: String::new(),
+    timing: sandbox::Timing::default(),
+  };
+  let record = record::Record::new_interrupted(&result);
+  assert_eq!(record.status, record::RecordStatus::RuntimeError);
+  assert_eq!(record.signal, Some(11));
+  assert_eq!(record.wall_time, time::Duration::from_millis(12));
+  assert!(record.message.starts_with("segmentation fault"));
+  assert_eq!(
+    record.detail,
+    Some(record::Detail::Signal {
+      number: 11,
+      description: Some("segmentation fault (SIGSEGV)".to_string()),
+    })
+  );
+
+  let record = record::Record::new_interrupted(&sandbox::ExecuteResult {
+    status: sandbox::Status::NonZeroExitStatus,
+    exit_code: 6,
+    ..result
+  });
+  assert_eq!(record.signal, None);
+  assert_eq!(record.detail, None);
+  assert!(record.message.starts_with("task executed failed"));
+}
+
+#[test]
+fn test_dangerous_syscall() {
+  let result = sandbox::ExecuteResult {
+    status: sandbox::Status::DangerousSyscall,
+    time: time::Duration::from_millis(1),
+    wall_time: time::Duration::from_millis(1),
+    memory: 1024,
+    exit_code: 0,
+    error: "execve\n".to_string(),
+    timing: sandbox::Timing::default(),
+  };
+  let record = record::Record::new_interrupted(&result);
+  assert_eq!(record.status, record::RecordStatus::RuntimeError);
+  assert!(record.message.starts_with("dangerous syscall (execve): "));
+  assert_eq!(
+    serde_json::to_value(&record.detail).unwrap(),
+    serde_json::json!({ "kind": "dangerous_syscall", "syscall": "execve" })
+  );
+
+  let record = record::Record::new_interrupted(&sandbox::ExecuteResult {
+    error: String::new(),
+    ..result
+  });
+  assert_eq!(
+    record.detail,
+    Some(record::Detail::DangerousSyscall { syscall: None })
+  );
+  assert!(record.message.starts_with("dangerous syscall: "));
+}
+
+#[test]
+fn test_idleness_limit() {
+  let record = record::Record::new_interrupted(&sandbox::ExecuteResult {
+    status: sandbox::Status::IdlenessLimitExceeded,
+    time: time::Duration::from_millis(5),
+    wall_time: time::Duration::from_secs(2),
+    memory: 1024,
+    exit_code: 0,
+    error: String::new(),
+    timing: sandbox::Timing::default(),
+  })
+  .with_almost_tle(time::Duration::from_secs(1));
+  assert_eq!(record.status, record::RecordStatus::IdlenessLimitExceeded);
+  assert_eq!(record.time, time::Duration::from_millis(5));
+  assert_eq!(record.wall_time, time::Duration::from_secs(2));
+  assert!(!record.almost_tle);
+}
+
+#[test]
+fn test_parse_test_id() {
+  let id = record::TestId {
+    testset: problem::Testset::Main,
+    subtask: 1,
+    index: 0,
+  };
+  assert_eq!(id.to_string().parse::<record::TestId>(), Ok(id));
+  assert!("main/1".parse::<record::TestId>().is_err());
+  assert!("main/1/0/2".parse::<record::TestId>().is_err());
+  assert!("hidden/1/0".parse::<record::TestId>().is_err());
+}
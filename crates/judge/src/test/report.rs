@@ -0,0 +1,55 @@
+use std::time;
+
+use crate::{problem, record, report};
+
+fn make_record(status: record::RecordStatus, index: usize, message: &str) -> record::Record {
+  let mut r = record::Record::new_system_error(message).with_id(
+    record::TestId {
+      testset: problem::Testset::Main,
+      subtask: 1,
+      index,
+    },
+    uuid::Uuid::nil(),
+  );
+  r.status = status;
+  r.time = time::Duration::from_millis(15);
+  r.memory = 2048 * 1024;
+  r
+}
+
+#[test]
+fn test_csv() {
+  let mut table = report::Table::default();
+  table.push(
+    "main, correct",
+    vec![
+      make_record(record::RecordStatus::Accepted, 0, "ok"),
+      make_record(record::RecordStatus::WrongAnswer, 1, "wrong answer"),
+    ],
+  );
+
+  assert_eq!(
+    table.to_csv(),
+    "solution,test,verdict,status,score,time_ms,memory_kb\n\
+    \"main, correct\",main/1/0,OK,accepted,0,15,2048\n\
+    \"main, correct\",main/1/1,WA,wrong_answer,0,15,2048\n"
+  );
+}
+
+#[test]
+fn test_html_escape() {
+  let mut table = report::Table::default();
+  table.push(
+    "<brute>",
+    vec![make_record(
+      record::RecordStatus::TimeLimitExceeded,
+      0,
+      "a < b",
+    )],
+  );
+
+  let html = table.to_html();
+  assert!(html.contains("<th>main/1/0</th>"));
+  assert!(html.contains("<th>&lt;brute&gt;</th>"));
+  assert!(html.contains("title=\"a &lt; b\">TL<br>15 ms<br>2048 KB</td>"));
+}
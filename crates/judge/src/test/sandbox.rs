@@ -1,4 +1,6 @@
-use crate::sandbox;
+use std::{collections::HashMap, time};
+
+use crate::{etc, sandbox, CONFIG};
 
 /// A test for sandbox compiling and running a C code with gcc.
 #[test]
@@ -11,7 +13,8 @@ fn test_hello_world() {
         sandbox::FileHandle::upload(
           "#include<stdio.h>\nint main(){puts(\"hello, world!\\n你好, 世界!\");}".as_bytes(),
         )
-        .await,
+        .await
+        .unwrap(),
       )]
       .into(),
       copy_out: vec!["a.out".to_string()],
@@ -42,3 +45,565 @@ fn test_hello_world() {
     );
   });
 }
+
+/// A test for two commands connected by pipes, with the transcript captured.
+#[test]
+fn test_run_piped() {
+  super::async_test(async {
+    let res = sandbox::Request::RunPiped {
+      cmds: [
+        sandbox::Cmd {
+          args: vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            "echo ping; read x; test \"$x\" = pong".to_string(),
+          ],
+          ..Default::default()
+        },
+        sandbox::Cmd {
+          args: vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            "read x; echo pong".to_string(),
+          ],
+          ..Default::default()
+        },
+      ],
+      transcript: Some(sandbox::Transcript {
+        names: ["a.log".to_string(), "b.log".to_string()],
+        max: 1024,
+      }),
+    }
+    .exec()
+    .await;
+
+    assert_eq!(res.len(), 2);
+    assert_eq!(res[0].result.status, sandbox::Status::Accepted);
+    assert_eq!(res[1].result.status, sandbox::Status::Accepted);
+
+    let transcript = res.iter().find_map(|r| r.files.get("a.log")).unwrap();
+    assert_eq!(transcript.context().await.unwrap(), "ping\n".as_bytes());
+  });
+}
+
+#[test]
+fn test_cmd_builder_validate() {
+  assert_eq!(
+    sandbox::CmdBuilder::new(&CONFIG.judge).build().unwrap_err(),
+    sandbox::CmdBuildError::MissingArgs
+  );
+
+  assert_eq!(
+    sandbox::CmdBuilder::new(&CONFIG.judge)
+      .arg("a.out")
+      .time_limit(time::Duration::ZERO)
+      .build()
+      .unwrap_err(),
+    sandbox::CmdBuildError::ZeroLimit("time")
+  );
+
+  assert_eq!(
+    sandbox::CmdBuilder::new(&CONFIG.judge)
+      .arg("a.out")
+      .copy_out("stdout")
+      .copy_out("stdout?")
+      .build()
+      .unwrap_err(),
+    sandbox::CmdBuildError::DuplicateCopyOut("stdout?".to_string())
+  );
+
+  assert_eq!(
+    sandbox::CmdBuilder::new(&CONFIG.judge)
+      .arg("a.out")
+      .clock_limit_ratio(0.5)
+      .build()
+      .unwrap_err(),
+    sandbox::CmdBuildError::ClockLimitRatio(0.5)
+  );
+
+  let cmd = sandbox::CmdBuilder::new(&CONFIG.judge)
+    .args(["a.out", "--flag"])
+    .memory_limit(64 * 1024 * 1024)
+    .build()
+    .unwrap();
+  assert_eq!(cmd.args, vec!["a.out", "--flag"]);
+  assert_eq!(cmd.time_limit, CONFIG.judge.time_limit);
+  assert_eq!(cmd.memory_limit, 64 * 1024 * 1024);
+  assert_eq!(cmd.proc_limit, CONFIG.judge.process_limit);
+}
+
+/// A test for passing the go-judge options of a command to the sandbox.
+#[test]
+fn test_cmd_options() {
+  let req = sandbox::CmdBuilder::new(&CONFIG.judge)
+    .arg("a.out")
+    .time_limit(time::Duration::from_secs(1))
+    .memory_limit(256 * 1024 * 1024)
+    .stack_limit(1024 * 1024 * 1024)
+    .proc_limit(1)
+    .cpu_rate_limit(500)
+    .cpu_set_limit("0-1")
+    .strict_memory_limit(true)
+    .clock_limit_ratio(3.)
+    .build_request()
+    .unwrap()
+    .to_proto_request(0);
+  let cmd = &req.cmd[0];
+  assert_eq!(cmd.clock_time_limit, 3_000_000_000);
+  assert_eq!(cmd.memory_limit, 256 * 1024 * 1024);
+  assert_eq!(cmd.stack_limit, 1024 * 1024 * 1024);
+  assert_eq!(cmd.proc_limit, 1);
+  assert_eq!(cmd.cpu_rate_limit, 500);
+  assert_eq!(cmd.cpu_set_limit, "0-1");
+  assert!(cmd.strict_memory_limit);
+  assert!(!cmd.tty);
+
+  // The stack is limited by the memory limit by default.
+  let req = sandbox::Request::Run(sandbox::Cmd {
+    args: vec!["a.out".to_string()],
+    memory_limit: 64 * 1024 * 1024,
+    ..Default::default()
+  })
+  .to_proto_request(0);
+  assert_eq!(req.cmd[0].stack_limit, 64 * 1024 * 1024);
+
+  // An explicit wall clock time limit overrides the ratio.
+  let req = sandbox::CmdBuilder::new(&CONFIG.judge)
+    .arg("a.out")
+    .time_limit(time::Duration::from_secs(1))
+    .wall_time_limit(time::Duration::from_millis(1500))
+    .build_request()
+    .unwrap()
+    .to_proto_request(0);
+  assert_eq!(req.cmd[0].cpu_time_limit, 1_000_000_000);
+  assert_eq!(req.cmd[0].clock_time_limit, 1_500_000_000);
+  assert_eq!(
+    sandbox::CmdBuilder::new(&CONFIG.judge)
+      .arg("a.out")
+      .wall_time_limit(time::Duration::ZERO)
+      .build()
+      .unwrap_err(),
+    sandbox::CmdBuildError::ZeroLimit("wall time")
+  );
+}
+
+/// A test for uploading files larger than a chunk by streaming.
+#[test]
+fn test_upload_stream() {
+  super::async_test(async {
+    let chunk_size = CONFIG.sandbox.upload_chunk_size;
+    for size in [0, 10, chunk_size, chunk_size * 5 / 2] {
+      let content: Vec<u8> = (0..size).map(|x| (x % 251) as u8).collect();
+      let file = sandbox::FileHandle::upload_stream(content.as_slice())
+        .await
+        .unwrap();
+      assert_eq!(file.context().await.unwrap(), content);
+    }
+  });
+}
+
+/// A test for reading files by streaming, with the size guard.
+#[test]
+fn test_read_stream() {
+  super::async_test(async {
+    let content: Vec<u8> = (0..100_000).map(|x| (x % 251) as u8).collect();
+    let file = sandbox::FileHandle::upload(&content).await.unwrap();
+
+    let mut read = vec![];
+    tokio::io::AsyncReadExt::read_to_end(&mut file.read_stream(), &mut read)
+      .await
+      .unwrap();
+    assert_eq!(read, content);
+
+    assert_eq!(file.context_limited(100_000).await.unwrap(), content);
+    assert!(matches!(
+      file.context_limited(99_999).await,
+      Err(sandbox::FileReadError::TooLarge { max: 99_999, .. })
+    ));
+  });
+}
+
+/// A test for verifying files against their digests computed in the sandbox.
+#[test]
+fn test_verify() {
+  super::async_test(async {
+    let content: Vec<u8> = (0..100_000).map(|x| (x % 251) as u8).collect();
+    let file = sandbox::FileHandle::upload(&content).await.unwrap();
+
+    let digest = file.sandbox_sha256().await.unwrap();
+    assert_eq!(digest, file.sha256().await.unwrap());
+    assert_eq!(digest.len(), 64);
+    file.verify().await.unwrap();
+    // The digest of an uploaded file is known without reading it back.
+    assert_eq!(file.digest().await.unwrap(), digest);
+  });
+}
+
+/// A test for converting gRPC status to recoverable sandbox errors.
+#[test]
+fn test_error_from_status() {
+  assert_eq!(
+    sandbox::Error::from(tonic::Status::unavailable("connection refused")),
+    sandbox::Error::Unavailable("connection refused".to_string())
+  );
+  assert_eq!(
+    sandbox::Error::from(tonic::Status::not_found("file")),
+    sandbox::Error::NotFound("file".to_string())
+  );
+  assert!(matches!(
+    sandbox::Error::from(tonic::Status::invalid_argument("bad request")),
+    sandbox::Error::Rpc(_)
+  ));
+}
+
+/// Hosts of backends never connected, for testing the scheduling.
+fn fake_hosts(n: usize) -> Vec<String> {
+  (0..n).map(|i| format!("http://[::1]:{}", i + 1)).collect()
+}
+
+/// A test for distributing requests over the alive backends in turn.
+#[test]
+fn test_scheduler_round_robin() {
+  super::async_test(async {
+    let scheduler = sandbox::scheduler::Scheduler::new(&fake_hosts(3), 1, etc::Balance::RoundRobin);
+    assert_eq!(
+      (0..4).map(|_| scheduler.pick()).collect::<Vec<_>>(),
+      [0, 1, 2, 0]
+    );
+
+    scheduler.set_alive(1, false);
+    assert!(!scheduler.is_alive(1));
+    assert!((0..4).all(|_| scheduler.pick() != 1));
+
+    // All the backends are scheduled if all of them are dead.
+    (0..3).for_each(|i| scheduler.set_alive(i, false));
+    let mut picked: Vec<_> = (0..3).map(|_| scheduler.pick()).collect();
+    picked.sort();
+    assert_eq!(picked, [0, 1, 2]);
+  });
+}
+
+/// A test for choosing the backend with the fewest running requests.
+#[test]
+fn test_scheduler_least_loaded() {
+  super::async_test(async {
+    let scheduler =
+      sandbox::scheduler::Scheduler::new(&fake_hosts(2), 1, etc::Balance::LeastLoaded);
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+    let (res, picked) = futures::join!(
+      scheduler.run(0, async {
+        rx.await.unwrap();
+        Ok(())
+      }),
+      async {
+        let picked: Vec<_> = (0..3).map(|_| scheduler.pick()).collect();
+        tx.send(()).unwrap();
+        picked
+      }
+    );
+    assert_eq!(res, Ok(()));
+    assert_eq!(picked, [1, 1, 1]);
+  });
+}
+
+/// A test for running requests on the backend holding most of their files.
+#[test]
+fn test_scheduler_place() {
+  super::async_test(async {
+    let scheduler = sandbox::scheduler::Scheduler::new(&fake_hosts(3), 1, etc::Balance::RoundRobin);
+    assert_eq!(scheduler.place(&[], [2, 1, 2]), Ok(2));
+
+    // Files on dead backends are copied to an alive one.
+    scheduler.set_alive(2, false);
+    assert_eq!(scheduler.place(&[], [2, 1, 2]), Ok(1));
+    assert_ne!(scheduler.place(&[], [2]), Ok(2));
+  });
+}
+
+/// A test for keeping the requests of a co-location group on one backend.
+#[test]
+fn test_scheduler_colocation() {
+  super::async_test(async {
+    let scheduler = sandbox::scheduler::Scheduler::new(&fake_hosts(3), 1, etc::Balance::RoundRobin);
+    let group = sandbox::Colocation::new();
+    assert_eq!(group.backend(), None);
+
+    // The first request binds the group to the backend holding most of its files.
+    assert_eq!(scheduler.place_in(&group, &[], [2, 1, 2]), Ok(2));
+    assert_eq!(group.backend(), Some(2));
+
+    // Later requests stay there, wherever their files are and even if the backend is dead.
+    assert_eq!(scheduler.place_in(&group, &[], [0, 0, 1]), Ok(2));
+    scheduler.set_alive(2, false);
+    assert_eq!(scheduler.place_in(&group, &[], [1]), Ok(2));
+
+    // Other groups are placed on their own.
+    assert_eq!(
+      scheduler.place_in(&sandbox::Colocation::new(), &[], [0, 0, 1]),
+      Ok(0)
+    );
+  });
+}
+
+/// A test for placing requests only on the backends having the labels they require.
+#[test]
+fn test_scheduler_labels() {
+  super::async_test(async {
+    let hosts = fake_hosts(3);
+    let labels = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    let scheduler = sandbox::scheduler::Scheduler::new(&hosts, 1, etc::Balance::RoundRobin)
+      .with_labels(&HashMap::from([
+        (hosts[0].clone(), labels(&["fast-cpu"])),
+        (hosts[1].clone(), labels(&["fast-cpu", "with-java"])),
+      ]));
+    let place = |required: &[&str], backends: &[usize]| {
+      scheduler.place(&labels(required), backends.iter().copied())
+    };
+
+    // Files on a backend without the labels are copied to one having them.
+    assert_eq!(place(&["with-java"], &[0, 2, 2]), Ok(1));
+    assert_eq!(place(&["fast-cpu"], &[0, 1, 1]), Ok(1));
+    assert_ne!(place(&["fast-cpu"], &[2]), Ok(2));
+    assert_eq!(place(&[], &[2]), Ok(2));
+
+    assert_eq!(
+      place(&["gpu"], &[0]),
+      Err(sandbox::Error::NoMatchingBackend(labels(&["gpu"])))
+    );
+
+    // A co-location group bound to a backend without the labels fails the request.
+    let group = sandbox::Colocation::new();
+    assert_eq!(scheduler.place_in(&group, &[], [2]), Ok(2));
+    assert!(scheduler
+      .place_in(&group, &labels(&["fast-cpu"]), [2])
+      .is_err());
+  });
+}
+
+/// A test for limiting the timed commands running at the same time on a backend.
+#[test]
+fn test_scheduler_timed_concurrency() {
+  super::async_test(async {
+    let scheduler = sandbox::scheduler::Scheduler::new(&fake_hosts(2), 1, etc::Balance::RoundRobin)
+      .with_timed_concurrency(1);
+    let wait = time::Duration::from_millis(50);
+    let slot = scheduler.acquire_timed(0).await;
+    assert!(slot.is_some());
+    assert!(tokio::time::timeout(wait, scheduler.acquire_timed(0))
+      .await
+      .is_err());
+    // Other backends have their own slots.
+    assert!(scheduler.acquire_timed(1).await.is_some());
+    drop(slot);
+    assert!(tokio::time::timeout(wait, scheduler.acquire_timed(0))
+      .await
+      .unwrap()
+      .is_some());
+
+    let scheduler = sandbox::scheduler::Scheduler::new(&fake_hosts(1), 1, etc::Balance::RoundRobin);
+    assert!(scheduler.acquire_timed(0).await.is_none());
+  });
+}
+
+/// A test for pinning the commands to distinct CPUs of a backend.
+#[test]
+fn test_scheduler_pin_cpus() {
+  super::async_test(async {
+    let scheduler = sandbox::scheduler::Scheduler::new(&fake_hosts(2), 1, etc::Balance::RoundRobin);
+    let wait = time::Duration::from_millis(50);
+    let pinned = scheduler
+      .pin_cpus(0, &[Some(vec![0, 2]), None, Some(vec![0, 2])])
+      .await;
+    assert_eq!(pinned.cpus, vec![Some(0), None, Some(2)]);
+    assert!(
+      tokio::time::timeout(wait, scheduler.pin_cpus(0, &[Some(vec![2])]))
+        .await
+        .is_err()
+    );
+    // Other backends have their own CPUs.
+    assert_eq!(
+      scheduler.pin_cpus(1, &[Some(vec![2])]).await.cpus,
+      vec![Some(2)]
+    );
+    let cpus = [Some(vec![0, 2]), Some(vec![0, 2])];
+    let waiting = scheduler.pin_cpus(0, &cpus);
+    tokio::pin!(waiting);
+    assert!(tokio::time::timeout(wait, &mut waiting).await.is_err());
+    drop(pinned);
+    assert_eq!(
+      tokio::time::timeout(wait, waiting).await.unwrap().cpus,
+      vec![Some(0), Some(2)]
+    );
+  });
+}
+
+/// A test for parsing cpusets and checking the CPU options of commands.
+#[test]
+fn test_cpu_set() {
+  assert_eq!(sandbox::parse_cpu_set(""), Some(vec![]));
+  assert_eq!(sandbox::parse_cpu_set("0-3,6"), Some(vec![0, 1, 2, 3, 6]));
+  assert_eq!(sandbox::parse_cpu_set(" 6, 2-3 ,3"), Some(vec![2, 3, 6]));
+  assert_eq!(sandbox::parse_cpu_set("3-1"), None);
+  assert_eq!(sandbox::parse_cpu_set("a"), None);
+
+  let builder = || {
+    sandbox::CmdBuilder::new(&CONFIG.judge)
+      .arg("a.out")
+      .cpu_set_limit("")
+  };
+  assert!(builder().build().is_ok());
+  assert!(builder().cpu_set_limit("0,2").pin_cpu(true).build().is_ok());
+  assert_eq!(
+    builder().pin_cpu(true).build().unwrap_err(),
+    sandbox::CmdBuildError::InvalidCpuSet(String::new())
+  );
+  assert_eq!(
+    builder().cpu_set_limit("1-").build().unwrap_err(),
+    sandbox::CmdBuildError::InvalidCpuSet("1-".to_string())
+  );
+}
+
+/// A test for listing the sandbox hosts without duplicates.
+#[test]
+fn test_sandbox_hosts() {
+  let conf = etc::SandboxCfg {
+    host: "http://a".to_string(),
+    hosts: vec!["http://b".to_string(), "http://a".to_string()],
+    ..CONFIG.sandbox.clone()
+  };
+  assert_eq!(conf.hosts(), ["http://a", "http://b"]);
+}
+
+/// A test for the WebSocket handshake key, by the example of RFC 6455.
+#[test]
+fn test_websocket_accept_key() {
+  assert_eq!(
+    sandbox::websocket::accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+    "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+  );
+}
+
+/// A test for writing and reading WebSocket frames of all the length encodings,
+/// and joining a fragmented message around a ping.
+#[test]
+fn test_websocket_frames() {
+  use sandbox::websocket::{self, MessageReader};
+  use tokio::io::AsyncWriteExt;
+
+  super::async_test(async {
+    let (mut client, server) = tokio::io::duplex(1 << 20);
+    let mut reader = MessageReader::new(server);
+    for len in [0, 5, 200, 70000] {
+      let payload: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+      websocket::write_frame(&mut client, websocket::OP_TEXT, &payload, [1, 2, 3, 4])
+        .await
+        .unwrap();
+      assert_eq!(
+        reader.read_message().await.unwrap(),
+        (websocket::OP_TEXT, payload)
+      );
+    }
+
+    // Unmasked fragments as a server sends: "ab", ping, "c".
+    client.write_all(&[websocket::OP_BINARY, 2]).await.unwrap();
+    client.write_all(b"ab").await.unwrap();
+    client
+      .write_all(&[0x80 | websocket::OP_PING, 0])
+      .await
+      .unwrap();
+    client
+      .write_all(&[0x80 | websocket::OP_CONTINUATION, 1, b'c'])
+      .await
+      .unwrap();
+    assert_eq!(
+      reader.read_message().await.unwrap(),
+      (websocket::OP_PING, vec![])
+    );
+    assert_eq!(
+      reader.read_message().await.unwrap(),
+      (websocket::OP_BINARY, b"abc".to_vec())
+    );
+
+    client
+      .write_all(&[0x80 | websocket::OP_CONTINUATION, 0])
+      .await
+      .unwrap();
+    assert!(reader.read_message().await.is_err());
+  });
+}
+
+/// A test for converting requests and responses to the JSON model of go-judge.
+#[test]
+fn test_websocket_json() {
+  use sandbox::{proto, websocket};
+
+  let req = sandbox::Request::Run(sandbox::Cmd {
+    args: vec!["/bin/cat".to_string()],
+    copy_out: vec!["out.txt".to_string()],
+    ..Default::default()
+  })
+  .to_proto_request(0);
+  let json =
+    serde_json::to_value(websocket::JsonRequest::new("1".to_string(), req).unwrap()).unwrap();
+  assert_eq!(json["requestId"], "1");
+  assert_eq!(json["cmd"][0]["args"], serde_json::json!(["/bin/cat"]));
+  assert_eq!(
+    json["cmd"][0]["copyOutCached"],
+    serde_json::json!(["out.txt"])
+  );
+
+  let res: websocket::JsonResponse = serde_json::from_value(serde_json::json!({
+    "requestId": "1",
+    "results": [{
+      "status": "Time Limit Exceeded",
+      "exitStatus": 0,
+      "time": 1000,
+      "memory": 2048,
+      "files": {"stdout": "hello"},
+    }],
+  }))
+  .unwrap();
+  let res = proto::Response::from(res);
+  assert_eq!(res.request_id, "1");
+  assert_eq!(
+    res.results[0].status,
+    proto::response::result::StatusType::TimeLimitExceeded as i32
+  );
+  assert_eq!(res.results[0].memory, 2048);
+  assert_eq!(res.results[0].files["stdout"], b"hello");
+}
+
+/// A test for cancelling a request before it runs.
+#[test]
+fn test_exec_cancelled() {
+  super::async_test(async {
+    let cancel = sandbox::CancellationToken::new();
+    cancel.cancel();
+    let res = sandbox::Request::Run(sandbox::Cmd {
+      args: vec!["/bin/sleep".to_string(), "10".to_string()],
+      ..Default::default()
+    })
+    .try_exec_cancellable(&cancel)
+    .await;
+    assert_eq!(res.unwrap_err(), sandbox::Error::Cancelled);
+  });
+}
+
+/// A test for a program sleeping beyond the wall clock time limit.
+#[test]
+fn test_idleness_limit() {
+  super::async_test(async {
+    let res = sandbox::Request::Run(sandbox::Cmd {
+      args: vec!["/bin/sleep".to_string(), "10".to_string()],
+      time_limit: time::Duration::from_secs(1),
+      wall_time_limit: Some(time::Duration::from_millis(500)),
+      ..Default::default()
+    })
+    .exec()
+    .await[0]
+      .clone();
+    assert_eq!(res.result.status, sandbox::Status::IdlenessLimitExceeded);
+    assert!(res.result.time < time::Duration::from_secs(1));
+    assert!(res.result.wall_time >= time::Duration::from_millis(500));
+  });
+}
@@ -0,0 +1,56 @@
+use crate::{problem, program, record, sandbox, schema};
+
+#[test]
+fn test_encode() {
+  let correlation_id = uuid::Uuid::new_v4();
+  let record = record::Record::new_skipped(record::SkipReason::Dependency);
+  let event = problem::Response::SubtaskFinished {
+    correlation_id,
+    timestamp: record::Timestamp::now(),
+    subtask: 0,
+    score: 0.,
+    records: vec![record.clone()],
+  };
+
+  let current = schema::encode(&event, schema::VERSION).unwrap().unwrap();
+  assert_eq!(current["schema_version"], schema::VERSION);
+  assert_eq!(current["records"][0]["skip_reason"], "dependency");
+  assert!(current["records"][0].get("timing").is_some());
+
+  let v1 = schema::encode(&event, 1).unwrap().unwrap();
+  assert!(v1.get("schema_version").is_none());
+  assert!(v1["records"][0].get("skip_reason").is_none());
+  assert!(v1["records"][0].get("timing").is_none());
+  assert_eq!(v1["records"][0]["status"], current["records"][0]["status"]);
+
+  let v1 = schema::encode(
+    &problem::Response::CompleteOne {
+      correlation_id,
+      timestamp: record::Timestamp::now(),
+      record,
+    },
+    1,
+  )
+  .unwrap()
+  .unwrap();
+  assert_eq!(v1["type"], "CompleteOne");
+  assert!(v1["record"].get("skip_reason").is_none());
+
+  // Events added after a version are not sent in it.
+  let event = problem::Response::CompileError {
+    correlation_id,
+    timestamp: record::Timestamp::now(),
+    program: problem::CompileTarget::Solution,
+    message: "error".to_string(),
+    result: program::CompileResult::from(&sandbox::ExecuteResult::internal_error()),
+  };
+  assert!(schema::encode(&event, schema::VERSION).unwrap().is_some());
+  assert_eq!(schema::encode(&event, 1), Ok(None));
+
+  for version in [0, schema::VERSION + 1] {
+    assert_eq!(
+      schema::encode(&event, version),
+      Err(schema::UnsupportedVersion(version))
+    );
+  }
+}
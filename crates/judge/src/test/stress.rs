@@ -0,0 +1,75 @@
+use std::{collections::HashMap, str::FromStr, time};
+
+use crate::{builtin, data, lang, program, record, sandbox, stress};
+
+#[test]
+fn test_find_counterexample() {
+  super::async_test(async {
+    let user_copy_in = HashMap::from([(
+      "testlib.h".to_string(),
+      sandbox::FileHandle::upload(
+        builtin::File::from_str("testlib:testlib.h")
+          .unwrap()
+          .as_bytes(),
+      )
+      .await
+      .unwrap(),
+    )]);
+
+    let compile = |lang: &str, code: &str| {
+      let src = program::Source {
+        lang: lang::Lang::from_str(lang).unwrap(),
+        data: data::Provider::Memory(code.as_bytes().to_vec()),
+      };
+      let user_copy_in = user_copy_in.clone();
+      async move { src.compile(vec![], user_copy_in).await.unwrap() }
+    };
+
+    let stress = stress::Stress {
+      generator: compile(
+        "cpp",
+        "
+        #include\"testlib.h\"
+        #include<iostream>
+        signed main(signed argc,char**argv){
+          registerGen(argc,argv,1);
+          std::cout<<rnd.next(0,3)<<' '<<rnd.next(0,3)<<'\\n';
+        }
+        ",
+      )
+      .await
+      .into(),
+      args: vec![],
+      validator: None,
+      reference: compile(
+        "c",
+        "#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a+b);}",
+      )
+      .await,
+      candidate: compile(
+        "c",
+        "#include<stdio.h>\nint main(){int a,b;scanf(\"%d%d\",&a,&b);printf(\"%d\\n\",a==b?0:a+b);}",
+      )
+      .await,
+      checker: compile("cpp", std::str::from_utf8(
+        builtin::File::from_str("checker:ncmp.cpp").unwrap().as_bytes(),
+      ).unwrap())
+      .await
+      .into(),
+      time_limit: time::Duration::from_secs(1),
+      memory_limit: 64 * 1024 * 1024,
+      user_copy_in: user_copy_in.clone(),
+      judge_copy_in: HashMap::new(),
+    };
+
+    match stress.run(100, 1).await.unwrap() {
+      stress::Outcome::Found(counterexample) => {
+        assert_eq!(
+          counterexample.record.status,
+          record::RecordStatus::WrongAnswer
+        );
+      }
+      stress::Outcome::Passed { .. } => panic!("counterexample should be found"),
+    }
+  });
+}
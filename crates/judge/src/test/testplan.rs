@@ -0,0 +1,107 @@
+use std::{collections::HashMap, str::FromStr};
+
+use crate::{builtin, data, generator, lang, problem::testplan, program, sandbox};
+
+#[test]
+fn test_parse() {
+  let plan = testplan::TestPlan::from_str(
+    "
+    # small tests
+    gen 1 5
+    gen 1 5 > $
+
+    gen --n 1000
+    ",
+  )
+  .unwrap();
+
+  assert_eq!(plan.commands.len(), 3);
+  assert_eq!(plan.commands[0].generator, "gen");
+  assert_eq!(plan.commands[0].args, ["1", "5"]);
+  assert_eq!(plan.commands[1].args, ["1", "5"]);
+  assert_eq!(plan.commands[2].args, ["--n", "1000"]);
+  assert_eq!(plan.generators(), ["gen"].into());
+
+  // The same command repeated gets a different seed.
+  assert_ne!(plan.commands[0].seed, plan.commands[1].seed);
+  assert_eq!(
+    plan.commands[1].args().last().unwrap(),
+    &plan.commands[1].seed.to_string()
+  );
+
+  // Seeds do not depend on other lines.
+  let other = testplan::TestPlan::from_str("gen --n 1000\ngen 1 5\ngen 2 5\ngen 1 5").unwrap();
+  assert_eq!(other.commands[0].seed, plan.commands[2].seed);
+  assert_eq!(other.commands[1].seed, plan.commands[0].seed);
+  assert_eq!(other.commands[3].seed, plan.commands[1].seed);
+
+  // The script is kept by serde, and so are the seeds.
+  let json = serde_json::to_string(&plan).unwrap();
+  assert_eq!(json, r#""gen 1 5\ngen 1 5\ngen --n 1000""#);
+  assert_eq!(
+    serde_json::from_str::<testplan::TestPlan>(&json).unwrap(),
+    plan
+  );
+
+  assert!(matches!(
+    testplan::TestPlan::from_str("gen 1\ngen 2 > 3"),
+    Err(testplan::TestPlanError::Parse { line: 2, .. })
+  ));
+  assert!(matches!(
+    testplan::TestPlan::from_str("gen 1")
+      .unwrap()
+      .expand(&HashMap::new()),
+    Err(testplan::TestPlanError::UnknownGenerator(x)) if x == "gen"
+  ));
+}
+
+#[test]
+fn test_generate_all() {
+  super::async_test(async {
+    let src = program::Source {
+      lang: lang::Lang::from_str("cpp").unwrap(),
+      data: data::Provider::Memory(
+        "
+        #include\"testlib.h\"
+        #include<iostream>
+        signed main(signed argc,char**argv){
+          registerGen(argc,argv,1);
+          int n=opt<int>(1);
+          std::cout<<n<<' '<<rnd.next(1,1000000000)<<'\\n';
+        }
+        "
+        .as_bytes()
+        .to_vec(),
+      ),
+    };
+    let copy_in = HashMap::from([(
+      "testlib.h".to_string(),
+      sandbox::FileHandle::upload(
+        builtin::File::from_str("testlib:testlib.h")
+          .unwrap()
+          .as_bytes(),
+      )
+      .await
+      .unwrap(),
+    )]);
+    let gen = generator::Generator::from(src.compile(vec![], copy_in.clone()).await.unwrap());
+
+    let plan = testplan::TestPlan::from_str("gen 1\ngen 2\ngen 2").unwrap();
+    let dir = std::env::temp_dir().join(format!("rindag-testplan-{}", uuid::Uuid::new_v4()));
+    let paths = plan
+      .generate_all(&HashMap::from([("gen".to_string(), gen)]), &copy_in, &dir)
+      .await
+      .unwrap();
+
+    assert_eq!(
+      paths,
+      [dir.join("1.in"), dir.join("2.in"), dir.join("3.in")]
+    );
+    let read = |i: usize| std::fs::read_to_string(&paths[i]).unwrap();
+    assert!(read(0).starts_with("1 "));
+    assert!(read(1).starts_with("2 "));
+    assert_ne!(read(1), read(2));
+
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+  });
+}
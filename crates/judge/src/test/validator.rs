@@ -1,6 +1,6 @@
 use std::{collections::HashMap, str::FromStr};
 
-use crate::{builtin, data, lang, program, sandbox, validator};
+use crate::{builtin, data, lang, program, sandbox, validator, CONFIG};
 
 #[test]
 fn test_val_a_plus_b() {
@@ -41,7 +41,8 @@ fn test_val_a_plus_b() {
                 .unwrap()
                 .as_bytes(),
             )
-            .await,
+            .await
+            .unwrap(),
           )]
           .into(),
         )
@@ -53,30 +54,36 @@ fn test_val_a_plus_b() {
       val
         .validate(
           vec!["--group".to_string(), "even_a_and_b".to_string()],
-          sandbox::FileHandle::upload("0 -10\n".as_bytes()).await,
+          sandbox::FileHandle::upload("0 -10\n".as_bytes())
+            .await
+            .unwrap(),
           HashMap::new(),
         )
         .await
-        .unwrap(),
-      validator::Overview {
-        variables: [
-          (
-            "a".to_string(),
-            validator::VariableBounds {
-              hit_min: false,
-              hit_max: false
-            }
-          ),
-          (
-            "b".to_string(),
-            validator::VariableBounds {
-              hit_min: false,
-              hit_max: false
-            }
-          ),
-        ]
-        .into(),
-        features: [("sum_0".to_string(), false)].into(),
+        .unwrap()
+        .output,
+      validator::Validation {
+        overview: validator::Overview {
+          variables: [
+            (
+              "a".to_string(),
+              validator::VariableBounds {
+                hit_min: false,
+                hit_max: false
+              }
+            ),
+            (
+              "b".to_string(),
+              validator::VariableBounds {
+                hit_min: false,
+                hit_max: false
+              }
+            ),
+          ]
+          .into(),
+          features: [("sum_0".to_string(), false)].into(),
+        },
+        warnings: vec![],
       }
     );
 
@@ -84,49 +91,194 @@ fn test_val_a_plus_b() {
       val
         .validate(
           vec![],
-          sandbox::FileHandle::upload("-100 100\n".as_bytes()).await,
+          sandbox::FileHandle::upload("-100 100\n".as_bytes())
+            .await
+            .unwrap(),
           HashMap::new(),
         )
         .await
-        .unwrap(),
-      validator::Overview {
-        variables: [
-          (
-            "a".to_string(),
-            validator::VariableBounds {
-              hit_min: true,
-              hit_max: false
-            }
-          ),
-          (
-            "b".to_string(),
-            validator::VariableBounds {
-              hit_min: false,
-              hit_max: true
-            }
-          ),
-        ]
-        .into(),
-        features: [("sum_0".to_string(), true)].into(),
+        .unwrap()
+        .output,
+      validator::Validation {
+        overview: validator::Overview {
+          variables: [
+            (
+              "a".to_string(),
+              validator::VariableBounds {
+                hit_min: true,
+                hit_max: false
+              }
+            ),
+            (
+              "b".to_string(),
+              validator::VariableBounds {
+                hit_min: false,
+                hit_max: true
+              }
+            ),
+          ]
+          .into(),
+          features: [("sum_0".to_string(), true)].into(),
+        },
+        warnings: vec![],
       }
     );
 
-    assert!(val
+    let err = val
       .validate(
         vec![],
-        sandbox::FileHandle::upload("-100 101\n".as_bytes()).await,
+        sandbox::FileHandle::upload("-100 101\n".as_bytes())
+          .await
+          .unwrap(),
         HashMap::new(),
       )
       .await
-      .is_err());
+      .unwrap_err();
+    assert_eq!(err.result.status, sandbox::Status::NonZeroExitStatus);
+    assert!(err.message.contains("b"));
+    assert!(validator::is_rejection(&err));
+    assert_eq!(err.rejection.unwrap().line, Some(1));
 
     assert!(val
       .validate(
         vec!["--group".to_string(), "even_a_and_b".to_string()],
-        sandbox::FileHandle::upload("1 2\n".as_bytes()).await,
+        sandbox::FileHandle::upload("1 2\n".as_bytes())
+          .await
+          .unwrap(),
         HashMap::new(),
       )
       .await
       .is_err());
+
+    let deadline = std::time::Duration::from_secs(2);
+    assert_eq!(
+      val
+        .validate_hack(vec![], "1 2\n".as_bytes(), HashMap::new(), deadline)
+        .await,
+      validator::HackVerdict::Valid
+    );
+    assert!(matches!(
+      val
+        .validate_hack(vec![], "1 200\n".as_bytes(), HashMap::new(), deadline)
+        .await,
+      validator::HackVerdict::Invalid { message } if message.contains("b")
+    ));
   });
 }
+
+/// A test for the warnings written by a validator accepting an input.
+#[test]
+fn test_validator_warnings() {
+  super::async_test(async {
+    let testlib = sandbox::FileHandle::upload(
+      builtin::File::from_str("testlib:testlib.h")
+        .unwrap()
+        .as_bytes(),
+    )
+    .await
+    .unwrap();
+    let val = validator::Validator::from(
+      program::Source {
+        lang: lang::Lang::from_str("cpp").unwrap(),
+        data: data::Provider::Memory(
+          "
+          #include\"testlib.h\"
+          signed main(signed argc,char**argv){
+            registerValidation(argc,argv);
+            int n=inf.readInt(1,100,\"n\");
+            inf.readEoln();
+            inf.readEof();
+            if(n>50)std::cerr<<\"n is larger than expected\"<<std::endl;
+          }
+          "
+          .as_bytes()
+          .to_vec(),
+        ),
+      }
+      .compile(vec![], [("testlib.h".to_string(), testlib)].into())
+      .await
+      .unwrap(),
+    );
+    let validate = |input: &'static str| {
+      let val = val.clone();
+      async move {
+        val
+          .validate(
+            vec![],
+            sandbox::FileHandle::upload(input.as_bytes()).await.unwrap(),
+            HashMap::new(),
+          )
+          .await
+      }
+    };
+
+    assert!(validate("10\n").await.unwrap().output.warnings.is_empty());
+    assert_eq!(
+      validate("60\n").await.unwrap().output.warnings,
+      vec!["n is larger than expected".to_string()]
+    );
+    let err = validate("200\n").await.unwrap_err();
+    assert!(validator::is_rejection(&err));
+  });
+}
+
+/// A test for a validator running out of time, whose execution result is reported in the error.
+#[test]
+fn test_validator_time_limit() {
+  super::async_test(async {
+    let val = validator::Validator::from(
+      program::Source {
+        lang: lang::Lang::from_str("c").unwrap(),
+        data: data::Provider::Memory(
+          "int main(){for(volatile int i=0;;i++);}"
+            .as_bytes()
+            .to_vec(),
+        ),
+      }
+      .compile(vec![], HashMap::new())
+      .await
+      .unwrap(),
+    );
+    let err = val
+      .validate(
+        vec![],
+        sandbox::FileHandle::upload("1".as_bytes()).await.unwrap(),
+        HashMap::new(),
+      )
+      .await
+      .unwrap_err();
+    assert_eq!(err.result.status, sandbox::Status::TimeLimitExceeded);
+    assert!(err.result.time >= CONFIG.judge.time_limit);
+  });
+}
+
+#[test]
+fn test_parse_rejection() {
+  assert_eq!(
+    validator::Rejection::parse(
+      "FAIL Integer parameter [name=n] equals to 0, violates the range [1, 100] (stdin, line 3)"
+    ),
+    Some(validator::Rejection {
+      line: Some(3),
+      column: None,
+      message: "Integer parameter [name=n] equals to 0, violates the range [1, 100]".to_string(),
+    })
+  );
+  assert_eq!(
+    validator::Rejection::parse("warning\nFAIL Expected EOLN (stdin, line 2, column 5)\n"),
+    Some(validator::Rejection {
+      line: Some(2),
+      column: Some(5),
+      message: "Expected EOLN".to_string(),
+    })
+  );
+  assert_eq!(
+    validator::Rejection::parse("FAIL n is odd"),
+    Some(validator::Rejection {
+      line: None,
+      column: None,
+      message: "n is odd".to_string(),
+    })
+  );
+  assert_eq!(validator::Rejection::parse("Segmentation fault"), None);
+}
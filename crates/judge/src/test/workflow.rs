@@ -0,0 +1,466 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{sandbox, workflow};
+
+/// A task writing fake files without the sandbox, for testing the executor.
+#[derive(Debug, Serialize, Deserialize)]
+struct FakeTask {
+  #[serde(default)]
+  inputs: Vec<String>,
+
+  #[serde(default)]
+  outputs: Vec<String>,
+
+  #[serde(default)]
+  fail: bool,
+
+  /// Time to sleep before finishing.
+  #[serde(default)]
+  sleep_ms: u64,
+
+  /// Finish without writing the outputs, like a buggy task.
+  #[serde(default)]
+  skip: bool,
+}
+
+#[typetag::serde(name = "fake")]
+#[async_trait]
+impl workflow::Task for FakeTask {
+  fn inputs(&self) -> Vec<String> {
+    return self.inputs.clone();
+  }
+
+  fn outputs(&self) -> Vec<String> {
+    return self.outputs.clone();
+  }
+
+  async fn run(&self, _inputs: workflow::Files) -> Result<workflow::Files, workflow::TaskError> {
+    tokio::time::sleep(std::time::Duration::from_millis(self.sleep_ms)).await;
+    if self.fail {
+      return Err(sandbox::Error::Rpc("fake task failed".to_string()).into());
+    }
+    if self.skip {
+      return Ok(workflow::Files::new());
+    }
+    return Ok(
+      self
+        .outputs
+        .iter()
+        .map(|x| (x.clone(), sandbox::FileHandle::from_id(0, x.clone())))
+        .collect(),
+    );
+  }
+}
+
+/// Run a workflow until it ends, and return all its responses.
+async fn exec(json: &str) -> Vec<workflow::Response> {
+  return workflow::Workflow::parse(json)
+    .unwrap()
+    .exec()
+    .unwrap()
+    .0
+    .collect()
+    .await;
+}
+
+/// A test for rejecting workflows writing a file twice, reading a file never written,
+/// or having a cycle.
+#[test]
+fn test_parse() {
+  let workflow = workflow::Workflow::parse(
+    r#"{
+      "tasks": [
+        {
+          "type": "compile",
+          "lang": "cpp",
+          "source": "gen.cpp",
+          "copy_in": { "testlib.h": "testlib.h" },
+          "exec": "gen"
+        },
+        { "type": "generate", "generator": { "lang": "cpp", "file": "gen" }, "output": "1.in" },
+        {
+          "type": "judge_batch",
+          "solution": { "lang": "cpp", "file": "gen" },
+          "input": "1.in",
+          "time_limit_ms": 1000,
+          "memory_limit_mb": 256,
+          "output": "1.out"
+        },
+        {
+          "type": "check",
+          "checker": { "lang": "cpp", "file": "gen" },
+          "input": "1.in",
+          "output": "1.out",
+          "answer": "1.in",
+          "report": "1.report"
+        }
+      ],
+      "copy_in": { "gen.cpp": [105, 110, 116], "testlib.h": "testlib:testlib.h" },
+      "copy_out": ["1.in", "1.out"]
+    }"#,
+  )
+  .unwrap();
+  assert_eq!(workflow.tasks.len(), 4);
+  assert_eq!(workflow.tasks[0].inputs(), ["gen.cpp", "testlib.h"]);
+  assert_eq!(workflow.tasks[2].inputs(), ["gen", "1.in"]);
+  assert_eq!(workflow.tasks[3].inputs(), ["gen", "1.in", "1.out", "1.in"]);
+  assert_eq!(workflow.tasks[3].outputs(), ["1.report"]);
+
+  let workflow = workflow::Workflow::parse(
+    r#"{
+      "tasks": [
+        {
+          "type": "interact",
+          "interactor": { "lang": "cpp", "file": "interactor" },
+          "solution": { "lang": "cpp", "file": "std" },
+          "input": "1.in",
+          "interactor_copy_in": { "extra.txt": "extra" },
+          "time_limit_ms": 1000,
+          "memory_limit_mb": 256,
+          "verdict": "1.verdict",
+          "pipes": { "interactor": "1.transcript" }
+        }
+      ],
+      "copy_in": { "interactor": [], "std": [], "1.in": [], "extra": [] },
+      "copy_out": ["1.verdict", "1.transcript"]
+    }"#,
+  )
+  .unwrap();
+  assert_eq!(
+    workflow.tasks[0].inputs(),
+    ["interactor", "std", "1.in", "extra"]
+  );
+  assert_eq!(workflow.tasks[0].outputs(), ["1.verdict", "1.transcript"]);
+
+  let workflow = workflow::Workflow::parse(
+    r#"{
+      "tasks": [
+        {
+          "type": "stress",
+          "generator": { "lang": "cpp", "file": "gen" },
+          "validator": { "lang": "cpp", "file": "val" },
+          "reference": { "lang": "cpp", "file": "brute" },
+          "candidate": { "lang": "cpp", "file": "std" },
+          "checker": { "lang": "cpp", "file": "chk" },
+          "time_limit_ms": 1000,
+          "memory_limit_mb": 256,
+          "rounds": 100,
+          "report": "stress.report",
+          "failing": { "input": "hack.in", "answer": "hack.ans", "output": "hack.out" }
+        }
+      ],
+      "copy_in": { "gen": [], "val": [], "brute": [], "std": [], "chk": [] },
+      "copy_out": ["stress.report", "hack.in"]
+    }"#,
+  )
+  .unwrap();
+  assert_eq!(
+    workflow.tasks[0].inputs(),
+    ["gen", "val", "brute", "std", "chk"]
+  );
+  assert_eq!(
+    workflow.tasks[0].outputs(),
+    ["stress.report", "hack.in", "hack.ans", "hack.out"]
+  );
+
+  let workflow = workflow::Workflow::parse(
+    r#"{
+      "tasks": [
+        {
+          "type": "verify_answers",
+          "standard_solution": { "lang": "cpp", "file": "std" },
+          "checker": { "lang": "cpp", "file": "chk" },
+          "tests": [
+            { "input": "1.in", "answer": "1.ans" },
+            { "input": "2.in", "answer": "2.ans" }
+          ],
+          "time_limit_ms": 1000,
+          "memory_limit_mb": 256,
+          "report": "answers.report"
+        }
+      ],
+      "copy_in": { "std": [], "chk": [], "1.in": [], "1.ans": [], "2.in": [], "2.ans": [] },
+      "copy_out": ["answers.report"]
+    }"#,
+  )
+  .unwrap();
+  assert_eq!(
+    workflow.tasks[0].inputs(),
+    ["std", "chk", "1.in", "1.ans", "2.in", "2.ans"]
+  );
+
+  let workflow = workflow::Workflow::parse(
+    r#"{
+      "tasks": [
+        {
+          "type": "test_plan",
+          "plan": "gen 1 5\ngen 1 5 > $\n# large\ngen --n 1000",
+          "generators": { "gen": { "lang": "cpp", "file": "gen" } },
+          "prefix": "tests/"
+        }
+      ],
+      "copy_in": { "gen": [] },
+      "copy_out": ["tests/1.in", "tests/3.in"]
+    }"#,
+  )
+  .unwrap();
+  assert_eq!(workflow.tasks[0].inputs(), ["gen"]);
+  assert_eq!(
+    workflow.tasks[0].outputs(),
+    ["tests/1.in", "tests/2.in", "tests/3.in"]
+  );
+  assert!(matches!(
+    workflow::Workflow::parse(
+      r#"{ "tasks": [{ "type": "test_plan", "plan": "gen > 1", "generators": {} }] }"#
+    ),
+    Err(workflow::ParseError::Json(_))
+  ));
+
+  assert!(matches!(
+    workflow::Workflow::parse(
+      r#"{ "tasks": [
+        { "type": "fake", "outputs": ["a"] },
+        { "type": "fake", "outputs": ["a"] }
+      ] }"#
+    ),
+    Err(workflow::ParseError::DuplicateFile(x)) if x == "a"
+  ));
+  assert!(matches!(
+    workflow::Workflow::parse(r#"{ "tasks": [{ "type": "fake", "inputs": ["a"] }] }"#),
+    Err(workflow::ParseError::UnknownFile(x)) if x == "a"
+  ));
+  assert!(matches!(
+    workflow::Workflow::parse(r#"{ "tasks": [], "copy_out": ["a"] }"#),
+    Err(workflow::ParseError::UnknownFile(x)) if x == "a"
+  ));
+  assert!(matches!(
+    workflow::Workflow::parse(
+      r#"{ "tasks": [
+        { "type": "fake", "outputs": ["x"] },
+        { "type": "fake", "inputs": ["x", "c"], "outputs": ["a"] },
+        { "type": "fake", "inputs": ["a"], "outputs": ["b"] },
+        { "type": "fake", "inputs": ["b"], "outputs": ["c"] }
+      ] }"#
+    ),
+    Err(workflow::ParseError::Cycle(x)) if x == [1, 3, 2]
+  ));
+  assert!(matches!(
+    workflow::Workflow::parse(r#"{ "tasks": [{ "type": "fake", "inputs": ["a"], "outputs": ["a"] }] }"#),
+    Err(workflow::ParseError::Cycle(x)) if x == [0]
+  ));
+  assert!(matches!(
+    workflow::Workflow::parse(r#"{ "tasks": [{ "type": "unknown" }] }"#),
+    Err(workflow::ParseError::Json(_))
+  ));
+}
+
+/// A test for delivering the files written before and besides a failed task.
+#[test]
+fn test_exec_partial() {
+  super::async_test(async {
+    let tasks = r#"
+      "tasks": [
+        { "type": "fake", "outputs": ["a"] },
+        { "type": "fake", "inputs": ["a"], "outputs": ["b"] },
+        { "type": "fake", "inputs": ["a"], "outputs": ["c"], "fail": true },
+        { "type": "fake", "inputs": ["c"], "outputs": ["d"] }
+      ],
+      "copy_out": ["b", "c", "d"]
+    "#;
+
+    let responses = exec(&format!("{{ {}, \"partial\": true }}", tasks)).await;
+    match responses.last().unwrap() {
+      workflow::Response::FinishedPartial { files, failures } => {
+        assert_eq!(files.len(), 1);
+        assert!(files.contains_key("b"));
+        assert_eq!(failures.len(), 2);
+        assert!(matches!(failures[0], (2, workflow::TaskError::Sandbox(_))));
+        assert!(matches!(&failures[1], (3, workflow::TaskError::Input(x)) if x == "c"));
+      }
+      x => panic!("unexpected response {:?}", x),
+    }
+    let finished = responses
+      .iter()
+      .filter(|x| matches!(x, workflow::Response::TaskFinished { .. }))
+      .count();
+    assert_eq!(finished, 2);
+
+    let responses = exec(&format!("{{ {} }}", tasks)).await;
+    assert!(matches!(
+      responses.last().unwrap(),
+      workflow::Response::Failed { task: Some(2), .. }
+    ));
+
+    let responses =
+      exec(r#"{ "tasks": [{ "type": "fake", "outputs": ["a"] }], "copy_out": ["a"] }"#).await;
+    assert!(matches!(
+      responses.last().unwrap(),
+      workflow::Response::Finished { files } if files.contains_key("a")
+    ));
+  });
+}
+
+/// A test for failing the tasks running longer than the task timeout,
+/// and the tasks reading their files.
+#[test]
+fn test_exec_task_timeout() {
+  super::async_test(async {
+    let responses = exec(
+      r#"{
+        "tasks": [
+          { "type": "fake", "outputs": ["a"], "sleep_ms": 10000 },
+          { "type": "fake", "inputs": ["a"], "outputs": ["b"] },
+          { "type": "fake", "outputs": ["c"] }
+        ],
+        "copy_out": ["b", "c"],
+        "partial": true,
+        "task_timeout_ms": 100
+      }"#,
+    )
+    .await;
+    match responses.last().unwrap() {
+      workflow::Response::FinishedPartial { files, failures } => {
+        assert_eq!(files.len(), 1);
+        assert!(files.contains_key("c"));
+        assert_eq!(failures.len(), 2);
+        assert!(matches!(
+          &failures[0],
+          (0, workflow::TaskError::Timeout { waiting: None })
+        ));
+        assert!(matches!(
+          &failures[1],
+          (1, workflow::TaskError::Timeout { waiting: Some(x) }) if x == "a"
+        ));
+      }
+      x => panic!("unexpected response {:?}", x),
+    }
+
+    // The timeout counts from the start of each task, so the tasks starting late finish.
+    let responses = exec(
+      r#"{
+        "tasks": [
+          { "type": "fake", "outputs": ["a"], "sleep_ms": 60 },
+          { "type": "fake", "inputs": ["a"], "outputs": ["b"], "sleep_ms": 60 },
+          { "type": "fake", "outputs": ["c"], "sleep_ms": 60 }
+        ],
+        "parallelism": 1,
+        "task_timeout_ms": 100
+      }"#,
+    )
+    .await;
+    assert!(matches!(
+      responses.last().unwrap(),
+      workflow::Response::Finished { .. }
+    ));
+  });
+}
+
+/// A test for failing the tasks waiting for files never written, instead of hanging.
+#[test]
+fn test_exec_blocked() {
+  super::async_test(async {
+    let tasks = r#"
+      "tasks": [
+        { "type": "fake", "outputs": ["a"], "skip": true },
+        { "type": "fake", "inputs": ["a"], "outputs": ["b"] },
+        { "type": "fake", "inputs": ["b"], "outputs": ["c"] },
+        { "type": "fake", "outputs": ["d"], "sleep_ms": 100 }
+      ],
+      "copy_out": ["c", "d"]
+    "#;
+
+    let responses = exec(&format!("{{ {}, \"partial\": true }}", tasks)).await;
+    match responses.last().unwrap() {
+      workflow::Response::FinishedPartial { files, failures } => {
+        assert_eq!(files.len(), 1);
+        assert!(files.contains_key("d"));
+        assert_eq!(failures.len(), 2);
+        assert!(matches!(&failures[0], (1, workflow::TaskError::Blocked(x)) if x == &["a"]));
+        assert!(matches!(&failures[1], (2, workflow::TaskError::Blocked(x)) if x == &["b"]));
+      }
+      x => panic!("unexpected response {:?}", x),
+    }
+
+    let responses = exec(&format!("{{ {} }}", tasks)).await;
+    assert!(matches!(
+      responses.last().unwrap(),
+      workflow::Response::Failed { task: Some(1), .. }
+    ));
+  });
+}
+
+/// A test for limiting the number of tasks running at the same time.
+#[test]
+fn test_exec_parallelism() {
+  super::async_test(async {
+    for parallelism in [1, 2] {
+      let responses = exec(&format!(
+        r#"{{
+          "tasks": [
+            {{ "type": "fake", "outputs": ["a"], "sleep_ms": 50 }},
+            {{ "type": "fake", "outputs": ["b"], "sleep_ms": 50 }},
+            {{ "type": "fake", "outputs": ["c"], "sleep_ms": 50 }}
+          ],
+          "parallelism": {}
+        }}"#,
+        parallelism
+      ))
+      .await;
+      assert!(matches!(
+        responses.last().unwrap(),
+        workflow::Response::Finished { .. }
+      ));
+
+      let (mut running, mut max_running) = (0, 0);
+      for x in &responses {
+        match x {
+          workflow::Response::TaskStarted { .. } => running += 1,
+          workflow::Response::TaskFinished { .. } => running -= 1,
+          _ => {}
+        }
+        max_running = max_running.max(running);
+      }
+      assert_eq!(max_running, parallelism);
+    }
+  });
+}
+
+/// A test for cancelling a workflow by its token or timeout.
+#[test]
+fn test_exec_cancel() {
+  super::async_test(async {
+    let tasks = r#"
+      "tasks": [
+        { "type": "fake", "outputs": ["a"] },
+        { "type": "fake", "inputs": ["a"], "outputs": ["b"], "sleep_ms": 10000 }
+      ]
+    "#;
+
+    let (mut rx, cancel) = workflow::Workflow::parse(&format!("{{ {} }}", tasks))
+      .unwrap()
+      .exec()
+      .unwrap();
+    while let Some(x) = rx.next().await {
+      if matches!(x, workflow::Response::TaskStarted { task: 1 }) {
+        break;
+      }
+    }
+    cancel.cancel();
+    let responses: Vec<_> = rx.collect().await;
+    assert!(matches!(
+      responses[..],
+      [workflow::Response::Cancelled { timed_out: false }]
+    ));
+
+    let responses = exec(&format!("{{ {}, \"timeout_ms\": 100 }}", tasks)).await;
+    assert!(matches!(
+      responses.last().unwrap(),
+      workflow::Response::Cancelled { timed_out: true }
+    ));
+    assert!(responses
+      .iter()
+      .any(|x| matches!(x, workflow::Response::TaskFinished { task: 0 })));
+  });
+}
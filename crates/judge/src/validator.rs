@@ -1,9 +1,48 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time};
 
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use crate::{error, program, sandbox};
+use crate::{checker, error, program, sandbox};
+
+/// Whether an error of `Validator::validate` is a rejection of the input,
+/// rather than the validator failing to finish normally.
+pub fn is_rejection(err: &error::ValidateError) -> bool {
+  return err.result.status == sandbox::Status::NonZeroExitStatus;
+}
+
+/// Where and why an input is rejected, parsed from a testlib message like
+/// `FAIL Integer parameter [name=n] equals to 0, violates the range [1, 100] (stdin, line 1)`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Rejection {
+  /// 1-based line of the input, None if it is not reported.
+  pub line: Option<usize>,
+
+  /// 1-based column of the input, None if it is not reported.
+  pub column: Option<usize>,
+
+  /// The message without the `FAIL` prefix and the position.
+  pub message: String,
+}
+
+impl Rejection {
+  /// Parse the first `FAIL` line of the stderr of a testlib validator,
+  /// None if there is no such line.
+  pub fn parse(s: &str) -> Option<Self> {
+    lazy_static! {
+      static ref FAIL_PAT: Regex = Regex::new(
+        r"(?m-s)^[ \t]*FAIL (.*?)(?: \((?:[^(),]*, )?line (\d+)(?:, column (\d+))?\))?[ \t]*$"
+      )
+      .unwrap();
+    }
+    let cap = FAIL_PAT.captures(s)?;
+    return Some(Self {
+      line: cap.get(2).and_then(|x| x.as_str().parse().ok()),
+      column: cap.get(3).and_then(|x| x.as_str().parse().ok()),
+      message: cap[1].to_string(),
+    });
+  }
+}
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct VariableBounds {
@@ -51,6 +90,15 @@ impl Overview {
   }
 }
 
+/// Result of a validator accepting an input.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Validation {
+  pub overview: Overview,
+
+  /// Non-fatal issues of the input, the lines the validator wrote to stderr while accepting it.
+  pub warnings: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Validator {
   pub exec: program::Executable,
@@ -62,8 +110,131 @@ impl From<program::Executable> for Validator {
   }
 }
 
+/// Result of validating a hack input on the fast path.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum HackVerdict {
+  /// The input is accepted by the validator.
+  Valid,
+
+  /// The input is rejected by the validator, with the testlib message.
+  Invalid { message: String },
+
+  /// The validator did not finish within the deadline.
+  Timeout,
+
+  /// The validator can not finish normally (e.g. signalled or sandbox error),
+  /// which says nothing about the input.
+  Error { message: String },
+}
+
 impl Validator {
-  /// Run the validator and returns the overview log file.
+  /// Validate a raw hack input within a strict deadline, without the rest of the hack pipeline.
+  ///
+  /// The deadline covers uploading the input and queueing in the sandbox.
+  /// Since the sandbox kills a program after twice its CPU time limit in wall time,
+  /// the validator gets half of the deadline as its CPU time limit.
+  pub async fn validate_hack(
+    &self,
+    args: Vec<String>,
+    input: &[u8],
+    mut copy_in: HashMap<String, sandbox::FileHandle>,
+    deadline: time::Duration,
+  ) -> HackVerdict {
+    let run = async {
+      copy_in.insert(self.exec.lang.exec().to_string(), self.exec.file.clone());
+      let input = match sandbox::FileHandle::upload(input).await {
+        Ok(x) => x,
+        Err(err) => {
+          return HackVerdict::Error {
+            message: err.to_string(),
+          }
+        }
+      };
+
+      let mut res = sandbox::Request::Run(sandbox::Cmd {
+        args: [self.exec.lang.run_cmd().clone(), args].concat(),
+        stdin: Some(input),
+        copy_in,
+        labels: self.exec.labels.clone(),
+        copy_out: vec!["stderr".to_string()],
+        time_limit: deadline / 2,
+        ..Default::default()
+      })
+      .exec()
+      .await;
+
+      assert_eq!(res.len(), 1);
+      let res = res.pop().unwrap();
+
+      let message = match res.files.get("stderr") {
+        Some(f) => f.context().await.map_or("broken message".to_string(), |x| {
+          String::from_utf8_lossy(&x).trim().to_string()
+        }),
+        None => String::new(),
+      };
+
+      match res.result.status {
+        sandbox::Status::Accepted => HackVerdict::Valid,
+        sandbox::Status::NonZeroExitStatus => HackVerdict::Invalid { message },
+        sandbox::Status::TimeLimitExceeded | sandbox::Status::IdlenessLimitExceeded => {
+          HackVerdict::Timeout
+        }
+        _ => HackVerdict::Error {
+          message: error::RuntimeError::from(res.result).to_string(),
+        },
+      }
+    };
+
+    match tokio::time::timeout(deadline, run).await {
+      Ok(x) => x,
+      Err(_) => HackVerdict::Timeout,
+    }
+  }
+
+  /// Run the validator on an input, without the overview log.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error with the stderr message of the validator
+  /// if the input is rejected or the validator does not finish normally.
+  pub async fn check(
+    &self,
+    args: Vec<String>,
+    input_file: sandbox::FileHandle,
+    mut copy_in: HashMap<String, sandbox::FileHandle>,
+  ) -> Result<(), error::ValidateError> {
+    copy_in.insert(self.exec.lang.exec().to_string(), self.exec.file.clone());
+
+    let mut res = sandbox::Request::Run(sandbox::Cmd {
+      args: [self.exec.lang.run_cmd().clone(), args].concat(),
+      stdin: Some(input_file),
+      copy_in,
+      labels: self.exec.labels.clone(),
+      copy_out: vec!["stderr".to_string()],
+      ..Default::default()
+    })
+    .exec()
+    .await;
+
+    assert_eq!(res.len(), 1);
+    let res = res.pop().unwrap();
+
+    if res.result.status == sandbox::Status::Accepted {
+      return Ok(());
+    }
+
+    let stderr = match res.files.get("stderr") {
+      Some(f) => f
+        .context()
+        .await
+        .unwrap_or_else(|_| b"broken message".to_vec()),
+      None => vec![],
+    };
+    return Err(error::ValidateError::new(res.result, &stderr));
+  }
+
+  /// Run the validator and returns the overview log file with the warnings.
   ///
   /// It will do these following:
   ///
@@ -71,17 +242,23 @@ impl Validator {
   /// 2. Execute this request with sandbox.
   /// 3. Check if there's an error happens, or return the parsed overview log.
   ///
+  /// The validator accepts the input by exiting with 0, and anything it writes to stderr
+  /// meanwhile is kept as warnings. Exiting with a non-zero code rejects the input
+  /// (see `is_rejection`).
+  ///
   /// # Errors
   ///
-  /// This function will return an error if validating abnormally
-  /// (e.g. validating time limit exceed or signaled)
+  /// This function will return an error with the execution result and the stderr of the validator
+  /// if the input is rejected, validating abnormally (e.g. validating time limit exceed or signaled)
   /// or a sandbox internal error was encountered.
+  /// A rejection reported by testlib is parsed into `ValidateError::rejection`,
+  /// telling the line of the input.
   pub async fn validate(
     &self,
     args: Vec<String>,
     input_file: sandbox::FileHandle,
     mut copy_in: HashMap<String, sandbox::FileHandle>,
-  ) -> Result<Overview, error::RuntimeError> {
+  ) -> Result<program::Run<Validation>, error::ValidateError> {
     copy_in.insert(self.exec.lang.exec().to_string(), self.exec.file.clone());
 
     let mut res = sandbox::Request::Run(sandbox::Cmd {
@@ -97,6 +274,7 @@ impl Validator {
       .concat(),
       stdin: Some(input_file),
       copy_in,
+      labels: self.exec.labels.clone(),
       copy_out: vec!["stderr".to_string(), "val.log".to_string()],
       ..Default::default()
     })
@@ -107,10 +285,33 @@ impl Validator {
     let res = res.pop().unwrap();
 
     match res.result.status {
-      sandbox::Status::Accepted => Ok(Overview::parse(&String::from_utf8_lossy(
-        &res.files["val.log"].clone().context().await.unwrap(),
-      ))),
-      _ => Err(res.result.into()),
+      sandbox::Status::Accepted => {
+        let warnings = match res.files.get("stderr") {
+          Some(f) => String::from_utf8_lossy(&f.context().await?)
+            .lines()
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(checker::limit_message)
+            .collect(),
+          None => vec![],
+        };
+        Ok(program::Run {
+          output: Validation {
+            overview: Overview::parse(&String::from_utf8_lossy(
+              &res.files["val.log"].clone().context().await?,
+            )),
+            warnings,
+          },
+          result: res.result,
+        })
+      }
+      _ => {
+        let stderr = match res.files.get("stderr") {
+          Some(f) => f.context().await.unwrap_or_default(),
+          None => vec![],
+        };
+        Err(error::ValidateError::new(res.result, &stderr))
+      }
     }
   }
 }
@@ -0,0 +1,502 @@
+mod task;
+
+use std::{
+  collections::{HashMap, HashSet, VecDeque},
+  future::Future,
+  num::NonZeroUsize,
+  time,
+};
+
+use futures::{channel::mpsc, stream::FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+  sync::{watch, Semaphore},
+  time::Instant,
+};
+
+use crate::{data, sandbox};
+
+pub use task::{
+  AnswerTest, CheckTask, CompileTask, Files, GenerateTask, InteractPipes, InteractTask,
+  JudgeBatchTask, ProgramFile, StressCase, StressTask, Task, TaskError, TestPlanTask, ValidateTask,
+  VerifyAnswersTask,
+};
+
+/// A workflow of tasks exchanging files by name, like compiling programs, generating inputs
+/// and running solutions on them when building a problem.
+///
+/// Each file is written once, by `copy_in` or by a single task,
+/// and a task runs once all the files it reads are written,
+/// so the tasks must not depend on each other in a cycle.
+/// The files in `copy_in` are builtin files or the bytes of the content (see `data::Provider`).
+///
+/// ```json
+/// {
+///   "copy_in": { "testlib.h": "testlib:testlib.h", "gen.cpp": [35, 105, 110, ...] },
+///   "tasks": [
+///     {
+///       "type": "compile", "lang": "cpp", "source": "gen.cpp", "exec": "gen",
+///       "copy_in": { "testlib.h": "testlib.h" }
+///     },
+///     { "type": "generate", "generator": { "lang": "cpp", "file": "gen" }, "output": "1.in" },
+///     // Compiling `std`, `brute` and `chk`, and running `std` writing `1.out` are omitted.
+///     {
+///       "type": "judge_batch", "solution": { "lang": "cpp", "file": "brute" }, "input": "1.in",
+///       "time_limit_ms": 10000, "memory_limit_mb": 512, "output": "1.ans"
+///     },
+///     {
+///       "type": "check", "checker": { "lang": "cpp", "file": "chk" },
+///       "input": "1.in", "output": "1.out", "answer": "1.ans", "report": "1.report"
+///     }
+///   ],
+///   "copy_out": ["1.in", "1.report"]
+/// }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Workflow {
+  /// Files given to the workflow by name.
+  #[serde(default)]
+  pub copy_in: HashMap<String, data::Provider>,
+
+  pub tasks: Vec<Box<dyn Task>>,
+
+  /// Names of the files delivered when the workflow finishes.
+  #[serde(default)]
+  pub copy_out: Vec<String>,
+
+  /// Keep running the other tasks when a task fails, and deliver the files written
+  /// in a `Response::FinishedPartial`, so the good outputs of a long build are not lost.
+  ///
+  /// Otherwise the workflow stops at the first failed task.
+  #[serde(default)]
+  pub partial: bool,
+
+  /// Wall-clock time limit of each task, counting from when it starts running
+  /// (all the files it reads are written, and it is allowed to run by `parallelism`),
+  /// as a task may hang because of a bug before reaching the sandbox.
+  ///
+  /// A task exceeding it fails with `TaskError::Timeout`, and so do the tasks reading
+  /// the files it writes, naming the file they wait for.
+  #[serde(default)]
+  pub task_timeout_ms: Option<u64>,
+
+  /// Maximum number of tasks running at the same time, unlimited if None.
+  ///
+  /// Tasks waiting for their files do not count.
+  #[serde(default)]
+  pub parallelism: Option<NonZeroUsize>,
+
+  /// Wall-clock time limit of the whole workflow, which is cancelled when it is exceeded.
+  #[serde(default)]
+  pub timeout_ms: Option<u64>,
+}
+
+/// Error when a workflow is invalid.
+#[derive(Debug, Error)]
+pub enum ParseError {
+  #[error("invalid workflow: {0}")]
+  Json(#[from] serde_json::Error),
+
+  /// A file is written by `copy_in` or tasks more than once.
+  #[error("file {0} is written more than once")]
+  DuplicateFile(String),
+
+  /// A file is read or copied out, but never written.
+  #[error("file {0} is never written")]
+  UnknownFile(String),
+
+  /// Tasks depend on each other in a cycle, each reading a file written by the next one
+  /// (and the last one reading a file written by the first one).
+  #[error("tasks {0:?} depend on each other in a cycle")]
+  Cycle(Vec<usize>),
+}
+
+/// Progress of a workflow.
+#[derive(Debug, Clone)]
+pub enum Response {
+  /// A task started running, as all the files it reads are written.
+  TaskStarted { task: usize },
+
+  /// A task finished, and wrote its files.
+  TaskFinished { task: usize },
+
+  /// A task failed, and so do the tasks reading the files it writes.
+  TaskFailed { task: usize, error: TaskError },
+
+  /// All the tasks finished, with the files to copy out.
+  Finished { files: Files },
+
+  /// Some tasks failed in a workflow delivering partial outputs (see `Workflow::partial`),
+  /// with the files to copy out which are written, and the error of each failed task.
+  FinishedPartial {
+    files: Files,
+    failures: Vec<(usize, TaskError)>,
+  },
+
+  /// The workflow stopped at a failed task, or before running any task if None
+  /// (e.g. `copy_in` can not be uploaded).
+  Failed { task: Option<usize>, error: String },
+
+  /// The workflow is cancelled by its token, or as it exceeds `Workflow::timeout_ms`
+  /// if `timed_out`, and the running sandbox requests are aborted.
+  Cancelled { timed_out: bool },
+}
+
+/// State of a file of a running workflow.
+#[derive(Debug, Clone)]
+enum FileState {
+  Pending,
+  Written(sandbox::FileHandle),
+
+  /// The task writing the file failed.
+  Failed,
+
+  /// The task writing the file exceeded `Workflow::task_timeout_ms`.
+  TimedOut,
+}
+
+impl Workflow {
+  /// Parse a workflow from json, and check it (see `check`).
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the json is not a workflow, or it is invalid.
+  pub fn parse(s: &str) -> Result<Self, ParseError> {
+    let workflow: Self = serde_json::from_str(s)?;
+    workflow.check()?;
+    return Ok(workflow);
+  }
+
+  /// Check that each file is written exactly once, before it is read or copied out,
+  /// and the tasks do not depend on each other in a cycle.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error naming the first file written more than once,
+  /// or read but never written, or the tasks of a cycle.
+  pub fn check(&self) -> Result<(), ParseError> {
+    let mut written: HashSet<String> = self.copy_in.keys().cloned().collect();
+    for name in self.tasks.iter().flat_map(|x| x.outputs()) {
+      if !written.insert(name.clone()) {
+        return Err(ParseError::DuplicateFile(name));
+      }
+    }
+    let read = self.tasks.iter().flat_map(|x| x.inputs());
+    for name in read.chain(self.copy_out.iter().cloned()) {
+      if !written.contains(&name) {
+        return Err(ParseError::UnknownFile(name));
+      }
+    }
+    self.order()?;
+    return Ok(());
+  }
+
+  /// The index of the task writing each file, except the files in `copy_in`.
+  fn writers(&self) -> HashMap<String, usize> {
+    return (self.tasks.iter().enumerate())
+      .flat_map(|(i, task)| task.outputs().into_iter().map(move |x| (x, i)))
+      .collect();
+  }
+
+  /// Sort the tasks topologically, so each task comes after the tasks writing the files it reads.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error with the tasks of a cycle
+  /// if some tasks depend on each other in a cycle.
+  fn order(&self) -> Result<Vec<usize>, ParseError> {
+    let writers = self.writers();
+    let deps: Vec<HashSet<usize>> = (self.tasks.iter())
+      .map(|task| {
+        (task.inputs().iter())
+          .filter_map(|x| writers.get(x).copied())
+          .collect()
+      })
+      .collect();
+    let mut dependents = vec![vec![]; self.tasks.len()];
+    for (i, x) in deps.iter().enumerate() {
+      for &j in x {
+        dependents[j].push(i);
+      }
+    }
+
+    // Kahn's algorithm, where `waiting[i]` is the number of unsorted tasks the `i`-th task reads.
+    let mut waiting: Vec<_> = deps.iter().map(|x| x.len()).collect();
+    let mut ready: VecDeque<_> = (0..self.tasks.len()).filter(|&i| waiting[i] == 0).collect();
+    let mut order = vec![];
+    while let Some(i) = ready.pop_front() {
+      order.push(i);
+      for &j in &dependents[i] {
+        waiting[j] -= 1;
+        if waiting[j] == 0 {
+          ready.push_back(j);
+        }
+      }
+    }
+    let mut i = match (0..self.tasks.len()).find(|&i| waiting[i] > 0) {
+      Some(i) => i,
+      None => return Ok(order),
+    };
+
+    // Each unsorted task reads a file written by another unsorted task,
+    // so following them from any unsorted task leads to a cycle.
+    let mut path: Vec<usize> = vec![];
+    loop {
+      if let Some(pos) = path.iter().position(|&x| x == i) {
+        return Err(ParseError::Cycle(path.split_off(pos)));
+      }
+      path.push(i);
+      i = *deps[i].iter().filter(|&&j| waiting[j] > 0).min().unwrap();
+    }
+  }
+
+  /// Check and run the workflow in the background, and return the stream of its progress,
+  /// which ends with `Finished`, `FinishedPartial`, `Failed` or `Cancelled`,
+  /// with a token cancelling the workflow.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the workflow is invalid (see `check`).
+  pub fn exec(
+    self,
+  ) -> Result<
+    (
+      mpsc::UnboundedReceiver<Response>,
+      sandbox::CancellationToken,
+    ),
+    ParseError,
+  > {
+    self.check()?;
+    let order = self.order()?;
+    let (tx, rx) = mpsc::unbounded();
+    let cancel = sandbox::CancellationToken::new();
+    let token = cancel.clone();
+    let deadline = self
+      .timeout_ms
+      .map(|x| Instant::now() + time::Duration::from_millis(x));
+    tokio::spawn(async move {
+      // Dropping the running tasks drops their sandbox requests, which aborts them on the sandbox.
+      let res = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Response::Cancelled { timed_out: false },
+        res = before(deadline, self.run(&order, &tx)) => {
+          res.unwrap_or(Response::Cancelled { timed_out: true })
+        }
+      };
+      _ = tx.unbounded_send(res);
+    });
+    return Ok((rx, token));
+  }
+
+  /// Run all the tasks in the topological order, and return the last response.
+  async fn run(&self, order: &[usize], tx: &mpsc::UnboundedSender<Response>) -> Response {
+    let mut states = HashMap::new();
+    for name in self.tasks.iter().flat_map(|x| x.outputs()) {
+      states.insert(name, watch::channel(FileState::Pending).0);
+    }
+    for (name, data) in &self.copy_in {
+      match data.upload().await {
+        Ok(file) => states.insert(name.clone(), watch::channel(FileState::Written(file)).0),
+        Err(err) => {
+          return Response::Failed {
+            task: None,
+            error: format!("file {} can not be uploaded: {}", name, err),
+          }
+        }
+      };
+    }
+
+    let states = &states;
+    let timeout = self.task_timeout_ms.map(time::Duration::from_millis);
+    let semaphore = &Semaphore::new(
+      self
+        .parallelism
+        .map_or(Semaphore::MAX_PERMITS, NonZeroUsize::get),
+    );
+    // Tasks are polled in the order they are pushed, so the earlier ones in the topological
+    // order get the permits first when they are ready at the same time.
+    let mut running: FuturesUnordered<_> = order
+      .iter()
+      .map(|&i| {
+        let task = self.tasks[i].as_ref();
+        async move { (i, run_task(i, task, states, semaphore, timeout, tx).await) }
+      })
+      .collect();
+    let mut finished = HashSet::new();
+    let mut failures = vec![];
+    while let Some((i, res)) = running.next().await {
+      finished.insert(i);
+      match res {
+        Ok(files) => {
+          // Files the task does not declare are dropped.
+          let outputs = self.tasks[i].outputs();
+          for (name, file) in files.into_iter().filter(|x| outputs.contains(&x.0)) {
+            states[&name].send_replace(FileState::Written(file));
+          }
+          _ = tx.unbounded_send(Response::TaskFinished { task: i });
+        }
+        Err(err) => {
+          if let Some(res) = self.fail(i, err, states, tx, &mut failures) {
+            return res;
+          }
+        }
+      }
+
+      let blocked = self.blocked(&finished, states);
+      if blocked.is_empty() {
+        continue;
+      }
+      // The blocked tasks would wait forever, so they fail to end the workflow.
+      for (task, missing) in blocked {
+        if let Some(res) = self.fail(task, TaskError::Blocked(missing), states, tx, &mut failures) {
+          return res;
+        }
+      }
+      break;
+    }
+
+    let files = self
+      .copy_out
+      .iter()
+      .filter_map(|name| match &*states[name].borrow() {
+        FileState::Written(file) => Some((name.clone(), file.clone())),
+        _ => None,
+      })
+      .collect();
+    if failures.is_empty() {
+      return Response::Finished { files };
+    }
+    return Response::FinishedPartial { files, failures };
+  }
+
+  /// Mark the files written by a failed task as failed, and return the last response
+  /// if the workflow stops (see `partial`), or record the failure otherwise.
+  fn fail(
+    &self,
+    task: usize,
+    err: TaskError,
+    states: &HashMap<String, watch::Sender<FileState>>,
+    tx: &mpsc::UnboundedSender<Response>,
+    failures: &mut Vec<(usize, TaskError)>,
+  ) -> Option<Response> {
+    let state = match err {
+      TaskError::Timeout { .. } => FileState::TimedOut,
+      _ => FileState::Failed,
+    };
+    for name in self.tasks[task].outputs() {
+      states[&name].send_replace(state.clone());
+    }
+    _ = tx.unbounded_send(Response::TaskFailed {
+      task,
+      error: err.clone(),
+    });
+    if !self.partial {
+      return Some(Response::Failed {
+        task: Some(task),
+        error: err.to_string(),
+      });
+    }
+    failures.push((task, err));
+    return None;
+  }
+
+  /// If all the unfinished tasks are blocked, return each of them with the files it waits for.
+  ///
+  /// A task is blocked if it waits for a file never written, as the task writing it
+  /// finished without writing it or is blocked too.
+  fn blocked(
+    &self,
+    finished: &HashSet<usize>,
+    states: &HashMap<String, watch::Sender<FileState>>,
+  ) -> Vec<(usize, Vec<String>)> {
+    let writers = self.writers();
+    let pending = |name: &String| matches!(*states[name].borrow(), FileState::Pending);
+
+    let mut blocked = HashSet::new();
+    loop {
+      let len = blocked.len();
+      for (i, task) in self.tasks.iter().enumerate() {
+        if finished.contains(&i) || blocked.contains(&i) {
+          continue;
+        }
+        let never_written = |name: &String| {
+          pending(name) && (finished.contains(&writers[name]) || blocked.contains(&writers[name]))
+        };
+        if task.inputs().iter().any(never_written) {
+          blocked.insert(i);
+        }
+      }
+      if blocked.len() == len {
+        break;
+      }
+    }
+
+    if blocked.is_empty() || finished.len() + blocked.len() < self.tasks.len() {
+      return vec![];
+    }
+    let mut blocked: Vec<_> = blocked.into_iter().collect();
+    blocked.sort();
+    return blocked
+      .into_iter()
+      .map(|i| {
+        (
+          i,
+          self.tasks[i].inputs().into_iter().filter(pending).collect(),
+        )
+      })
+      .collect();
+  }
+}
+
+/// Wait until the files read by the `index`-th task are written, and run it
+/// with a permit of the semaphore limiting the parallelism, within the `timeout`.
+///
+/// # Errors
+///
+/// This function will return an error if the task fails or times out,
+/// or a file it reads is not written as the task writing it fails or times out.
+async fn run_task(
+  index: usize,
+  task: &dyn Task,
+  states: &HashMap<String, watch::Sender<FileState>>,
+  semaphore: &Semaphore,
+  timeout: Option<time::Duration>,
+  tx: &mpsc::UnboundedSender<Response>,
+) -> Result<Files, TaskError> {
+  let mut inputs = Files::new();
+  for name in task.inputs() {
+    let mut rx = states[&name].subscribe();
+    // The senders live until all the tasks finish.
+    let state = rx
+      .wait_for(|x| !matches!(x, FileState::Pending))
+      .await
+      .unwrap()
+      .clone();
+    match state {
+      FileState::Written(file) => inputs.insert(name, file),
+      FileState::TimedOut => {
+        return Err(TaskError::Timeout {
+          waiting: Some(name),
+        })
+      }
+      _ => return Err(TaskError::Input(name)),
+    };
+  }
+  // The semaphore is never closed.
+  let _permit = semaphore.acquire().await.unwrap();
+  _ = tx.unbounded_send(Response::TaskStarted { task: index });
+  let deadline = timeout.map(|x| Instant::now() + x);
+  return match before(deadline, task.run(inputs)).await {
+    Some(res) => res,
+    None => Err(TaskError::Timeout { waiting: None }),
+  };
+}
+
+/// Run a future until the deadline, and return None if it is reached first.
+async fn before<T>(deadline: Option<Instant>, fut: impl Future<Output = T>) -> Option<T> {
+  return match deadline {
+    Some(x) => tokio::time::timeout_at(x, fut).await.ok(),
+    None => Some(fut.await),
+  };
+}
@@ -0,0 +1,845 @@
+use std::{collections::HashMap, fmt, time};
+
+use async_trait::async_trait;
+use futures::{future, stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+  checker, error, generator, interactor, judge, lang, problem::testplan, program, record, sandbox,
+  stress, validator, CONFIG,
+};
+
+/// Files of a workflow by name.
+pub type Files = HashMap<String, sandbox::FileHandle>;
+
+/// A step of a workflow, which reads some files of the workflow and writes some others.
+///
+/// Tasks are (de)serialized with their type name in the `type` field
+/// (like `{"type": "compile", ...}`), so more kinds of tasks can be added by implementing it.
+#[typetag::serde(tag = "type")]
+#[async_trait]
+pub trait Task: fmt::Debug + Send + Sync {
+  /// Names of the files read by the task.
+  fn inputs(&self) -> Vec<String>;
+
+  /// Names of the files written by the task.
+  fn outputs(&self) -> Vec<String>;
+
+  /// Run the task on the files it reads, and return the files it writes by name.
+  async fn run(&self, inputs: Files) -> Result<Files, TaskError>;
+}
+
+/// Error when a task of a workflow fails.
+#[derive(Debug, Error, Clone)]
+pub enum TaskError {
+  #[error("{0}")]
+  Compile(#[from] error::CompileError),
+
+  #[error("{0}")]
+  Runtime(#[from] error::RuntimeError),
+
+  #[error("{0}")]
+  Validate(#[from] error::ValidateError),
+
+  #[error("{0}")]
+  Sandbox(#[from] sandbox::Error),
+
+  #[error("{0}")]
+  Stress(#[from] stress::StressError),
+
+  #[error("{0}")]
+  TestPlan(#[from] testplan::TestPlanError),
+
+  /// A file read by the task is never written, as the task writing it failed.
+  #[error("file {0} is not written, as the task writing it failed")]
+  Input(String),
+
+  /// The task never runs, as the files it waits for are never written
+  /// (e.g. the task writing them finished without writing them), and no other task can run.
+  #[error("task is blocked waiting for files {}", .0.join(", "))]
+  Blocked(Vec<String>),
+
+  /// The task does not finish within `Workflow::task_timeout_ms` after it starts running,
+  /// or the task writing the file `waiting` which it reads does not, if Some.
+  #[error("task timed out{}", waiting.as_ref().map(|x| format!(" waiting for file {}", x)).unwrap_or_default())]
+  Timeout { waiting: Option<String> },
+}
+
+/// A compiled program among the files of a workflow.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgramFile {
+  pub lang: lang::Lang,
+
+  /// Name of the executable file, usually written by a `CompileTask`.
+  pub file: String,
+}
+
+impl ProgramFile {
+  /// The executable of the program in the files read by a task.
+  fn executable(&self, files: &Files) -> program::Executable {
+    return program::Executable {
+      lang: self.lang.clone(),
+      file: files[&self.file].clone(),
+      report: program::CompileReport::default(),
+      message: String::new(),
+      labels: self.lang.labels().clone(),
+    };
+  }
+}
+
+/// Extra files copied into the sandbox, by the path in the sandbox to the name in the workflow.
+type CopyIn = HashMap<String, String>;
+
+/// The extra files of a task from the files it reads.
+fn copy_in(copy_in: &CopyIn, files: &Files) -> Files {
+  return copy_in
+    .iter()
+    .map(|(path, name)| (path.clone(), files[name].clone()))
+    .collect();
+}
+
+/// Compile a source file into an executable file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompileTask {
+  pub lang: lang::Lang,
+
+  pub source: String,
+
+  /// Extra compile arguments.
+  #[serde(default)]
+  pub args: Vec<String>,
+
+  /// Extra files (like `testlib.h`), see `CopyIn`.
+  #[serde(default)]
+  pub copy_in: CopyIn,
+
+  /// Name of the executable file to write.
+  pub exec: String,
+}
+
+#[typetag::serde(name = "compile")]
+#[async_trait]
+impl Task for CompileTask {
+  fn inputs(&self) -> Vec<String> {
+    return [
+      vec![self.source.clone()],
+      self.copy_in.values().cloned().collect(),
+    ]
+    .concat();
+  }
+
+  fn outputs(&self) -> Vec<String> {
+    return vec![self.exec.clone()];
+  }
+
+  async fn run(&self, inputs: Files) -> Result<Files, TaskError> {
+    let exec = program::compile_file(
+      &self.lang,
+      inputs[&self.source].clone(),
+      self.args.clone(),
+      copy_in(&self.copy_in, &inputs),
+    )
+    .await?;
+    return Ok(Files::from([(self.exec.clone(), exec.file)]));
+  }
+}
+
+/// Generate a file by the stdout of a generator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerateTask {
+  pub generator: ProgramFile,
+
+  #[serde(default)]
+  pub args: Vec<String>,
+
+  #[serde(default)]
+  pub copy_in: CopyIn,
+
+  /// Name of the generated file to write.
+  pub output: String,
+}
+
+#[typetag::serde(name = "generate")]
+#[async_trait]
+impl Task for GenerateTask {
+  fn inputs(&self) -> Vec<String> {
+    return [
+      vec![self.generator.file.clone()],
+      self.copy_in.values().cloned().collect(),
+    ]
+    .concat();
+  }
+
+  fn outputs(&self) -> Vec<String> {
+    return vec![self.output.clone()];
+  }
+
+  async fn run(&self, inputs: Files) -> Result<Files, TaskError> {
+    let file = generator::Generator::from(self.generator.executable(&inputs))
+      .generate(self.args.clone(), copy_in(&self.copy_in, &inputs))
+      .await?;
+    return Ok(Files::from([(self.output.clone(), file)]));
+  }
+}
+
+/// Validate an input file, the task fails if the validator rejects it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidateTask {
+  pub validator: ProgramFile,
+
+  pub input: String,
+
+  #[serde(default)]
+  pub args: Vec<String>,
+
+  #[serde(default)]
+  pub copy_in: CopyIn,
+}
+
+#[typetag::serde(name = "validate")]
+#[async_trait]
+impl Task for ValidateTask {
+  fn inputs(&self) -> Vec<String> {
+    return [
+      vec![self.validator.file.clone(), self.input.clone()],
+      self.copy_in.values().cloned().collect(),
+    ]
+    .concat();
+  }
+
+  fn outputs(&self) -> Vec<String> {
+    return vec![];
+  }
+
+  async fn run(&self, inputs: Files) -> Result<Files, TaskError> {
+    validator::Validator::from(self.validator.executable(&inputs))
+      .check(
+        self.args.clone(),
+        inputs[&self.input].clone(),
+        copy_in(&self.copy_in, &inputs),
+      )
+      .await?;
+    return Ok(Files::new());
+  }
+}
+
+/// Run a solution on an input file like judging a batch problem,
+/// the task fails if the solution is not accepted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JudgeBatchTask {
+  pub solution: ProgramFile,
+
+  pub input: String,
+
+  #[serde(default)]
+  pub args: Vec<String>,
+
+  #[serde(default)]
+  pub copy_in: CopyIn,
+
+  pub time_limit_ms: u64,
+
+  pub memory_limit_mb: u64,
+
+  /// Name of the file to write the stdout of the solution to.
+  pub output: String,
+}
+
+#[typetag::serde(name = "judge_batch")]
+#[async_trait]
+impl Task for JudgeBatchTask {
+  fn inputs(&self) -> Vec<String> {
+    return [
+      vec![self.solution.file.clone(), self.input.clone()],
+      self.copy_in.values().cloned().collect(),
+    ]
+    .concat();
+  }
+
+  fn outputs(&self) -> Vec<String> {
+    return vec![self.output.clone()];
+  }
+
+  async fn run(&self, inputs: Files) -> Result<Files, TaskError> {
+    let (result, output) = self
+      .solution
+      .executable(&inputs)
+      .judge_batch(
+        self.args.clone(),
+        inputs[&self.input].clone(),
+        copy_in(&self.copy_in, &inputs),
+        time::Duration::from_millis(self.time_limit_ms),
+        self.memory_limit_mb * 1024 * 1024,
+        None,
+        judge::TimeKind::Cpu,
+        None,
+        CONFIG.judge.stdout_limit,
+        &judge::OutputSource::Stdout,
+      )
+      .await;
+    return match output {
+      Some(file) => Ok(Files::from([(self.output.clone(), file)])),
+      None => Err(error::RuntimeError::from(result).into()),
+    };
+  }
+}
+
+/// Check an output file of a solution by a testlib checker,
+/// and write the report of the checker (the json of `checker::Output`).
+///
+/// The task fails only if the checker does not finish normally,
+/// an output rejected by the checker is told by the report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckTask {
+  pub checker: ProgramFile,
+
+  pub input: String,
+
+  pub output: String,
+
+  pub answer: String,
+
+  #[serde(default)]
+  pub args: Vec<String>,
+
+  #[serde(default)]
+  pub copy_in: CopyIn,
+
+  /// Name of the report file to write.
+  pub report: String,
+}
+
+#[typetag::serde(name = "check")]
+#[async_trait]
+impl Task for CheckTask {
+  fn inputs(&self) -> Vec<String> {
+    return [
+      vec![
+        self.checker.file.clone(),
+        self.input.clone(),
+        self.output.clone(),
+        self.answer.clone(),
+      ],
+      self.copy_in.values().cloned().collect(),
+    ]
+    .concat();
+  }
+
+  fn outputs(&self) -> Vec<String> {
+    return vec![self.report.clone()];
+  }
+
+  async fn run(&self, inputs: Files) -> Result<Files, TaskError> {
+    let run = checker::Checker::from(self.checker.executable(&inputs))
+      .check(
+        self.args.clone(),
+        inputs[&self.input].clone(),
+        inputs[&self.output].clone(),
+        inputs[&self.answer].clone(),
+        copy_in(&self.copy_in, &inputs),
+      )
+      .await?;
+    let report = sandbox::FileHandle::upload(&serde_json::to_vec(&run.output).unwrap()).await?;
+    return Ok(Files::from([(self.report.clone(), report)]));
+  }
+}
+
+/// Files to write with the data transferred through the pipes of an `InteractTask`.
+///
+/// The stdout of each program is piped to the stdin of the other one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InteractPipes {
+  /// Name of the file to write the data written by the solution to, None not to write it.
+  #[serde(default)]
+  pub solution: Option<String>,
+
+  /// Name of the file to write the data written by the interactor to, None not to write it.
+  #[serde(default)]
+  pub interactor: Option<String>,
+}
+
+/// Run a solution on an input file with a testlib interactor like judging an interactive
+/// problem, and write the verdict of the interactor (the json of `checker::Output`).
+///
+/// The task fails if the interactor does not finish normally, or the solution is not accepted
+/// by the sandbox while the interactor accepts it. A rejection of the interactor is told by
+/// the verdict.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InteractTask {
+  pub interactor: ProgramFile,
+
+  pub solution: ProgramFile,
+
+  pub input: String,
+
+  #[serde(default)]
+  pub args: Vec<String>,
+
+  /// Extra files of the solution.
+  #[serde(default)]
+  pub copy_in: CopyIn,
+
+  /// Extra files of the interactor.
+  #[serde(default)]
+  pub interactor_copy_in: CopyIn,
+
+  pub time_limit_ms: u64,
+
+  pub memory_limit_mb: u64,
+
+  /// Name of the verdict file to write.
+  pub verdict: String,
+
+  /// Name of the file to write the output file (`tout.txt`) of the interactor to,
+  /// None not to write it.
+  #[serde(default)]
+  pub output: Option<String>,
+
+  /// Files to write the transcript to.
+  #[serde(default)]
+  pub pipes: InteractPipes,
+}
+
+#[typetag::serde(name = "interact")]
+#[async_trait]
+impl Task for InteractTask {
+  fn inputs(&self) -> Vec<String> {
+    return [
+      vec![
+        self.interactor.file.clone(),
+        self.solution.file.clone(),
+        self.input.clone(),
+      ],
+      self.copy_in.values().cloned().collect(),
+      self.interactor_copy_in.values().cloned().collect(),
+    ]
+    .concat();
+  }
+
+  fn outputs(&self) -> Vec<String> {
+    return [
+      Some(self.verdict.clone()),
+      self.output.clone(),
+      self.pipes.solution.clone(),
+      self.pipes.interactor.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+  }
+
+  async fn run(&self, inputs: Files) -> Result<Files, TaskError> {
+    let res = interactor::Interactor::from(self.interactor.executable(&inputs))
+      .interact(
+        &self.solution.executable(&inputs),
+        self.args.clone(),
+        inputs[&self.input].clone(),
+        copy_in(&self.copy_in, &inputs),
+        copy_in(&self.interactor_copy_in, &inputs),
+        time::Duration::from_millis(self.time_limit_ms),
+        judge::TimeKind::Cpu,
+        self.memory_limit_mb * 1024 * 1024,
+      )
+      .await;
+
+    let verdict = match res.interactor_output {
+      Some(x) => x,
+      None => return Err(error::RuntimeError::from(res.interactor).into()),
+    };
+    if verdict.status == checker::Status::Accepted
+      && res.solution.status != sandbox::Status::Accepted
+    {
+      return Err(error::RuntimeError::from(res.solution).into());
+    }
+
+    let mut files = Files::from([(
+      self.verdict.clone(),
+      sandbox::FileHandle::upload(&serde_json::to_vec(&verdict).unwrap()).await?,
+    )]);
+    let [solution_transcript, interactor_transcript] = res.transcript;
+    for (name, file) in [
+      (&self.output, res.output_file),
+      (&self.pipes.solution, solution_transcript),
+      (&self.pipes.interactor, interactor_transcript),
+    ] {
+      if let (Some(name), Some(file)) = (name, file) {
+        files.insert(name.clone(), file);
+      }
+    }
+    return Ok(files);
+  }
+}
+
+/// Files to write with the failing case found by a `StressTask`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StressCase {
+  /// Name of the file to write the generated input to.
+  pub input: String,
+
+  /// Name of the file to write the output of the reference solution to.
+  pub answer: String,
+
+  /// Name of the file to write the output of the candidate solution to,
+  /// which is not written if the candidate exits abnormally.
+  pub output: String,
+}
+
+/// Report of a `StressTask`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+enum StressReport<'a> {
+  Passed {
+    rounds: usize,
+  },
+  Found {
+    seed: u64,
+    record: &'a record::Record,
+  },
+}
+
+/// Stress test a candidate solution against a reference solution (see `stress::Stress`),
+/// and write the report (with the seed and the record of the candidate if a failing case
+/// is found), and the failing case.
+///
+/// The files of the failing case are not written if all the rounds pass,
+/// so the tasks reading them are blocked. The task fails if the stress testing itself is
+/// broken (see `stress::StressError`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StressTask {
+  pub generator: ProgramFile,
+
+  /// Generator arguments, the seed of each round is appended after them.
+  #[serde(default)]
+  pub args: Vec<String>,
+
+  /// Validator for the generated inputs, None to skip validation.
+  #[serde(default)]
+  pub validator: Option<ProgramFile>,
+
+  pub reference: ProgramFile,
+
+  pub candidate: ProgramFile,
+
+  pub checker: ProgramFile,
+
+  /// Extra files of the generator, the validator and the checker.
+  #[serde(default)]
+  pub copy_in: CopyIn,
+
+  /// Extra files of the solutions.
+  #[serde(default)]
+  pub judge_copy_in: CopyIn,
+
+  pub time_limit_ms: u64,
+
+  pub memory_limit_mb: u64,
+
+  pub rounds: usize,
+
+  /// Seed of the first round, round `i` uses `seed + i`.
+  #[serde(default)]
+  pub seed: u64,
+
+  /// Name of the report file to write.
+  pub report: String,
+
+  pub failing: StressCase,
+}
+
+#[typetag::serde(name = "stress")]
+#[async_trait]
+impl Task for StressTask {
+  fn inputs(&self) -> Vec<String> {
+    return [
+      vec![self.generator.file.clone()],
+      self.validator.iter().map(|x| x.file.clone()).collect(),
+      vec![
+        self.reference.file.clone(),
+        self.candidate.file.clone(),
+        self.checker.file.clone(),
+      ],
+      self.copy_in.values().cloned().collect(),
+      self.judge_copy_in.values().cloned().collect(),
+    ]
+    .concat();
+  }
+
+  fn outputs(&self) -> Vec<String> {
+    return vec![
+      self.report.clone(),
+      self.failing.input.clone(),
+      self.failing.answer.clone(),
+      self.failing.output.clone(),
+    ];
+  }
+
+  async fn run(&self, inputs: Files) -> Result<Files, TaskError> {
+    let outcome = stress::Stress {
+      generator: self.generator.executable(&inputs).into(),
+      args: self.args.clone(),
+      validator: self
+        .validator
+        .as_ref()
+        .map(|x| x.executable(&inputs).into()),
+      reference: self.reference.executable(&inputs),
+      candidate: self.candidate.executable(&inputs),
+      checker: self.checker.executable(&inputs).into(),
+      time_limit: time::Duration::from_millis(self.time_limit_ms),
+      memory_limit: self.memory_limit_mb * 1024 * 1024,
+      user_copy_in: copy_in(&self.copy_in, &inputs),
+      judge_copy_in: copy_in(&self.judge_copy_in, &inputs),
+    }
+    .run(self.rounds, self.seed)
+    .await?;
+
+    let counterexample = match outcome {
+      stress::Outcome::Passed { rounds } => {
+        let report = serde_json::to_vec(&StressReport::Passed { rounds }).unwrap();
+        return Ok(Files::from([(
+          self.report.clone(),
+          sandbox::FileHandle::upload(&report).await?,
+        )]));
+      }
+      stress::Outcome::Found(x) => x,
+    };
+    let report = serde_json::to_vec(&StressReport::Found {
+      seed: counterexample.seed,
+      record: &counterexample.record,
+    })
+    .unwrap();
+    let mut files = Files::from([
+      (
+        self.report.clone(),
+        sandbox::FileHandle::upload(&report).await?,
+      ),
+      (self.failing.input.clone(), counterexample.input),
+      (self.failing.answer.clone(), counterexample.reference_output),
+    ]);
+    if let Some(file) = counterexample.candidate_output {
+      files.insert(self.failing.output.clone(), file);
+    }
+    return Ok(files);
+  }
+}
+
+/// A test whose answer file is verified by a `VerifyAnswersTask`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerTest {
+  pub input: String,
+
+  pub answer: String,
+}
+
+/// A test whose answer file is not accepted by the checker against the output
+/// of the standard solution, or the output can not be made.
+#[derive(Debug, Serialize)]
+struct AnswerDiscrepancy<'a> {
+  /// Name of the input file of the test.
+  input: &'a str,
+
+  message: String,
+}
+
+/// Run the standard solution on the input of each test, check the provided answer file
+/// against its output by the checker (the output takes the place of the answer,
+/// like `problem::Problem::check_answers`), and write the report of the discrepancies
+/// (the json of the list of `{ input, message }`).
+///
+/// The task fails only if writing the report fails, the discrepancies are told by the report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyAnswersTask {
+  pub standard_solution: ProgramFile,
+
+  pub checker: ProgramFile,
+
+  pub tests: Vec<AnswerTest>,
+
+  /// Checker arguments.
+  #[serde(default)]
+  pub args: Vec<String>,
+
+  /// Extra files of the checker.
+  #[serde(default)]
+  pub copy_in: CopyIn,
+
+  /// Extra files of the standard solution.
+  #[serde(default)]
+  pub judge_copy_in: CopyIn,
+
+  pub time_limit_ms: u64,
+
+  pub memory_limit_mb: u64,
+
+  /// Name of the report file to write.
+  pub report: String,
+}
+
+#[typetag::serde(name = "verify_answers")]
+#[async_trait]
+impl Task for VerifyAnswersTask {
+  fn inputs(&self) -> Vec<String> {
+    return [
+      vec![
+        self.standard_solution.file.clone(),
+        self.checker.file.clone(),
+      ],
+      (self.tests.iter())
+        .flat_map(|x| [x.input.clone(), x.answer.clone()])
+        .collect(),
+      self.copy_in.values().cloned().collect(),
+      self.judge_copy_in.values().cloned().collect(),
+    ]
+    .concat();
+  }
+
+  fn outputs(&self) -> Vec<String> {
+    return vec![self.report.clone()];
+  }
+
+  async fn run(&self, inputs: Files) -> Result<Files, TaskError> {
+    let standard_solution = self.standard_solution.executable(&inputs);
+    let checker = checker::Checker::from(self.checker.executable(&inputs));
+    let (user_copy_in, judge_copy_in) = (
+      copy_in(&self.copy_in, &inputs),
+      copy_in(&self.judge_copy_in, &inputs),
+    );
+
+    let checks: Vec<_> = (self.tests.iter())
+      .map(|test| {
+        let (standard_solution, checker, inputs) = (&standard_solution, &checker, &inputs);
+        let (user_copy_in, judge_copy_in) = (&user_copy_in, &judge_copy_in);
+        async move {
+          let discrepancy = |message: String| {
+            Some(AnswerDiscrepancy {
+              input: &test.input,
+              message,
+            })
+          };
+          let input = inputs[&test.input].clone();
+          let (result, output) = standard_solution
+            .judge_batch(
+              vec![],
+              input.clone(),
+              judge_copy_in.clone(),
+              time::Duration::from_millis(self.time_limit_ms),
+              self.memory_limit_mb * 1024 * 1024,
+              None,
+              judge::TimeKind::Cpu,
+              None,
+              CONFIG.judge.stdout_limit,
+              &judge::OutputSource::Stdout,
+            )
+            .await;
+          let output = match output {
+            Some(x) => x,
+            None => {
+              return discrepancy(format!(
+                "standard solution failed: {}",
+                error::RuntimeError::from(result)
+              ))
+            }
+          };
+          return match checker
+            .check(
+              self.args.clone(),
+              input,
+              output,
+              inputs[&test.answer].clone(),
+              user_copy_in.clone(),
+            )
+            .await
+          {
+            Ok(x) if x.output.status == checker::Status::Accepted => None,
+            Ok(x) => discrepancy(format!("{}: {}", x.output.status, x.output.message)),
+            Err(err) => discrepancy(format!("checker execute failed: {}", err)),
+          };
+        }
+      })
+      .collect();
+    let discrepancies: Vec<_> = stream::iter(checks)
+      .buffered(CONFIG.judge.answer_concurrency.max(1))
+      .filter_map(future::ready)
+      .collect()
+      .await;
+
+    let report = sandbox::FileHandle::upload(&serde_json::to_vec(&discrepancies).unwrap()).await?;
+    return Ok(Files::from([(self.report.clone(), report)]));
+  }
+}
+
+/// Generate the inputs of a test plan in parallel, and write them as `1.in`, `2.in`, …,
+/// in order of the plan (see `testplan::TestPlan::generate_all`).
+///
+/// The plan is given as its script.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestPlanTask {
+  pub plan: testplan::TestPlan,
+
+  /// Generators used by the plan by name.
+  pub generators: HashMap<String, ProgramFile>,
+
+  #[serde(default)]
+  pub copy_in: CopyIn,
+
+  /// Prefix of the names of the files to write (like `tests/`).
+  #[serde(default)]
+  pub prefix: String,
+}
+
+impl TestPlanTask {
+  /// Name of the file to write the input of a test (0-based index in the plan) to.
+  fn output(&self, index: usize) -> String {
+    return format!("{}{}.in", self.prefix, index + 1);
+  }
+}
+
+#[typetag::serde(name = "test_plan")]
+#[async_trait]
+impl Task for TestPlanTask {
+  fn inputs(&self) -> Vec<String> {
+    // Generators not given are reported when running the task.
+    let mut generators: Vec<_> = self.plan.generators().into_iter().collect();
+    generators.sort_unstable();
+    return [
+      (generators.into_iter())
+        .filter_map(|x| self.generators.get(x).map(|x| x.file.clone()))
+        .collect::<Vec<_>>(),
+      self.copy_in.values().cloned().collect(),
+    ]
+    .concat();
+  }
+
+  fn outputs(&self) -> Vec<String> {
+    return (0..self.plan.commands.len())
+      .map(|i| self.output(i))
+      .collect();
+  }
+
+  async fn run(&self, inputs: Files) -> Result<Files, TaskError> {
+    let generators = (self.plan.generators().into_iter())
+      .filter_map(|name| {
+        self.generators.get(name).map(|x| {
+          (
+            name.to_string(),
+            generator::Generator::from(x.executable(&inputs)),
+          )
+        })
+      })
+      .collect();
+    let copy_in = copy_in(&self.copy_in, &inputs);
+    let files = future::try_join_all(self.plan.expand(&generators)?.iter().enumerate().map(
+      |(index, input)| {
+        let copy_in = copy_in.clone();
+        async move {
+          return match input.make(copy_in).await {
+            Ok(file) => Ok((self.output(index), file)),
+            Err(error) => Err(testplan::TestPlanError::Generate { index, error }),
+          };
+        }
+      },
+    ))
+    .await?;
+    return Ok(files.into_iter().collect());
+  }
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// Status of an output judged by a testlib checker.
+#[derive(Debug, PartialEq, strum::EnumString, Serialize, Deserialize, Clone, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Status {
+  Accepted,
+  WrongAnswer,
+  PartiallyCorrect,
+  PresentationError,
+  SystemError,
+}
@@ -0,0 +1,12 @@
+//! Result models shared by the rindag server and rindag-judge,
+//! which are stored and sent over the wire, so they are defined once here.
+//!
+//! rindag-judge re-exports them at its own paths (like `rindag_judge::record::Timestamp`).
+
+pub mod checker;
+pub mod problem;
+pub mod record;
+pub mod sandbox;
+
+#[macro_use]
+extern crate lazy_static;
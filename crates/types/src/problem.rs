@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// Test set of a subtask or test case.
+#[derive(
+  Debug,
+  PartialEq,
+  Eq,
+  Hash,
+  strum::EnumString,
+  strum::Display,
+  strum::EnumIter,
+  Serialize,
+  Deserialize,
+  Clone,
+  Copy,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Testset {
+  Sample,
+  Pretests,
+  Main,
+  Hack,
+}
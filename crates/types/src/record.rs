@@ -0,0 +1,129 @@
+use std::{fmt, str::FromStr, time};
+
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use thiserror::Error;
+
+use crate::{checker, problem, sandbox};
+
+/// Judge result status for a program.
+#[derive(Debug, PartialEq, strum::EnumString, Serialize, Deserialize, Clone, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum RecordStatus {
+  Waiting,
+  Skipped,
+  Accepted,
+  WrongAnswer,
+  PartiallyCorrect,
+  PresentationError,
+  TimeLimitExceeded,
+  IdlenessLimitExceeded,
+  MemoryLimitExceeded,
+  OutputLimitExceeded,
+  FileError,
+  RuntimeError,
+  SystemError,
+}
+
+impl From<sandbox::Status> for RecordStatus {
+  fn from(s: sandbox::Status) -> Self {
+    match s {
+      sandbox::Status::Accepted => Self::Accepted,
+      sandbox::Status::TimeLimitExceeded => Self::TimeLimitExceeded,
+      sandbox::Status::IdlenessLimitExceeded => Self::IdlenessLimitExceeded,
+      sandbox::Status::MemoryLimitExceeded => Self::MemoryLimitExceeded,
+      sandbox::Status::OutputLimitExceeded => Self::OutputLimitExceeded,
+      sandbox::Status::FileError => Self::FileError,
+      sandbox::Status::NonZeroExitStatus => Self::RuntimeError,
+      sandbox::Status::DangerousSyscall => Self::RuntimeError,
+      sandbox::Status::Signalled => Self::RuntimeError,
+      sandbox::Status::InternalError => Self::SystemError,
+    }
+  }
+}
+
+impl From<checker::Status> for RecordStatus {
+  fn from(s: checker::Status) -> Self {
+    match s {
+      checker::Status::Accepted => Self::Accepted,
+      checker::Status::WrongAnswer => Self::WrongAnswer,
+      checker::Status::PartiallyCorrect => Self::PartiallyCorrect,
+      checker::Status::PresentationError => Self::PresentationError,
+      checker::Status::SystemError => Self::SystemError,
+    }
+  }
+}
+
+lazy_static! {
+  /// Monotonic anchor for `Timestamp::monotonic`, captured the first time a timestamp is taken.
+  static ref MONOTONIC_ANCHOR: time::Instant = time::Instant::now();
+}
+
+/// A point in time, recorded with both the wall clock and the monotonic clock.
+///
+/// The wall clock may jump (NTP adjustment, clock skew between machines),
+/// so use `monotonic` for ordering and latency analysis of events from the same process.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub struct Timestamp {
+  /// Wall-clock time, serialized as RFC3339.
+  pub wall: chrono::DateTime<chrono::Utc>,
+
+  /// Monotonic time elapsed since the judge process took its first timestamp.
+  pub monotonic: time::Duration,
+}
+
+impl Timestamp {
+  /// Take a timestamp of the current moment.
+  pub fn now() -> Self {
+    Self {
+      monotonic: MONOTONIC_ANCHOR.elapsed(),
+      wall: chrono::Utc::now(),
+    }
+  }
+}
+
+/// Stable identifier of a test inside a problem.
+///
+/// It does not change between rejudges of the same problem,
+/// so it can be used to join records, logs and stored rows.
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
+pub struct TestId {
+  /// Test set of the subtask which contains the test.
+  pub testset: problem::Testset,
+
+  /// Id of the subtask which contains the test.
+  pub subtask: usize,
+
+  /// Index of the test in the subtask (0-based).
+  pub index: usize,
+}
+
+impl fmt::Display for TestId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}/{}/{}", self.testset, self.subtask, self.index)
+  }
+}
+
+impl FromStr for TestId {
+  type Err = TestIdError;
+
+  /// Parse a test id in the form of `Display`, e.g. `main/1/0`.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let err = || TestIdError(s.to_string());
+    let mut parts = s.split('/');
+    let (testset, subtask, index) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+      (Some(a), Some(b), Some(c), None) => (a, b, c),
+      _ => return Err(err()),
+    };
+    return Ok(Self {
+      testset: testset.parse().map_err(|_| err())?,
+      subtask: subtask.parse().map_err(|_| err())?,
+      index: index.parse().map_err(|_| err())?,
+    });
+  }
+}
+
+/// Error when a test id can not be parsed.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("invalid test id `{0}`, expected `testset/subtask/index`")]
+pub struct TestIdError(pub String);
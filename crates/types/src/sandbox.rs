@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// Judge result status for a program.
+/// This enum is only used to represent the result after executing the program,
+/// and does not represent the result after the checker checks the correctness of the answer.
+#[derive(Debug, PartialEq, strum::EnumString, Serialize, Deserialize, Clone, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Status {
+  Accepted,
+  TimeLimitExceeded,
+
+  /// The wall clock time limit is exceeded but the time limit is not,
+  /// e.g. the program sleeps or waits for input (see `Cmd::wall_time_limit`).
+  IdlenessLimitExceeded,
+
+  MemoryLimitExceeded,
+  OutputLimitExceeded,
+  FileError,
+  NonZeroExitStatus,
+  DangerousSyscall,
+  Signalled,
+  InternalError,
+}
@@ -0,0 +1,86 @@
+use clap::{Parser, Subcommand};
+use shadow_rs::shadow;
+
+shadow!(build);
+
+// Command line args
+#[derive(Parser, Default)]
+#[clap(version = build::CLAP_LONG_VERSION)]
+#[clap(about = clap::crate_description!(), long_about = None)]
+pub struct Args {
+  #[clap(short, long, value_parser)]
+  pub config_search_path: Vec<String>,
+
+  /// Disable caching of compiled executables, generated files and checker outputs.
+  #[clap(long, value_parser)]
+  pub no_cache: bool,
+
+  #[clap(subcommand)]
+  pub command: Option<Command>,
+}
+
+/// Subcommands, the http server is run if none is given.
+#[derive(Subcommand, Clone)]
+pub enum Command {
+  /// Export the stored judge records for analytics.
+  Export(ExportArgs),
+
+  /// Check the configured languages end to end in the sandbox.
+  Doctor,
+
+  /// Measure the speed factor of the sandbox, to be set as `sandbox.time_factor` in config.
+  Calibrate,
+
+  /// Render the stored records of a problem as a verdict table (solutions × tests).
+  Report(ReportArgs),
+}
+
+#[derive(clap::Args, Clone, Default)]
+pub struct ExportArgs {
+  /// Output format, `csv` or `parquet`.
+  #[clap(long, value_parser, default_value = "csv")]
+  pub format: String,
+
+  /// Comma separated columns to export, all columns if not given.
+  #[clap(long, value_parser, value_delimiter = ',')]
+  pub columns: Vec<String>,
+
+  /// Only export jobs of the problem.
+  #[clap(long, value_parser)]
+  pub problem: Option<String>,
+
+  /// Only export jobs submitted at or after the time (RFC3339).
+  #[clap(long, value_parser)]
+  pub since: Option<String>,
+
+  /// Only export jobs submitted before the time (RFC3339).
+  #[clap(long, value_parser)]
+  pub until: Option<String>,
+
+  /// Output file, stdout if not given.
+  #[clap(short, long, value_parser)]
+  pub output: Option<String>,
+}
+
+#[derive(clap::Args, Clone, Default)]
+pub struct ReportArgs {
+  /// Problem whose finished jobs are reported, one row per job.
+  #[clap(long, value_parser)]
+  pub problem: String,
+
+  /// Output format, `html` or `csv`.
+  #[clap(long, value_parser, default_value = "html")]
+  pub format: String,
+
+  /// Output file, stdout if not given.
+  #[clap(short, long, value_parser)]
+  pub output: Option<String>,
+}
+
+lazy_static! {
+  pub static ref ARGS: Args = if cfg!(test) {
+    Args::default()
+  } else {
+    Args::parse()
+  };
+}
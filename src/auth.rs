@@ -0,0 +1,193 @@
+use std::time;
+
+use axum::{
+  http::{header, Method, Request, StatusCode},
+  middleware::Next,
+  response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use rindag_judge::CONFIG;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+
+use crate::service::ApiError;
+
+/// What a token is allowed to do, a scope includes all the scopes before it.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+  /// Query jobs and their progress.
+  ReadOnly,
+
+  /// Submit jobs.
+  Submit,
+
+  /// Cancel jobs and control the judging pipeline.
+  Admin,
+}
+
+/// Claims of a token.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct Claims {
+  /// Who the token is issued to.
+  pub sub: String,
+
+  pub scopes: Vec<Scope>,
+
+  /// Expiration time as a unix timestamp in seconds, None means never expires.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub exp: Option<u64>,
+}
+
+impl Claims {
+  /// Whether the token is allowed to do things of the given scope.
+  pub fn allows(&self, scope: Scope) -> bool {
+    return self.scopes.iter().any(|&x| x >= scope);
+  }
+}
+
+/// Error when a token is rejected.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum AuthError {
+  #[error("missing bearer token")]
+  Missing,
+
+  #[error("malformed token")]
+  Malformed,
+
+  #[error("unsupported token algorithm, only HS256 is supported")]
+  Algorithm,
+
+  #[error("invalid token signature")]
+  Signature,
+
+  #[error("token expired")]
+  Expired,
+
+  #[error("token does not have the {0:?} scope")]
+  Scope(Scope),
+}
+
+impl IntoResponse for AuthError {
+  fn into_response(self) -> Response {
+    let status = match self {
+      Self::Scope(_) => StatusCode::FORBIDDEN,
+      _ => StatusCode::UNAUTHORIZED,
+    };
+    return ApiError(status, self.to_string()).into_response();
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+  alg: String,
+}
+
+/// HMAC-SHA256 of the message, keyed by the secret.
+fn mac(secret: &[u8], message: &[u8]) -> Hmac<Sha256> {
+  let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+  mac.update(message);
+  return mac;
+}
+
+#[cfg(test)]
+fn encode(data: &[u8]) -> String {
+  return base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+}
+
+fn decode(data: &str) -> Result<Vec<u8>, AuthError> {
+  return base64::decode_config(data, base64::URL_SAFE_NO_PAD).map_err(|_| AuthError::Malformed);
+}
+
+/// Issue a JWT (HS256) token with the claims.
+///
+/// Tokens are issued by the platform in front of the judge, so this is only used by tests.
+#[cfg(test)]
+pub fn sign(claims: &Claims, secret: &[u8]) -> String {
+  let header = Header {
+    alg: "HS256".to_string(),
+  };
+  let message = format!(
+    "{}.{}",
+    encode(&serde_json::to_vec(&header).unwrap()),
+    encode(&serde_json::to_vec(claims).unwrap())
+  );
+  let signature = encode(&mac(secret, message.as_bytes()).finalize().into_bytes());
+  return format!("{}.{}", message, signature);
+}
+
+/// Verify a JWT (HS256) token and return its claims.
+///
+/// `now` is the current unix timestamp in seconds, to check the expiration time.
+///
+/// # Errors
+///
+/// This function will return an error if the token is malformed, the signature does not match
+/// the secret, or the token has expired.
+pub fn verify(token: &str, secret: &[u8], now: u64) -> Result<Claims, AuthError> {
+  let (message, signature) = token.rsplit_once('.').ok_or(AuthError::Malformed)?;
+  let (header, payload) = message.split_once('.').ok_or(AuthError::Malformed)?;
+
+  let header: Header =
+    serde_json::from_slice(&decode(header)?).map_err(|_| AuthError::Malformed)?;
+  if header.alg != "HS256" {
+    return Err(AuthError::Algorithm);
+  }
+  // Compared in constant time, so the signature can not be guessed byte by byte.
+  mac(secret, message.as_bytes())
+    .verify_slice(&decode(signature)?)
+    .map_err(|_| AuthError::Signature)?;
+
+  let claims: Claims =
+    serde_json::from_slice(&decode(payload)?).map_err(|_| AuthError::Malformed)?;
+  if claims.exp.is_some_and(|x| x <= now) {
+    return Err(AuthError::Expired);
+  }
+  return Ok(claims);
+}
+
+/// Scope needed by a request, reading needs the least,
+/// and cancelling or the admin endpoints need the most.
+fn required_scope(method: &Method, path: &str) -> Scope {
+  if path.starts_with("/admin/") {
+    return Scope::Admin;
+  }
+  return match *method {
+    Method::GET | Method::HEAD => Scope::ReadOnly,
+    Method::DELETE => Scope::Admin,
+    _ => Scope::Submit,
+  };
+}
+
+/// Middleware rejecting requests without a valid bearer token of the required scope,
+/// if a secret is set in config.
+///
+/// The claims of the token are added to the request extensions.
+pub async fn authenticate<B>(mut req: Request<B>, next: Next<B>) -> Result<Response, AuthError> {
+  let secret = match &CONFIG.secret {
+    Some(x) => x,
+    None => return Ok(next.run(req).await),
+  };
+
+  let token = req
+    .headers()
+    .get(header::AUTHORIZATION)
+    .and_then(|x| x.to_str().ok())
+    .and_then(|x| x.strip_prefix("Bearer "))
+    .ok_or(AuthError::Missing)?;
+  let now = time::SystemTime::now()
+    .duration_since(time::UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_secs();
+  let claims = verify(token, secret.as_bytes(), now)?;
+
+  let scope = required_scope(req.method(), req.uri().path());
+  if !claims.allows(scope) {
+    return Err(AuthError::Scope(scope));
+  }
+
+  log::debug!("request {} {} by {}", req.method(), req.uri(), claims.sub);
+  req.extensions_mut().insert(claims);
+  return Ok(next.run(req).await);
+}
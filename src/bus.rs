@@ -0,0 +1,77 @@
+use futures::{stream::BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Channel of the invalidations sent between the instances sharing a Redis server
+/// (see `cluster::Cluster`).
+pub const CHANNEL: &str = "rindag:invalidations";
+
+/// Number of invalidations kept for slow subscribers,
+/// a subscriber lagging behind more than this has to drop all of its cached problems.
+const CAPACITY: usize = 256;
+
+/// Invalidation of the cached data of a problem, e.g. when it is rebuilt.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Invalidation {
+  /// Problem id.
+  pub problem: String,
+
+  /// Revision of the rebuilt problem (see `problem::Problem::revision`),
+  /// cached data of this revision is still valid.
+  ///
+  /// None to invalidate any revision.
+  pub revision: Option<String>,
+}
+
+/// An invalidation sent to the other instances, with the instance sending it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RemoteInvalidation {
+  pub instance: String,
+
+  #[serde(flatten)]
+  pub invalidation: Invalidation,
+}
+
+/// In-process bus delivering invalidations to every cache of the server,
+/// so a rebuild announced once drops the stale problem everywhere.
+///
+/// Invalidations of other instances are delivered to the bus by `relay`,
+/// so a rebuild announced to any instance drops the stale problem on all of them.
+pub struct InvalidationBus {
+  tx: broadcast::Sender<Invalidation>,
+}
+
+impl Default for InvalidationBus {
+  fn default() -> Self {
+    return Self {
+      tx: broadcast::channel(CAPACITY).0,
+    };
+  }
+}
+
+impl InvalidationBus {
+  /// Publish an invalidation, and return the number of subscribers it is delivered to.
+  pub fn publish(&self, invalidation: Invalidation) -> usize {
+    log::info!(
+      "problem {} invalidated (revision: {:?})",
+      invalidation.problem,
+      invalidation.revision
+    );
+    return self.tx.send(invalidation).unwrap_or(0);
+  }
+
+  /// Subscribe to the invalidations published from now on.
+  pub fn subscribe(&self) -> broadcast::Receiver<Invalidation> {
+    return self.tx.subscribe();
+  }
+
+  /// Publish the invalidations sent by other instances than `instance` (this instance),
+  /// until the stream of them ends.
+  pub async fn relay(&self, instance: &str, mut remote: BoxStream<'static, RemoteInvalidation>) {
+    while let Some(x) = remote.next().await {
+      if x.instance != instance {
+        self.publish(x.invalidation);
+      }
+    }
+  }
+}
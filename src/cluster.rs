@@ -0,0 +1,94 @@
+use futures::{stream::BoxStream, StreamExt};
+use redis::{aio::ConnectionManager, RedisResult};
+use rindag_judge::CONFIG;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::queue;
+
+/// Prefix of the keys of the shared queue (see `queue::SharedQueue`).
+const QUEUE_PREFIX: &str = "rindag:queue";
+
+/// Instances of the judge service sharing a Redis server,
+/// which share the judge queue and send each other messages by pub/sub channels.
+///
+/// Each instance has a channel named by its id (see `instance_channel`),
+/// where the instances judging its jobs send the progress of the jobs.
+pub struct Cluster {
+  /// Id of this instance.
+  pub instance: String,
+
+  client: redis::Client,
+
+  conn: ConnectionManager,
+
+  pub queue: queue::SharedQueue,
+}
+
+impl Cluster {
+  /// Connect to the Redis server by a connection string (like `redis://localhost:6379`).
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the connection string is invalid,
+  /// or the server can not be connected.
+  pub async fn connect(url: &str, instance: String) -> RedisResult<Self> {
+    let client = redis::Client::open(url)?;
+    let conn = ConnectionManager::new(client.clone()).await?;
+    return Ok(Self {
+      instance,
+      client,
+      queue: queue::SharedQueue::new(conn.clone(), QUEUE_PREFIX.to_string()),
+      conn,
+    });
+  }
+
+  /// Connect to the Redis server of `redis` in config, None if it is not set.
+  pub async fn from_config(instance: String) -> RedisResult<Option<Self>> {
+    return match &CONFIG.redis {
+      Some(url) => Ok(Some(Self::connect(url, instance).await?)),
+      None => Ok(None),
+    };
+  }
+
+  /// Name of the channel of an instance.
+  pub fn instance_channel(instance: &str) -> String {
+    return format!("rindag:instance:{}", instance);
+  }
+
+  /// Publish a message as JSON to a channel.
+  pub async fn publish(&self, channel: &str, message: &impl Serialize) -> RedisResult<()> {
+    return redis::cmd("PUBLISH")
+      .arg(channel)
+      .arg(serde_json::to_string(message).unwrap())
+      .query_async(&mut self.conn.clone())
+      .await;
+  }
+
+  /// Subscribe to the messages published to a channel from now on.
+  ///
+  /// The stream ends when the connection is lost, so the messages in between are missed.
+  /// Messages which can not be decoded are skipped.
+  pub async fn subscribe<T: DeserializeOwned + Send + 'static>(
+    &self,
+    channel: &str,
+  ) -> RedisResult<BoxStream<'static, T>> {
+    let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+    pubsub.subscribe(channel).await?;
+    let channel = channel.to_string();
+    return Ok(
+      pubsub
+        .into_on_message()
+        .filter_map(move |x| {
+          let decoded = x
+            .get_payload::<String>()
+            .map_err(|err| err.to_string())
+            .and_then(|x| serde_json::from_str(&x).map_err(|err| err.to_string()));
+          if let Err(err) = &decoded {
+            log::warn!("invalid message on {}: {}", channel, err);
+          }
+          return futures::future::ready(decoded.ok());
+        })
+        .boxed(),
+    );
+  }
+}
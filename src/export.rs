@@ -0,0 +1,425 @@
+use std::{fmt, io, str::FromStr, sync::Arc};
+
+use parquet::{
+  basic::{LogicalType, Repetition, Type as PhysicalType},
+  data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type},
+  errors::ParquetError,
+  file::{properties::WriterProperties, writer::SerializedFileWriter},
+  schema::types::Type,
+};
+use rindag_judge::{record, report};
+use thiserror::Error;
+
+use crate::{
+  args::ExportArgs,
+  service::JobStatus,
+  storage::{Filter, JobRow},
+};
+
+/// File format of exported records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  Csv,
+  Parquet,
+}
+
+impl FromStr for Format {
+  type Err = ExportError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    return match s {
+      "csv" => Ok(Self::Csv),
+      "parquet" => Ok(Self::Parquet),
+      _ => Err(ExportError::Format(s.to_string())),
+    };
+  }
+}
+
+impl Format {
+  /// MIME type of the exported file.
+  pub fn content_type(&self) -> &'static str {
+    return match self {
+      Self::Csv => "text/csv",
+      Self::Parquet => "application/vnd.apache.parquet",
+    };
+  }
+}
+
+/// A column of exported records.
+///
+/// Each exported row is a record of a test with the metadata of its job,
+/// a job without records (e.g. compile error) is exported as a single row
+/// whose test columns are null.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+  JobId,
+  Problem,
+  Lang,
+  /// Submission time in RFC3339.
+  SubmittedAt,
+  /// Finish time in RFC3339.
+  FinishedAt,
+  /// `finished` or `failed`.
+  JobStatus,
+  JobScore,
+  Testset,
+  Subtask,
+  /// Index of the test in the subtask.
+  Test,
+  Status,
+  Score,
+  TimeMs,
+  WallTimeMs,
+  /// Memory in bytes.
+  Memory,
+  Attempts,
+  AlmostTle,
+  /// Sandbox node which judged the job.
+  Node,
+  JudgeVersion,
+}
+
+/// Type of the values of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+  Str,
+  Int,
+  Float,
+  Bool,
+}
+
+/// A value of a cell, None is null.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+  Str(Option<String>),
+  Int(Option<i64>),
+  Float(Option<f64>),
+  Bool(Option<bool>),
+}
+
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Value::Str(Some(x)) => write!(f, "{}", x),
+      Value::Int(Some(x)) => write!(f, "{}", x),
+      Value::Float(Some(x)) => write!(f, "{}", x),
+      Value::Bool(Some(x)) => write!(f, "{}", x),
+      _ => Ok(()),
+    }
+  }
+}
+
+impl Column {
+  pub const ALL: &'static [Column] = &[
+    Self::JobId,
+    Self::Problem,
+    Self::Lang,
+    Self::SubmittedAt,
+    Self::FinishedAt,
+    Self::JobStatus,
+    Self::JobScore,
+    Self::Testset,
+    Self::Subtask,
+    Self::Test,
+    Self::Status,
+    Self::Score,
+    Self::TimeMs,
+    Self::WallTimeMs,
+    Self::Memory,
+    Self::Attempts,
+    Self::AlmostTle,
+    Self::Node,
+    Self::JudgeVersion,
+  ];
+
+  pub fn name(&self) -> &'static str {
+    return match self {
+      Self::JobId => "job_id",
+      Self::Problem => "problem",
+      Self::Lang => "lang",
+      Self::SubmittedAt => "submitted_at",
+      Self::FinishedAt => "finished_at",
+      Self::JobStatus => "job_status",
+      Self::JobScore => "job_score",
+      Self::Testset => "testset",
+      Self::Subtask => "subtask",
+      Self::Test => "test",
+      Self::Status => "status",
+      Self::Score => "score",
+      Self::TimeMs => "time_ms",
+      Self::WallTimeMs => "wall_time_ms",
+      Self::Memory => "memory",
+      Self::Attempts => "attempts",
+      Self::AlmostTle => "almost_tle",
+      Self::Node => "node",
+      Self::JudgeVersion => "judge_version",
+    };
+  }
+
+  fn kind(&self) -> Kind {
+    return match self {
+      Self::JobScore | Self::Score => Kind::Float,
+      Self::Subtask
+      | Self::Test
+      | Self::TimeMs
+      | Self::WallTimeMs
+      | Self::Memory
+      | Self::Attempts => Kind::Int,
+      Self::AlmostTle => Kind::Bool,
+      _ => Kind::Str,
+    };
+  }
+
+  /// Value of the column in the row of a record of a job.
+  fn value(&self, job: &JobRow, record: Option<&record::Record>) -> Value {
+    let test_id = record.and_then(|x| x.test_id);
+    return match self {
+      Self::JobId => Value::Str(Some(job.id.to_string())),
+      Self::Problem => Value::Str(Some(job.problem.clone())),
+      Self::Lang => Value::Str(Some(job.lang.to_string())),
+      Self::SubmittedAt => Value::Str(Some(job.submitted_at.wall.to_rfc3339())),
+      Self::FinishedAt => Value::Str(Some(job.finished_at.wall.to_rfc3339())),
+      Self::JobStatus => Value::Str(Some(
+        match job.info.status {
+          JobStatus::Queued => "queued",
+          JobStatus::Judging => "judging",
+          JobStatus::Finished { .. } => "finished",
+          JobStatus::Compiled { .. } => "compiled",
+          JobStatus::Failed { .. } => "failed",
+          JobStatus::Cancelled { .. } => "cancelled",
+        }
+        .to_string(),
+      )),
+      Self::JobScore => Value::Float(match job.info.status {
+        JobStatus::Finished { score, .. } => Some(score as f64),
+        _ => None,
+      }),
+      Self::Testset => Value::Str(test_id.map(|x| x.testset.to_string())),
+      Self::Subtask => Value::Int(test_id.map(|x| x.subtask as i64)),
+      Self::Test => Value::Int(test_id.map(|x| x.index as i64)),
+      Self::Status => Value::Str(record.map(|x| x.status.to_string())),
+      Self::Score => Value::Float(record.map(|x| x.score as f64)),
+      Self::TimeMs => Value::Int(record.map(|x| x.time.as_millis() as i64)),
+      Self::WallTimeMs => Value::Int(record.map(|x| x.wall_time.as_millis() as i64)),
+      Self::Memory => Value::Int(record.map(|x| x.memory as i64)),
+      Self::Attempts => Value::Int(record.map(|x| x.attempts as i64)),
+      Self::AlmostTle => Value::Bool(record.map(|x| x.almost_tle)),
+      Self::Node => Value::Str(job.environment.as_ref().map(|x| x.node.clone())),
+      Self::JudgeVersion => Value::Str(job.environment.as_ref().map(|x| x.judge_version.clone())),
+    };
+  }
+}
+
+impl FromStr for Column {
+  type Err = ExportError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    return Self::ALL
+      .iter()
+      .find(|x| x.name() == s)
+      .copied()
+      .ok_or_else(|| ExportError::Column(s.to_string()));
+  }
+}
+
+/// An export of stored judge records.
+#[derive(Debug, Clone)]
+pub struct Export {
+  pub format: Format,
+
+  pub columns: Vec<Column>,
+
+  /// Which jobs to export.
+  pub filter: Filter,
+}
+
+impl TryFrom<&ExportArgs> for Export {
+  type Error = ExportError;
+
+  /// Parse the export options given on the command line or in the query of the admin endpoint.
+  fn try_from(args: &ExportArgs) -> Result<Self, Self::Error> {
+    let time = |x: &Option<String>| {
+      x.as_ref()
+        .map(|x| {
+          chrono::DateTime::parse_from_rfc3339(x)
+            .map(|x| x.with_timezone(&chrono::Utc))
+            .map_err(|_| ExportError::Time(x.clone()))
+        })
+        .transpose()
+    };
+    let columns = args
+      .columns
+      .iter()
+      .map(|x| x.parse())
+      .collect::<Result<Vec<_>, _>>()?;
+    return Ok(Self {
+      format: args.format.parse()?,
+      columns: if columns.is_empty() {
+        Column::ALL.to_vec()
+      } else {
+        columns
+      },
+      filter: Filter {
+        problem: args.problem.clone(),
+        since: time(&args.since)?,
+        until: time(&args.until)?,
+      },
+    });
+  }
+}
+
+impl Export {
+  /// Write the records of the jobs matching the filter to `out`.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the file can not be encoded or written.
+  pub fn write<W: io::Write + Send>(&self, jobs: &[JobRow], out: W) -> Result<(), ExportError> {
+    let rows: Vec<_> = jobs
+      .iter()
+      .filter(|x| self.filter.matches(x))
+      .flat_map(|job| match &job.info.status {
+        JobStatus::Finished { records, .. } | JobStatus::Cancelled { records, .. }
+          if !records.is_empty() =>
+        {
+          records.iter().map(|x| (job, Some(x))).collect()
+        }
+        _ => vec![(job, None)],
+      })
+      .collect();
+
+    return match self.format {
+      Format::Csv => self.write_csv(&rows, out),
+      Format::Parquet => self.write_parquet(&rows, out),
+    };
+  }
+
+  fn write_csv<W: io::Write>(
+    &self,
+    rows: &[(&JobRow, Option<&record::Record>)],
+    out: W,
+  ) -> Result<(), ExportError> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(self.columns.iter().map(|x| x.name()))?;
+    for (job, record) in rows {
+      writer.write_record(
+        self
+          .columns
+          .iter()
+          .map(|x| x.value(job, *record).to_string()),
+      )?;
+    }
+    writer.flush()?;
+    return Ok(());
+  }
+
+  /// Write all rows in a single row group, every column is optional.
+  fn write_parquet<W: io::Write + Send>(
+    &self,
+    rows: &[(&JobRow, Option<&record::Record>)],
+    out: W,
+  ) -> Result<(), ExportError> {
+    let fields = self
+      .columns
+      .iter()
+      .map(|column| {
+        let (physical, logical) = match column.kind() {
+          Kind::Str => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+          Kind::Int => (PhysicalType::INT64, None),
+          Kind::Float => (PhysicalType::DOUBLE, None),
+          Kind::Bool => (PhysicalType::BOOLEAN, None),
+        };
+        return Ok(Arc::new(
+          Type::primitive_type_builder(column.name(), physical)
+            .with_repetition(Repetition::OPTIONAL)
+            .with_logical_type(logical)
+            .build()?,
+        ));
+      })
+      .collect::<Result<Vec<_>, ParquetError>>()?;
+    let schema = Arc::new(
+      Type::group_type_builder("record")
+        .with_fields(fields)
+        .build()?,
+    );
+
+    let mut writer =
+      SerializedFileWriter::new(out, schema, Arc::new(WriterProperties::builder().build()))?;
+    let mut row_group = writer.next_row_group()?;
+    for column in &self.columns {
+      let values: Vec<_> = rows
+        .iter()
+        .map(|(job, record)| column.value(job, *record))
+        .collect();
+      let mut def_levels = vec![];
+      let mut strs = vec![];
+      let mut ints = vec![];
+      let mut floats = vec![];
+      let mut bools = vec![];
+      for value in values {
+        let present = match value {
+          Value::Str(x) => x.map(|x| strs.push(ByteArray::from(x.into_bytes()))),
+          Value::Int(x) => x.map(|x| ints.push(x)),
+          Value::Float(x) => x.map(|x| floats.push(x)),
+          Value::Bool(x) => x.map(|x| bools.push(x)),
+        };
+        def_levels.push(present.is_some() as i16);
+      }
+
+      let mut column_writer = row_group.next_column()?.unwrap();
+      let def_levels = Some(def_levels.as_slice());
+      match column.kind() {
+        Kind::Str => column_writer
+          .typed::<ByteArrayType>()
+          .write_batch(&strs, def_levels, None)?,
+        Kind::Int => column_writer
+          .typed::<Int64Type>()
+          .write_batch(&ints, def_levels, None)?,
+        Kind::Float => column_writer
+          .typed::<DoubleType>()
+          .write_batch(&floats, def_levels, None)?,
+        Kind::Bool => column_writer
+          .typed::<BoolType>()
+          .write_batch(&bools, def_levels, None)?,
+      };
+      column_writer.close()?;
+    }
+    row_group.close()?;
+    writer.close()?;
+    return Ok(());
+  }
+}
+
+/// Verdict table of the finished jobs of a problem, one row per job in order of submission,
+/// named by the job id and the language of the solution.
+pub fn verdict_table(jobs: &[JobRow], problem: &str) -> report::Table {
+  let mut table = report::Table::default();
+  for job in jobs.iter().filter(|x| x.problem == problem) {
+    if let JobStatus::Finished { records, .. } = &job.info.status {
+      table.push(&format!("{} ({})", job.id, job.lang), records.clone());
+    }
+  }
+  return table;
+}
+
+/// Error when exporting records.
+#[derive(Debug, Error)]
+pub enum ExportError {
+  #[error("unknown export format {0}, expected csv or parquet")]
+  Format(String),
+
+  #[error("unknown export column {0}")]
+  Column(String),
+
+  #[error("invalid time {0}, expected RFC3339")]
+  Time(String),
+
+  #[error(transparent)]
+  Io(#[from] io::Error),
+
+  #[error(transparent)]
+  Csv(#[from] csv::Error),
+
+  #[error(transparent)]
+  Parquet(#[from] ParquetError),
+}
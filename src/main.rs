@@ -1,3 +1,113 @@
-fn main() {
-  todo!();
+#[macro_use]
+extern crate lazy_static;
+
+mod args;
+mod auth;
+mod bus;
+mod cluster;
+mod export;
+mod leader;
+mod problems;
+mod queue;
+mod service;
+mod storage;
+#[cfg(test)]
+mod test;
+
+use std::{fs, io, net};
+
+use args::ARGS;
+use rindag_judge::{calibrate, doctor, etc, report, CONFIG};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+  env_logger::init();
+  _ = etc::init(etc::Options {
+    config_search_path: ARGS.config_search_path.clone(),
+    no_cache: ARGS.no_cache,
+  });
+
+  match &ARGS.command {
+    Some(args::Command::Export(args)) => return export(args).await,
+    Some(args::Command::Doctor) => return doctor().await,
+    Some(args::Command::Calibrate) => return calibrate().await,
+    Some(args::Command::Report(args)) => return report(args).await,
+    None => {}
+  }
+
+  match calibrate::auto_calibrate().await {
+    Ok(Some(x)) => log::info!(
+      "sandbox calibrated, time limits are scaled by {:.3}",
+      x.factor
+    ),
+    Ok(None) => {}
+    Err(err) => log::warn!(
+      "sandbox calibration failed, time limits are not scaled: {}",
+      err
+    ),
+  }
+
+  // Listen on all interfaces if only the port is given (e.g. `:8080`).
+  let addr: net::SocketAddr = match CONFIG.host.strip_prefix(':') {
+    Some(port) => net::SocketAddr::from(([0, 0, 0, 0], port.parse()?)),
+    None => CONFIG.host.parse()?,
+  };
+  log::info!("listening on {}", addr);
+
+  axum::Server::bind(&addr)
+    .serve(service::router().await?.into_make_service())
+    .await?;
+  return Ok(());
+}
+
+/// Check the configured languages, and fail if any probe fails.
+async fn doctor() -> Result<(), Box<dyn std::error::Error>> {
+  let report = doctor::diagnose().await;
+  print!("{}", report);
+  if !report.is_ok() {
+    return Err("some languages are misconfigured".into());
+  }
+  return Ok(());
+}
+
+/// Measure the speed factor of the sandbox and print it.
+async fn calibrate() -> Result<(), Box<dyn std::error::Error>> {
+  print!("{}", calibrate::calibrate().await?);
+  return Ok(());
+}
+
+/// Render the stored records of a problem as a verdict table to a file or stdout.
+async fn report(args: &args::ReportArgs) -> Result<(), Box<dyn std::error::Error>> {
+  let render = match args.format.as_str() {
+    "html" => report::Table::to_html,
+    "csv" => report::Table::to_csv,
+    x => return Err(format!("unknown report format {}, expected html or csv", x).into()),
+  };
+  let storage = storage::from_config()
+    .await?
+    .ok_or("jobs are not stored, set postgres or data_dir in config")?;
+  let filter = storage::Filter {
+    problem: Some(args.problem.clone()),
+    ..Default::default()
+  };
+  let table = export::verdict_table(&storage.list(&filter).await?, &args.problem);
+  match &args.output {
+    Some(path) => fs::write(path, render(&table))?,
+    None => print!("{}", render(&table)),
+  }
+  return Ok(());
+}
+
+/// Export the stored records to a file or stdout.
+async fn export(args: &args::ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+  let export = export::Export::try_from(args)?;
+  let storage = storage::from_config()
+    .await?
+    .ok_or("jobs are not stored, set postgres or data_dir in config")?;
+  let jobs = storage.list(&export.filter).await?;
+  match &args.output {
+    Some(path) => export.write(&jobs, io::BufWriter::new(fs::File::create(path)?))?,
+    None => export.write(&jobs, io::stdout())?,
+  }
+  return Ok(());
 }
@@ -0,0 +1,158 @@
+use std::{collections::HashMap, sync::Arc, sync::Mutex};
+
+use rindag_judge::CONFIG;
+use serde::Serialize;
+
+/// Cache of recently judged problems, by problem id,
+/// so that hot problems are not imported and prepared again for every job.
+///
+/// The least recently used problems are evicted when there are more than `capacity` problems,
+/// or their total size is more than `max_size`.
+///
+/// Each problem is cached with its revision (see `problem::Problem::revision`),
+/// so that it can be invalidated when the problem is rebuilt (see `bus::Invalidation`).
+pub struct ProblemCache<T> {
+  inner: Mutex<Inner<T>>,
+  capacity: usize,
+  max_size: u64,
+}
+
+struct Inner<T> {
+  entries: HashMap<String, Entry<T>>,
+
+  /// Total size of the entries.
+  size: u64,
+
+  /// Use counter, the entry with the smallest `used` is the least recently used.
+  tick: u64,
+
+  hits: u64,
+  misses: u64,
+}
+
+struct Entry<T> {
+  value: Arc<T>,
+  revision: String,
+  size: u64,
+  used: u64,
+}
+
+/// Usage of a problem cache.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct CacheMetrics {
+  /// Number of cached problems.
+  pub problems: usize,
+
+  /// Total size of cached problems in bytes.
+  pub size: u64,
+
+  pub hits: u64,
+  pub misses: u64,
+}
+
+impl<T> Default for ProblemCache<T> {
+  /// Cache with the limits in config.
+  fn default() -> Self {
+    return Self::new(CONFIG.problem_cache.capacity, CONFIG.problem_cache.max_size);
+  }
+}
+
+impl<T> ProblemCache<T> {
+  pub fn new(capacity: usize, max_size: u64) -> Self {
+    return Self {
+      inner: Mutex::new(Inner {
+        entries: HashMap::new(),
+        size: 0,
+        tick: 0,
+        hits: 0,
+        misses: 0,
+      }),
+      capacity,
+      max_size,
+    };
+  }
+
+  /// Get a cached problem, and mark it as recently used.
+  pub fn get(&self, id: &str) -> Option<Arc<T>> {
+    let mut inner = self.inner.lock().unwrap();
+    inner.tick += 1;
+    let tick = inner.tick;
+    let ret = inner.entries.get_mut(id).map(|entry| {
+      entry.used = tick;
+      entry.value.clone()
+    });
+    match ret {
+      Some(_) => inner.hits += 1,
+      None => inner.misses += 1,
+    }
+    return ret;
+  }
+
+  /// Cache a problem of the given revision and size, replacing the cached one with the same id,
+  /// and evict the least recently used problems until both limits are met.
+  ///
+  /// A problem larger than `max_size` is not cached.
+  pub fn insert(&self, id: String, revision: String, value: Arc<T>, size: u64) {
+    if self.capacity == 0 || size > self.max_size {
+      return;
+    }
+    let mut inner = self.inner.lock().unwrap();
+    inner.tick += 1;
+    let used = inner.tick;
+    let entry = Entry {
+      value,
+      revision,
+      size,
+      used,
+    };
+    if let Some(old) = inner.entries.insert(id, entry) {
+      inner.size -= old.size;
+    }
+    inner.size += size;
+
+    while inner.entries.len() > self.capacity || inner.size > self.max_size {
+      let lru = inner
+        .entries
+        .iter()
+        .min_by_key(|x| x.1.used)
+        .map(|x| x.0.clone())
+        .unwrap();
+      let entry = inner.entries.remove(&lru).unwrap();
+      inner.size -= entry.size;
+      log::debug!("problem {} evicted from cache", lru);
+    }
+  }
+
+  /// Remove a cached problem, e.g. when it is rebuilt,
+  /// unless it is already of the given revision.
+  ///
+  /// Returns whether the problem was removed.
+  /// Jobs already holding the problem keep judging with it.
+  pub fn invalidate(&self, id: &str, revision: Option<&str>) -> bool {
+    let mut inner = self.inner.lock().unwrap();
+    match inner.entries.get(id) {
+      Some(entry) if Some(entry.revision.as_str()) != revision => {}
+      _ => return false,
+    }
+    let entry = inner.entries.remove(id).unwrap();
+    inner.size -= entry.size;
+    return true;
+  }
+
+  /// Remove all cached problems.
+  pub fn clear(&self) {
+    let mut inner = self.inner.lock().unwrap();
+    inner.entries.clear();
+    inner.size = 0;
+  }
+
+  pub fn metrics(&self) -> CacheMetrics {
+    let inner = self.inner.lock().unwrap();
+    return CacheMetrics {
+      problems: inner.entries.len(),
+      size: inner.size,
+      hits: inner.hits,
+      misses: inner.misses,
+    };
+  }
+}
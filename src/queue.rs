@@ -0,0 +1,415 @@
+use std::{
+  cmp,
+  collections::{BinaryHeap, HashMap},
+  sync::Mutex,
+};
+
+use redis::aio::ConnectionManager;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use tokio::sync::Notify;
+
+/// Queue of judge jobs waiting for workers, ordered by priority and then by arrival.
+///
+/// Jobs with the same deduplication key are judged only once,
+/// a duplicate pushed while the first one is pending or judging is rejected.
+///
+/// The queue can be held, so that jobs are kept pending and no worker takes them until released.
+/// Holding by the administrator and suspending while the sandbox is unreachable are tracked apart,
+/// so that the sandbox coming back does not release a queue held on purpose.
+pub struct Queue<T> {
+  inner: Mutex<Inner<T>>,
+  notify: Notify,
+}
+
+struct Inner<T> {
+  pending: BinaryHeap<Entry<T>>,
+
+  /// Deduplication keys of pending and in-flight jobs, to the id of the job.
+  keys: HashMap<String, uuid::Uuid>,
+
+  in_flight: usize,
+
+  held: bool,
+
+  suspended: bool,
+
+  /// Arrival counter, keeps jobs of the same priority first in first out.
+  seq: u64,
+}
+
+struct Entry<T> {
+  priority: i32,
+  seq: u64,
+  id: uuid::Uuid,
+  key: String,
+  job: T,
+}
+
+impl<T> PartialEq for Entry<T> {
+  fn eq(&self, other: &Self) -> bool {
+    return self.cmp(other) == cmp::Ordering::Equal;
+  }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+  fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+    return Some(self.cmp(other));
+  }
+}
+
+impl<T> Ord for Entry<T> {
+  fn cmp(&self, other: &Self) -> cmp::Ordering {
+    // Higher priority first, then earlier arrival first.
+    return self
+      .priority
+      .cmp(&other.priority)
+      .then_with(|| other.seq.cmp(&self.seq));
+  }
+}
+
+/// Load of a queue.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct Metrics {
+  /// Number of jobs waiting for workers.
+  pub depth: usize,
+
+  /// Number of jobs being judged.
+  pub in_flight: usize,
+
+  /// Whether the queue is held.
+  pub held: bool,
+
+  /// Whether the queue is suspended because the sandbox is unreachable.
+  pub suspended: bool,
+}
+
+impl<T> Default for Queue<T> {
+  fn default() -> Self {
+    return Self {
+      inner: Mutex::new(Inner {
+        pending: BinaryHeap::new(),
+        keys: HashMap::new(),
+        in_flight: 0,
+        held: false,
+        suspended: false,
+        seq: 0,
+      }),
+      notify: Notify::new(),
+    };
+  }
+}
+
+impl<T> Queue<T> {
+  /// Push a job to the queue.
+  ///
+  /// # Errors
+  ///
+  /// This function will return the id of the existing job if a job with the same `key`
+  /// is pending or in flight.
+  pub fn push(&self, id: uuid::Uuid, key: String, priority: i32, job: T) -> Result<(), uuid::Uuid> {
+    let mut inner = self.inner.lock().unwrap();
+    if let Some(&existing) = inner.keys.get(&key) {
+      return Err(existing);
+    }
+    inner.keys.insert(key.clone(), id);
+    inner.seq += 1;
+    let seq = inner.seq;
+    inner.pending.push(Entry {
+      priority,
+      seq,
+      id,
+      key,
+      job,
+    });
+    drop(inner);
+    self.notify.notify_one();
+    return Ok(());
+  }
+
+  /// Wait for the next job, and count it in flight until `done` is called with its key.
+  ///
+  /// It waits while the queue is held or suspended.
+  pub async fn pop(&self) -> (uuid::Uuid, String, T) {
+    loop {
+      // Register for notifications before checking, so a push or release in between is not missed.
+      let notified = self.notify.notified();
+      tokio::pin!(notified);
+      notified.as_mut().enable();
+      {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.held && !inner.suspended {
+          if let Some(entry) = inner.pending.pop() {
+            inner.in_flight += 1;
+            return (entry.id, entry.key, entry.job);
+          }
+        }
+      }
+      notified.await;
+    }
+  }
+
+  /// Wait until the queue is neither held nor suspended,
+  /// for workers taking jobs from elsewhere (see `SharedQueue`).
+  pub async fn wait_open(&self) {
+    loop {
+      let notified = self.notify.notified();
+      tokio::pin!(notified);
+      notified.as_mut().enable();
+      {
+        let inner = self.inner.lock().unwrap();
+        if !inner.held && !inner.suspended {
+          return;
+        }
+      }
+      notified.await;
+    }
+  }
+
+  /// Hold the queue or release it.
+  pub fn set_held(&self, held: bool) {
+    self.inner.lock().unwrap().held = held;
+    if !held {
+      self.notify.notify_waiters();
+    }
+  }
+
+  /// Suspend the queue or resume it.
+  pub fn set_suspended(&self, suspended: bool) {
+    self.inner.lock().unwrap().suspended = suspended;
+    if !suspended {
+      self.notify.notify_waiters();
+    }
+  }
+
+  /// Mark a popped job as finished, so that its key can be pushed again.
+  pub fn done(&self, key: &str) {
+    let mut inner = self.inner.lock().unwrap();
+    inner.keys.remove(key);
+    inner.in_flight -= 1;
+  }
+
+  pub fn metrics(&self) -> Metrics {
+    let inner = self.inner.lock().unwrap();
+    return Metrics {
+      depth: inner.pending.len(),
+      in_flight: inner.in_flight,
+      held: inner.held,
+      suspended: inner.suspended,
+    };
+  }
+}
+
+/// Time a deduplication key of `SharedQueue` is kept, so the key of a job lost
+/// with a crashed instance can be pushed again after it.
+const DEDUP_TTL_SECS: u64 = 60 * 60;
+
+/// Take the first pending job of `SharedQueue`, with `KEYS` being its pending jobs,
+/// its jobs by id and its in-flight counter, so a job is never taken without being counted.
+///
+/// Returns nil if there is no pending job, or the popped member followed by the job if found.
+const POP_SCRIPT: &str = r"
+local popped = redis.call('ZPOPMIN', KEYS[1])
+if #popped == 0 then
+  return false
+end
+local id = string.sub(popped[1], 25)
+local job = redis.call('HGET', KEYS[2], id)
+if not job then
+  return {popped[1]}
+end
+redis.call('HDEL', KEYS[2], id)
+redis.call('INCR', KEYS[3])
+return {popped[1], job}
+";
+
+/// Error of a shared queue.
+#[derive(Debug, Error)]
+pub enum Error {
+  /// A job with the same key is pending or in flight, with the id of the existing job.
+  #[error("duplicate of job {0}")]
+  Duplicate(uuid::Uuid),
+
+  #[error("redis: {0}")]
+  Redis(#[from] redis::RedisError),
+
+  #[error(transparent)]
+  Json(#[from] serde_json::Error),
+
+  /// A member of the pending jobs is not pushed by `SharedQueue::push`.
+  #[error("invalid pending job {0}")]
+  Invalid(String),
+}
+
+/// Queue of judge jobs in a Redis server, shared by the instances using it,
+/// ordered by priority and then by arrival, and deduplicated like `Queue`.
+///
+/// Keys are prefixed by `prefix`:
+///
+/// - `{prefix}:pending`: the pending jobs, as members of a sorted set all scored 0,
+///   so they are ordered by the member, the priority and the arrival followed by the job id.
+/// - `{prefix}:jobs`: the pending jobs by id, with their keys (see `push`).
+/// - `{prefix}:key:{key}`: the id of the pending or in-flight job of a deduplication key.
+/// - `{prefix}:seq`: the arrival counter.
+/// - `{prefix}:in_flight`: the number of jobs taken but not done.
+///
+/// A job taken by an instance which crashes before it is done is lost,
+/// and its key is released after `DEDUP_TTL_SECS`.
+#[derive(Clone)]
+pub struct SharedQueue {
+  conn: ConnectionManager,
+  prefix: String,
+}
+
+impl SharedQueue {
+  pub fn new(conn: ConnectionManager, prefix: String) -> Self {
+    return Self { conn, prefix };
+  }
+
+  fn key(&self, name: &str) -> String {
+    return format!("{}:{}", self.prefix, name);
+  }
+
+  /// Push a job to the queue.
+  ///
+  /// # Errors
+  ///
+  /// This function will return `Error::Duplicate` with the id of the existing job
+  /// if a job with the same `key` is pending or in flight.
+  pub async fn push<T: Serialize>(
+    &self,
+    id: uuid::Uuid,
+    key: String,
+    priority: i32,
+    job: &T,
+  ) -> Result<(), Error> {
+    let mut conn = self.conn.clone();
+    let dedup = self.key(&format!("key:{}", key));
+    loop {
+      let set: Option<String> = redis::cmd("SET")
+        .arg(&dedup)
+        .arg(id.to_string())
+        .arg("NX")
+        .arg("EX")
+        .arg(DEDUP_TTL_SECS)
+        .query_async(&mut conn)
+        .await?;
+      if set.is_some() {
+        break;
+      }
+      // The existing key may expire in between, then try again.
+      let existing: Option<String> = redis::cmd("GET").arg(&dedup).query_async(&mut conn).await?;
+      if let Some(existing) = existing.and_then(|x| x.parse().ok()) {
+        return Err(Error::Duplicate(existing));
+      }
+    }
+
+    let pushed = async {
+      let seq: u64 = redis::cmd("INCR")
+        .arg(self.key("seq"))
+        .query_async(&mut conn.clone())
+        .await?;
+      // Higher priority first, so the priority is flipped to sort in ascending order.
+      let member = format!(
+        "{:08x}{:016x}{}",
+        (i32::MAX as i64 - priority as i64) as u32,
+        seq,
+        id
+      );
+      redis::pipe()
+        .atomic()
+        .hset(
+          self.key("jobs"),
+          id.to_string(),
+          serde_json::to_string(&(&key, job))?,
+        )
+        .ignore()
+        .zadd(self.key("pending"), member, 0)
+        .ignore()
+        .query_async::<_, ()>(&mut conn.clone())
+        .await?;
+      return Ok::<_, Error>(());
+    }
+    .await;
+    if pushed.is_err() {
+      // Release the key, which would hold back the same job for a job never pushed.
+      if let Err(err) = redis::cmd("DEL")
+        .arg(&dedup)
+        .query_async::<_, i64>(&mut conn)
+        .await
+      {
+        log::warn!("deduplication key {} is not released: {}", key, err);
+      }
+    }
+    return pushed;
+  }
+
+  /// Take the next job if any, and count it in flight until `done` is called with its key.
+  ///
+  /// # Errors
+  ///
+  /// This function will return an error if the server is unreachable,
+  /// or the job can not be decoded, in which case the job is dropped.
+  /// The job is popped and counted in flight at once (see `POP_SCRIPT`),
+  /// so it is not lost if this instance crashes in between.
+  pub async fn try_pop<T: DeserializeOwned>(
+    &self,
+  ) -> Result<Option<(uuid::Uuid, String, T)>, Error> {
+    let mut conn = self.conn.clone();
+    let popped: Option<Vec<String>> = redis::Script::new(POP_SCRIPT)
+      .key(self.key("pending"))
+      .key(self.key("jobs"))
+      .key(self.key("in_flight"))
+      .invoke_async(&mut conn)
+      .await?;
+    let (member, job) = match popped {
+      Some(x) if x.len() == 2 => (x[0].clone(), x[1].clone()),
+      Some(x) => return Err(Error::Invalid(x.concat())),
+      None => return Ok(None),
+    };
+    let id: Result<uuid::Uuid, _> = member
+      .get(24..)
+      .and_then(|x| x.parse().ok())
+      .ok_or_else(|| Error::Invalid(member.clone()));
+    let decoded = id.and_then(|id| {
+      return serde_json::from_str::<(String, T)>(&job)
+        .map(|(key, job)| (id, key, job))
+        .map_err(Error::from);
+    });
+    return match decoded {
+      Ok(x) => Ok(Some(x)),
+      Err(err) => {
+        redis::cmd("DECR")
+          .arg(self.key("in_flight"))
+          .query_async::<_, i64>(&mut conn)
+          .await?;
+        Err(err)
+      }
+    };
+  }
+
+  /// Mark a taken job as finished, so that its key can be pushed again.
+  pub async fn done(&self, key: &str) -> Result<(), Error> {
+    redis::pipe()
+      .atomic()
+      .del(self.key(&format!("key:{}", key)))
+      .ignore()
+      .decr(self.key("in_flight"), 1)
+      .ignore()
+      .query_async::<_, ()>(&mut self.conn.clone())
+      .await?;
+    return Ok(());
+  }
+
+  /// Number of pending jobs and number of jobs in flight, of all the instances.
+  pub async fn load(&self) -> Result<(usize, usize), Error> {
+    let (depth, in_flight): (usize, Option<i64>) = redis::pipe()
+      .zcard(self.key("pending"))
+      .get(self.key("in_flight"))
+      .query_async(&mut self.conn.clone())
+      .await?;
+    return Ok((depth, in_flight.unwrap_or(0).max(0) as usize));
+  }
+}
@@ -0,0 +1,1793 @@
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap},
+  convert::Infallible,
+  path,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
+  time,
+};
+
+use axum::{
+  extract::{Path, Query, State},
+  http::{header, StatusCode},
+  middleware,
+  response::{
+    sse::{Event, Sse},
+    IntoResponse, Response,
+  },
+  routing::{delete, get, post},
+  Extension, Json, Router,
+};
+use futures::{channel::mpsc, stream, stream::BoxStream, Stream, StreamExt};
+use rindag_judge::{
+  artifact, builtin, doctor, environment, etc, problem, program, record, sandbox, schema, CONFIG,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::{broadcast, watch, Mutex};
+
+use crate::{args, auth, bus, cluster, export, leader, problems, queue, storage};
+
+/// Interval of workers polling the shared queue (see `cluster::Cluster`) while it is empty.
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Interval of retrying to subscribe to the channel of this instance after losing it.
+const RESUBSCRIBE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// A judge job submitted to the server.
+struct Job {
+  /// Progress events sent so far, replayed to new subscribers.
+  events: Vec<problem::Response>,
+
+  /// Live progress events.
+  notify: broadcast::Sender<problem::Response>,
+
+  status: JobStatus,
+
+  /// Resource usage of the compiled programs.
+  compile: BTreeMap<problem::CompileTarget, program::CompileReport>,
+
+  cancel: watch::Sender<Option<problem::Cancellation>>,
+
+  /// Id of the problem, with the solution and the profile to reproduce a test (see `bundle`).
+  problem: String,
+  solution: program::Source,
+  profile: etc::ProfileCfg,
+
+  /// Who submitted the job, None if authentication is disabled.
+  submitter: Option<String>,
+
+  /// Instance judging the job, None if it is not taken yet.
+  worker: Option<String>,
+
+  /// When the job is done, None if it is not done yet (see `evict_jobs`).
+  done_at: Option<time::Instant>,
+}
+
+impl Job {
+  /// Record a progress event and send it to the subscribers.
+  fn push_event(&mut self, res: problem::Response) {
+    if let problem::Response::CompileFinished {
+      program, report, ..
+    } = &res
+    {
+      self.compile.insert(*program, *report);
+    }
+    self.events.push(res.clone());
+    _ = self.notify.send(res);
+  }
+
+  /// Set the final status of the job, and drop the sender of the live events,
+  /// so subscribers stop waiting even if the job ends without a last event (e.g. it failed).
+  fn finish(&mut self, status: JobStatus) {
+    self.status = status;
+    self.notify = broadcast::channel(1).0;
+    self.done_at = Some(time::Instant::now());
+  }
+}
+
+/// Status of a judge job.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+  /// Waiting in the queue for a worker.
+  Queued,
+  Judging,
+  Finished {
+    score: f32,
+    records: Vec<record::Record>,
+  },
+  /// A compile-only job is done, whether the solution compiled or not.
+  Compiled {
+    check: program::CompileCheck,
+  },
+  /// The job can not be judged (e.g. compile error).
+  Failed {
+    message: String,
+  },
+  /// The job is cancelled (see `cancel`), with the records of the tests,
+  /// where the tests not judged before the cancellation are skipped.
+  Cancelled {
+    reason: problem::CancelReason,
+
+    /// Who cancelled the job, None if it is cancelled by the system.
+    by: Option<String>,
+
+    records: Vec<record::Record>,
+  },
+}
+
+impl JobStatus {
+  /// Whether the job is over, so it sends no more events.
+  pub fn is_done(&self) -> bool {
+    return matches!(
+      self,
+      JobStatus::Finished { .. }
+        | JobStatus::Compiled { .. }
+        | JobStatus::Failed { .. }
+        | JobStatus::Cancelled { .. }
+    );
+  }
+
+  /// Records of the tests, empty if the job is not judged.
+  pub fn records(&self) -> &[record::Record] {
+    return match self {
+      JobStatus::Finished { records, .. } | JobStatus::Cancelled { records, .. } => records,
+      _ => &[],
+    };
+  }
+}
+
+/// Status of a job with the resource usage of its compilations.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobInfo {
+  #[serde(flatten)]
+  pub status: JobStatus,
+
+  pub compile: BTreeMap<problem::CompileTarget, program::CompileReport>,
+}
+
+/// Problem of a queued job.
+enum TaskProblem {
+  /// Taken from the cache.
+  Prepared(Arc<problem::PreparedProblem>),
+
+  /// Imported from the package, to be prepared by the worker.
+  Imported(Box<problem::Problem>),
+
+  /// Taken from the cache of the worker, or imported from the package and prepared by it,
+  /// for jobs submitted to another instance.
+  Package,
+}
+
+/// A job waiting in the queue, with everything needed to judge it.
+struct Task {
+  problem: TaskProblem,
+  req: SubmitRequest,
+  profile: etc::ProfileCfg,
+  cancel: problem::CancelToken,
+  submitted_at: record::Timestamp,
+}
+
+/// A job waiting in the shared queue, judged by the worker of any instance.
+#[derive(Debug, Serialize, Deserialize)]
+struct SharedTask {
+  /// Instance which the job is submitted to, which serves its status and events.
+  home: String,
+  req: SubmitRequest,
+  profile: etc::ProfileCfg,
+  submitted_at: record::Timestamp,
+}
+
+/// Message about a job between the instance it is submitted to and the instance judging it,
+/// sent to the channel of the receiving instance (see `cluster::Cluster::instance_channel`).
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JobMessage {
+  /// The job is taken by the worker of an instance.
+  Started { job: uuid::Uuid, worker: String },
+
+  /// A progress event of the job.
+  Event {
+    job: uuid::Uuid,
+    event: Box<problem::Response>,
+  },
+
+  /// The job is over.
+  Finished { job: uuid::Uuid, info: JobInfo },
+
+  /// Cancel the job, sent to the instance judging it.
+  Cancel {
+    job: uuid::Uuid,
+    cancellation: problem::Cancellation,
+  },
+}
+
+#[derive(Default)]
+pub struct AppState {
+  jobs: Mutex<HashMap<uuid::Uuid, Job>>,
+
+  queue: queue::Queue<Task>,
+
+  intake: std::sync::Mutex<Intake>,
+
+  /// Storage of finished jobs, None to keep jobs in memory only.
+  storage: Option<Box<dyn storage::Storage>>,
+
+  /// Recently judged problems.
+  problems: problems::ProblemCache<problem::PreparedProblem>,
+
+  /// Invalidations of rebuilt problems.
+  bus: bus::InvalidationBus,
+
+  /// Number of sample runs in progress.
+  sample_runs: AtomicUsize,
+
+  /// Election of the instance running the background duties,
+  /// None if the state is not served by `router`, which never leads.
+  election: Option<Box<dyn leader::Election>>,
+
+  /// Reachability of the sandbox, watched by `standby`.
+  sandbox: std::sync::Mutex<SandboxState>,
+
+  /// Instances sharing the judge queue, None if `redis` is not set and the queue is local.
+  cluster: Option<cluster::Cluster>,
+
+  /// Cancellations of the jobs of other instances being judged by this instance.
+  remote_cancels:
+    std::sync::Mutex<HashMap<uuid::Uuid, watch::Sender<Option<problem::Cancellation>>>>,
+}
+
+/// A sample run in progress, counted in `AppState::sample_runs` until it is dropped.
+struct SampleSlot<'a>(&'a AtomicUsize);
+
+impl Drop for SampleSlot<'_> {
+  fn drop(&mut self) {
+    self.0.fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
+/// Whether new jobs are accepted.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct Intake {
+  /// New jobs of all problems are rejected.
+  pub paused: bool,
+
+  /// Problems whose new jobs are rejected.
+  pub paused_problems: BTreeSet<String>,
+}
+
+/// Reachability of the sandbox.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SandboxState {
+  /// The sandbox is unreachable, so the queue is suspended until it is back.
+  pub down: bool,
+
+  /// When the sandbox became unreachable, None if it is reachable.
+  pub down_since: Option<record::Timestamp>,
+
+  /// Error of the last failed check.
+  pub last_error: Option<String>,
+
+  /// Number of times the sandbox became unreachable since the service started.
+  pub outages: u64,
+}
+
+/// State of the judging pipeline, the intake, the queue and the problem cache.
+#[derive(Debug, Serialize, Clone)]
+pub struct Pipeline {
+  pub intake: Intake,
+
+  pub sandbox: SandboxState,
+
+  #[serde(flatten)]
+  pub queue: queue::Metrics,
+
+  pub problems: problems::CacheMetrics,
+}
+
+impl AppState {
+  /// Pause the intake of a problem, or all problems if `problem` is None.
+  ///
+  /// Queued jobs are still judged (drained) unless the queue is held by `hold`,
+  /// which is only allowed when pausing all problems.
+  pub fn pause(&self, problem: Option<String>, hold: bool) {
+    let mut intake = self.intake.lock().unwrap();
+    match problem {
+      Some(problem) => {
+        intake.paused_problems.insert(problem);
+      }
+      None => {
+        intake.paused = true;
+        if hold {
+          self.queue.set_held(true);
+        }
+      }
+    }
+  }
+
+  /// Resume the intake of a problem, or all problems and the queue if `problem` is None.
+  ///
+  /// Resuming all problems does not resume the problems paused one by one.
+  pub fn resume(&self, problem: Option<&str>) {
+    let mut intake = self.intake.lock().unwrap();
+    match problem {
+      Some(problem) => {
+        intake.paused_problems.remove(problem);
+      }
+      None => {
+        intake.paused = false;
+        self.queue.set_held(false);
+      }
+    }
+  }
+
+  /// Whether new jobs of the problem are accepted.
+  pub fn accepts(&self, problem: &str) -> bool {
+    let intake = self.intake.lock().unwrap();
+    return !intake.paused && !intake.paused_problems.contains(problem);
+  }
+
+  /// Record the result of a sandbox check, suspending the queue when the sandbox becomes
+  /// unreachable and resuming it when the sandbox is back.
+  ///
+  /// Returns whether the reachability changed.
+  pub fn set_sandbox_result(&self, result: Result<(), String>) -> bool {
+    let mut sandbox = self.sandbox.lock().unwrap();
+    let changed = sandbox.down != result.is_err();
+    match result {
+      Ok(()) => {
+        sandbox.down = false;
+        sandbox.down_since = None;
+      }
+      Err(err) => {
+        if !sandbox.down {
+          sandbox.down = true;
+          sandbox.down_since = Some(record::Timestamp::now());
+          sandbox.outages += 1;
+        }
+        sandbox.last_error = Some(err);
+      }
+    }
+    if changed {
+      self.queue.set_suspended(sandbox.down);
+    }
+    return changed;
+  }
+
+  /// Whether the sandbox is unreachable.
+  pub fn sandbox_down(&self) -> bool {
+    return self.sandbox.lock().unwrap().down;
+  }
+
+  pub fn pipeline(&self) -> Pipeline {
+    return Pipeline {
+      intake: self.intake.lock().unwrap().clone(),
+      sandbox: self.sandbox.lock().unwrap().clone(),
+      queue: self.queue.metrics(),
+      problems: self.problems.metrics(),
+    };
+  }
+
+  /// The pipeline with the queue depth and the jobs in flight of all the instances
+  /// if the queue is shared, or of this instance if the shared queue is unreachable.
+  pub async fn shared_pipeline(&self) -> Pipeline {
+    let mut pipeline = self.pipeline();
+    if let Some(cluster) = &self.cluster {
+      match cluster.queue.load().await {
+        Ok((depth, in_flight)) => {
+          pipeline.queue.depth = depth;
+          pipeline.queue.in_flight = in_flight;
+        }
+        Err(err) => log::warn!("failed to query the shared queue: {}", err),
+      }
+    }
+    return pipeline;
+  }
+
+  /// Send a message about a job to another instance.
+  async fn send_job_message(&self, instance: &str, message: &JobMessage) {
+    if let Some(cluster) = &self.cluster {
+      let channel = cluster::Cluster::instance_channel(instance);
+      if let Err(err) = cluster.publish(&channel, message).await {
+        log::error!(
+          "failed to send a job message to instance {}: {}",
+          instance,
+          err
+        );
+      }
+    }
+  }
+}
+
+/// Request body of pausing the pipeline.
+#[derive(Debug, Deserialize)]
+pub struct PauseRequest {
+  /// Problem to pause, None to pause all problems.
+  pub problem: Option<String>,
+
+  /// Hold the queued jobs instead of draining them.
+  #[serde(default)]
+  pub hold: bool,
+}
+
+/// Request body of resuming the pipeline.
+#[derive(Debug, Deserialize)]
+pub struct ResumeRequest {
+  /// Problem to resume, None to resume all problems and the queue.
+  pub problem: Option<String>,
+}
+
+/// Query of invalidating a problem, see `bus::Invalidation`.
+#[derive(Debug, Deserialize)]
+pub struct InvalidateQuery {
+  /// Revision of the rebuilt problem, None to invalidate any revision.
+  pub revision: Option<String>,
+}
+
+/// Query of downloading the bundle of a failed test, see `problem::Bundle`.
+#[derive(Debug, Deserialize)]
+pub struct BundleQuery {
+  /// Id of the test (e.g. `main/1/0`), the first failed test if not given.
+  pub test: Option<String>,
+}
+
+/// Query of subscribing to the events of a job.
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+  /// Schema version of the events (see `schema::VERSION`), the current version if not given.
+  pub schema_version: Option<u32>,
+}
+
+/// Query of exporting the stored records, see `export::Export`.
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+  /// `csv` (default) or `parquet`.
+  pub format: Option<String>,
+
+  /// Comma separated columns, all columns if not given.
+  pub columns: Option<String>,
+
+  pub problem: Option<String>,
+
+  /// Only jobs submitted at or after the time (RFC3339).
+  pub since: Option<String>,
+
+  /// Only jobs submitted before the time (RFC3339).
+  pub until: Option<String>,
+}
+
+/// Response body of the health check.
+#[derive(Debug, Serialize)]
+pub struct Health {
+  /// `ok`, `paused` if the intake of all problems is paused, `held` if the queue is held,
+  /// or `system_paused` if the sandbox is unreachable and the queued jobs wait for it.
+  pub status: &'static str,
+
+  /// Whether this instance runs the background duties (see `leader::Election`).
+  pub leader: bool,
+
+  #[serde(flatten)]
+  pub pipeline: Pipeline,
+}
+
+/// Request body of submitting a judge job.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubmitRequest {
+  /// Id of the problem, which is the name of its package directory in `problem_dir`.
+  pub problem: String,
+
+  pub solution: program::Source,
+
+  /// Test sets to judge on.
+  #[serde(default = "default_testsets")]
+  pub testsets: Vec<problem::Testset>,
+
+  /// Name of the judging profile in config.
+  pub profile: Option<String>,
+
+  /// Jobs of higher priority are judged first.
+  #[serde(default)]
+  pub priority: i32,
+
+  #[serde(default)]
+  pub kind: JobKind,
+}
+
+/// What a job does with the solution.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+  /// Judge the solution on the tests.
+  #[default]
+  Judge,
+
+  /// Only compile the solution with the extra files of the problem, and report the diagnostics
+  /// and the hash of the executable, e.g. for syntax checks before submitting.
+  ///
+  /// The test sets and the profile are ignored.
+  Compile,
+}
+
+impl SubmitRequest {
+  /// Key to deduplicate the same solution submitted again by the same submitter
+  /// to the same problem, so the job of a submitter is never returned to another one.
+  fn dedup_key(&self, submitter: Option<&str>) -> String {
+    let content = serde_json::to_vec(&(
+      submitter,
+      &self.problem,
+      &self.solution,
+      &self.testsets,
+      &self.profile,
+      self.kind,
+    ))
+    .unwrap();
+    return format!("{:x}", Sha256::digest(content));
+  }
+}
+
+/// Request body of running a solution on the sample tests.
+#[derive(Debug, Deserialize)]
+pub struct SampleRequest {
+  /// Id of the problem, which is the name of its package directory in `problem_dir`.
+  pub problem: String,
+
+  pub solution: program::Source,
+
+  /// Name of the judging profile in config, whose limits are used.
+  pub profile: Option<String>,
+}
+
+fn default_testsets() -> Vec<problem::Testset> {
+  return vec![problem::Testset::Main];
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubmitResponse {
+  pub id: uuid::Uuid,
+}
+
+/// Error response of the api.
+pub struct ApiError(pub StatusCode, pub String);
+
+impl IntoResponse for ApiError {
+  fn into_response(self) -> Response {
+    return (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response();
+  }
+}
+
+/// Build the router of the judge service.
+///
+/// - `POST /jobs`: submit a judge job, or a compile-only job (see `JobKind`).
+/// - `GET /jobs/:id`: query the status of a job.
+/// - `GET /jobs/:id/events`: stream the progress events of a job by server-sent events.
+/// - `GET /jobs/:id/bundle`: download the input, output and answer of a failed test as a tar
+///   archive (see `BundleQuery`).
+/// - `DELETE /jobs/:id`: cancel a job.
+/// - `POST /samples`: run a solution on the sample tests and respond the outputs,
+///   without creating a job (see `problem::PreparedProblem::run_samples`).
+/// - `GET /checkers`: list the builtin checkers with their metadata
+///   (see `builtin::CheckerInfo`).
+/// - `GET /metrics`: query the queue depth, the number of jobs in flight and the pipeline state.
+/// - `GET /health`: check the health and the pipeline state.
+/// - `POST /admin/pause`: pause the intake of a problem or all problems, optionally hold the queue.
+/// - `POST /admin/resume`: resume the intake of a problem or all problems and the queue.
+/// - `GET /admin/export`: export the stored records as CSV or Parquet (see `ExportQuery`).
+/// - `GET /admin/artifacts/:id`: download an artifact spilled while judging, like the full
+///   checker message of a record (see `record::Record::message_artifact`).
+/// - `GET /admin/doctor`: check the configured languages end to end (see `doctor::diagnose`).
+/// - `DELETE /admin/problems/:id`: invalidate a problem after it is rebuilt,
+///   on all the instances sharing the Redis server of `redis` if it is set in config
+///   (see `InvalidateQuery`).
+///
+/// Requests need a token of the required scope if a secret is set in config
+/// (see `auth::authenticate`).
+///
+/// Finished jobs are stored in the PostgreSQL database of `postgres` if it is set in config,
+/// or in `data_dir` if it is set (see `storage::from_config`).
+/// Several instances may share the same `data_dir` and database,
+/// and the finished jobs of all of them are queried from any instance.
+/// One of them is elected as the leader to run the background duties
+/// (see `duties` and `leader::from_config`).
+///
+/// If `redis` is set in config, the instances share the judge queue (see `cluster::Cluster`),
+/// so a job submitted to any instance is judged by the first free worker of all of them.
+/// The status and the events of a job are still served by the instance it is submitted to,
+/// which the judging instance sends them to.
+/// Instances sharing the queue must serve the same `problem_dir`.
+///
+/// While the sandbox is unreachable, jobs are still accepted and queued,
+/// and judged when the sandbox is back (see `standby`).
+///
+/// Problems are prepared (see `problem::Problem::prepare`) once and cached for later jobs,
+/// so the programs of a problem are only compiled on the first job after a cache miss.
+/// Invalidations are delivered to the cache through `bus::InvalidationBus`,
+/// and to the other instances through the Redis server if `redis` is set in config,
+/// otherwise each instance has to be invalidated on its own.
+///
+/// Submitted jobs are queued and judged by `workers` (in config) workers,
+/// which are spawned on the current tokio runtime.
+pub async fn router() -> Result<Router, Box<dyn std::error::Error>> {
+  let instance = uuid::Uuid::new_v4().to_string();
+  let cluster = cluster::Cluster::from_config(instance.clone()).await?;
+  // Subscribe before serving, so no message about the jobs submitted from now on is missed.
+  let messages = match &cluster {
+    Some(cluster) => Some((
+      cluster
+        .subscribe(&cluster::Cluster::instance_channel(&instance))
+        .await?,
+      cluster.subscribe(bus::CHANNEL).await?,
+    )),
+    None => None,
+  };
+  let state = AppState {
+    storage: storage::from_config().await?,
+    election: Some(leader::from_config(instance.clone()).await?),
+    cluster,
+    ..Default::default()
+  };
+  let state = Arc::new(state);
+  if let Some((jobs, invalidations)) = messages {
+    tokio::spawn(listen_jobs(state.clone(), jobs));
+    tokio::spawn(listen_invalidations(state.clone(), invalidations));
+  }
+  tokio::spawn(invalidator(state.clone(), state.bus.subscribe()));
+  tokio::spawn(duties(state.clone()));
+  tokio::spawn(standby(state.clone()));
+  for _ in 0..CONFIG.workers.max(1) {
+    tokio::spawn(worker(state.clone()));
+  }
+
+  return Ok(
+    Router::new()
+      .route("/jobs", post(submit))
+      .route("/jobs/:id", get(status).delete(cancel))
+      .route("/jobs/:id/events", get(events))
+      .route("/jobs/:id/records", get(records))
+      .route("/jobs/:id/bundle", get(bundle))
+      .route("/samples", post(run_samples))
+      .route("/checkers", get(list_checkers))
+      .route("/metrics", get(metrics))
+      .route("/health", get(health))
+      .route("/admin/pause", post(pause))
+      .route("/admin/resume", post(resume))
+      .route("/admin/export", get(export_records))
+      .route("/admin/artifacts/:id", get(download_artifact))
+      .route("/admin/doctor", get(diagnose))
+      .route("/admin/problems/:id", delete(invalidate_problem))
+      .route("/admin/problems/:id/builds", get(problem_builds))
+      .layer(middleware::from_fn(auth::authenticate))
+      .with_state(state),
+  );
+}
+
+async fn submit(
+  State(state): State<Arc<AppState>>,
+  claims: Option<Extension<auth::Claims>>,
+  Json(req): Json<SubmitRequest>,
+) -> Result<(StatusCode, Json<SubmitResponse>), ApiError> {
+  check_problem_id(&req.problem)?;
+  if !state.accepts(&req.problem) {
+    return Err(ApiError(
+      StatusCode::SERVICE_UNAVAILABLE,
+      "judging is paused".to_string(),
+    ));
+  }
+  let profile = find_profile(req.profile.as_deref())?;
+  let problem = match state.problems.get(&req.problem) {
+    Some(x) => TaskProblem::Prepared(x),
+    None => TaskProblem::Imported(Box::new(
+      problem::package::import_polygon(&path::Path::new(&CONFIG.problem_dir).join(&req.problem))
+        .await
+        .map_err(|err| ApiError(StatusCode::NOT_FOUND, err.to_string()))?,
+    )),
+  };
+  // Cached problems are prepared, so they are always judgeable.
+  if let TaskProblem::Imported(x) = &problem {
+    x.check_judgeable()
+      .map_err(|err| ApiError(StatusCode::BAD_REQUEST, err.to_string()))?;
+  }
+
+  let id = uuid::Uuid::new_v4();
+  let submitter = claims.map(|x| x.0.sub);
+  let key = req.dedup_key(submitter.as_deref());
+  let priority = req.priority;
+  let (cancel_tx, cancel_rx) = problem::cancel_channel();
+  let job = Job {
+    events: vec![],
+    notify: broadcast::channel(1024).0,
+    status: JobStatus::Queued,
+    compile: BTreeMap::new(),
+    cancel: cancel_tx,
+    problem: req.problem.clone(),
+    solution: req.solution.clone(),
+    profile: profile.clone(),
+    submitter,
+    worker: None,
+    done_at: None,
+  };
+  if let Some(cluster) = &state.cluster {
+    // Only deduplicate to the jobs submitted to this instance, which is sent their progress
+    // by the instances taking them, so it can serve the returned job.
+    let key = format!("{}:{}", cluster.instance, key);
+    let task = SharedTask {
+      home: cluster.instance.clone(),
+      req,
+      profile,
+      submitted_at: record::Timestamp::now(),
+    };
+    // Add the job first, so messages from the instance taking it are never missed.
+    state.jobs.lock().await.insert(id, job);
+    let pushed = cluster.queue.push(id, key, priority, &task).await;
+    if pushed.is_err() {
+      state.jobs.lock().await.remove(&id);
+    }
+    return match pushed {
+      Ok(()) => {
+        log::info!("job {} submitted to the shared queue", id);
+        Ok((StatusCode::CREATED, Json(SubmitResponse { id })))
+      }
+      Err(queue::Error::Duplicate(existing)) => {
+        log::info!("job deduplicated to {}", existing);
+        Ok((StatusCode::OK, Json(SubmitResponse { id: existing })))
+      }
+      Err(err) => Err(ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
+    };
+  }
+  let task = Task {
+    problem,
+    req,
+    profile,
+    cancel: cancel_rx,
+    submitted_at: record::Timestamp::now(),
+  };
+
+  // Hold the lock until the job is added, so workers never see a queued job without it.
+  let mut jobs = state.jobs.lock().await;
+  if let Err(existing) = state.queue.push(id, key, priority, task) {
+    log::info!("job deduplicated to {}", existing);
+    return Ok((StatusCode::OK, Json(SubmitResponse { id: existing })));
+  }
+  jobs.insert(id, job);
+  log::info!("job {} submitted", id);
+
+  return Ok((StatusCode::CREATED, Json(SubmitResponse { id })));
+}
+
+/// Check that a problem id names a package directory in `problem_dir`.
+fn check_problem_id(id: &str) -> Result<(), ApiError> {
+  if id.is_empty()
+    || !id
+      .chars()
+      .all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_')
+  {
+    return Err(ApiError(
+      StatusCode::BAD_REQUEST,
+      "invalid problem id".to_string(),
+    ));
+  }
+  return Ok(());
+}
+
+/// Find a judging profile in config by name, the default profile if not given.
+fn find_profile(name: Option<&str>) -> Result<etc::ProfileCfg, ApiError> {
+  return match name {
+    Some(name) => CONFIG
+      .profiles
+      .get(name)
+      .cloned()
+      .ok_or_else(|| ApiError(StatusCode::BAD_REQUEST, format!("unknown profile {}", name))),
+    None => Ok(etc::ProfileCfg::default()),
+  };
+}
+
+/// Get a prepared problem from the cache, or import and prepare it and add it to the cache.
+async fn prepared_problem(
+  state: &AppState,
+  problem_id: &str,
+  correlation_id: uuid::Uuid,
+) -> Result<Arc<problem::PreparedProblem>, ApiError> {
+  if let Some(x) = state.problems.get(problem_id) {
+    return Ok(x);
+  }
+  let problem =
+    problem::package::import_polygon(&path::Path::new(&CONFIG.problem_dir).join(problem_id))
+      .await
+      .map_err(|err| ApiError(StatusCode::NOT_FOUND, err.to_string()))?;
+  let x = Arc::new(
+    problem
+      .prepare(correlation_id, None)
+      .await
+      .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?,
+  );
+  state.problems.insert(
+    problem_id.to_string(),
+    x.revision.clone(),
+    x.clone(),
+    x.size(),
+  );
+  return Ok(x);
+}
+
+/// Apply the invalidations published on the bus to the problem cache.
+///
+/// If some invalidations are missed, all the cached problems are dropped to be safe.
+async fn invalidator(state: Arc<AppState>, mut rx: broadcast::Receiver<bus::Invalidation>) {
+  loop {
+    match rx.recv().await {
+      Ok(x) => {
+        if state.problems.invalidate(&x.problem, x.revision.as_deref()) {
+          log::info!("problem {} dropped from cache", x.problem);
+        }
+      }
+      Err(broadcast::error::RecvError::Lagged(n)) => {
+        log::warn!("{} invalidations missed, clearing the problem cache", n);
+        state.problems.clear();
+      }
+      Err(broadcast::error::RecvError::Closed) => return,
+    }
+  }
+}
+
+/// Run the background duties every `duties.interval` (in config) while this instance is the leader.
+///
+/// Other instances try to become the leader at each interval,
+/// so the duties move to another instance soon after the leader exits.
+/// Every instance evicts its own finished jobs at each interval (see `evict_jobs`).
+async fn duties(state: Arc<AppState>) {
+  let mut interval = tokio::time::interval(CONFIG.duties.interval);
+  loop {
+    interval.tick().await;
+    evict_jobs(&state, CONFIG.duties.job_ttl).await;
+    let leading = match &state.election {
+      Some(election) => election.try_lead().await.unwrap_or_else(|err| {
+        log::warn!("leader election failed: {}", err);
+        false
+      }),
+      None => false,
+    };
+    if leading {
+      remove_orphans(&state).await;
+    }
+  }
+}
+
+/// Drop the jobs done for longer than `ttl` from memory, with their event history,
+/// so the memory does not grow with the finished jobs.
+///
+/// The status and the records of the evicted jobs are served from the storage
+/// (see `status` and `records`), they are gone if no storage is set.
+async fn evict_jobs(state: &AppState, ttl: time::Duration) {
+  let mut jobs = state.jobs.lock().await;
+  let before = jobs.len();
+  jobs.retain(|_, job| !job.done_at.is_some_and(|x| x.elapsed() >= ttl));
+  let evicted = before - jobs.len();
+  if evicted > 0 {
+    log::info!("{} finished jobs evicted from memory", evicted);
+  }
+}
+
+/// Check the sandbox every `sandbox.health_check_interval` (in config),
+/// and keep the queued jobs waiting while it is unreachable,
+/// instead of failing them with system errors.
+///
+/// Outages are logged and counted in the pipeline metrics (see `SandboxState`).
+async fn standby(state: Arc<AppState>) {
+  let mut interval = tokio::time::interval(CONFIG.sandbox.health_check_interval);
+  loop {
+    interval.tick().await;
+    let result = sandbox::ping().await.map_err(|err| err.to_string());
+    let err = result.clone().err();
+    if state.set_sandbox_result(result) {
+      match err {
+        Some(err) => log::error!("sandbox is unreachable, queued jobs wait for it: {}", err),
+        None => log::warn!("sandbox is back, resuming the queue"),
+      }
+    }
+  }
+}
+
+/// Remove the files left half written in the storage and the artifact store.
+async fn remove_orphans(state: &AppState) {
+  let age = CONFIG.duties.orphan_age;
+  let removed = match &state.storage {
+    Some(storage) => storage.remove_orphans(age).await,
+    None => Ok(0),
+  };
+  match removed {
+    Ok(0) => {}
+    Ok(n) => log::info!("{} orphan job files removed", n),
+    Err(err) => log::warn!("failed to remove orphan job files: {}", err),
+  }
+  let removed = match artifact::STORE.as_ref() {
+    Some(store) => store.remove_orphans(age).await,
+    None => Ok(0),
+  };
+  match removed {
+    Ok(0) => {}
+    Ok(n) => log::info!("{} orphan artifacts removed", n),
+    Err(err) => log::warn!("failed to remove orphan artifacts: {}", err),
+  }
+}
+
+/// Take jobs from the queue and judge them one by one.
+///
+/// If the queue is shared, the jobs of other instances are judged for them,
+/// the progress is sent to the instance of each job (see `JobMessage`).
+async fn worker(state: Arc<AppState>) {
+  loop {
+    let (id, key, task, home) = match &state.cluster {
+      Some(cluster) => take_shared(&state, cluster).await,
+      None => {
+        let (id, key, task) = state.queue.pop().await;
+        (id, key, task, None)
+      }
+    };
+    if home.is_none() {
+      if let Some(job) = state.jobs.lock().await.get_mut(&id) {
+        job.status = JobStatus::Judging;
+      }
+    }
+    // Judge in a separate task, so a panic fails the job instead of the worker.
+    if let Err(err) = tokio::spawn(run(state.clone(), id, task, home.clone())).await {
+      log::error!("job {} panicked: {}", id, err);
+      let status = JobStatus::Failed {
+        message: "internal error".to_string(),
+      };
+      match &home {
+        Some(home) => {
+          let info = JobInfo {
+            status,
+            compile: BTreeMap::new(),
+          };
+          state
+            .send_job_message(home, &JobMessage::Finished { job: id, info })
+            .await;
+        }
+        None => {
+          if let Some(job) = state.jobs.lock().await.get_mut(&id) {
+            job.finish(status);
+          }
+        }
+      }
+    }
+    match &state.cluster {
+      Some(cluster) => {
+        state.remote_cancels.lock().unwrap().remove(&id);
+        if let Err(err) = cluster.queue.done(&key).await {
+          log::error!(
+            "failed to mark job {} done in the shared queue: {}",
+            id,
+            err
+          );
+        }
+      }
+      None => state.queue.done(&key),
+    }
+  }
+}
+
+/// Wait for the next job of the shared queue while the local queue is open,
+/// and return it with the instance it is submitted to, None if it is this instance.
+///
+/// The other instance is told the job is started, and its cancellations are delivered
+/// through `AppState::remote_cancels`.
+async fn take_shared(
+  state: &AppState,
+  cluster: &cluster::Cluster,
+) -> (uuid::Uuid, String, Task, Option<String>) {
+  loop {
+    state.queue.wait_open().await;
+    let (id, key, task) = match cluster.queue.try_pop::<SharedTask>().await {
+      Ok(Some(x)) => x,
+      Ok(None) => {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        continue;
+      }
+      Err(err) => {
+        log::error!("failed to take a job from the shared queue: {}", err);
+        tokio::time::sleep(POLL_INTERVAL).await;
+        continue;
+      }
+    };
+
+    let (cancel, home) = if task.home == cluster.instance {
+      let mut jobs = state.jobs.lock().await;
+      let cancel = match jobs.get_mut(&id) {
+        Some(job) => {
+          job.worker = Some(cluster.instance.clone());
+          job.cancel.subscribe()
+        }
+        None => problem::cancel_channel().1,
+      };
+      (cancel, None)
+    } else {
+      let (cancel_tx, cancel_rx) = problem::cancel_channel();
+      state.remote_cancels.lock().unwrap().insert(id, cancel_tx);
+      let message = JobMessage::Started {
+        job: id,
+        worker: cluster.instance.clone(),
+      };
+      state.send_job_message(&task.home, &message).await;
+      (cancel_rx, Some(task.home))
+    };
+    let task = Task {
+      problem: TaskProblem::Package,
+      req: task.req,
+      profile: task.profile,
+      cancel,
+      submitted_at: task.submitted_at,
+    };
+    return (id, key, task, home);
+  }
+}
+
+/// Judge the job, record the progress events and the final status,
+/// or send them to the instance `home` which the job is submitted to.
+async fn run(state: Arc<AppState>, id: uuid::Uuid, task: Task, home: Option<String>) {
+  let Task {
+    problem,
+    req,
+    profile,
+    cancel,
+    submitted_at,
+  } = task;
+  let (tx, mut rx) = mpsc::unbounded();
+  // Revision of the problem if it is built by this job, so the build is stored.
+  let mut built = None;
+  let judge = async {
+    let problem = match problem {
+      TaskProblem::Prepared(x) => x,
+      TaskProblem::Imported(x) => {
+        let x = prepare_imported(&state, id, &req.problem, *x, &tx).await?;
+        built = Some(x.revision.clone());
+        x
+      }
+      TaskProblem::Package => match state.problems.get(&req.problem) {
+        Some(x) => x,
+        None => {
+          let x = problem::package::import_polygon(
+            &path::Path::new(&CONFIG.problem_dir).join(&req.problem),
+          )
+          .await
+          .map_err(|err| err.to_string())?;
+          let x = prepare_imported(&state, id, &req.problem, x, &tx).await?;
+          built = Some(x.revision.clone());
+          x
+        }
+      },
+    };
+    if req.kind == JobKind::Compile {
+      return match problem.compile_only(id, &req.solution, Some(tx)).await {
+        Ok(check) => Ok(JobStatus::Compiled { check }),
+        Err(err) => Err(err.to_string()),
+      };
+    }
+    return match problem
+      .judge_source(
+        id,
+        &profile,
+        &req.testsets,
+        &req.solution,
+        Some(tx),
+        None,
+        Some(cancel),
+      )
+      .await
+    {
+      Ok((score, records)) => Ok(JobStatus::Finished {
+        score,
+        records: records.into_iter().flat_map(|x| x.1).collect(),
+      }),
+      Err(err) => Err(err.to_string()),
+    };
+  };
+  let mut compile = BTreeMap::new();
+  let mut cancellation = None;
+  let forward = async {
+    while let Some(res) = rx.next().await {
+      match &res {
+        problem::Response::CompileFinished {
+          program, report, ..
+        } => {
+          compile.insert(*program, *report);
+        }
+        problem::Response::Cancelled { reason, by, .. } => {
+          cancellation = Some(problem::Cancellation {
+            reason: reason.clone(),
+            by: by.clone(),
+          });
+        }
+        _ => {}
+      }
+      match &home {
+        Some(home) => {
+          let message = JobMessage::Event {
+            job: id,
+            event: Box::new(res),
+          };
+          state.send_job_message(home, &message).await;
+        }
+        None => {
+          if let Some(job) = state.jobs.lock().await.get_mut(&id) {
+            job.push_event(res);
+          }
+        }
+      }
+    }
+  };
+  let (result, _) = futures::join!(judge, forward);
+
+  // A cancelled job is judged to the end with the remaining tests skipped,
+  // so it is told by the `Cancelled` event.
+  let status = match (result, cancellation) {
+    (Ok(JobStatus::Finished { records, .. }), Some(cancellation)) => JobStatus::Cancelled {
+      reason: cancellation.reason,
+      by: cancellation.by,
+      records,
+    },
+    (result, _) => result.unwrap_or_else(|message| JobStatus::Failed { message }),
+  };
+  log::info!("job {} done", id);
+  let info = JobInfo { status, compile };
+  match &home {
+    Some(home) => {
+      let message = JobMessage::Finished {
+        job: id,
+        info: info.clone(),
+      };
+      state.send_job_message(home, &message).await;
+    }
+    None => match state.jobs.lock().await.get_mut(&id) {
+      Some(job) => job.finish(info.status.clone()),
+      None => return,
+    },
+  }
+
+  if let Some(storage) = &state.storage {
+    if let Some(revision) = built {
+      let build = storage::BuildRow {
+        job: id,
+        problem: req.problem.clone(),
+        revision,
+        built_at: record::Timestamp::now(),
+        compile: info
+          .compile
+          .iter()
+          .filter(|x| *x.0 != problem::CompileTarget::Solution)
+          .map(|(target, report)| (*target, *report))
+          .collect(),
+      };
+      if let Err(err) = storage.save_build(&build).await {
+        log::error!(
+          "failed to store the build of problem {}: {}",
+          req.problem,
+          err
+        );
+      }
+    }
+    let row = storage::JobRow {
+      id,
+      problem: req.problem,
+      lang: req.solution.lang,
+      testsets: req.testsets,
+      submitted_at,
+      finished_at: record::Timestamp::now(),
+      info,
+      environment: Some(environment::ENVIRONMENT.clone()),
+    };
+    if let Err(err) = storage.save(&row).await {
+      log::error!("failed to store job {}: {}", id, err);
+    }
+  }
+}
+
+/// Prepare an imported problem for a job, and add it to the cache.
+async fn prepare_imported(
+  state: &AppState,
+  id: uuid::Uuid,
+  problem_id: &str,
+  problem: problem::Problem,
+  tx: &mpsc::UnboundedSender<problem::Response>,
+) -> Result<Arc<problem::PreparedProblem>, String> {
+  let x = Arc::new(
+    problem
+      .prepare(id, Some(tx.clone()))
+      .await
+      .map_err(|err| err.to_string())?,
+  );
+  state.problems.insert(
+    problem_id.to_string(),
+    x.revision.clone(),
+    x.clone(),
+    x.size(),
+  );
+  return Ok(x);
+}
+
+/// Apply the messages sent to the channel of this instance about its jobs
+/// judged by other instances (see `JobMessage`), and about the jobs of other instances
+/// judged by this instance.
+///
+/// If the subscription is lost, the channel is subscribed again,
+/// the messages in between are missed.
+async fn listen_jobs(state: Arc<AppState>, mut messages: BoxStream<'static, JobMessage>) {
+  let cluster = match &state.cluster {
+    Some(x) => x,
+    None => return,
+  };
+  let channel = cluster::Cluster::instance_channel(&cluster.instance);
+  loop {
+    while let Some(message) = messages.next().await {
+      apply_job_message(&state, message).await;
+    }
+    messages = resubscribe(cluster, &channel).await;
+  }
+}
+
+/// Deliver the invalidations of other instances to the bus of this instance.
+///
+/// If the subscription is lost, the channel is subscribed again,
+/// and all the cached problems are dropped, as some invalidations may be missed.
+async fn listen_invalidations(
+  state: Arc<AppState>,
+  mut invalidations: BoxStream<'static, bus::RemoteInvalidation>,
+) {
+  let cluster = match &state.cluster {
+    Some(x) => x,
+    None => return,
+  };
+  loop {
+    state.bus.relay(&cluster.instance, invalidations).await;
+    invalidations = resubscribe(cluster, bus::CHANNEL).await;
+    log::warn!("invalidations may be missed, clearing the problem cache");
+    state.problems.clear();
+  }
+}
+
+/// Subscribe to a channel again after losing the subscription, retrying until it succeeds.
+async fn resubscribe<T: DeserializeOwned + Send + 'static>(
+  cluster: &cluster::Cluster,
+  channel: &str,
+) -> BoxStream<'static, T> {
+  log::error!("lost the subscription to {}, subscribing again", channel);
+  loop {
+    tokio::time::sleep(RESUBSCRIBE_INTERVAL).await;
+    match cluster.subscribe(channel).await {
+      Ok(x) => return x,
+      Err(err) => log::error!("failed to subscribe to {}: {}", channel, err),
+    }
+  }
+}
+
+async fn apply_job_message(state: &AppState, message: JobMessage) {
+  match message {
+    JobMessage::Started { job, worker } => {
+      let cancellation = match state.jobs.lock().await.get_mut(&job) {
+        Some(x) => {
+          x.status = JobStatus::Judging;
+          x.worker = Some(worker.clone());
+          x.cancel.borrow().clone()
+        }
+        None => None,
+      };
+      // Cancelled while queued, so the worker has not got the cancellation.
+      if let Some(cancellation) = cancellation {
+        let message = JobMessage::Cancel { job, cancellation };
+        state.send_job_message(&worker, &message).await;
+      }
+    }
+    JobMessage::Event { job, event } => {
+      if let Some(x) = state.jobs.lock().await.get_mut(&job) {
+        x.push_event(*event);
+      }
+    }
+    JobMessage::Finished { job, info } => {
+      if let Some(x) = state.jobs.lock().await.get_mut(&job) {
+        x.compile = info.compile;
+        x.finish(info.status);
+      }
+    }
+    JobMessage::Cancel { job, cancellation } => {
+      if let Some(cancel) = state.remote_cancels.lock().unwrap().get(&job) {
+        cancel.send_replace(Some(cancellation));
+      }
+    }
+  }
+}
+
+async fn status(
+  State(state): State<Arc<AppState>>,
+  Path(id): Path<uuid::Uuid>,
+) -> Result<Json<JobInfo>, ApiError> {
+  if let Some(job) = state.jobs.lock().await.get(&id) {
+    return Ok(Json(JobInfo {
+      status: job.status.clone(),
+      compile: job.compile.clone(),
+    }));
+  }
+
+  // Jobs finished before a restart or evicted (see `evict_jobs`) are only in the storage.
+  let row = match &state.storage {
+    Some(storage) => storage
+      .load(&id)
+      .await
+      .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?,
+    None => None,
+  };
+  return match row {
+    Some(row) => Ok(Json(row.info)),
+    None => Err(ApiError(StatusCode::NOT_FOUND, "job not found".to_string())),
+  };
+}
+
+/// Records of the tests of a job, empty if it is not judged yet.
+async fn records(
+  State(state): State<Arc<AppState>>,
+  Path(id): Path<uuid::Uuid>,
+) -> Result<Json<Vec<record::Record>>, ApiError> {
+  if let Some(job) = state.jobs.lock().await.get(&id) {
+    return Ok(Json(job.status.records().to_vec()));
+  }
+
+  let storage = state
+    .storage
+    .as_ref()
+    .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, "job not found".to_string()))?;
+  if storage
+    .load(&id)
+    .await
+    .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+    .is_none()
+  {
+    return Err(ApiError(StatusCode::NOT_FOUND, "job not found".to_string()));
+  }
+  return Ok(Json(storage.records(&id).await.map_err(|err| {
+    ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+  })?));
+}
+
+async fn list_checkers() -> Json<Vec<builtin::CheckerInfo>> {
+  return Json(builtin::checkers());
+}
+
+async fn metrics(State(state): State<Arc<AppState>>) -> Json<Pipeline> {
+  return Json(state.shared_pipeline().await);
+}
+
+async fn health(State(state): State<Arc<AppState>>) -> Json<Health> {
+  let pipeline = state.shared_pipeline().await;
+  let status = if pipeline.sandbox.down {
+    "system_paused"
+  } else if pipeline.queue.held {
+    "held"
+  } else if pipeline.intake.paused {
+    "paused"
+  } else {
+    "ok"
+  };
+  let leader = state.election.as_ref().is_some_and(|x| x.is_leader());
+  return Json(Health {
+    status,
+    leader,
+    pipeline,
+  });
+}
+
+async fn pause(
+  State(state): State<Arc<AppState>>,
+  Json(req): Json<PauseRequest>,
+) -> Result<Json<Pipeline>, ApiError> {
+  if req.problem.is_some() && req.hold {
+    return Err(ApiError(
+      StatusCode::BAD_REQUEST,
+      "the queue can only be held for all problems".to_string(),
+    ));
+  }
+  log::warn!("pausing {:?} (hold: {})", req.problem, req.hold);
+  state.pause(req.problem, req.hold);
+  return Ok(Json(state.shared_pipeline().await));
+}
+
+async fn resume(
+  State(state): State<Arc<AppState>>,
+  Json(req): Json<ResumeRequest>,
+) -> Json<Pipeline> {
+  log::warn!("resuming {:?}", req.problem);
+  state.resume(req.problem.as_deref());
+  return Json(state.shared_pipeline().await);
+}
+
+async fn cancel(
+  State(state): State<Arc<AppState>>,
+  claims: Option<Extension<auth::Claims>>,
+  Path(id): Path<uuid::Uuid>,
+) -> Result<StatusCode, ApiError> {
+  let cancellation = problem::Cancellation {
+    reason: problem::CancelReason::Admin,
+    by: claims.map(|x| x.0.sub),
+  };
+  let found = state.jobs.lock().await.get(&id).map(|job| {
+    // Kept even without a receiver, for a job still in the shared queue.
+    job.cancel.send_replace(Some(cancellation.clone()));
+    return job.worker.clone();
+  });
+  let worker = match found {
+    Some(x) => x,
+    None => return Err(evicted_error(&state, &id).await),
+  };
+  // Jobs judged by other instances are cancelled there.
+  if let (Some(worker), Some(cluster)) = (worker, &state.cluster) {
+    if worker != cluster.instance {
+      let message = JobMessage::Cancel {
+        job: id,
+        cancellation,
+      };
+      state.send_job_message(&worker, &message).await;
+    }
+  }
+  return Ok(StatusCode::ACCEPTED);
+}
+
+/// Download the bundle of a failed test of a finished job.
+///
+/// Administrators can download any test, while the submitter can only download the tests
+/// of the test sets in `bundle.submitter_testsets` (in config), e.g. not the hidden tests.
+async fn bundle(
+  State(state): State<Arc<AppState>>,
+  claims: Option<Extension<auth::Claims>>,
+  Path(id): Path<uuid::Uuid>,
+  Query(query): Query<BundleQuery>,
+) -> Result<Response, ApiError> {
+  let test_id = match &query.test {
+    Some(x) => Some(
+      x.parse::<record::TestId>()
+        .map_err(|err| ApiError(StatusCode::BAD_REQUEST, err.to_string()))?,
+    ),
+    None => None,
+  };
+
+  let (problem_id, solution, profile, submitter, record) = {
+    let jobs = state.jobs.lock().await;
+    // Jobs finished before a restart or evicted (see `evict_jobs`) are only in the storage,
+    // without their solutions.
+    let job = jobs.get(&id).ok_or_else(|| {
+      ApiError(
+        StatusCode::NOT_FOUND,
+        "job not found or not in memory".to_string(),
+      )
+    })?;
+    let records = match &job.status {
+      JobStatus::Finished { records, .. } | JobStatus::Cancelled { records, .. } => records,
+      _ => {
+        return Err(ApiError(
+          StatusCode::CONFLICT,
+          "job is not finished".to_string(),
+        ))
+      }
+    };
+    let record = records
+      .iter()
+      .find(|x| match test_id {
+        Some(test_id) => x.test_id == Some(test_id),
+        None => !matches!(
+          x.status,
+          record::RecordStatus::Accepted | record::RecordStatus::Skipped
+        ),
+      })
+      .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, "no failed test".to_string()))?;
+    if record.status == record::RecordStatus::Accepted {
+      return Err(ApiError(
+        StatusCode::BAD_REQUEST,
+        "the test is accepted".to_string(),
+      ));
+    }
+    (
+      job.problem.clone(),
+      job.solution.clone(),
+      job.profile.clone(),
+      job.submitter.clone(),
+      record.clone(),
+    )
+  };
+
+  if let Some(Extension(claims)) = &claims {
+    let allowed = claims.allows(auth::Scope::Admin)
+      || (submitter.as_ref() == Some(&claims.sub)
+        && record
+          .test_id
+          .is_some_and(|x| CONFIG.bundle.submitter_testsets.contains(&x.testset)));
+    if !allowed {
+      return Err(ApiError(
+        StatusCode::FORBIDDEN,
+        "bundle of the test is not allowed".to_string(),
+      ));
+    }
+  }
+
+  let prepared = prepared_problem(&state, &problem_id, id).await?;
+  let bundle = prepared
+    .bundle(&profile, &solution, &record, CONFIG.bundle.file_limit)
+    .await
+    .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+  if !bundle.truncated.is_empty() {
+    log::info!("bundle of job {} truncated: {:?}", id, bundle.truncated);
+  }
+  let body = bundle
+    .to_tar()
+    .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+
+  return Ok(
+    (
+      [
+        (header::CONTENT_TYPE, "application/x-tar".to_string()),
+        (
+          header::CONTENT_DISPOSITION,
+          format!("attachment; filename=\"{}.tar\"", id),
+        ),
+      ],
+      body,
+    )
+      .into_response(),
+  );
+}
+
+/// Run a solution on the sample tests of a problem, and respond the record and files of each.
+///
+/// Nothing is stored, and at most `samples.concurrency` (in config) runs are served at a time,
+/// so pre-submit checks never hold up the judge jobs.
+async fn run_samples(
+  State(state): State<Arc<AppState>>,
+  Json(req): Json<SampleRequest>,
+) -> Result<Json<Vec<problem::SampleRun>>, ApiError> {
+  check_problem_id(&req.problem)?;
+  if !state.accepts(&req.problem) {
+    return Err(ApiError(
+      StatusCode::SERVICE_UNAVAILABLE,
+      "judging is paused".to_string(),
+    ));
+  }
+  if state.sandbox_down() {
+    return Err(ApiError(
+      StatusCode::SERVICE_UNAVAILABLE,
+      "sandbox is unreachable".to_string(),
+    ));
+  }
+  let profile = find_profile(req.profile.as_deref())?;
+
+  let running = state.sample_runs.fetch_add(1, Ordering::Relaxed);
+  let _slot = SampleSlot(&state.sample_runs);
+  if running >= CONFIG.samples.concurrency {
+    return Err(ApiError(
+      StatusCode::TOO_MANY_REQUESTS,
+      "too many sample runs, retry later".to_string(),
+    ));
+  }
+
+  let prepared = prepared_problem(&state, &req.problem, uuid::Uuid::new_v4()).await?;
+  let runs = prepared
+    .run_samples(&profile, &req.solution, CONFIG.samples.file_limit)
+    .await
+    .map_err(|err| {
+      let status = match err {
+        problem::SampleRunError::Unsupported(_) => StatusCode::BAD_REQUEST,
+        problem::SampleRunError::Compile(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+      };
+      ApiError(status, err.to_string())
+    })?;
+  return Ok(Json(runs));
+}
+
+/// Stream the events of a job, in the schema version asked by the consumer,
+/// so consumers keep working when the events grow new fields.
+async fn events(
+  State(state): State<Arc<AppState>>,
+  Path(id): Path<uuid::Uuid>,
+  Query(query): Query<EventsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+  let version = query.schema_version.unwrap_or(schema::VERSION);
+  schema::check(version).map_err(|err| ApiError(StatusCode::BAD_REQUEST, err.to_string()))?;
+  let found = state.jobs.lock().await.get(&id).map(|job| {
+    return (
+      job.events.clone(),
+      job.notify.subscribe(),
+      job.status.is_done(),
+    );
+  });
+  let (history, rx, done) = match found {
+    Some(x) => x,
+    None => return Err(evicted_error(&state, &id).await),
+  };
+
+  return Ok(Sse::new(job_events(history, rx, done, version).filter_map(
+    |x| async move {
+      return match Event::default().json_data(x) {
+        Ok(x) => Some(Ok(x)),
+        Err(err) => {
+          log::error!("failed to send an event: {}", err);
+          None
+        }
+      };
+    },
+  )));
+}
+
+/// Error of a job which is not in memory, `GONE` if it is evicted (see `evict_jobs`)
+/// and only its status is in the storage, `NOT_FOUND` otherwise.
+async fn evicted_error(state: &AppState, id: &uuid::Uuid) -> ApiError {
+  let stored = match &state.storage {
+    Some(storage) => storage.load(id).await,
+    None => Ok(None),
+  };
+  return match stored {
+    Ok(Some(_)) => ApiError(
+      StatusCode::GONE,
+      "job is finished and evicted from memory, see its status".to_string(),
+    ),
+    Ok(None) => ApiError(StatusCode::NOT_FOUND, "job not found".to_string()),
+    Err(err) => ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+  };
+}
+
+/// Events of a job in a schema version, the events sent so far in `history`
+/// followed by the live events from `rx` until the job ends, none if it is `done` already.
+///
+/// Live events end with the last event of the job, or when the job drops the sender
+/// (see `Job::finish`), e.g. when it fails without a last event.
+/// Events which do not exist in the version, or can not be encoded, are not sent.
+pub fn job_events(
+  history: Vec<problem::Response>,
+  rx: broadcast::Receiver<problem::Response>,
+  done: bool,
+  version: u32,
+) -> impl Stream<Item = serde_json::Value> {
+  let live = stream::unfold((rx, done), |(mut rx, done)| async move {
+    if done {
+      return None;
+    }
+    loop {
+      match rx.recv().await {
+        Ok(x) => {
+          let done = matches!(
+            x,
+            problem::Response::Finished { .. }
+              | problem::Response::Compiled { .. }
+              | problem::Response::Cancelled { .. }
+          );
+          return Some((x, (rx, done)));
+        }
+        Err(broadcast::error::RecvError::Lagged(n)) => {
+          log::warn!("event subscriber lagged behind {} events", n);
+        }
+        Err(broadcast::error::RecvError::Closed) => return None,
+      }
+    }
+  });
+
+  return stream::iter(history)
+    .chain(live)
+    .filter_map(move |x| async move {
+      return match schema::encode(&x, version) {
+        Ok(x) => x,
+        Err(err) => {
+          log::error!("failed to encode an event: {}", err);
+          None
+        }
+      };
+    });
+}
+
+async fn export_records(
+  State(state): State<Arc<AppState>>,
+  Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+  let export = export::Export::try_from(&args::ExportArgs {
+    format: query.format.unwrap_or_else(|| "csv".to_string()),
+    columns: query
+      .columns
+      .map(|x| x.split(',').map(str::to_string).collect())
+      .unwrap_or_default(),
+    problem: query.problem,
+    since: query.since,
+    until: query.until,
+    output: None,
+  })
+  .map_err(|err| ApiError(StatusCode::BAD_REQUEST, err.to_string()))?;
+
+  let storage = state.storage.as_ref().ok_or_else(|| {
+    ApiError(
+      StatusCode::NOT_FOUND,
+      "jobs are not stored, set postgres or data_dir in config".to_string(),
+    )
+  })?;
+  let jobs = storage
+    .list(&export.filter)
+    .await
+    .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+
+  let mut body = vec![];
+  export
+    .write(&jobs, &mut body)
+    .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+  log::info!("exported {} jobs as {:?}", jobs.len(), export.format);
+  return Ok(([(header::CONTENT_TYPE, export.format.content_type())], body).into_response());
+}
+
+async fn download_artifact(Path(id): Path<String>) -> Result<Response, ApiError> {
+  let store = artifact::STORE.as_ref().ok_or_else(|| {
+    ApiError(
+      StatusCode::NOT_FOUND,
+      "artifacts are not stored, set data_dir in config".to_string(),
+    )
+  })?;
+  let content = store
+    .load(&id)
+    .await
+    .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+    .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, "artifact not found".to_string()))?;
+  return Ok(
+    (
+      [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+      content,
+    )
+      .into_response(),
+  );
+}
+
+/// Check the configured languages, responds 503 if any probe fails.
+async fn diagnose() -> (StatusCode, Json<doctor::Report>) {
+  let report = doctor::diagnose().await;
+  let status = match report.is_ok() {
+    true => StatusCode::OK,
+    false => StatusCode::SERVICE_UNAVAILABLE,
+  };
+  return (status, Json(report));
+}
+
+/// Stored builds of a problem, in order of build time.
+async fn problem_builds(
+  State(state): State<Arc<AppState>>,
+  Path(id): Path<String>,
+) -> Result<Json<Vec<storage::BuildRow>>, ApiError> {
+  let storage = state.storage.as_ref().ok_or_else(|| {
+    ApiError(
+      StatusCode::NOT_FOUND,
+      "builds are not stored, set postgres or data_dir in config".to_string(),
+    )
+  })?;
+  return Ok(Json(storage.builds(&id).await.map_err(|err| {
+    ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+  })?));
+}
+
+/// Invalidate a problem on this instance, and on the other instances if the Redis server is set.
+async fn invalidate_problem(
+  State(state): State<Arc<AppState>>,
+  Path(id): Path<String>,
+  Query(query): Query<InvalidateQuery>,
+) -> Result<StatusCode, ApiError> {
+  let invalidation = bus::Invalidation {
+    problem: id,
+    revision: query.revision,
+  };
+  state.bus.publish(invalidation.clone());
+  if let Some(cluster) = &state.cluster {
+    let remote = bus::RemoteInvalidation {
+      instance: cluster.instance.clone(),
+      invalidation,
+    };
+    cluster
+      .publish(bus::CHANNEL, &remote)
+      .await
+      .map_err(|err| {
+        ApiError(
+          StatusCode::INTERNAL_SERVER_ERROR,
+          format!("problem is only invalidated on this instance: {}", err),
+        )
+      })?;
+  }
+  return Ok(StatusCode::NO_CONTENT);
+}
@@ -0,0 +1,59 @@
+use crate::auth;
+
+#[test]
+fn test_token() {
+  let claims = auth::Claims {
+    sub: "alice".to_string(),
+    scopes: vec![auth::Scope::Submit],
+    exp: Some(100),
+  };
+  let token = auth::sign(&claims, b"secret");
+
+  assert_eq!(auth::verify(&token, b"secret", 99), Ok(claims.clone()));
+  assert_eq!(
+    auth::verify(&token, b"secret", 100),
+    Err(auth::AuthError::Expired)
+  );
+  assert_eq!(
+    auth::verify(&token, b"other", 99),
+    Err(auth::AuthError::Signature)
+  );
+  assert_eq!(
+    auth::verify("not a token", b"secret", 99),
+    Err(auth::AuthError::Malformed)
+  );
+
+  // Tampered claims.
+  let mut parts: Vec<_> = token.split('.').collect();
+  let forged = auth::sign(
+    &auth::Claims {
+      scopes: vec![auth::Scope::Admin],
+      ..claims.clone()
+    },
+    b"other",
+  );
+  parts[1] = forged.split('.').nth(1).unwrap();
+  assert_eq!(
+    auth::verify(&parts.join("."), b"secret", 99),
+    Err(auth::AuthError::Signature)
+  );
+
+  assert!(claims.allows(auth::Scope::ReadOnly));
+  assert!(claims.allows(auth::Scope::Submit));
+  assert!(!claims.allows(auth::Scope::Admin));
+}
+
+#[test]
+fn test_token_interop() {
+  // Signed by another implementation of HMAC-SHA256 with the secret `Jefe`.
+  let token = "eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiJib2IiLCJzY29wZXMiOlsicmVhZF9vbmx5Il19.\
+               lW1_h3ubWu-8J3EyoggWAmOyWhX-kruZvdn75HZWLl4";
+  let claims = auth::Claims {
+    sub: "bob".to_string(),
+    scopes: vec![auth::Scope::ReadOnly],
+    exp: None,
+  };
+
+  assert_eq!(auth::verify(token, b"Jefe", 0), Ok(claims.clone()));
+  assert_eq!(auth::sign(&claims, b"Jefe"), token);
+}
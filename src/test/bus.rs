@@ -0,0 +1,51 @@
+use crate::{bus, cluster};
+
+#[tokio::test]
+async fn test_invalidation_bus() {
+  let bus = bus::InvalidationBus::default();
+  let invalidation = bus::Invalidation {
+    problem: "a".to_string(),
+    revision: Some("1".to_string()),
+  };
+  assert_eq!(bus.publish(invalidation.clone()), 0);
+
+  let (mut rx1, mut rx2) = (bus.subscribe(), bus.subscribe());
+  assert_eq!(bus.publish(invalidation.clone()), 2);
+  assert_eq!(rx1.recv().await.unwrap(), invalidation);
+  assert_eq!(rx2.recv().await.unwrap(), invalidation);
+}
+
+/// A test for relaying invalidations between instances, on the server of `start-redis.sh`.
+#[tokio::test]
+async fn test_relay_invalidations() {
+  let instance = uuid::Uuid::new_v4().to_string();
+  let cluster = cluster::Cluster::connect("redis://localhost:6379", instance.clone())
+    .await
+    .unwrap();
+  let bus = std::sync::Arc::new(bus::InvalidationBus::default());
+  let mut rx = bus.subscribe();
+  let remote = cluster.subscribe(bus::CHANNEL).await.unwrap();
+  tokio::spawn({
+    let bus = bus.clone();
+    let instance = instance.clone();
+    async move { bus.relay(&instance, remote).await }
+  });
+
+  // Invalidations sent by this instance are already published on its bus, so they are skipped.
+  let own = bus::Invalidation {
+    problem: "a".to_string(),
+    revision: None,
+  };
+  let other = bus::Invalidation {
+    problem: "b".to_string(),
+    revision: Some("1".to_string()),
+  };
+  for (instance, invalidation) in [(instance, own), ("other".to_string(), other.clone())] {
+    let remote = bus::RemoteInvalidation {
+      instance,
+      invalidation,
+    };
+    cluster.publish(bus::CHANNEL, &remote).await.unwrap();
+  }
+  assert_eq!(rx.recv().await.unwrap(), other);
+}
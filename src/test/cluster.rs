@@ -0,0 +1,19 @@
+use futures::StreamExt;
+
+use crate::cluster;
+
+/// A test for sending messages between instances, on the server of `start-redis.sh`.
+#[tokio::test]
+async fn test_cluster_messages() {
+  let instance = uuid::Uuid::new_v4().to_string();
+  let cluster = cluster::Cluster::connect("redis://localhost:6379", instance.clone())
+    .await
+    .unwrap();
+  let channel = cluster::Cluster::instance_channel(&instance);
+  let mut messages = cluster.subscribe::<(String, u32)>(&channel).await.unwrap();
+
+  // Messages which can not be decoded are skipped.
+  cluster.publish(&channel, &"invalid").await.unwrap();
+  cluster.publish(&channel, &("a", 1)).await.unwrap();
+  assert_eq!(messages.next().await.unwrap(), ("a".to_string(), 1));
+}
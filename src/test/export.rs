@@ -0,0 +1,169 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use rindag_judge::{lang, problem, record};
+
+use crate::{args, export, service, storage};
+
+fn job(problem: &str, at: &str, status: service::JobStatus) -> storage::JobRow {
+  let at = record::Timestamp {
+    wall: chrono::DateTime::parse_from_rfc3339(at)
+      .unwrap()
+      .with_timezone(&chrono::Utc),
+    monotonic: std::time::Duration::ZERO,
+  };
+  return storage::JobRow {
+    id: uuid::Uuid::new_v4(),
+    problem: problem.to_string(),
+    lang: lang::Lang::from_str("c").unwrap(),
+    testsets: vec![problem::Testset::Main],
+    submitted_at: at,
+    finished_at: at,
+    info: service::JobInfo {
+      status,
+      compile: BTreeMap::new(),
+    },
+    environment: None,
+  };
+}
+
+fn jobs() -> Vec<storage::JobRow> {
+  let test_id = |index| record::TestId {
+    testset: problem::Testset::Main,
+    subtask: 0,
+    index,
+  };
+  let records = vec![
+    record::RECORD_SKIPPED
+      .clone()
+      .with_id(test_id(0), uuid::Uuid::nil()),
+    record::RECORD_SKIPPED
+      .clone()
+      .with_id(test_id(1), uuid::Uuid::nil()),
+  ];
+  return vec![
+    job(
+      "a",
+      "2024-01-01T00:00:00Z",
+      service::JobStatus::Finished { score: 0., records },
+    ),
+    job(
+      "a",
+      "2024-02-01T00:00:00Z",
+      service::JobStatus::Failed {
+        message: "compile error".to_string(),
+      },
+    ),
+    job(
+      "b",
+      "2024-01-15T00:00:00Z",
+      service::JobStatus::Failed {
+        message: "compile error".to_string(),
+      },
+    ),
+  ];
+}
+
+#[test]
+fn test_export_csv() {
+  let export = export::Export::try_from(&args::ExportArgs {
+    format: "csv".to_string(),
+    columns: vec![
+      "problem".to_string(),
+      "test".to_string(),
+      "status".to_string(),
+    ],
+    problem: Some("a".to_string()),
+    since: None,
+    until: Some("2024-03-01T00:00:00+00:00".to_string()),
+    output: None,
+  })
+  .unwrap();
+
+  let mut out = vec![];
+  export.write(&jobs(), &mut out).unwrap();
+  assert_eq!(
+    String::from_utf8(out).unwrap(),
+    "problem,test,status\na,0,skipped\na,1,skipped\na,,\n"
+  );
+
+  let export = export::Export::try_from(&args::ExportArgs {
+    format: "csv".to_string(),
+    since: Some("2024-01-10T00:00:00Z".to_string()),
+    until: Some("2024-02-01T00:00:00Z".to_string()),
+    ..Default::default()
+  })
+  .unwrap();
+  let mut out = vec![];
+  export.write(&jobs(), &mut out).unwrap();
+  let out = String::from_utf8(out).unwrap();
+  let lines: Vec<_> = out.lines().collect();
+  assert_eq!(lines.len(), 2);
+  assert!(lines[0].starts_with("job_id,problem,lang,"));
+  assert!(lines[1].contains(",b,c,"));
+}
+
+#[test]
+fn test_verdict_table() {
+  let jobs = jobs();
+  let table = export::verdict_table(&jobs, "a");
+  assert_eq!(table.rows.len(), 1);
+  assert_eq!(table.rows[0].0, format!("{} (c)", jobs[0].id));
+  assert_eq!(table.rows[0].1.len(), 2);
+  assert!(table.to_csv().lines().count() > 1);
+
+  assert!(export::verdict_table(&jobs, "b").rows.is_empty());
+}
+
+#[test]
+fn test_export_invalid() {
+  let parse = |format: &str, column: &str, since: &str| {
+    export::Export::try_from(&args::ExportArgs {
+      format: format.to_string(),
+      columns: vec![column.to_string()],
+      since: Some(since.to_string()),
+      ..Default::default()
+    })
+  };
+  assert!(parse("csv", "score", "2024-01-01T00:00:00Z").is_ok());
+  assert!(matches!(
+    parse("xlsx", "score", "2024-01-01T00:00:00Z"),
+    Err(export::ExportError::Format(_))
+  ));
+  assert!(matches!(
+    parse("csv", "verdict", "2024-01-01T00:00:00Z"),
+    Err(export::ExportError::Column(_))
+  ));
+  assert!(matches!(
+    parse("csv", "score", "yesterday"),
+    Err(export::ExportError::Time(_))
+  ));
+}
+
+#[test]
+fn test_export_parquet() {
+  let export = export::Export::try_from(&args::ExportArgs {
+    format: "parquet".to_string(),
+    ..Default::default()
+  })
+  .unwrap();
+
+  let path = std::env::temp_dir().join(format!("rindag-export-{}.parquet", uuid::Uuid::new_v4()));
+  export
+    .write(&jobs(), std::fs::File::create(&path).unwrap())
+    .unwrap();
+
+  let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+  let metadata = reader.metadata();
+  assert_eq!(metadata.file_metadata().num_rows(), 4);
+  assert_eq!(
+    metadata.file_metadata().schema_descr().num_columns(),
+    export::Column::ALL.len()
+  );
+  assert_eq!(
+    metadata.file_metadata().schema_descr().column(0).name(),
+    "job_id"
+  );
+
+  std::fs::remove_file(&path).unwrap();
+}
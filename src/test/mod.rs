@@ -0,0 +1,9 @@
+mod auth;
+mod bus;
+mod cluster;
+mod export;
+mod leader;
+mod problems;
+mod queue;
+mod service;
+mod storage;
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use crate::problems;
+
+#[test]
+fn test_problem_cache() {
+  let cache = problems::ProblemCache::new(2, 100);
+  cache.insert("a".to_string(), "1".to_string(), Arc::new(1), 10);
+  cache.insert("b".to_string(), "1".to_string(), Arc::new(2), 10);
+  assert_eq!(cache.get("a").as_deref(), Some(&1));
+
+  // "b" is the least recently used.
+  cache.insert("c".to_string(), "1".to_string(), Arc::new(3), 10);
+  assert!(cache.get("b").is_none());
+  assert_eq!(cache.get("c").as_deref(), Some(&3));
+
+  // Evicted by size, "a" is the least recently used now.
+  cache.insert("d".to_string(), "1".to_string(), Arc::new(4), 85);
+  assert!(cache.get("a").is_none());
+  assert_eq!(cache.get("d").as_deref(), Some(&4));
+  assert_eq!(cache.metrics().size, 95);
+
+  // Too large to cache.
+  cache.insert("e".to_string(), "1".to_string(), Arc::new(5), 101);
+  assert!(cache.get("e").is_none());
+
+  // Already of the rebuilt revision.
+  assert!(!cache.invalidate("d", Some("1")));
+  assert!(cache.invalidate("d", Some("2")));
+  assert!(!cache.invalidate("d", None));
+  assert!(cache.invalidate("c", None));
+  assert_eq!(
+    cache.metrics(),
+    problems::CacheMetrics {
+      problems: 0,
+      size: 0,
+      hits: 3,
+      misses: 3,
+    }
+  );
+}
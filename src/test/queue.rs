@@ -0,0 +1,145 @@
+use crate::queue;
+
+#[tokio::test]
+async fn test_queue() {
+  let queue = queue::Queue::default();
+  let ids: Vec<_> = (0..4).map(|_| uuid::Uuid::new_v4()).collect();
+
+  queue.push(ids[0], "a".to_string(), 0, 0).unwrap();
+  queue.push(ids[1], "b".to_string(), 1, 1).unwrap();
+  queue.push(ids[2], "c".to_string(), 0, 2).unwrap();
+  assert_eq!(queue.push(ids[3], "a".to_string(), 5, 3), Err(ids[0]));
+  assert_eq!(
+    queue.metrics(),
+    queue::Metrics {
+      depth: 3,
+      in_flight: 0,
+      held: false,
+      suspended: false,
+    }
+  );
+
+  // Higher priority first, then first in first out.
+  let (id, key, job) = queue.pop().await;
+  assert_eq!((id, key.as_str(), job), (ids[1], "b", 1));
+  let (_, key_a, job) = queue.pop().await;
+  assert_eq!(job, 0);
+  assert_eq!(
+    queue.metrics(),
+    queue::Metrics {
+      depth: 1,
+      in_flight: 2,
+      held: false,
+      suspended: false,
+    }
+  );
+
+  // A key can be pushed again after its job is done.
+  assert!(queue.push(ids[3], "a".to_string(), 0, 3).is_err());
+  queue.done(&key_a);
+  queue.push(ids[3], "a".to_string(), 0, 3).unwrap();
+  assert_eq!(queue.pop().await.2, 2);
+  assert_eq!(queue.pop().await.2, 3);
+}
+
+#[tokio::test]
+async fn test_queue_held() {
+  let queue = std::sync::Arc::new(queue::Queue::default());
+  queue.set_held(true);
+  queue
+    .push(uuid::Uuid::new_v4(), "a".to_string(), 0, 0)
+    .unwrap();
+
+  let worker = tokio::spawn({
+    let queue = queue.clone();
+    async move { queue.pop().await.2 }
+  });
+  tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+  assert!(!worker.is_finished());
+  assert_eq!(queue.metrics().depth, 1);
+
+  queue.set_held(false);
+  assert_eq!(worker.await.unwrap(), 0);
+  assert_eq!(queue.metrics().in_flight, 1);
+}
+
+#[tokio::test]
+async fn test_queue_suspended() {
+  let queue = std::sync::Arc::new(queue::Queue::default());
+  queue.set_held(true);
+  queue.set_suspended(true);
+  queue
+    .push(uuid::Uuid::new_v4(), "a".to_string(), 0, 0)
+    .unwrap();
+
+  let worker = tokio::spawn({
+    let queue = queue.clone();
+    async move { queue.pop().await.2 }
+  });
+
+  // Resuming does not release a queue held by the administrator.
+  queue.set_suspended(false);
+  tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+  assert!(!worker.is_finished());
+  assert!(queue.metrics().held);
+
+  queue.set_suspended(true);
+  queue.set_held(false);
+  tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+  assert!(!worker.is_finished());
+  assert!(queue.metrics().suspended);
+
+  queue.set_suspended(false);
+  assert_eq!(worker.await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_queue_wait_open() {
+  let queue = std::sync::Arc::new(queue::Queue::<()>::default());
+  queue.set_held(true);
+
+  let worker = tokio::spawn({
+    let queue = queue.clone();
+    async move { queue.wait_open().await }
+  });
+  tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+  assert!(!worker.is_finished());
+
+  queue.set_held(false);
+  worker.await.unwrap();
+}
+
+/// A test for the shared queue, on the server of `start-redis.sh`.
+#[tokio::test]
+async fn test_shared_queue() {
+  let client = redis::Client::open("redis://localhost:6379").unwrap();
+  let conn = redis::aio::ConnectionManager::new(client).await.unwrap();
+  // Keys of a new prefix, so the jobs left by earlier runs are not seen.
+  let queue = queue::SharedQueue::new(conn, format!("test-{}", uuid::Uuid::new_v4()));
+  let ids: Vec<_> = (0..4).map(|_| uuid::Uuid::new_v4()).collect();
+
+  queue.push(ids[0], "a".to_string(), 0, &0).await.unwrap();
+  queue.push(ids[1], "b".to_string(), 1, &1).await.unwrap();
+  queue.push(ids[2], "c".to_string(), -1, &2).await.unwrap();
+  assert!(matches!(
+    queue.push(ids[3], "a".to_string(), 5, &3).await,
+    Err(queue::Error::Duplicate(x)) if x == ids[0]
+  ));
+  assert_eq!(queue.load().await.unwrap(), (3, 0));
+
+  // Higher priority first, then first in first out.
+  let (id, key, job) = queue.try_pop::<i32>().await.unwrap().unwrap();
+  assert_eq!((id, key.as_str(), job), (ids[1], "b", 1));
+  let (_, key_a, job) = queue.try_pop::<i32>().await.unwrap().unwrap();
+  assert_eq!(job, 0);
+  assert_eq!(queue.load().await.unwrap(), (1, 2));
+
+  // A key can be pushed again after its job is done.
+  assert!(queue.push(ids[3], "a".to_string(), 0, &3).await.is_err());
+  queue.done(&key_a).await.unwrap();
+  queue.push(ids[3], "a".to_string(), 0, &3).await.unwrap();
+  assert_eq!(queue.try_pop::<i32>().await.unwrap().unwrap().2, 3);
+  assert_eq!(queue.try_pop::<i32>().await.unwrap().unwrap().2, 2);
+  assert!(queue.try_pop::<i32>().await.unwrap().is_none());
+  assert_eq!(queue.load().await.unwrap(), (0, 3));
+}
@@ -0,0 +1,148 @@
+use futures::StreamExt;
+use rindag_judge::{environment, problem, program, record, sandbox, schema};
+use tokio::sync::broadcast;
+
+use crate::service;
+
+fn compile_error() -> problem::Response {
+  return problem::Response::CompileError {
+    correlation_id: uuid::Uuid::new_v4(),
+    timestamp: record::Timestamp::now(),
+    program: problem::CompileTarget::Solution,
+    message: "error".to_string(),
+    result: program::CompileResult::from(&sandbox::ExecuteResult::internal_error()),
+  };
+}
+
+#[test]
+fn test_pause() {
+  let state = service::AppState::default();
+  assert!(state.accepts("a"));
+
+  state.pause(Some("a".to_string()), false);
+  assert!(!state.accepts("a"));
+  assert!(state.accepts("b"));
+
+  state.pause(None, true);
+  assert!(!state.accepts("b"));
+  let pipeline = state.pipeline();
+  assert!(pipeline.intake.paused);
+  assert!(pipeline.queue.held);
+
+  // Resuming all problems keeps the problems paused one by one.
+  state.resume(None);
+  assert!(state.accepts("b"));
+  assert!(!state.accepts("a"));
+  assert!(!state.pipeline().queue.held);
+
+  state.resume(Some("a"));
+  assert!(state.accepts("a"));
+}
+
+#[test]
+fn test_sandbox_down() {
+  let state = service::AppState::default();
+  state.pause(None, true);
+
+  assert!(state.set_sandbox_result(Err("refused".to_string())));
+  assert!(!state.set_sandbox_result(Err("refused again".to_string())));
+  let pipeline = state.pipeline();
+  assert!(pipeline.sandbox.down);
+  assert!(pipeline.sandbox.down_since.is_some());
+  assert_eq!(
+    pipeline.sandbox.last_error.as_deref(),
+    Some("refused again")
+  );
+  assert_eq!(pipeline.sandbox.outages, 1);
+  assert!(pipeline.queue.suspended);
+
+  // The sandbox coming back does not release a queue held by the administrator.
+  assert!(state.set_sandbox_result(Ok(())));
+  let pipeline = state.pipeline();
+  assert!(!pipeline.sandbox.down);
+  assert!(pipeline.sandbox.down_since.is_none());
+  assert!(!pipeline.queue.suspended);
+  assert!(pipeline.queue.held);
+
+  state.set_sandbox_result(Err("refused".to_string()));
+  assert_eq!(state.pipeline().sandbox.outages, 2);
+}
+
+#[test]
+fn test_compile_job() {
+  let req: service::SubmitRequest = serde_json::from_value(serde_json::json!({
+    "problem": "a-plus-b",
+    "solution": { "lang": "c", "data": [] },
+    "kind": "compile",
+  }))
+  .unwrap();
+  assert_eq!(req.kind, service::JobKind::Compile);
+
+  let info = service::JobInfo {
+    status: service::JobStatus::Compiled {
+      check: program::CompileCheck {
+        executable: None,
+        diagnostics: "error: expected ';'".to_string(),
+        result: program::CompileResult::from(&sandbox::ExecuteResult::internal_error()),
+      },
+    },
+    compile: Default::default(),
+  };
+  let value = serde_json::to_value(&info).unwrap();
+  assert_eq!(value["status"], "compiled");
+  assert_eq!(value["check"]["diagnostics"], "error: expected ';'");
+  let info: service::JobInfo = serde_json::from_value(value).unwrap();
+  assert!(matches!(info.status, service::JobStatus::Compiled { check } if !check.is_ok()));
+}
+
+#[tokio::test]
+async fn test_job_events_dropped_sender() {
+  // A failed job sends no last event, and drops the sender instead.
+  let (tx, rx) = broadcast::channel(16);
+  let events = tokio::spawn(
+    service::job_events(vec![compile_error()], rx, false, schema::VERSION).collect::<Vec<_>>(),
+  );
+  tx.send(compile_error()).unwrap();
+  drop(tx);
+  let events = events.await.unwrap();
+  assert_eq!(events.len(), 2);
+  assert_eq!(events[1]["type"], "CompileError");
+
+  // Events which do not exist in the version are skipped.
+  let (_tx, rx) = broadcast::channel(16);
+  let events: Vec<_> = service::job_events(vec![compile_error()], rx, true, 1)
+    .collect()
+    .await;
+  assert!(events.is_empty());
+}
+
+#[tokio::test]
+async fn test_job_events_queued() {
+  // Subscribing to a job still in the queue waits for the events sent once it is judged.
+  let status = service::JobStatus::Queued;
+  assert!(!status.is_done());
+  let (tx, rx) = broadcast::channel(16);
+  let events = tokio::spawn(
+    service::job_events(vec![], rx, status.is_done(), schema::VERSION).collect::<Vec<_>>(),
+  );
+  tx.send(compile_error()).unwrap();
+  tx.send(problem::Response::Finished {
+    correlation_id: uuid::Uuid::new_v4(),
+    timestamp: record::Timestamp::now(),
+    score: 0.,
+    provisional_score: 0.,
+    final_score: 0.,
+    records: vec![],
+    environment: environment::ENVIRONMENT.clone(),
+  })
+  .unwrap();
+  tx.send(compile_error()).unwrap();
+  let events = events.await.unwrap();
+  assert_eq!(events.len(), 2);
+  assert_eq!(events[1]["type"], "Finished");
+
+  assert!(service::JobStatus::Failed {
+    message: "error".to_string()
+  }
+  .is_done());
+}